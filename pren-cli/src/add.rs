@@ -0,0 +1,303 @@
+use crate::check::{check_content, check_prompt_name, check_references};
+use crate::clipboard::read_clipboard;
+use crate::config::{ModelConfig, PrenCliConfig};
+use crate::output::{OutputMode, print_json};
+use crate::tagging::{confirm_tags, print_tag_suggestions, suggest_tags};
+use crate::usage::print_tokens;
+use anyhow::{Context, Result, bail};
+use pren_core::file_storage::{FileStorage, FileStorageError};
+use pren_core::llm::ModelSettings;
+use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
+use pren_core::storage::PromptStorage;
+use serde_json::json;
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// What `pren add` saves and how.
+pub struct AddOptions {
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub content: Option<String>,
+    pub file: Option<PathBuf>,
+    pub from_clipboard: bool,
+    pub author: Option<String>,
+    pub source_url: Option<String>,
+    pub license: Option<String>,
+    pub overwrite: bool,
+    pub override_lock: bool,
+    pub skip_checks: bool,
+    pub create_stubs: bool,
+    pub auto_tag: bool,
+    pub yes: bool,
+}
+
+/// Saves a new prompt, checking its content and references first, and prints its
+/// arguments and references.
+pub async fn run_add(
+    storage: &FileStorage,
+    config: &PrenCliConfig,
+    model_config: &ModelConfig,
+    options: AddOptions,
+    output: OutputMode,
+    use_clipboard: bool,
+) -> Result<()> {
+    let AddOptions {
+        name,
+        description,
+        tags,
+        content,
+        file,
+        from_clipboard,
+        author,
+        source_url,
+        license,
+        overwrite,
+        override_lock,
+        skip_checks,
+        create_stubs,
+        auto_tag,
+        yes,
+    } = options;
+    check_prompt_name(&name)?;
+    if storage.get_prompt(&name).is_ok() && !overwrite {
+        bail!(
+            "Prompt '{}' already exists. Use --overwrite to replace it.",
+            name
+        );
+    }
+    let content = if from_clipboard {
+        Some(read_clipboard(use_clipboard).context("Failed to read the prompt content")?)
+    } else {
+        content
+    };
+    let content = read_content(content, file.as_deref())?;
+    if !skip_checks {
+        check_content(&content)?;
+    }
+    let mut metadata = PromptMetadata::new(name, description, tags);
+    metadata.author = author.or(config.author.clone());
+    metadata.source_url = source_url;
+    metadata.license = license;
+    let mut prompt = Prompt::new(metadata, content);
+    let mut suggested_tags = Vec::new();
+    if auto_tag {
+        let settings = ModelSettings::default();
+        match suggest_tags(
+            storage,
+            &model_config.provider_config(None, None),
+            &model_config.model_name,
+            &settings,
+            &prompt,
+        )
+        .await
+        {
+            Ok((suggested, vocabulary, usage)) => {
+                if output == OutputMode::Text {
+                    print_tag_suggestions(&prompt.metadata.name, &suggested, &vocabulary);
+                    print_tokens(config, &model_config.model_name, usage);
+                }
+                if !suggested.is_empty()
+                    && (yes || output == OutputMode::Text && confirm_tags(&prompt.metadata.name)?)
+                {
+                    prompt.metadata.tags.extend(suggested.iter().cloned());
+                }
+                suggested_tags = suggested;
+            }
+            Err(e) => warn!("Couldn't suggest tags: {}", e),
+        }
+    }
+    let (arguments, references) = match PromptTemplate::new(prompt.clone()) {
+        Ok(template) => {
+            let mut references = template.prompt_references();
+            let mut seen = HashSet::new();
+            references.retain(|reference| seen.insert(reference.clone()));
+            let arguments: Vec<String> = template
+                .argument_info()
+                .into_iter()
+                .map(|argument| argument.name)
+                .collect();
+            (arguments, references)
+        }
+        Err(e) => {
+            warn!("Prompt '{}' can't be parsed: {}", prompt.metadata.name, e);
+            (Vec::new(), Vec::new())
+        }
+    };
+    let missing: Vec<String> = references
+        .iter()
+        .filter(|reference| {
+            **reference != prompt.metadata.name && storage.get_prompt(reference).is_err()
+        })
+        .cloned()
+        .collect();
+
+    check_references(storage, &prompt, config.validate_on_save)?;
+    if override_lock {
+        storage.save_prompt_overriding_lock(&prompt)?;
+    } else {
+        match storage.save_prompt(&prompt) {
+            Err(FileStorageError::PromptLocked(name)) => bail!(
+                "Prompt '{}' is locked. Use --override-lock to overwrite it.",
+                name
+            ),
+            result => result?,
+        }
+    }
+    if create_stubs {
+        for reference in &missing {
+            let description = format!("Stub referenced by '{}'", prompt.metadata.name);
+            storage.save_prompt(&Prompt::new(
+                PromptMetadata::new(reference.clone(), Some(description), vec![]),
+                String::new(),
+            ))?;
+        }
+    }
+
+    if output == OutputMode::Json {
+        let mut added = serde_json::to_value(&prompt)?;
+        added["arguments"] = json!(arguments);
+        added["references"] = json!(references);
+        added["missing_references"] = json!(missing);
+        added["stubs_created"] = json!(create_stubs && !missing.is_empty());
+        if auto_tag {
+            added["suggested_tags"] = json!(suggested_tags);
+        }
+        return print_json(&added);
+    }
+    println!("Prompt '{}' saved", prompt.metadata.name);
+    if !arguments.is_empty() {
+        println!("Arguments: {}", arguments.join(", "));
+    }
+    if !references.is_empty() {
+        println!("References: {}", references.join(", "));
+    }
+    for reference in &missing {
+        if create_stubs {
+            println!("Created an empty prompt for '{}'", reference);
+        } else {
+            warn!(
+                "Prompt '{}' doesn't exist yet. Use --create-stubs to create it.",
+                reference
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Gets the content of a new prompt from `--content`, `--file` or, if neither is given,
+/// stdin, unless it's a terminal.
+fn read_content(content: Option<String>, file: Option<&Path>) -> Result<String> {
+    let content = match (content, file) {
+        (Some(content), _) => content,
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the prompt content from {:?}", path))?,
+        (None, None) if !std::io::stdin().is_terminal() => {
+            std::io::read_to_string(std::io::stdin())
+                .context("Failed to read the prompt content from stdin")?
+        }
+        (None, None) => bail!("Provide the prompt content with --content, --file or stdin"),
+    };
+    if content.trim().is_empty() {
+        bail!("The prompt content can't be empty");
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::{NamedTempFile, TempDir};
+
+    fn options(name: &str, content: &str) -> AddOptions {
+        AddOptions {
+            name: name.to_string(),
+            description: None,
+            tags: Vec::new(),
+            content: Some(content.to_string()),
+            file: None,
+            from_clipboard: false,
+            author: None,
+            source_url: None,
+            license: None,
+            overwrite: false,
+            override_lock: false,
+            skip_checks: true,
+            create_stubs: false,
+            auto_tag: false,
+            yes: false,
+        }
+    }
+
+    #[test]
+    fn test_read_content() {
+        assert_eq!(
+            "Hello",
+            read_content(Some("Hello".to_string()), None).unwrap()
+        );
+        assert!(read_content(Some("  \n".to_string()), None).is_err());
+
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "From a file").unwrap();
+        assert_eq!(
+            "From a file",
+            read_content(None, Some(file.path())).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_add_creates_stubs() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        let config = PrenCliConfig {
+            author: Some("Ada".to_string()),
+            ..PrenCliConfig::default()
+        };
+        let mut add = options("greeting", "{{prompt:persona}} Say hi to {{name}}.");
+        add.create_stubs = true;
+        run_add(
+            &storage,
+            &config,
+            &ModelConfig::default(),
+            add,
+            OutputMode::Json,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let saved = storage.get_prompt("greeting").unwrap();
+        assert_eq!(Some("Ada".to_string()), saved.metadata.author);
+        assert_eq!("", storage.get_prompt("persona").unwrap().content);
+
+        let again = run_add(
+            &storage,
+            &config,
+            &ModelConfig::default(),
+            options("greeting", "Hi"),
+            OutputMode::Json,
+            false,
+        )
+        .await;
+        assert!(again.is_err());
+
+        let invalid = run_add(
+            &storage,
+            &config,
+            &ModelConfig::default(),
+            options("../outside", "Hi"),
+            OutputMode::Json,
+            false,
+        )
+        .await;
+        assert!(invalid.is_err());
+        assert!(!sandbox.path().join("../outside.md").exists());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::constants::ARG_ENV_PREFIX;
+use anyhow::{Context, Result, bail};
+use pren_core::arguments::{ArgumentSource, merge_arguments};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::Path;
+
+/// Merge the `--context` file, the `PREN_ARG_*` environment variables, the `--args-json`
+/// object and the `--args` pairs into a single argument map.
+///
+/// Each source takes precedence over the ones before it, so values passed with `--args`
+/// win over everything else.
+pub fn build_args_map(
+    args: &[(String, String)],
+    args_json: Option<&str>,
+    context: Option<&Path>,
+) -> Result<HashMap<String, Value>> {
+    let mut sources = Vec::new();
+    if let Some(path) = context {
+        sources.push(ArgumentSource::File(path.to_path_buf()));
+    }
+    sources.push(ArgumentSource::Env {
+        prefix: ARG_ENV_PREFIX.to_string(),
+    });
+    if let Some(json) = args_json {
+        sources.push(ArgumentSource::Values(
+            serde_json::from_str(json).context("--args-json must be a JSON object")?,
+        ));
+    }
+    sources.push(ArgumentSource::Values(
+        args.iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect(),
+    ));
+    Ok(merge_arguments(&sources)?)
+}
+
+/// Adds the content piped to stdin as the value of the `--arg-stdin` argument, after the
+/// `--args` pairs so it takes precedence over them.
+pub fn add_stdin_arg(args: &mut Vec<(String, String)>, key: Option<&str>) -> Result<()> {
+    let Some(key) = key else {
+        return Ok(());
+    };
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        bail!(
+            "--arg-stdin expects the value of '{}' to be piped to stdin",
+            key
+        );
+    }
+    let value = std::io::read_to_string(stdin)
+        .with_context(|| format!("Couldn't read the value of '{}' from stdin", key))?;
+    args.push((key.to_string(), value));
+    Ok(())
+}
+
+/// Parse a single key-value pair
+///
+/// Values starting with `@` are read from a file (`@path`) or from stdin (`@-`),
+/// while `@@` escapes a literal `@`.
+pub fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    let pos = s
+        .find('=')
+        .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{}`", s))?;
+    let (key, value) = (&s[..pos], &s[pos + 1..]);
+    let value = match value.strip_prefix('@') {
+        Some(escaped) if escaped.starts_with('@') => escaped.to_string(),
+        Some("-") => std::io::read_to_string(std::io::stdin())
+            .map_err(|e| format!("couldn't read the value of `{}` from stdin: {}", key, e))?,
+        Some(path) => std::fs::read_to_string(path).map_err(|e| {
+            format!(
+                "couldn't read the value of `{}` from `{}`: {}",
+                key, path, e
+            )
+        })?,
+        None => value.to_string(),
+    };
+    Ok((key.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_parse_key_val() {
+        assert_eq!(
+            Ok(("name".to_string(), "a=b".to_string())),
+            parse_key_val("name=a=b")
+        );
+        assert_eq!(
+            Ok(("handle".to_string(), "@ana".to_string())),
+            parse_key_val("handle=@@ana")
+        );
+        assert!(parse_key_val("name").is_err());
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"fn main() {}\n").unwrap();
+        let arg = format!("code=@{}", file.path().display());
+        assert_eq!(
+            Ok(("code".to_string(), "fn main() {}\n".to_string())),
+            parse_key_val(&arg)
+        );
+        assert!(parse_key_val("code=@/does/not/exist").is_err());
+    }
+
+    #[test]
+    fn test_build_args_map() {
+        let args = vec![("name".to_string(), "Ana".to_string())];
+        let args_map =
+            build_args_map(&args, Some(r#"{"name": "Bob", "user": {"age": 30}}"#), None).unwrap();
+        // --args win over --args-json
+        assert_eq!(
+            Some(&Value::String("Ana".to_string())),
+            args_map.get("name")
+        );
+        assert_eq!(Some(&serde_json::json!({"age": 30})), args_map.get("user"));
+
+        assert!(build_args_map(&[], Some("[1, 2]"), None).is_err());
+    }
+
+    #[test]
+    fn test_add_stdin_arg_without_key() {
+        let mut args = vec![("name".to_string(), "Ana".to_string())];
+        add_stdin_arg(&mut args, None).unwrap();
+        assert_eq!(1, args.len());
+    }
+}
@@ -0,0 +1,161 @@
+#[cfg(feature = "keyring")]
+use crate::AuthCommands;
+#[cfg(feature = "keyring")]
+use crate::config::ModelConfig;
+#[cfg(feature = "keyring")]
+use crate::output::{OutputMode, print_json};
+#[cfg(feature = "keyring")]
+use anyhow::{Context, Result, bail};
+use pren_core::llm::Provider;
+#[cfg(feature = "keyring")]
+use serde_json::json;
+#[cfg(feature = "keyring")]
+use std::io::IsTerminal;
+
+/// Where an API key was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    /// `PREN_API_KEY`, or the config file
+    Config,
+    /// The OS keyring, where `pren auth set` stores keys
+    Keyring,
+    /// The provider's own environment variable, e.g. `OPENAI_API_KEY`
+    ProviderEnv,
+}
+
+/// The environment variable a provider's own tools read its API key from.
+pub fn provider_key_env(provider: Provider) -> Option<&'static str> {
+    match provider {
+        Provider::OpenAi => Some("OPENAI_API_KEY"),
+        Provider::Anthropic => Some("ANTHROPIC_API_KEY"),
+        Provider::Gemini => Some("GEMINI_API_KEY"),
+        Provider::Ollama => None,
+    }
+}
+
+/// Finds the API key of a provider when none is configured: in the provider's environment
+/// variable, and then in the OS keyring, if pren is built with keyring support.
+pub fn find_api_key(provider: Provider) -> Option<(String, KeySource)> {
+    provider_key_env(provider)
+        .and_then(|name| std::env::var(name).ok())
+        .filter(|api_key| !api_key.is_empty())
+        .map(|api_key| (api_key, KeySource::ProviderEnv))
+        .or_else(|| keyring::get(provider).map(|api_key| (api_key, KeySource::Keyring)))
+}
+
+#[cfg(feature = "keyring")]
+pub mod keyring {
+    use crate::constants::KEYRING_SERVICE;
+    use anyhow::{Context, Result};
+    use keyring::{Entry, Error};
+    use pren_core::llm::Provider;
+    use tracing::warn;
+
+    fn entry(provider: Provider) -> keyring::Result<Entry> {
+        Entry::new(KEYRING_SERVICE, &provider.to_string())
+    }
+
+    /// Gets the API key of a provider from the keyring. Failing to read it only logs a
+    /// warning, since the key can come from elsewhere.
+    pub fn get(provider: Provider) -> Option<String> {
+        match entry(provider).and_then(|entry| entry.get_password()) {
+            Ok(api_key) => Some(api_key),
+            Err(Error::NoEntry) => None,
+            Err(e) => {
+                warn!("Couldn't read the API key from the keyring: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set(provider: Provider, api_key: &str) -> Result<()> {
+        entry(provider)
+            .and_then(|entry| entry.set_password(api_key))
+            .context("Couldn't store the API key in the keyring")
+    }
+
+    /// Removes the API key of a provider from the keyring.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - Whether there was a key to remove.
+    pub fn remove(provider: Provider) -> Result<bool> {
+        match entry(provider).and_then(|entry| entry.delete_credential()) {
+            Ok(()) => Ok(true),
+            Err(Error::NoEntry) => Ok(false),
+            Err(e) => Err(e).context("Couldn't remove the API key from the keyring"),
+        }
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+pub mod keyring {
+    use pren_core::llm::Provider;
+
+    /// Without keyring support there are no keys in the keyring.
+    pub fn get(_provider: Provider) -> Option<String> {
+        None
+    }
+}
+
+/// Stores or removes API keys in the keyring. Keys are only read from stdin or a hidden
+/// prompt, so they don't end up in the shell history.
+#[cfg(feature = "keyring")]
+pub fn run_auth_command(
+    command: AuthCommands,
+    model_config: &ModelConfig,
+    output: OutputMode,
+) -> Result<()> {
+    match command {
+        AuthCommands::Set { provider } => {
+            let provider = provider.unwrap_or(model_config.provider);
+            let api_key = if std::io::stdin().is_terminal() {
+                dialoguer::Password::new()
+                    .with_prompt(format!("API key for {}", provider))
+                    .interact()?
+            } else {
+                std::io::read_to_string(std::io::stdin())
+                    .context("Failed to read the API key from stdin")?
+            };
+            let api_key = api_key.trim();
+            if api_key.is_empty() {
+                bail!("The API key can't be empty");
+            }
+            keyring::set(provider, api_key)?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "provider": provider, "stored": true }))?,
+                OutputMode::Text => println!("API key for {} stored in the keyring", provider),
+            }
+        }
+        AuthCommands::Remove { provider } => {
+            let provider = provider.unwrap_or(model_config.provider);
+            let removed = keyring::remove(provider)?;
+            match output {
+                OutputMode::Json => {
+                    print_json(&json!({ "provider": provider, "removed": removed }))?
+                }
+                OutputMode::Text if removed => {
+                    println!("API key for {} removed from the keyring", provider)
+                }
+                OutputMode::Text => println!("No API key for {} in the keyring", provider),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_key_env() {
+        assert_eq!(Some("OPENAI_API_KEY"), provider_key_env(Provider::OpenAi));
+        assert_eq!(
+            Some("ANTHROPIC_API_KEY"),
+            provider_key_env(Provider::Anthropic)
+        );
+        // Ollama runs locally without a key
+        assert_eq!(None, provider_key_env(Provider::Ollama));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::output::write_output_file;
+use crate::transcript::save_transcript;
+use crate::usage::record_tokens;
+use anyhow::Result;
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::{ModelSettings, ProviderConfig, stream_chat_completion};
+use pren_core::messages::{RenderedMessage, Role, join_messages};
+use pren_core::transcript::Transcript;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use tracing::error;
+
+/// Lines typed to end the session, besides closing stdin.
+const EXIT_COMMANDS: [&str; 2] = ["/exit", "/quit"];
+
+/// Chats with the model, reading the user's messages from stdin, one per line, and streaming
+/// the responses to stdout. The history starts with the messages of `transcript`, usually
+/// the rendered system prompt, or a saved conversation being resumed.
+///
+/// A failed request is reported without ending the session, and the message can be sent
+/// again. With `save`, the history is written to that file with role markers when the
+/// session ends, so it can be added as a chat prompt. With `keep_transcript`, the
+/// transcript is saved with the new messages, if there are any. The tokens used by every
+/// response are recorded in the storage's usage statistics.
+pub async fn run_chat(
+    storage: &FileStorage,
+    provider: &ProviderConfig,
+    model_name: &str,
+    mut transcript: Transcript,
+    settings: &ModelSettings,
+    save: Option<&Path>,
+    keep_transcript: bool,
+) -> Result<()> {
+    let mut messages = std::mem::take(&mut transcript.messages);
+    let initial_count = messages.len();
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+    let used_model = settings.model.as_deref().unwrap_or(model_name);
+    if interactive {
+        eprintln!(
+            "Chatting with {}. Type {} or press Ctrl-D to quit.",
+            used_model, EXIT_COMMANDS[0]
+        );
+    }
+
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            eprint!("> ");
+            std::io::stderr().flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+        if EXIT_COMMANDS.contains(&input) {
+            break;
+        }
+
+        messages.push(RenderedMessage {
+            role: Role::User,
+            content: input.to_string(),
+        });
+        let mut stdout = std::io::stdout();
+        let result = stream_chat_completion(provider, model_name, &messages, settings, |text| {
+            print!("{}", text);
+            let _ = stdout.flush();
+        })
+        .await;
+        println!();
+        match result {
+            Ok(completion) => {
+                record_tokens(storage, used_model, completion.usage);
+                messages.push(RenderedMessage {
+                    role: Role::Assistant,
+                    content: completion.text,
+                });
+            }
+            Err(e) => {
+                error!("{}", e);
+                messages.pop();
+            }
+        }
+    }
+
+    if let Some(path) = save {
+        write_output_file(path, &join_messages(&messages), false)?;
+        eprintln!("Conversation saved to {}", path.display());
+    }
+    if keep_transcript && messages.len() > initial_count {
+        transcript.model = used_model.to_string();
+        transcript.messages = messages;
+        save_transcript(storage, transcript);
+    }
+    Ok(())
+}
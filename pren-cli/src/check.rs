@@ -0,0 +1,225 @@
+use crate::config::ValidateOnSave;
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result, bail};
+use pren_core::composition::composition_problems;
+use pren_core::file_storage::FileStorage;
+use pren_core::parser::is_valid_prompt_name;
+use pren_core::policy::{ContentChecker, Severity};
+use pren_core::prompt::{Prompt, PromptTemplate};
+use pren_core::validation::{Diagnostic, validate_prompt_file};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::warn;
+
+/// A prompt file to check, with the path it's reported with.
+struct CheckedFile {
+    path: PathBuf,
+    content: String,
+}
+
+/// Validates prompt files, printing their problems as `path:line: severity: message`.
+/// Checks the staged prompt files of the git repo with `staged`, the given files if any, or
+/// else every prompt of the storage. Fails if any error is found, so it can run as a git
+/// pre-commit hook.
+pub fn run_check(
+    storage: &FileStorage,
+    staged: bool,
+    paths: &[PathBuf],
+    output: OutputMode,
+) -> Result<()> {
+    let files = if staged {
+        staged_files(storage)?
+    } else if !paths.is_empty() {
+        paths
+            .iter()
+            .map(|path| {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Ok(CheckedFile {
+                    path: path.clone(),
+                    content,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        storage
+            .get_prompt_files()?
+            .into_iter()
+            .map(|path| {
+                let content = fs::read_to_string(&path)?;
+                Ok(CheckedFile { path, content })
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    // References resolve to stored prompts, or to prompts added along with the checked ones
+    let names: HashSet<String> = storage
+        .get_prompt_files()?
+        .iter()
+        .chain(files.iter().map(|file| &file.path))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .collect();
+
+    let results: Vec<(&Path, Vec<Diagnostic>)> = files
+        .iter()
+        .map(|file| {
+            let diagnostics =
+                validate_prompt_file(&file.path, &file.content, |name| names.contains(name));
+            (file.path.as_path(), diagnostics)
+        })
+        .collect();
+    let errors = results
+        .iter()
+        .flat_map(|(_, diagnostics)| diagnostics)
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+
+    match output {
+        OutputMode::Json => {
+            let diagnostics: Vec<_> = results
+                .iter()
+                .flat_map(|(path, diagnostics)| {
+                    diagnostics.iter().map(move |diagnostic| {
+                        json!({
+                            "path": path,
+                            "line": diagnostic.line,
+                            "severity": diagnostic.severity,
+                            "message": diagnostic.message
+                        })
+                    })
+                })
+                .collect();
+            print_json(&diagnostics)?;
+        }
+        OutputMode::Text => {
+            for (path, diagnostics) in &results {
+                for diagnostic in diagnostics {
+                    println!("{}:{}", path.display(), diagnostic);
+                }
+            }
+            if errors == 0 {
+                println!("Checked {} prompt file(s), no errors found", files.len());
+            }
+        }
+    }
+    if errors > 0 {
+        bail!("{} error(s) found", errors);
+    }
+    Ok(())
+}
+
+/// Gets the prompt files of the storage staged in the git repo of the current directory,
+/// as they're staged rather than as they're on disk.
+fn staged_files(storage: &FileStorage) -> Result<Vec<CheckedFile>> {
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let prompts_dir = storage
+        .base_path
+        .canonicalize()
+        .with_context(|| format!("Failed to find {}", storage.base_path.display()))?;
+    let staged = git(&[
+        "diff",
+        "--cached",
+        "--name-only",
+        "--diff-filter=ACMR",
+        "-z",
+    ])?;
+
+    let mut files = Vec::new();
+    for name in staged.split('\0').filter(|name| !name.is_empty()) {
+        let path = root.join(name);
+        if path.extension().is_none_or(|extension| extension != "md")
+            || !path.starts_with(&prompts_dir)
+        {
+            continue;
+        }
+        let content = git(&["show", &format!(":{}", name)])?;
+        files.push(CheckedFile {
+            path: PathBuf::from(name),
+            content,
+        });
+    }
+    Ok(files)
+}
+
+/// Runs git in the current directory, returning what it printed.
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Fails unless a prompt name can be used in prompt references, which also keeps it from
+/// being a path.
+pub fn check_prompt_name(name: &str) -> Result<()> {
+    if !is_valid_prompt_name(name) {
+        bail!(
+            "Invalid prompt name '{}'. Use letters, digits, '-', '_' and '.' in names.",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Runs the built-in content policies, printing warnings and failing on errors.
+pub fn check_content(content: &str) -> Result<()> {
+    let violations = ContentChecker::default().check(content);
+    for violation in violations
+        .iter()
+        .filter(|violation| violation.severity == Severity::Warning)
+    {
+        warn!("{}", violation);
+    }
+
+    let errors: Vec<String> = violations
+        .iter()
+        .filter(|violation| violation.severity == Severity::Error)
+        .map(|violation| violation.to_string())
+        .collect();
+    if !errors.is_empty() {
+        bail!(
+            "Content checks failed:\n  {}\nUse --skip-checks to continue anyway.",
+            errors.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the references of a prompt about to be saved would render, as set by
+/// `validate_on_save`, printing the problems found or failing on them.
+pub fn check_references(
+    storage: &FileStorage,
+    prompt: &Prompt,
+    validate_on_save: ValidateOnSave,
+) -> Result<()> {
+    if validate_on_save == ValidateOnSave::Off {
+        return Ok(());
+    }
+    let Ok(template) = PromptTemplate::new(prompt.clone()) else {
+        return Ok(());
+    };
+    let problems = composition_problems(&template, storage);
+    if validate_on_save == ValidateOnSave::Warn || problems.is_empty() {
+        for problem in &problems {
+            warn!("{}", problem);
+        }
+        return Ok(());
+    }
+    let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+    bail!(
+        "Prompt '{}' wouldn't render:\n  {}",
+        prompt.metadata.name,
+        problems.join("\n  ")
+    );
+}
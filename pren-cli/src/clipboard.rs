@@ -0,0 +1,95 @@
+use anyhow::{Context, Result, bail};
+use arboard::Clipboard;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use tracing::warn;
+
+/// Where copied text ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Copied {
+    /// The system clipboard
+    System,
+    /// The clipboard of the local terminal, through an OSC 52 escape sequence
+    Terminal,
+}
+
+/// Copies text to the clipboard.
+///
+/// In SSH sessions the text is sent to the local terminal with an OSC 52 escape sequence,
+/// since the clipboard of the server isn't the user's, falling back to the system clipboard
+/// if there's no terminal to send it to.
+pub fn copy_text(text: &str) -> Result<Copied> {
+    if is_ssh_session() && copy_with_osc52(text).is_ok() {
+        return Ok(Copied::Terminal);
+    }
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .context("Couldn't copy to the clipboard")?;
+    Ok(Copied::System)
+}
+
+/// Copies text to the clipboard unless it's disabled, logging a warning instead of failing
+/// if there's no clipboard, e.g. on headless servers.
+///
+/// Returns whether the text was copied, so callers can print it otherwise.
+pub fn copy_or_warn(text: &str, enabled: bool) -> bool {
+    if !enabled {
+        return false;
+    }
+    match copy_text(text) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("{:#}. Use --no-clipboard to skip copying.", e);
+            false
+        }
+    }
+}
+
+/// Reads text from the clipboard, falling back to stdin with a warning if the clipboard is
+/// disabled or can't be read and stdin isn't a terminal.
+pub fn read_clipboard(enabled: bool) -> Result<String> {
+    let reason = if enabled {
+        match Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+            Ok(text) => return Ok(text),
+            Err(e) => format!("The clipboard can't be read: {}", e),
+        }
+    } else {
+        "The clipboard is disabled".to_string()
+    };
+    if std::io::stdin().is_terminal() {
+        bail!("{}. Pipe the content to stdin instead.", reason);
+    }
+    warn!("{}, reading from stdin instead", reason);
+    std::io::read_to_string(std::io::stdin()).context("Failed to read from stdin")
+}
+
+fn is_ssh_session() -> bool {
+    ["SSH_TTY", "SSH_CONNECTION"]
+        .iter()
+        .any(|variable| std::env::var_os(variable).is_some())
+}
+
+/// Asks the terminal to copy text with an OSC 52 escape sequence, written to the
+/// controlling terminal so it doesn't end up in piped output.
+fn copy_with_osc52(text: &str) -> std::io::Result<()> {
+    let mut sequence = format!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+    // tmux only forwards escape sequences to the outer terminal when wrapped
+    if std::env::var_os("TMUX").is_some() {
+        sequence = format!("\x1bPtmux;\x1b{}\x1b\\", sequence);
+    }
+    let mut terminal = OpenOptions::new().write(true).open("/dev/tty")?;
+    terminal.write_all(sequence.as_bytes())?;
+    terminal.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_or_warn_when_disabled() {
+        assert!(!copy_or_warn("Hello", false));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde_json::json;
+use std::env::home_dir;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A shell pren can install completions for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl Shell {
+    fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Powershell => "powershell",
+        }
+    }
+
+    /// Detects the user's shell from `$SHELL`.
+    fn detect() -> Option<Shell> {
+        let shell = PathBuf::from(std::env::var_os("SHELL")?);
+        match shell.file_stem()?.to_str()? {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "pwsh" | "powershell" => Some(Shell::Powershell),
+            _ => None,
+        }
+    }
+
+    /// The snippet registering pren's completions. The completion script is generated by
+    /// pren each time the shell loads it, so it never gets out of date.
+    fn loader(self) -> &'static str {
+        match self {
+            Shell::Bash => "source <(COMPLETE=bash pren)",
+            Shell::Zsh => "source <(COMPLETE=zsh pren)",
+            Shell::Fish => "COMPLETE=fish pren | source",
+            Shell::Powershell => {
+                "$env:COMPLETE = \"powershell\"; pren | Out-String | Invoke-Expression; \
+                 Remove-Item Env:\\COMPLETE"
+            }
+        }
+    }
+
+    /// Where the loader is installed, and whether it's added to a startup file of the shell
+    /// rather than written to a completions file of its own.
+    fn install_path(self) -> Result<(PathBuf, bool)> {
+        let home = home_dir().context("Couldn't find the home directory")?;
+        let xdg_dir = |var: &str, default: &str| {
+            std::env::var_os(var)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(default))
+        };
+        Ok(match self {
+            Shell::Bash => (
+                xdg_dir("XDG_DATA_HOME", ".local/share").join("bash-completion/completions/pren"),
+                false,
+            ),
+            Shell::Zsh => (
+                std::env::var_os("ZDOTDIR")
+                    .map_or_else(|| home.clone(), PathBuf::from)
+                    .join(".zshrc"),
+                true,
+            ),
+            Shell::Fish => (
+                xdg_dir("XDG_CONFIG_HOME", ".config").join("fish/completions/pren.fish"),
+                false,
+            ),
+            Shell::Powershell if cfg!(windows) => (
+                home.join("Documents/PowerShell/Microsoft.PowerShell_profile.ps1"),
+                true,
+            ),
+            Shell::Powershell => (
+                xdg_dir("XDG_CONFIG_HOME", ".config")
+                    .join("powershell/Microsoft.PowerShell_profile.ps1"),
+                true,
+            ),
+        })
+    }
+}
+
+/// Installs the completions of pren for a shell, or the detected one, or prints the snippet
+/// registering them if `print` is set.
+pub fn install_completions(shell: Option<Shell>, print: bool, output: OutputMode) -> Result<()> {
+    let Some(shell) = shell.or_else(Shell::detect) else {
+        bail!("Couldn't detect your shell, give it as an argument (bash, zsh, fish or powershell)");
+    };
+    if print {
+        match output {
+            OutputMode::Json => {
+                print_json(&json!({ "shell": shell.name(), "loader": shell.loader() }))?
+            }
+            OutputMode::Text => println!("{}", shell.loader()),
+        }
+        return Ok(());
+    }
+
+    let (path, startup_file) = shell.install_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let installed = if startup_file {
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        let installed = !content.contains(shell.loader());
+        if installed {
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            writeln!(file, "\n# Completions for pren\n{}", shell.loader())?;
+        }
+        installed
+    } else {
+        fs::write(&path, format!("{}\n", shell.loader()))?;
+        true
+    };
+
+    match output {
+        OutputMode::Json => print_json(&json!({
+            "shell": shell.name(),
+            "path": path,
+            "installed": installed
+        }))?,
+        OutputMode::Text if installed => println!(
+            "Installed completions for {} in {}. Open a new shell to use them.",
+            shell.name(),
+            path.display()
+        ),
+        OutputMode::Text => println!(
+            "Completions for {} are already installed in {}",
+            shell.name(),
+            path.display()
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loader_generates_the_completions_of_the_shell() {
+        for shell in Shell::value_variants() {
+            assert!(shell.loader().contains(shell.name()));
+        }
+        install_completions(Some(Shell::Zsh), true, OutputMode::Json).unwrap();
+    }
+}
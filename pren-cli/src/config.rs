@@ -1,21 +1,244 @@
-use crate::constants::PREN_CLI;
-use anyhow::{Context, Result};
+use crate::ConfigCommands;
+use crate::auth::{KeySource, find_api_key};
+use crate::constants::{
+    API_KEY_ENV, DEFAULT_PROFILE, MODEL_ENV, PREN_CLI, PROFILE_ENV, PROJECT_DIR, PROJECT_MARKER,
+    PROJECT_PROMPTS_DIR, REPOSITORY_MARKER, STORAGE_PATH_ENV,
+};
+use crate::editor::{choose, edit_text};
+use crate::errors::ConfigError;
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result, bail};
 use pren_core::file_storage::FileStorage;
+use pren_core::llm::{Provider, ProviderConfig, RetryPolicy, TokenUsage, ToolDefinition};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
 use std::env::home_dir;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, warn};
 
 #[derive(Serialize, Deserialize)]
 pub struct PrenCliConfig {
     pub base_path: String,
     pub(crate) model_config: ModelConfig,
+    /// The author set on prompts added without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// The profile used when none is given with `--profile` or `PREN_PROFILE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
+    /// Named prompt collections, each with its own storage path and model config.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Named model settings chosen with `--preset`, e.g. a local model and a hosted one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub presets: BTreeMap<String, ModelPreset>,
+    /// Whether to print the tokens used after generating.
+    #[serde(default = "default_show_usage")]
+    pub show_usage: bool,
+    /// The prices of models by model name, to estimate the cost of the tokens they used.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prices: BTreeMap<String, ModelPrice>,
+    /// Functions models can call, chosen with `generate --tool`. The ones with a command
+    /// are the only ones `generate --run-tools` runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolConfig>,
+    /// Whether to check the references of prompts for circular references and too deep
+    /// nesting when adding or editing them.
+    #[serde(default)]
+    pub validate_on_save: ValidateOnSave,
 }
 
-#[derive(Serialize, Deserialize)]
+fn default_show_usage() -> bool {
+    true
+}
+
+/// What to do with prompts whose references wouldn't render when saving them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidateOnSave {
+    /// Save them without checking their references.
+    #[default]
+    Off,
+    /// Save them, printing the problems found.
+    Warn,
+    /// Don't save them.
+    Reject,
+}
+
+/// A named prompt collection.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub base_path: String,
+    /// The model config of the profile. The default one is used if it isn't set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) model_config: Option<ModelConfig>,
+    /// Whether the prompts are the notes of an Obsidian vault.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub vault: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ModelConfig {
     pub model_name: String,
-    pub api_key: String,
+    /// The API key of the provider, stored in plaintext. Without it, the key is looked up
+    /// in the provider's environment variable and the keyring.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// The URL of the provider's API. Empty for the provider's default one.
     pub base_url: String,
+    /// The API serving the model.
+    #[serde(default)]
+    pub provider: Provider,
+    /// How many seconds to wait for a response, or for the next chunk of a streamed one.
+    /// `0` waits forever.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// How many times a request failing with a rate limit, a server error or a dropped
+    /// connection is retried, waiting longer after every failure.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// The model embedding prompts for semantic search. The provider's usual one is used if
+    /// it isn't set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+}
+
+fn default_timeout_secs() -> u64 {
+    RetryPolicy::default()
+        .timeout
+        .map_or(0, |timeout| timeout.as_secs())
+}
+
+fn default_max_retries() -> u32 {
+    RetryPolicy::default().max_retries
+}
+
+/// Model settings replacing the ones of the profile in use for a single command. Settings
+/// that aren't set are kept.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ModelPreset {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<Provider>,
+}
+
+/// A function models can call, optionally run as a local command.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ToolConfig {
+    #[serde(flatten)]
+    pub definition: ToolDefinition,
+    /// The program and arguments run for calls of the tool. They get the arguments of the
+    /// call as JSON on stdin, and their output is sent back to the model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+}
+
+/// The price of a model, in dollars per million tokens.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    /// The cost of the tokens used by requests, in dollars.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.input_tokens as f64 * self.input + usage.output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+impl ModelConfig {
+    /// Returns this config with the settings of a preset. Like with `--provider`, a preset
+    /// changing the provider without a base URL or an API key uses the provider's default
+    /// URL and looks its key up.
+    pub fn with_preset(&self, preset: &ModelPreset) -> ModelConfig {
+        let base_url = match (&preset.base_url, preset.provider) {
+            (Some(base_url), _) => base_url.clone(),
+            (None, Some(provider)) if provider != self.provider => String::new(),
+            (None, _) => self.base_url.clone(),
+        };
+        let api_key = match (&preset.api_key, preset.provider) {
+            (Some(api_key), _) => Some(api_key.clone()),
+            (None, Some(provider)) if provider != self.provider => None,
+            (None, _) => self.api_key.clone(),
+        };
+        let embedding_model = match preset.provider {
+            Some(provider) if provider != self.provider => None,
+            _ => self.embedding_model.clone(),
+        };
+        ModelConfig {
+            model_name: preset.model_name.clone().unwrap_or(self.model_name.clone()),
+            api_key,
+            base_url,
+            provider: preset.provider.unwrap_or(self.provider),
+            embedding_model,
+            ..self.clone()
+        }
+    }
+
+    /// The model embedding prompts, for the provider if it's given: the configured one, or
+    /// the provider's usual one for other providers and when none is configured.
+    pub fn embedding_model(&self, provider: Option<Provider>) -> Result<String> {
+        let provider = provider.unwrap_or(self.provider);
+        self.embedding_model
+            .clone()
+            .filter(|_| provider == self.provider)
+            .or_else(|| provider.default_embedding_model().map(String::from))
+            .with_context(|| {
+                format!(
+                    "{} doesn't serve embedding models. Use --provider to embed prompts with another provider",
+                    provider
+                )
+            })
+    }
+
+    /// The config of the provider serving the model, with the provider and the base URL
+    /// overridden for a single call if they're given.
+    ///
+    /// The base URL and the API key are only used with the provider they're configured for,
+    /// so overriding the provider alone sends requests to that provider's default URL, with
+    /// its key from its environment variable or the keyring.
+    pub fn provider_config(
+        &self,
+        provider: Option<Provider>,
+        base_url: Option<&str>,
+    ) -> ProviderConfig {
+        let base_url = match (provider, base_url) {
+            (_, Some(base_url)) => base_url,
+            (Some(provider), None) if provider != self.provider => "",
+            _ => &self.base_url,
+        };
+        let provider = provider.unwrap_or(self.provider);
+        let api_key = self
+            .api_key(provider)
+            .map(|(api_key, _)| api_key)
+            .unwrap_or_default();
+        ProviderConfig::new(provider, &api_key, base_url).with_retry_policy(RetryPolicy {
+            timeout: (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs)),
+            max_retries: self.max_retries,
+            ..RetryPolicy::default()
+        })
+    }
+
+    /// Gets the API key of a provider, and where it was found: the configured key for the
+    /// configured provider, or else the one in the provider's environment variable or the
+    /// keyring.
+    pub fn api_key(&self, provider: Provider) -> Option<(String, KeySource)> {
+        self.api_key
+            .as_ref()
+            .filter(|api_key| provider == self.provider && !api_key.is_empty())
+            .map(|api_key| (api_key.clone(), KeySource::Config))
+            .or_else(|| find_api_key(provider))
+    }
 }
 
 impl Default for PrenCliConfig {
@@ -27,6 +250,14 @@ impl Default for PrenCliConfig {
         Self {
             base_path: base_path.display().to_string(),
             model_config: ModelConfig::default(),
+            author: None,
+            active_profile: None,
+            profiles: BTreeMap::new(),
+            presets: BTreeMap::new(),
+            show_usage: default_show_usage(),
+            prices: BTreeMap::new(),
+            tools: Vec::new(),
+            validate_on_save: ValidateOnSave::default(),
         }
     }
 }
@@ -35,17 +266,461 @@ impl Default for ModelConfig {
     fn default() -> Self {
         Self {
             model_name: String::from("qwen/qwen3-30b-a3b-2507"),
-            api_key: String::from(""), // TODO: We should be getting this from env, this is just temporary
+            api_key: None,
             base_url: String::from("http://192.168.0.20:1234/v1"),
+            provider: Provider::OpenAi,
+            timeout_secs: default_timeout_secs(),
+            max_retries: default_max_retries(),
+            embedding_model: None,
         }
     }
 }
 
+/// The profile a command runs with, resolved from the config.
+pub struct ActiveProfile {
+    pub name: String,
+    pub storage: FileStorage,
+    pub model_config: ModelConfig,
+}
+
+impl PrenCliConfig {
+    /// Resolves a profile by name, or the active one if no name is given, storing prompts
+    /// in `storage_path` instead of the profile's path if it's given. Otherwise, prompts
+    /// are stored in `project_dir` if it's given, layered over the profile's prompts, with
+    /// the files holding state rather than prompts added to its `.gitignore`. Only the
+    /// profile's own path is read as an Obsidian vault, if the profile is one.
+    ///
+    /// The `default` profile is made of the top-level `base_path` and `model_config`,
+    /// which is also used by profiles without their own model config. The model and API
+    /// key can be overridden with the `PREN_MODEL` and `PREN_API_KEY` environment variables.
+    pub fn resolve_profile(
+        &self,
+        name: Option<&str>,
+        storage_path: Option<PathBuf>,
+        project_dir: Option<PathBuf>,
+    ) -> Result<ActiveProfile> {
+        let name = name
+            .or(self.active_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE);
+        let (base_path, model_config, vault) = if name == DEFAULT_PROFILE {
+            (&self.base_path, &self.model_config, false)
+        } else {
+            match self.profiles.get(name) {
+                Some(profile) => (
+                    &profile.base_path,
+                    profile.model_config.as_ref().unwrap_or(&self.model_config),
+                    profile.vault,
+                ),
+                None => {
+                    return Err(ConfigError(format!(
+                        "Profile '{}' doesn't exist. Use `pren profile add` to create it.",
+                        name
+                    ))
+                    .into());
+                }
+            }
+        };
+
+        let mut model_config = model_config.clone();
+        if let Some(model_name) = env_override(MODEL_ENV) {
+            model_config.model_name = model_name;
+        }
+        if let Some(api_key) = env_override(API_KEY_ENV) {
+            model_config.api_key = Some(api_key);
+        }
+        Ok(ActiveProfile {
+            name: name.to_string(),
+            storage: match (storage_path, project_dir) {
+                (Some(storage_path), _) => FileStorage {
+                    base_path: storage_path,
+                    fallback_paths: Vec::new(),
+                    vault: false,
+                },
+                (None, Some(project_dir)) => {
+                    let storage = FileStorage {
+                        base_path: project_dir,
+                        fallback_paths: vec![PathBuf::from(base_path)],
+                        vault: false,
+                    };
+                    // The project's prompts are committed, but not the history and such
+                    if let Err(e) = storage.ignore_state_files() {
+                        warn!("Couldn't add pren's state files to the .gitignore: {}", e);
+                    }
+                    storage
+                }
+                (None, None) => FileStorage {
+                    base_path: PathBuf::from(base_path),
+                    fallback_paths: Vec::new(),
+                    vault,
+                },
+            },
+            model_config,
+        })
+    }
+
+    /// Gets a preset by name.
+    pub fn preset(&self, name: &str) -> Result<&ModelPreset> {
+        self.presets.get(name).ok_or_else(|| {
+            ConfigError(format!(
+                "Preset '{}' doesn't exist. Use `pren preset add` to create it.",
+                name
+            ))
+            .into()
+        })
+    }
+
+    /// Gets a value of the config by its dotted key, e.g. `model_config.model_name`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Value))` - The value, which is an object for keys like `profiles`.
+    /// * `Ok(None)` - If the key isn't set, or doesn't exist.
+    pub fn get_value(&self, key: &str) -> Result<Option<Value>> {
+        let mut value = serde_json::to_value(self)?;
+        for field in key.split('.') {
+            match value {
+                Value::Object(mut map) => match map.remove(field) {
+                    Some(field_value) => value = field_value,
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// Sets a value of the config by its dotted key. The value is parsed as JSON if the
+    /// current value isn't a string, e.g. for numbers.
+    pub fn set_value(&mut self, key: &str, value: &str) -> Result<()> {
+        let mut config = serde_json::to_value(&*self)?;
+        let (parent_key, field) = match key.rsplit_once('.') {
+            Some((parent_key, field)) => (Some(parent_key), field),
+            None => (None, key),
+        };
+        let mut parent = config.as_object_mut();
+        for parent_field in parent_key.into_iter().flat_map(|key| key.split('.')) {
+            parent = parent
+                .and_then(|map| map.get_mut(parent_field))
+                .and_then(Value::as_object_mut);
+        }
+        let Some(parent) = parent else {
+            return Err(ConfigError(format!("Unknown config key '{}'", key)).into());
+        };
+
+        let new_value = match parent.get(field) {
+            None | Some(Value::String(_)) => Value::String(value.to_string()),
+            Some(_) => serde_json::from_str(value)
+                .with_context(|| format!("Invalid value for '{}': {}", key, value))?,
+        };
+        parent.insert(field.to_string(), new_value.clone());
+        let config: PrenCliConfig = serde_json::from_value(config)
+            .with_context(|| format!("Invalid value for '{}': {}", key, value))?;
+        // Unknown fields are dropped when deserializing, so they don't come back
+        if config.get_value(key)? != Some(new_value) {
+            return Err(ConfigError(format!("Unknown config key '{}'", key)).into());
+        }
+        *self = config;
+        Ok(())
+    }
+
+    /// Lists every value of the config with its dotted key, sorted by key.
+    pub fn values(&self) -> Result<Vec<(String, Value)>> {
+        fn flatten(prefix: &str, map: Map<String, Value>, values: &mut Vec<(String, Value)>) {
+            for (field, value) in map {
+                let key = if prefix.is_empty() {
+                    field
+                } else {
+                    format!("{}.{}", prefix, field)
+                };
+                match value {
+                    Value::Object(map) => flatten(&key, map, values),
+                    value => values.push((key, value)),
+                }
+            }
+        }
+
+        let mut values = Vec::new();
+        if let Value::Object(map) = serde_json::to_value(self)? {
+            flatten("", map, &mut values);
+        }
+        Ok(values)
+    }
+}
+
+/// The value of an environment variable overriding the config, if it's set and not empty.
+fn env_override(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Gets the storage at `PREN_STORAGE_PATH`, or else the storage of the profile selected by
+/// `PREN_PROFILE` or of the active profile, under the prompts of the current project.
 pub fn get_storage() -> Result<FileStorage> {
     let config =
         confy::load::<PrenCliConfig>(PREN_CLI, None).context("Failed to load configuration")?;
 
-    Ok(FileStorage {
-        base_path: PathBuf::from(config.base_path),
-    })
+    let profile = std::env::var(PROFILE_ENV).ok();
+    let storage_path = env_override(STORAGE_PATH_ENV).map(PathBuf::from);
+    let project_dir = find_project_dir(&std::env::current_dir()?);
+    Ok(config
+        .resolve_profile(profile.as_deref(), storage_path, project_dir)?
+        .storage)
+}
+
+/// Finds the prompts of the project `dir` belongs to: a `.pren` directory, or a `prompts`
+/// directory with a `.pren-project` file, in `dir` or the closest of its ancestors.
+///
+/// The search stops at the root of the git repository `dir` is in, or at the home
+/// directory, so a stray `.pren` higher up isn't picked up.
+pub fn find_project_dir(dir: &Path) -> Option<PathBuf> {
+    find_project_dir_up_to(dir, home_dir().as_deref())
+}
+
+fn find_project_dir_up_to(dir: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    for dir in dir.ancestors() {
+        let project_dir = dir.join(PROJECT_DIR);
+        if project_dir.is_dir() {
+            return Some(project_dir);
+        }
+        let prompts_dir = dir.join(PROJECT_PROMPTS_DIR);
+        if prompts_dir.join(PROJECT_MARKER).is_file() {
+            return Some(prompts_dir);
+        }
+        if dir.join(REPOSITORY_MARKER).exists() || Some(dir) == home {
+            return None;
+        }
+    }
+    None
+}
+
+/// Hides the value of API keys when listing the config.
+fn redact_config_value(key: &str, value: Value) -> Value {
+    match value {
+        Value::String(api_key) if key.ends_with("api_key") && !api_key.is_empty() => {
+            Value::String("********".to_string())
+        }
+        value => value,
+    }
+}
+
+/// Gets, sets, lists or edits the values of the config file.
+pub fn run_config_command(
+    mut config: PrenCliConfig,
+    command: ConfigCommands,
+    output: OutputMode,
+) -> Result<()> {
+    match command {
+        ConfigCommands::Get { key } => {
+            let Some(value) = config.get_value(&key)? else {
+                bail!("Config key '{}' isn't set", key);
+            };
+            match (output, value) {
+                (OutputMode::Json, value) => print_json(&json!({ "key": key, "value": value }))?,
+                (OutputMode::Text, Value::String(text)) => println!("{}", text),
+                (OutputMode::Text, value) => println!("{}", serde_json::to_string_pretty(&value)?),
+            }
+        }
+        ConfigCommands::Set { key, value } => {
+            config.set_value(&key, &value)?;
+            if key.ends_with("api_key") && !value.is_empty() {
+                warn!(
+                    "API keys are stored in plaintext in the config file. \
+                     Consider setting {} or using `pren auth set` instead.",
+                    API_KEY_ENV
+                );
+            }
+            confy::store(PREN_CLI, None, &config).context("Failed to save configuration")?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "key": key, "value": value }))?,
+                OutputMode::Text => println!("Set '{}' to '{}'", key, value),
+            }
+        }
+        ConfigCommands::List => {
+            let values = config.values()?.into_iter().map(|(key, value)| {
+                let value = redact_config_value(&key, value);
+                (key, value)
+            });
+            if output == OutputMode::Json {
+                return print_json(&values.collect::<serde_json::Map<String, Value>>());
+            }
+            for (key, value) in values {
+                println!("{} = {}", key, value);
+            }
+        }
+        ConfigCommands::Path => {
+            let path = confy::get_configuration_file_path(PREN_CLI, None)
+                .context("Failed to find the configuration file")?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "path": path }))?,
+                OutputMode::Text => println!("{}", path.display()),
+            }
+        }
+        ConfigCommands::Edit => {
+            let path = confy::get_configuration_file_path(PREN_CLI, None)
+                .context("Failed to find the configuration file")?;
+            if !path.exists() {
+                confy::store_path(&path, &config).context("Failed to save configuration")?;
+            }
+            let original = std::fs::read_to_string(&path)?;
+            let mut text = original.clone();
+            loop {
+                text = edit_text(&text)?;
+                if text == original {
+                    println!("No changes to the configuration");
+                    return Ok(());
+                }
+                // Load the edited config from a copy, so an invalid one is never saved
+                let mut file = tempfile::Builder::new().suffix(".toml").tempfile()?;
+                file.write_all(text.as_bytes())?;
+                match confy::load_path::<PrenCliConfig>(file.path()) {
+                    Ok(_) => {
+                        std::fs::write(&path, &text)?;
+                        println!("Configuration saved");
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        error!("{:#}", anyhow::Error::from(e));
+                        if choose(
+                            "[e]dit again or [d]iscard the changes?",
+                            &["edit", "discard"],
+                        )? == 'd'
+                        {
+                            println!("Changes to the configuration discarded");
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_project_dir() {
+        let repo = TempDir::new().unwrap();
+        fs::create_dir(repo.path().join(REPOSITORY_MARKER)).unwrap();
+        let nested = repo.path().join("src").join("module");
+        fs::create_dir_all(&nested).unwrap();
+
+        // Not found: the search stops at the repository root
+        assert_eq!(None, find_project_dir(&nested));
+
+        // Found in an ancestor
+        let project_dir = repo.path().join(PROJECT_DIR);
+        fs::create_dir(&project_dir).unwrap();
+        assert_eq!(Some(project_dir.clone()), find_project_dir(&nested));
+
+        // A nested project is closer than the outer one
+        let inner_prompts = repo.path().join("src").join(PROJECT_PROMPTS_DIR);
+        fs::create_dir(&inner_prompts).unwrap();
+        fs::write(inner_prompts.join(PROJECT_MARKER), "").unwrap();
+        assert_eq!(Some(inner_prompts), find_project_dir(&nested));
+        assert_eq!(Some(project_dir), find_project_dir(repo.path()));
+    }
+
+    #[test]
+    fn test_find_project_dir_stops_at_the_repository_root_and_home() {
+        let outer = TempDir::new().unwrap();
+        fs::create_dir(outer.path().join(PROJECT_DIR)).unwrap();
+        let inner = outer.path().join("inner");
+        fs::create_dir(&inner).unwrap();
+
+        assert!(find_project_dir_up_to(&inner, None).is_some());
+        assert_eq!(None, find_project_dir_up_to(&inner, Some(&inner)));
+
+        fs::create_dir(inner.join(REPOSITORY_MARKER)).unwrap();
+        assert_eq!(None, find_project_dir_up_to(&inner, None));
+    }
+
+    #[test]
+    fn test_redact_config_value() {
+        let api_key = Value::String("sk-secret".to_string());
+        assert_eq!(
+            Value::String("********".to_string()),
+            redact_config_value("model_config.api_key", api_key.clone())
+        );
+        assert_eq!(
+            Value::String(String::new()),
+            redact_config_value("model_config.api_key", Value::String(String::new()))
+        );
+        assert_eq!(
+            api_key,
+            redact_config_value("model_config.model_name", api_key.clone())
+        );
+    }
+
+    #[test]
+    fn test_resolve_profile_storage() {
+        let config = PrenCliConfig {
+            base_path: "/prompts".to_string(),
+            ..PrenCliConfig::default()
+        };
+
+        let storage = config.resolve_profile(None, None, None).unwrap().storage;
+        assert_eq!(PathBuf::from("/prompts"), storage.base_path);
+
+        let project_dir = TempDir::new().unwrap();
+        let project = config
+            .resolve_profile(None, None, Some(project_dir.path().to_path_buf()))
+            .unwrap()
+            .storage;
+        assert_eq!(project_dir.path(), project.base_path);
+        assert_eq!(vec![PathBuf::from("/prompts")], project.fallback_paths);
+        let gitignore = fs::read_to_string(project_dir.path().join(".gitignore")).unwrap();
+        assert!(gitignore.lines().any(|line| line == ".pren-history.jsonl"));
+
+        // --storage-path wins over the project prompts
+        let overridden = config
+            .resolve_profile(
+                None,
+                Some(PathBuf::from("/elsewhere")),
+                Some(PathBuf::from("/project/.pren")),
+            )
+            .unwrap()
+            .storage;
+        assert_eq!(PathBuf::from("/elsewhere"), overridden.base_path);
+        assert!(overridden.fallback_paths.is_empty());
+
+        assert!(config.resolve_profile(Some("work"), None, None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_vault_profile() {
+        let mut config = PrenCliConfig::default();
+        config.profiles.insert(
+            "notes".to_string(),
+            Profile {
+                base_path: "/vault".to_string(),
+                model_config: None,
+                vault: true,
+            },
+        );
+
+        assert!(
+            !config
+                .resolve_profile(None, None, None)
+                .unwrap()
+                .storage
+                .vault
+        );
+        let storage = config
+            .resolve_profile(Some("notes"), None, None)
+            .unwrap()
+            .storage;
+        assert_eq!(PathBuf::from("/vault"), storage.base_path);
+        assert!(storage.vault);
+
+        // Other directories aren't read as notes
+        let overridden = config
+            .resolve_profile(Some("notes"), Some(PathBuf::from("/elsewhere")), None)
+            .unwrap()
+            .storage;
+        assert!(!overridden.vault);
+    }
 }
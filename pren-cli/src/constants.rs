@@ -1 +1,66 @@
 pub const PREN_CLI: &str = "pren-cli";
+
+/// The prefix of environment variables holding template arguments (e.g., `PREN_ARG_NAME`).
+pub const ARG_ENV_PREFIX: &str = "PREN_ARG_";
+
+/// How many times `generate` asks the model to fix a response failing `--validate` or the
+/// prompt's guardrails, unless the prompt or `--max-attempts` say otherwise.
+pub const VALIDATION_RETRIES: usize = 2;
+
+/// The directory of the prompt storage holding the pipelines run with `run`.
+pub const PIPELINES_DIR: &str = "pipelines";
+
+/// The name of the profile made of the top-level storage path and model config.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The environment variable selecting the profile to use.
+pub const PROFILE_ENV: &str = "PREN_PROFILE";
+
+/// The environment variable overriding the storage path of the profile in use.
+pub const STORAGE_PATH_ENV: &str = "PREN_STORAGE_PATH";
+
+/// The environment variable overriding the model of the profile in use.
+pub const MODEL_ENV: &str = "PREN_MODEL";
+
+/// The environment variable overriding the API key of the profile in use.
+pub const API_KEY_ENV: &str = "PREN_API_KEY";
+
+/// The environment variable holding the token clients of `serve` must send.
+#[cfg(feature = "server")]
+pub const SERVER_TOKEN_ENV: &str = "PREN_SERVER_TOKEN";
+
+/// The environment variable holding the LangSmith API key the LangChain Hub is used with.
+pub const HUB_API_KEY_ENV: &str = "LANGSMITH_API_KEY";
+
+/// The environment variable overriding the URL of the LangChain Hub API.
+pub const HUB_ENDPOINT_ENV: &str = "LANGSMITH_ENDPOINT";
+
+/// The URL of the LangChain Hub API, unless overridden.
+pub const DEFAULT_HUB_ENDPOINT: &str = "https://api.smith.langchain.com";
+
+/// The environment variable holding the URL (or directory) of the registry packs are
+/// installed from.
+pub const REGISTRY_ENV: &str = "PREN_REGISTRY";
+
+/// The keyring service API keys are stored under, one entry per provider.
+#[cfg(feature = "keyring")]
+pub const KEYRING_SERVICE: &str = "pren";
+
+/// The environment variable disabling the clipboard, like `--no-clipboard`.
+pub const NO_CLIPBOARD_ENV: &str = "PREN_NO_CLIPBOARD";
+
+/// The directory holding the prompts of a project, found in the current directory or
+/// any of its ancestors up to the repository root or the home directory.
+pub const PROJECT_DIR: &str = ".pren";
+
+/// The entry marking the root of a git repository, where the search for a project stops.
+pub const REPOSITORY_MARKER: &str = ".git";
+
+/// The directory of a project that holds its prompts if it contains [`PROJECT_MARKER`].
+pub const PROJECT_PROMPTS_DIR: &str = "prompts";
+
+/// The file marking a `prompts` directory as the prompts of a project.
+pub const PROJECT_MARKER: &str = ".pren-project";
+
+/// The editor used by `edit` when neither `VISUAL` nor `EDITOR` is set.
+pub const DEFAULT_EDITOR: &str = "vi";
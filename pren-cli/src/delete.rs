@@ -0,0 +1,193 @@
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::prompt::Prompt;
+use pren_core::search::{is_glob_pattern, matches_glob};
+use pren_core::storage::PromptStorage;
+use serde_json::json;
+use std::collections::HashSet;
+
+/// Deletes the prompts selected by names, glob patterns and tags, asking for confirmation
+/// unless `force` is set.
+pub fn run_delete(
+    storage: &FileStorage,
+    names: &[String],
+    tags: &[String],
+    force: bool,
+    override_lock: bool,
+    output: OutputMode,
+) -> Result<()> {
+    let prompts = select_prompts(storage, names, tags)?;
+    let names: Vec<String> = prompts
+        .iter()
+        .map(|prompt| prompt.metadata.name.clone())
+        .collect();
+    let locked: Vec<&str> = prompts
+        .iter()
+        .filter(|prompt| prompt.metadata.locked)
+        .map(|prompt| prompt.metadata.name.as_str())
+        .collect();
+    if !locked.is_empty() && !override_lock {
+        bail!(
+            "Prompt(s) '{}' are locked. Use --override-lock to delete them.",
+            locked.join("', '")
+        );
+    }
+
+    if !force {
+        if let [name] = names.as_slice() {
+            println!("Are you sure you want to delete prompt '{}'? [y/N]", name);
+        } else {
+            println!("The following {} prompts will be deleted:", names.len());
+            for name in &names {
+                println!("  {}", name);
+            }
+            println!("Are you sure you want to delete them? [y/N]");
+        }
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let input = input.trim().to_lowercase();
+        if input != "y" && input != "yes" {
+            if output == OutputMode::Json {
+                return print_json(&json!({ "names": names, "deleted": false }));
+            }
+            println!("Delete operation cancelled.");
+            return Ok(());
+        }
+    }
+    for name in &names {
+        if override_lock {
+            storage.delete_prompt_overriding_lock(name)?;
+        } else {
+            storage.delete_prompt(name)?;
+        }
+        if output == OutputMode::Text {
+            println!("Prompt '{}' deleted successfully.", name);
+        }
+    }
+    if output == OutputMode::Json {
+        return print_json(&json!({ "names": names, "deleted": true }));
+    }
+    Ok(())
+}
+
+/// Gets the prompts selected by names, glob patterns and tags, in that order and without
+/// duplicates. Names must exist, while patterns and tags must select at least one prompt.
+fn select_prompts(storage: &FileStorage, names: &[String], tags: &[String]) -> Result<Vec<Prompt>> {
+    let mut selected: Vec<String> = Vec::new();
+    let stored: Vec<String> = storage
+        .get_prompt_summaries()?
+        .into_iter()
+        .map(|summary| summary.name)
+        .collect();
+    for name in names {
+        if is_glob_pattern(name) {
+            let matching: Vec<&String> = stored
+                .iter()
+                .filter(|stored| matches_glob(name, stored))
+                .collect();
+            if matching.is_empty() {
+                bail!("No prompts match '{}'", name);
+            }
+            selected.extend(matching.into_iter().cloned());
+        } else {
+            selected.push(name.clone());
+        }
+    }
+    if !tags.is_empty() {
+        let tagged = storage.get_prompts_by_tag(tags)?;
+        if tagged.is_empty() {
+            bail!("No prompts are tagged '{}'", tags.join("' or '"));
+        }
+        selected.extend(tagged.into_iter().map(|prompt| prompt.metadata.name));
+    }
+
+    let mut seen = HashSet::new();
+    selected.retain(|name| seen.insert(name.clone()));
+    selected
+        .iter()
+        .map(|name| {
+            storage
+                .get_prompt(name)
+                .with_context(|| format!("Couldn't delete prompt: '{}'", name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::prompt::PromptMetadata;
+    use tempfile::TempDir;
+
+    fn storage_with(sandbox: &TempDir, prompts: &[(&str, &[&str])]) -> FileStorage {
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        for (name, tags) in prompts {
+            let tags = tags.iter().map(|tag| tag.to_string()).collect();
+            storage
+                .save_prompt(&Prompt::new(
+                    PromptMetadata::new(name.to_string(), None, tags),
+                    "Content".to_string(),
+                ))
+                .unwrap();
+        }
+        storage
+    }
+
+    fn names(prompts: Vec<Prompt>) -> Vec<String> {
+        prompts
+            .into_iter()
+            .map(|prompt| prompt.metadata.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_select_prompts() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = storage_with(
+            &sandbox,
+            &[
+                ("draft-1", &[]),
+                ("draft-2", &["old"]),
+                ("keep", &[]),
+                ("legacy", &["old"]),
+            ],
+        );
+
+        let selected = select_prompts(
+            &storage,
+            &["legacy".to_string(), "draft-*".to_string()],
+            &["old".to_string()],
+        )
+        .unwrap();
+        assert_eq!(vec!["legacy", "draft-1", "draft-2"], names(selected));
+
+        assert!(select_prompts(&storage, &["missing".to_string()], &[]).is_err());
+        assert!(select_prompts(&storage, &["none-*".to_string()], &[]).is_err());
+        assert!(select_prompts(&storage, &[], &["unused".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_run_delete() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = storage_with(
+            &sandbox,
+            &[("draft-1", &[]), ("draft-2", &[]), ("keep", &[])],
+        );
+
+        run_delete(
+            &storage,
+            &["draft-*".to_string()],
+            &[],
+            true,
+            false,
+            OutputMode::Json,
+        )
+        .unwrap();
+        assert_eq!(vec!["keep"], names(storage.get_prompts().unwrap()));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::auth::{KeySource, provider_key_env};
+use crate::config::ModelConfig;
+use crate::constants::API_KEY_ENV;
+use crate::output::{OutputMode, print_json};
+use anyhow::{Result, bail};
+use pren_core::doctor::{Check, CheckStatus, check_storage};
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::Provider;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long to wait for the model endpoint to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks the storage and the model config, printing what's wrong and how to fix it.
+/// Fails if any check found an error.
+pub fn run_doctor(
+    storage: &FileStorage,
+    model_config: &ModelConfig,
+    output: OutputMode,
+) -> Result<()> {
+    let mut checks = check_storage(storage);
+    checks.push(check_api_key(model_config));
+    checks.push(check_endpoint(model_config));
+
+    let errors = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Error)
+        .count();
+    match output {
+        OutputMode::Json => print_json(&checks)?,
+        OutputMode::Text => {
+            let width = checks
+                .iter()
+                .map(|check| check.name.len())
+                .max()
+                .unwrap_or(0);
+            for check in &checks {
+                let status = match check.status {
+                    CheckStatus::Ok => "OK",
+                    CheckStatus::Warning => "WARN",
+                    CheckStatus::Error => "ERROR",
+                };
+                println!(
+                    "{:<5} {:<width$}  {}",
+                    status,
+                    check.name,
+                    check.message,
+                    width = width
+                );
+                if let Some(fix) = &check.fix {
+                    println!("      {:<width$}  fix: {}", "", fix, width = width);
+                }
+            }
+        }
+    }
+    if errors > 0 {
+        bail!("{} problem(s) found", errors);
+    }
+    Ok(())
+}
+
+fn check_api_key(model_config: &ModelConfig) -> Check {
+    if model_config.provider == Provider::Ollama {
+        return Check::ok("api key", "Ollama doesn't need an API key");
+    }
+    match model_config.api_key(model_config.provider) {
+        Some((_, KeySource::Config)) => Check::ok("api key", "An API key is set"),
+        Some((_, KeySource::Keyring)) => Check::ok("api key", "An API key is in the keyring"),
+        Some((_, KeySource::ProviderEnv)) => Check::ok(
+            "api key",
+            format!(
+                "An API key is set in {}",
+                provider_key_env(model_config.provider).unwrap_or_default()
+            ),
+        ),
+        // Local servers usually don't need a key, so it's only a warning
+        None => Check::warning(
+            "api key",
+            "No API key is set",
+            format!(
+                "Run `pren auth set`, if pren is built with keyring support, or set {}, \
+                 unless the model server doesn't need one",
+                API_KEY_ENV
+            ),
+        ),
+    }
+}
+
+/// Checks that the host of the model endpoint accepts connections.
+fn check_endpoint(model_config: &ModelConfig) -> Check {
+    let fix = "Check that the model server is running, \
+               or run `pren config set model_config.base_url <url>`";
+    let provider = model_config.provider_config(None, None);
+    let base_url = provider.base_url();
+    let Some(address) = endpoint_address(base_url) else {
+        return Check::error(
+            "endpoint",
+            format!("'{}' isn't a valid URL", base_url),
+            "Run `pren config set model_config.base_url <url>`",
+        );
+    };
+    let addresses = match address.to_socket_addrs() {
+        Ok(addresses) => addresses.collect::<Vec<_>>(),
+        Err(e) => {
+            return Check::error(
+                "endpoint",
+                format!("{} can't be resolved: {}", address, e),
+                fix,
+            );
+        }
+    };
+    let mut error = None;
+    for socket_address in addresses {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(_) => {
+                return Check::ok(
+                    "endpoint",
+                    format!("{} ({}) is reachable", base_url, provider.provider()),
+                );
+            }
+            Err(e) => error = Some(e),
+        }
+    }
+    Check::error(
+        "endpoint",
+        match error {
+            Some(e) => format!("{} isn't reachable: {}", base_url, e),
+            None => format!("{} isn't reachable", base_url),
+        },
+        fix,
+    )
+}
+
+/// The `host:port` of a URL, with the default port of its scheme if it has none.
+fn endpoint_address(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let default_port = match scheme {
+        "http" => 80,
+        "https" => 443,
+        _ => return None,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    if host_port.is_empty() {
+        return None;
+    }
+    // The port comes after the last colon, unless it's part of an IPv6 address
+    match host_port.rsplit_once(':') {
+        Some((host, port)) if !port.ends_with(']') => {
+            Some(format!("{}:{}", host, port.parse::<u16>().ok()?))
+        }
+        _ => Some(format!("{}:{}", host_port, default_port)),
+    }
+}
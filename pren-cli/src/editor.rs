@@ -0,0 +1,153 @@
+use crate::check::check_references;
+use crate::config::ValidateOnSave;
+use crate::constants::DEFAULT_EDITOR;
+use anyhow::{Context, Result, bail};
+use pren_core::file_storage::{FileStorage, parse_prompt_file};
+use pren_core::prompt::{Prompt, PromptTemplate};
+use pren_core::storage::PromptStorage;
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use tracing::error;
+
+/// Opens text in the user's editor (`VISUAL`, `EDITOR` or `vi`) and returns the edited
+/// text once the editor exits.
+pub fn edit_text(text: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    // Editors are often configured with arguments, e.g. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+
+    let mut file = tempfile::Builder::new()
+        .prefix("pren-")
+        .suffix(".md")
+        .tempfile()
+        .context("Failed to create a temporary file to edit")?;
+    file.write_all(text.as_bytes())?;
+    file.flush()?;
+
+    let status = Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .with_context(|| format!("Failed to open editor '{}'", editor))?;
+    if !status.success() {
+        bail!("Editor '{}' exited with {}", editor, status);
+    }
+    Ok(fs::read_to_string(file.path())?)
+}
+
+/// Asks the user a question on stdin, falling back to `default` on empty input.
+/// The default is only shown if it isn't empty.
+pub fn ask(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+    if input.is_empty() {
+        Ok(default.to_string())
+    } else {
+        Ok(input.to_string())
+    }
+}
+
+/// Asks the user to pick one of the options by their first letter, until a valid one is given.
+pub fn choose(question: &str, options: &[&str]) -> Result<char> {
+    loop {
+        print!("{} ", question);
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            bail!("No answer given");
+        }
+        let input = input.trim().to_lowercase();
+        if let Some(option) = options
+            .iter()
+            .find(|option| !input.is_empty() && option.starts_with(input.as_str()))
+        {
+            return Ok(option.chars().next().unwrap_or_default());
+        }
+    }
+}
+
+/// Checks that an edited prompt file parses, keeps its name and has a valid template.
+fn validate_prompt_file(text: &str, name: &str) -> Result<Prompt> {
+    let prompt = parse_prompt_file(text).context("Invalid frontmatter")?;
+    if prompt.metadata.name != name {
+        bail!(
+            "The name can't be changed while editing (was '{}', now '{}')",
+            name,
+            prompt.metadata.name
+        );
+    }
+    PromptTemplate::new(prompt.clone()).context("Invalid template")?;
+    Ok(prompt)
+}
+
+/// Edits the file of a stored prompt, validating it before saving.
+///
+/// Invalid files, including ones whose references wouldn't render when `validate_on_save`
+/// rejects them, aren't saved unless the user confirms it, and can be edited again instead.
+pub fn run_edit(
+    storage: &FileStorage,
+    name: &str,
+    override_lock: bool,
+    validate_on_save: ValidateOnSave,
+) -> Result<()> {
+    let prompt = storage.get_prompt(name)?;
+    if prompt.metadata.locked && !override_lock {
+        bail!(
+            "Prompt '{}' is locked. Use --override-lock to edit it.",
+            name
+        );
+    }
+    let path = storage.get_prompt_path(name)?;
+    let original = fs::read_to_string(&path)?;
+
+    let mut text = original.clone();
+    loop {
+        text = edit_text(&text)?;
+        if text == original {
+            println!("No changes to prompt '{}'", name);
+            return Ok(());
+        }
+
+        let validated = validate_prompt_file(&text, name).and_then(|prompt| {
+            check_references(storage, &prompt, validate_on_save)?;
+            Ok(prompt)
+        });
+        match validated {
+            Ok(prompt) => {
+                if override_lock {
+                    storage.save_prompt_overriding_lock(&prompt)?;
+                } else {
+                    storage.save_prompt(&prompt)?;
+                }
+                println!("Prompt '{}' saved", name);
+                return Ok(());
+            }
+            Err(e) => {
+                error!("{:#}", e);
+                match choose(
+                    "[e]dit again, [s]ave anyway or [d]iscard the changes?",
+                    &["edit", "save", "discard"],
+                )? {
+                    'e' => continue,
+                    's' => {
+                        storage.overwrite_prompt_file(name, &text)?;
+                        println!("Prompt '{}' saved without validation", name);
+                    }
+                    _ => println!("Changes to prompt '{}' discarded", name),
+                }
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::output::{OutputMode, print_json};
+use pren_core::file_storage::FileStorageError;
+use pren_core::import::ImportError;
+use pren_core::improve::ImproveError;
+use pren_core::llm::LlmError;
+use pren_core::pipeline::PipelineError;
+use pren_core::prompt::{ParseTemplateError, RenderTemplateError};
+use serde::Serialize;
+use serde_json::json;
+use std::fmt;
+use std::process::ExitCode;
+
+/// A problem with the configuration, such as a profile or a config key that doesn't exist.
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The kinds of failures with an exit code of their own, so scripts can tell them apart
+/// without parsing error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// A prompt that doesn't exist
+    NotFound,
+    /// A prompt file or template that can't be parsed
+    Parse,
+    /// A render without the value of an argument
+    MissingArguments,
+    /// A failed request to the model
+    Model,
+    /// A config file that can't be loaded, or an unknown profile or config key
+    Config,
+    /// Anything else
+    Other,
+}
+
+impl ErrorKind {
+    /// Finds the kind of an error from the first error in its chain with a known kind.
+    pub fn of(error: &anyhow::Error) -> ErrorKind {
+        error
+            .chain()
+            .find_map(|cause| {
+                if let Some(error) = cause.downcast_ref::<FileStorageError>() {
+                    return match error {
+                        FileStorageError::PromptNotFound(_)
+                        | FileStorageError::TranscriptNotFound(_) => Some(ErrorKind::NotFound),
+                        FileStorageError::DeserializationError(_)
+                        | FileStorageError::ParseTemplateError(_) => Some(ErrorKind::Parse),
+                        _ => None,
+                    };
+                }
+                if let Some(error) = cause.downcast_ref::<RenderTemplateError>() {
+                    return match error {
+                        RenderTemplateError::MissingArgument { .. } => {
+                            Some(ErrorKind::MissingArguments)
+                        }
+                        RenderTemplateError::PromptNotFound { .. } => Some(ErrorKind::NotFound),
+                        RenderTemplateError::InvalidPrompt { .. } => Some(ErrorKind::Parse),
+                        _ => None,
+                    };
+                }
+                if let Some(error) = cause.downcast_ref::<PipelineError>() {
+                    return match error {
+                        PipelineError::GenerationError { .. } => Some(ErrorKind::Model),
+                        _ => None,
+                    };
+                }
+                if let Some(ImportError::InvalidFile(..)) = cause.downcast_ref::<ImportError>() {
+                    return Some(ErrorKind::Parse);
+                }
+                if cause.is::<ParseTemplateError>() {
+                    Some(ErrorKind::Parse)
+                } else if cause.is::<LlmError>() || cause.is::<ImproveError>() {
+                    Some(ErrorKind::Model)
+                } else if cause.is::<ConfigError>() || cause.is::<confy::ConfyError>() {
+                    Some(ErrorKind::Config)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(ErrorKind::Other)
+    }
+
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::NotFound => 4,
+            ErrorKind::Parse => 5,
+            ErrorKind::MissingArguments => 6,
+            ErrorKind::Model => 7,
+            ErrorKind::Config => exitcode::CONFIG as u8,
+            ErrorKind::Other => 1,
+        }
+    }
+}
+
+/// Reports an error that stopped a command, as text on stderr or, with JSON output, as an
+/// envelope on stdout, and returns the exit code of its kind.
+pub fn report_error(error: &anyhow::Error, output: OutputMode) -> ExitCode {
+    let kind = ErrorKind::of(error);
+    match output {
+        OutputMode::Json => {
+            let envelope = json!({
+                "error": {
+                    "kind": kind,
+                    "message": format!("{:#}", error),
+                    "exit_code": kind.exit_code()
+                }
+            });
+            if print_json(&envelope).is_err() {
+                eprintln!("Error: {:?}", error);
+            }
+        }
+        OutputMode::Text => eprintln!("Error: {:?}", error),
+    }
+    ExitCode::from(kind.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_kind_of_referenced_prompts() {
+        let not_found: anyhow::Result<()> = Err(RenderTemplateError::PromptNotFound {
+            name: "missing".to_string(),
+            referenced_from: "main".to_string(),
+        })
+        .context("Error rendering prompt 'main'");
+        assert_eq!(ErrorKind::NotFound, ErrorKind::of(&not_found.unwrap_err()));
+
+        // A corrupt prompt is a parse error, found through the source of the storage error
+        let corrupt = anyhow::Error::from(RenderTemplateError::Storage {
+            name: "broken".to_string(),
+            referenced_from: "main".to_string(),
+            source: Box::new(FileStorageError::DeserializationError(
+                "invalid frontmatter".to_string(),
+            )),
+        });
+        assert_eq!(ErrorKind::Parse, ErrorKind::of(&corrupt));
+
+        let unreadable = anyhow::Error::from(RenderTemplateError::Storage {
+            name: "locked".to_string(),
+            referenced_from: "main".to_string(),
+            source: Box::new(FileStorageError::IoError(std::io::Error::from(
+                std::io::ErrorKind::PermissionDenied,
+            ))),
+        });
+        assert_eq!(ErrorKind::Other, ErrorKind::of(&unreadable));
+    }
+}
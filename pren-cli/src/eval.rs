@@ -0,0 +1,183 @@
+use crate::check::check_content;
+use crate::output::{OutputMode, print_json};
+use crate::usage::record_tokens;
+use anyhow::{Context, Result};
+use pren_core::eval::{Judgement, MAX_SCORE, judge_messages, parse_judgement, run_evals};
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::{ModelSettings, ProviderConfig, get_chat_completion};
+use pren_core::messages::{RenderedMessage, split_messages};
+use pren_core::prompt::{Prompt, PromptTemplate};
+use pren_core::storage::EvalStorage;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Where the responses of eval cases are sent, and how they're judged.
+pub struct EvalModel<'a> {
+    pub provider: &'a ProviderConfig,
+    /// The model used when prompts don't prefer one, or the one given on the command line.
+    pub model_name: &'a str,
+    /// Whether `model_name` overrides the models preferred by prompts.
+    pub override_model: bool,
+    /// The prompt scoring responses against the criteria of eval cases. The built-in judge
+    /// is used if it's `None`.
+    pub judge: Option<PromptTemplate>,
+    pub skip_checks: bool,
+}
+
+/// Runs the eval cases of prompts against the model, recording every run, and reports
+/// their results with how the pass rate changed since the previous run of each prompt.
+pub async fn run_eval(
+    storage: &FileStorage,
+    prompts: Vec<Prompt>,
+    model: &EvalModel<'_>,
+    output: OutputMode,
+) -> Result<()> {
+    let previous_runs = storage.get_evals()?;
+    let mut runs = Vec::new();
+    for prompt in prompts {
+        let name = prompt.metadata.name.clone();
+        let mut settings = prompt.metadata.model_settings.clone();
+        if model.override_model || settings.model.is_none() {
+            settings.model = Some(model.model_name.to_string());
+        }
+        let used_model = settings.model.clone().unwrap_or_default();
+        let template =
+            PromptTemplate::new(prompt).context(format!("Error parsing prompt '{}'", name))?;
+
+        let results = run_evals(
+            &template,
+            storage,
+            async |messages: &[RenderedMessage]| send(storage, model, messages, &settings).await,
+            async |criteria: &str, input: &str, response: &str| {
+                judge(storage, model, criteria, input, response).await
+            },
+        )
+        .await;
+        let run = storage.record_eval(&name, &used_model, results)?;
+
+        if output == OutputMode::Text {
+            for result in &run.results {
+                let score = result
+                    .judgement
+                    .as_ref()
+                    .map(|judgement| format!(" ({}/{})", judgement.score, MAX_SCORE))
+                    .unwrap_or_default();
+                let status = if result.passed() { "PASS" } else { "FAIL" };
+                println!("{} {} {}{}", status, name, result.case, score);
+                for failure in &result.failures {
+                    println!("  {}", failure);
+                }
+            }
+            let previous = previous_runs
+                .iter()
+                .rev()
+                .find(|previous| previous.prompt == name)
+                .map(|previous| {
+                    format!(
+                        ", previously {:.0}% on {} with {}",
+                        previous.pass_rate() * 100.0,
+                        previous.timestamp.format("%Y-%m-%d %H:%M"),
+                        previous.model
+                    )
+                })
+                .unwrap_or_default();
+            println!(
+                "{}: {}/{} passed ({:.0}%){}",
+                name,
+                run.passed_count(),
+                run.results.len(),
+                run.pass_rate() * 100.0,
+                previous
+            );
+        }
+        runs.push(run);
+    }
+    if output == OutputMode::Json {
+        return print_json(&runs);
+    }
+    Ok(())
+}
+
+/// Prints the past eval runs, newest first.
+pub fn run_eval_history(
+    storage: &FileStorage,
+    name: Option<&str>,
+    output: OutputMode,
+) -> Result<()> {
+    let runs: Vec<_> = storage
+        .get_evals()?
+        .into_iter()
+        .rev()
+        .filter(|run| name.is_none_or(|name| run.prompt == name))
+        .collect();
+    if output == OutputMode::Json {
+        return print_json(&runs);
+    }
+    if runs.is_empty() {
+        println!("No evals run yet");
+        return Ok(());
+    }
+    for run in runs {
+        println!(
+            "{}  {}  {}  {}  {}/{} passed ({:.0}%)",
+            run.id,
+            run.timestamp.format("%Y-%m-%d %H:%M"),
+            run.prompt,
+            run.model,
+            run.passed_count(),
+            run.results.len(),
+            run.pass_rate() * 100.0
+        );
+    }
+    Ok(())
+}
+
+async fn send(
+    storage: &FileStorage,
+    model: &EvalModel<'_>,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+) -> Result<String, String> {
+    if !model.skip_checks {
+        for message in messages {
+            check_content(&message.content).map_err(|e| format!("{:#}", e))?;
+        }
+    }
+    let completion = get_chat_completion(model.provider, model.model_name, messages, settings)
+        .await
+        .map_err(|e| e.to_string())?;
+    let used_model = settings.model.as_deref().unwrap_or(model.model_name);
+    record_tokens(storage, used_model, completion.usage);
+    Ok(completion.text)
+}
+
+/// Scores a response with the judge prompt rendered with the `criteria`, `input` and
+/// `output` arguments, or with the built-in judge.
+async fn judge(
+    storage: &FileStorage,
+    model: &EvalModel<'_>,
+    criteria: &str,
+    input: &str,
+    output: &str,
+) -> Result<Judgement, String> {
+    let messages = match &model.judge {
+        Some(judge) => {
+            let arguments = HashMap::from([
+                ("criteria".to_string(), Value::from(criteria)),
+                ("input".to_string(), Value::from(input)),
+                ("output".to_string(), Value::from(output)),
+            ]);
+            let rendered = judge
+                .render(&arguments, storage)
+                .map_err(|e| e.to_string())?;
+            split_messages(&rendered)
+        }
+        None => judge_messages(criteria, input, output),
+    };
+    let settings = ModelSettings {
+        model: Some(model.model_name.to_string()),
+        ..ModelSettings::default()
+    };
+    let response = send(storage, model, &messages, &settings).await?;
+    parse_judgement(&response).map_err(|e| e.to_string())
+}
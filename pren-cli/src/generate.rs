@@ -0,0 +1,348 @@
+use crate::arguments::{add_stdin_arg, build_args_map};
+use crate::check::{check_content, check_prompt_name};
+use crate::config::{ModelConfig, PrenCliConfig};
+use crate::constants::VALIDATION_RETRIES;
+use crate::history::{record_history, record_usage};
+use crate::output::{OutputMode, print_json, print_warnings, write_output_file};
+use crate::preset::apply_preset;
+use crate::tools::{print_tool_calls, run_tool, tool_definitions};
+use crate::transcript::save_transcript;
+use crate::usage::{print_tokens, record_tokens};
+use anyhow::{Result, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::history::output_hash;
+use pren_core::llm::{
+    ModelSettings, Provider, ToolCall, get_chat_completion, get_chat_completion_with_tools,
+    get_completion_checked,
+};
+use pren_core::messages::{RenderedMessage, Role, split_messages};
+use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
+use pren_core::stats::UsageKind;
+use pren_core::storage::PromptStorage;
+use pren_core::transcript::Transcript;
+use serde_json::json;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// The prompt `pren generate` sends, the model settings to send it with, and where the
+/// response goes.
+pub struct GenerateOptions {
+    pub generation_prompt: String,
+    pub args: Vec<(String, String)>,
+    pub args_json: Option<String>,
+    pub arg_stdin: Option<String>,
+    pub context: Option<PathBuf>,
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    pub stop: Vec<String>,
+    pub preset: Option<String>,
+    pub provider: Option<Provider>,
+    pub base_url: Option<String>,
+    pub validate: bool,
+    pub max_attempts: Option<usize>,
+    pub tool: Vec<String>,
+    pub run_tools: bool,
+    pub max_tool_steps: usize,
+    pub skip_checks: bool,
+    pub out: Option<PathBuf>,
+    pub append: bool,
+    pub save_as: Option<String>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub overwrite: bool,
+    pub transcript: bool,
+}
+
+/// Renders a prompt, sends it to the model and prints, writes or saves the response.
+pub async fn run_generate(
+    storage: &FileStorage,
+    config: &PrenCliConfig,
+    model_config: &ModelConfig,
+    options: GenerateOptions,
+    output: OutputMode,
+) -> Result<()> {
+    let GenerateOptions {
+        generation_prompt,
+        mut args,
+        args_json,
+        arg_stdin,
+        context,
+        model,
+        temperature,
+        max_tokens,
+        stop,
+        preset,
+        provider,
+        base_url,
+        validate,
+        max_attempts,
+        tool,
+        run_tools,
+        max_tool_steps,
+        skip_checks,
+        out,
+        append,
+        save_as,
+        tags,
+        description,
+        overwrite,
+        transcript,
+    } = options;
+    // Checked before generating, so the response isn't thrown away
+    check_save_as(storage, save_as.as_deref(), overwrite)?;
+    let (model_config, model) = apply_preset(config, model_config, preset.as_deref(), model)?;
+    let provider = model_config.provider_config(provider, base_url.as_deref());
+    let prompt = storage.get_prompt(&generation_prompt)?;
+    let output_format = match (validate, &prompt.metadata.output_format) {
+        (true, None) => bail!(
+            "Prompt '{}' doesn't declare an output format to validate against",
+            generation_prompt
+        ),
+        (true, Some(format)) => Some(format.clone()),
+        (false, _) => None,
+    };
+    let tools = tool_definitions(&config.tools, &prompt.metadata.tools, &tool)?;
+    let guardrails = match &prompt.metadata.guardrails {
+        Some(_) if !tools.is_empty() => {
+            warn!(
+                "The guardrails of '{}' aren't checked when the model can call tools",
+                generation_prompt
+            );
+            None
+        }
+        guardrails => guardrails.clone(),
+    };
+    let max_retries = max_attempts
+        .or(guardrails.as_ref().and_then(|g| g.max_attempts))
+        .map_or(VALIDATION_RETRIES, |attempts| attempts.saturating_sub(1));
+    let settings = prompt
+        .metadata
+        .model_settings
+        .with_overrides(&ModelSettings {
+            model,
+            temperature,
+            max_tokens,
+            stop,
+        });
+    add_stdin_arg(&mut args, arg_stdin.as_deref())?;
+    let args_map = build_args_map(&args, args_json.as_deref(), context.as_deref())?;
+    let rendered = PromptTemplate::new(prompt)?.render_with_warnings(&args_map, storage)?;
+    if !skip_checks {
+        check_content(&rendered.text)?;
+    }
+    print_warnings(&rendered.warnings);
+    let messages = split_messages(&rendered.text);
+    let completion = match (output_format, guardrails) {
+        (None, None) if tools.is_empty() => {
+            get_chat_completion(&provider, &model_config.model_name, &messages, &settings).await?
+        }
+        (None, None) => {
+            get_chat_completion_with_tools(
+                &provider,
+                &model_config.model_name,
+                &messages,
+                &settings,
+                &tools,
+                max_tool_steps,
+                async |call: &ToolCall| {
+                    if run_tools {
+                        run_tool(&config.tools, call)
+                    } else {
+                        None
+                    }
+                },
+            )
+            .await?
+        }
+        (output_format, guardrails) => {
+            get_completion_checked(
+                &provider,
+                &model_config.model_name,
+                &messages,
+                &settings,
+                |response| {
+                    let response = match &output_format {
+                        Some(format) => format.validate(response)?,
+                        None => response.to_string(),
+                    };
+                    if let Some(guardrails) = &guardrails {
+                        guardrails.validate(&response)?;
+                    }
+                    Ok(response)
+                },
+                max_retries,
+            )
+            .await?
+        }
+    };
+    let used_model = settings
+        .model
+        .as_deref()
+        .unwrap_or(&model_config.model_name);
+    record_tokens(storage, used_model, completion.usage);
+    let response = completion.text;
+    if transcript {
+        let mut messages = messages;
+        messages.push(RenderedMessage {
+            role: Role::Assistant,
+            content: response.clone(),
+        });
+        let arguments = args_map.clone().into_iter().collect();
+        save_transcript(
+            storage,
+            Transcript::new(
+                generation_prompt.clone(),
+                arguments,
+                used_model.to_string(),
+                messages,
+            ),
+        );
+    }
+
+    if let Some(path) = &out {
+        write_output_file(path, &response, append)?;
+    }
+    if let Some(name) = &save_as {
+        let prompt = generated_prompt(
+            config,
+            name,
+            &generation_prompt,
+            description,
+            tags,
+            response.clone(),
+        );
+        storage.save_prompt(&prompt)?;
+    }
+    match (output, &out, &save_as) {
+        (OutputMode::Json, _, Some(name)) => print_json(&json!({
+            "name": generation_prompt,
+            "saved_as": name,
+            "tool_calls": completion.tool_calls,
+            "usage": completion.usage
+        }))?,
+        (OutputMode::Json, Some(path), None) => print_json(&json!({
+            "name": generation_prompt,
+            "out": path,
+            "tool_calls": completion.tool_calls,
+            "usage": completion.usage
+        }))?,
+        (OutputMode::Json, None, None) => print_json(&json!({
+            "name": generation_prompt,
+            "response": response,
+            "tool_calls": completion.tool_calls,
+            "usage": completion.usage
+        }))?,
+        (OutputMode::Text, _, Some(name)) => {
+            println!("Prompt '{}' saved", name);
+            print_tool_calls(&completion.tool_calls);
+            print_tokens(config, used_model, completion.usage);
+        }
+        (OutputMode::Text, Some(_), None) => {
+            print_tool_calls(&completion.tool_calls);
+            print_tokens(config, used_model, completion.usage);
+        }
+        (OutputMode::Text, None, None) => {
+            if !response.is_empty() || completion.tool_calls.is_empty() {
+                println!("{}", response);
+            }
+            print_tool_calls(&completion.tool_calls);
+            print_tokens(config, used_model, completion.usage);
+        }
+    }
+    record_usage(storage, &generation_prompt, UsageKind::Generate);
+    record_history(
+        storage,
+        &generation_prompt,
+        UsageKind::Generate,
+        &args_map,
+        output_hash(&rendered.text),
+    );
+    Ok(())
+}
+
+/// Fails if the name a response would be saved as is invalid, or if the prompt already
+/// exists, unless it's overwritten.
+fn check_save_as(storage: &FileStorage, save_as: Option<&str>, overwrite: bool) -> Result<()> {
+    if let Some(name) = save_as {
+        check_prompt_name(name)?;
+    }
+    if let Some(name) = save_as
+        && !overwrite
+        && storage.get_prompt(name).is_ok()
+    {
+        bail!(
+            "Prompt '{}' already exists. Use --overwrite to replace it.",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Builds the prompt a response generated with `generation_prompt` is saved as.
+fn generated_prompt(
+    config: &PrenCliConfig,
+    name: &str,
+    generation_prompt: &str,
+    description: Option<String>,
+    tags: Vec<String>,
+    response: String,
+) -> Prompt {
+    let description =
+        description.unwrap_or_else(|| format!("Generated with '{}'", generation_prompt));
+    let mut metadata = PromptMetadata::new(name.to_string(), Some(description), tags);
+    metadata.author = config.author.clone();
+    Prompt::new(metadata, response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_save_as() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("summary".to_string(), None, vec![]),
+                "Old".to_string(),
+            ))
+            .unwrap();
+
+        assert!(check_save_as(&storage, None, false).is_ok());
+        assert!(check_save_as(&storage, Some("new"), false).is_ok());
+        assert!(check_save_as(&storage, Some("summary"), false).is_err());
+        assert!(check_save_as(&storage, Some("summary"), true).is_ok());
+        assert!(check_save_as(&storage, Some("../summary"), true).is_err());
+    }
+
+    #[test]
+    fn test_generated_prompt() {
+        let config = PrenCliConfig {
+            author: Some("Ada".to_string()),
+            ..PrenCliConfig::default()
+        };
+        let prompt = generated_prompt(
+            &config,
+            "summary",
+            "summarize",
+            None,
+            vec!["notes".to_string()],
+            "Short".to_string(),
+        );
+        assert_eq!("summary", prompt.metadata.name);
+        assert_eq!(
+            Some("Generated with 'summarize'".to_string()),
+            prompt.metadata.description
+        );
+        assert_eq!(vec!["notes"], prompt.metadata.tags);
+        assert_eq!(Some("Ada".to_string()), prompt.metadata.author);
+        assert_eq!("Short", prompt.content);
+    }
+}
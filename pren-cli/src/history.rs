@@ -0,0 +1,198 @@
+use crate::check::check_content;
+use crate::config::ModelConfig;
+use crate::output::print_warnings;
+use crate::output::{OutputMode, print_json};
+use crate::usage::record_tokens;
+use anyhow::{Context, Result, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::history::{HistoryEntry, output_hash};
+use pren_core::llm::get_chat_completion;
+use pren_core::messages::split_messages;
+use pren_core::prompt::PromptTemplate;
+use pren_core::stats::UsageKind;
+use pren_core::storage::{HistoryStorage, PromptStorage, StatsStorage};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// The longest argument value shown in the history, in characters.
+const MAX_VALUE_WIDTH: usize = 40;
+
+/// Prints the most recent history entries, newest first.
+pub fn run_history(
+    storage: &FileStorage,
+    name: Option<&str>,
+    limit: usize,
+    output: OutputMode,
+) -> Result<()> {
+    let entries: Vec<HistoryEntry> = storage
+        .get_history()?
+        .into_iter()
+        .rev()
+        .filter(|entry| name.is_none_or(|name| entry.prompt == name))
+        .take(limit)
+        .collect();
+    if output == OutputMode::Json {
+        return print_json(&entries);
+    }
+    if entries.is_empty() {
+        println!("No prompts rendered yet");
+        return Ok(());
+    }
+
+    let rows: Vec<[String; 5]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.id.to_string(),
+                entry.timestamp.format("%Y-%m-%d %H:%M").to_string(),
+                format!("{:?}", entry.kind).to_lowercase(),
+                entry.prompt.clone(),
+                format_arguments(entry),
+            ]
+        })
+        .collect();
+    let header = ["ID", "TIME", "KIND", "PROMPT", "ARGUMENTS"].map(String::from);
+    let mut widths = [0; 4];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = format!(
+            "{:<id$}  {:<time$}  {:<kind$}  {:<prompt$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            id = widths[0],
+            time = widths[1],
+            kind = widths[2],
+            prompt = widths[3]
+        );
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+/// The arguments of an entry as `key=value` pairs, with long values shortened.
+fn format_arguments(entry: &HistoryEntry) -> String {
+    entry
+        .arguments
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(text) => text.clone(),
+                value => value.to_string(),
+            };
+            let mut value = value.replace('\n', "\\n");
+            if value.chars().count() > MAX_VALUE_WIDTH {
+                value = value.chars().take(MAX_VALUE_WIDTH - 3).collect::<String>() + "...";
+            }
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Renders the prompt of a history entry again with the same arguments, sending it to the
+/// model again for generations. Warns if the rendered prompt isn't the same as back then.
+pub async fn run_replay(
+    storage: &FileStorage,
+    model_config: &ModelConfig,
+    id: u64,
+    skip_checks: bool,
+    output: OutputMode,
+) -> Result<()> {
+    let Some(entry) = storage
+        .get_history()?
+        .into_iter()
+        .find(|entry| entry.id == id)
+    else {
+        bail!("There's no history entry with id {}", id);
+    };
+    let prompt = storage
+        .get_prompt(&entry.prompt)
+        .with_context(|| format!("Couldn't replay history entry {}", id))?;
+    let settings = prompt.metadata.model_settings.clone();
+
+    let arguments: HashMap<String, Value> = entry.arguments.into_iter().collect();
+    let rendered = PromptTemplate::new(prompt)?.render_with_warnings(&arguments, storage)?;
+    print_warnings(&rendered.warnings);
+    let rendered_hash = output_hash(&rendered.text);
+    let changed = rendered_hash != entry.output_hash;
+    if changed {
+        warn!(
+            "The rendered prompt differs from history entry {}, \
+             as '{}' or the prompts it references changed",
+            id, entry.prompt
+        );
+    }
+
+    let text = match entry.kind {
+        UsageKind::Render => rendered.text,
+        UsageKind::Generate => {
+            if !skip_checks {
+                check_content(&rendered.text)?;
+            }
+            let completion = get_chat_completion(
+                &model_config.provider_config(None, None),
+                &model_config.model_name,
+                &split_messages(&rendered.text),
+                &settings,
+            )
+            .await?;
+            let used_model = settings
+                .model
+                .as_deref()
+                .unwrap_or(&model_config.model_name);
+            record_tokens(storage, used_model, completion.usage);
+            completion.text
+        }
+    };
+    match output {
+        OutputMode::Json => print_json(&json!({
+            "id": id,
+            "name": entry.prompt,
+            "kind": entry.kind,
+            "changed": changed,
+            "text": text
+        }))?,
+        OutputMode::Text => println!("{}", text),
+    }
+    record_usage(storage, &entry.prompt, entry.kind);
+    record_history(
+        storage,
+        &entry.prompt,
+        entry.kind,
+        &arguments,
+        rendered_hash,
+    );
+    Ok(())
+}
+
+/// Records a use of a prompt. Failing to record it doesn't fail the command.
+pub fn record_usage(storage: &FileStorage, name: &str, kind: UsageKind) {
+    if let Err(e) = storage.record_usage(name, kind) {
+        warn!("Couldn't record the usage of '{}': {}", name, e);
+    }
+}
+
+/// Records a use of a prompt in the history. Failing to record it doesn't fail the command.
+pub fn record_history(
+    storage: &FileStorage,
+    name: &str,
+    kind: UsageKind,
+    arguments: &HashMap<String, Value>,
+    rendered_hash: String,
+) {
+    let arguments = arguments
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    if let Err(e) = storage.record_history(kind, name, arguments, rendered_hash) {
+        warn!("Couldn't record '{}' in the history: {}", name, e);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::check::check_prompt_name;
+use crate::constants::HUB_API_KEY_ENV;
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result, anyhow, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::hub::{from_manifest, to_manifest};
+use pren_core::prompt::PromptTemplate;
+use pren_core::storage::PromptStorage;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// A repo of the LangChain Hub, optionally at a commit, written as `owner/repo:commit`.
+/// Repos without an owner belong to the owner of the API key.
+pub struct HubRepoRef {
+    pub owner: String,
+    pub repo: String,
+    pub commit: Option<String>,
+}
+
+impl HubRepoRef {
+    pub fn parse(value: &str) -> Result<HubRepoRef> {
+        let (path, commit) = match value.split_once(':') {
+            Some((path, commit)) => (path, Some(commit.to_string())),
+            None => (value, None),
+        };
+        let (owner, repo) = path.split_once('/').unwrap_or(("-", path));
+        if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+            bail!(
+                "Invalid Hub repo '{}', expected owner/repo, repo or owner/repo:commit",
+                value
+            );
+        }
+        Ok(HubRepoRef {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            commit,
+        })
+    }
+
+    fn path(&self) -> String {
+        format!("{}/{}", self.owner, self.repo)
+    }
+}
+
+/// The description and tags of a Hub repo.
+#[derive(Deserialize)]
+pub struct HubRepo {
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A commit of a Hub repo, with the manifest of its prompt.
+#[derive(Deserialize)]
+pub struct HubCommit {
+    pub commit_hash: String,
+    pub manifest: Value,
+}
+
+/// A client of the LangChain Hub API, authenticated with a LangSmith API key.
+pub struct HubClient {
+    http: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+}
+
+impl HubClient {
+    pub fn new(endpoint: &str, api_key: &str) -> HubClient {
+        HubClient {
+            http: reqwest::Client::new(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.endpoint, path))
+            .header("x-api-key", &self.api_key)
+    }
+
+    /// Sends a request, failing with the body of the response on error statuses.
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        check_status(self.dispatch(request).await?).await
+    }
+
+    /// Sends a request, whatever the status of the response.
+    async fn dispatch(&self, request: RequestBuilder) -> Result<Response> {
+        request
+            .send()
+            .await
+            .with_context(|| format!("Couldn't reach the LangChain Hub at {}", self.endpoint))
+    }
+
+    /// Gets a repo, or `None` if it doesn't exist.
+    pub async fn repo(&self, repo: &HubRepoRef) -> Result<Option<HubRepo>> {
+        #[derive(Deserialize)]
+        struct RepoResponse {
+            repo: HubRepo,
+        }
+
+        let path = format!("/repos/{}", repo.path());
+        let response = self.dispatch(self.request(Method::GET, &path)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response: RepoResponse = check_status(response).await?.json().await?;
+        Ok(Some(response.repo))
+    }
+
+    /// Creates a repo of the owner of the API key.
+    pub async fn create_repo(
+        &self,
+        repo: &HubRepoRef,
+        description: Option<&str>,
+        tags: &[String],
+        public: bool,
+    ) -> Result<()> {
+        let body = json!({
+            "repo_handle": repo.repo,
+            "description": description.unwrap_or_default(),
+            "tags": tags,
+            "is_public": public,
+        });
+        self.send(self.request(Method::POST, "/repos/").json(&body))
+            .await?;
+        Ok(())
+    }
+
+    /// Updates the description and tags of a repo.
+    pub async fn update_repo(
+        &self,
+        repo: &HubRepoRef,
+        description: Option<&str>,
+        tags: &[String],
+    ) -> Result<()> {
+        let body = json!({ "description": description.unwrap_or_default(), "tags": tags });
+        let path = format!("/repos/{}", repo.path());
+        self.send(self.request(Method::PATCH, &path).json(&body))
+            .await?;
+        Ok(())
+    }
+
+    /// Gets a commit of a repo, the latest one unless the reference names one.
+    pub async fn commit(&self, repo: &HubRepoRef) -> Result<HubCommit> {
+        let commit = repo.commit.as_deref().unwrap_or("latest");
+        let path = format!("/commits/{}/{}", repo.path(), commit);
+        let response = self.send(self.request(Method::GET, &path)).await?;
+        response
+            .json()
+            .await
+            .context("The LangChain Hub answered with an unexpected commit")
+    }
+
+    /// Gets the hash of the latest commit of a repo, or `None` if it has none.
+    async fn latest_commit_hash(&self, repo: &HubRepoRef) -> Result<Option<String>> {
+        #[derive(Deserialize)]
+        struct CommitsResponse {
+            commits: Vec<CommitSummary>,
+        }
+        #[derive(Deserialize)]
+        struct CommitSummary {
+            commit_hash: String,
+        }
+
+        let path = format!("/commits/{}/?limit=1&offset=0", repo.path());
+        let response = self.send(self.request(Method::GET, &path)).await?;
+        let commits: CommitsResponse = response.json().await?;
+        Ok(commits
+            .commits
+            .into_iter()
+            .next()
+            .map(|commit| commit.commit_hash))
+    }
+
+    /// Commits a manifest on top of the latest commit of a repo.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(String)` - The hash of the new commit.
+    /// * `None` - If the manifest is the same as the latest commit's.
+    pub async fn push(&self, repo: &HubRepoRef, manifest: &Value) -> Result<Option<String>> {
+        #[derive(Deserialize)]
+        struct PushResponse {
+            commit: CommitSummary,
+        }
+        #[derive(Deserialize)]
+        struct CommitSummary {
+            commit_hash: String,
+        }
+
+        let parent_commit = self.latest_commit_hash(repo).await?;
+        let body = json!({ "manifest": manifest, "parent_commit": parent_commit });
+        let path = format!("/commits/{}", repo.path());
+        let response = self
+            .dispatch(self.request(Method::POST, &path).json(&body))
+            .await?;
+        // The Hub refuses commits that don't change anything
+        if response.status() == StatusCode::CONFLICT {
+            return Ok(None);
+        }
+        let response: PushResponse = check_status(response).await?.json().await?;
+        Ok(Some(response.commit.commit_hash))
+    }
+}
+
+/// Fails with the body of the response if its status is an error.
+async fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("The LangChain Hub answered {}: {}", status, body.trim());
+    }
+    Ok(response)
+}
+
+/// Gets the API key the Hub is used with.
+pub fn hub_api_key(api_key: Option<String>) -> Result<String> {
+    api_key.filter(|key| !key.is_empty()).ok_or_else(|| {
+        anyhow!(
+            "The LangChain Hub needs a LangSmith API key. Set {} or pass --api-key.",
+            HUB_API_KEY_ENV
+        )
+    })
+}
+
+/// Pushes a prompt to a Hub repo as a new commit, creating the repo if it doesn't exist.
+/// The repo gets the prompt's description and tags.
+pub async fn run_hub_push(
+    storage: &FileStorage,
+    client: &HubClient,
+    name: &str,
+    repo: Option<&str>,
+    arguments: &HashMap<String, Value>,
+    public: bool,
+    output: OutputMode,
+) -> Result<()> {
+    let repo = HubRepoRef::parse(repo.unwrap_or(name))?;
+    if let Some(commit) = &repo.commit {
+        bail!(
+            "Prompts are pushed on top of the latest commit. Remove ':{}' from the repo.",
+            commit
+        );
+    }
+    let template = PromptTemplate::new(storage.get_prompt(name)?)?;
+    let manifest = to_manifest(&template, arguments, storage)
+        .with_context(|| format!("Couldn't convert prompt '{}'", name))?;
+
+    let metadata = &template.prompt.metadata;
+    let description = metadata.description.as_deref();
+    match client.repo(&repo).await? {
+        Some(_) => {
+            client
+                .update_repo(&repo, description, &metadata.tags)
+                .await?
+        }
+        None => {
+            client
+                .create_repo(&repo, description, &metadata.tags, public)
+                .await?
+        }
+    }
+    let commit = client.push(&repo, &manifest).await?;
+
+    if output == OutputMode::Json {
+        return print_json(&json!({ "name": name, "repo": repo.path(), "commit": commit }));
+    }
+    match commit {
+        Some(commit) => println!("Pushed '{}' to {} ({})", name, repo.path(), short(&commit)),
+        None => println!("'{}' is already up to date in {}", name, repo.path()),
+    }
+    Ok(())
+}
+
+/// Pulls the prompt of a Hub repo, with the repo's description and tags.
+pub async fn run_hub_pull(
+    storage: &FileStorage,
+    client: &HubClient,
+    repo: &str,
+    name: Option<&str>,
+    overwrite: bool,
+    output: OutputMode,
+) -> Result<()> {
+    let repo = HubRepoRef::parse(repo)?;
+    let name = name.unwrap_or(&repo.repo);
+    check_prompt_name(name)?;
+    if storage.get_prompt(name).is_ok() && !overwrite {
+        bail!(
+            "Prompt '{}' already exists. Use --overwrite to replace it.",
+            name
+        );
+    }
+    let commit = client.commit(&repo).await?;
+    let mut prompt = from_manifest(&commit.manifest, name)?;
+    if let Some(info) = client.repo(&repo).await? {
+        prompt.metadata.description = info.description.filter(|d| !d.is_empty());
+        prompt.metadata.tags = info.tags;
+        prompt.metadata.source_url = info
+            .owner
+            .map(|owner| format!("https://smith.langchain.com/hub/{}/{}", owner, repo.repo));
+    }
+    storage.save_prompt(&prompt)?;
+
+    if output == OutputMode::Json {
+        return print_json(&json!({
+            "name": name,
+            "repo": repo.path(),
+            "commit": commit.commit_hash
+        }));
+    }
+    println!(
+        "Pulled '{}' from {} ({})",
+        name,
+        repo.path(),
+        short(&commit.commit_hash)
+    );
+    Ok(())
+}
+
+/// Shortens a commit hash as Git does.
+fn short(commit: &str) -> &str {
+    commit.get(..8).unwrap_or(commit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_run_hub_pull_rejects_invalid_names() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().join("prompts"),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        // The name is checked before the Hub is reached
+        let client = HubClient::new("http://127.0.0.1:9", "key");
+
+        let result = run_hub_pull(
+            &storage,
+            &client,
+            "owner/repo",
+            Some("../outside"),
+            false,
+            OutputMode::Json,
+        )
+        .await;
+        let error = format!("{:#}", result.err().unwrap());
+        assert!(
+            error.starts_with("Invalid prompt name '../outside'"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::output::print_json;
+use anyhow::Result;
+use clap::ValueEnum;
+use pren_core::file_storage::{FileStorage, PromptFileTimes};
+use pren_core::prompt::Prompt;
+use pren_core::stats::PromptStats;
+use pren_core::storage::{PromptStorage, StatsStorage};
+use pren_core::tag_query::TagQuery;
+use serde_json::json;
+use std::cmp::Reverse;
+
+/// How the listed prompts are sorted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    /// Alphabetically
+    Name,
+    /// Most recently created first
+    Created,
+    /// Most recently modified first
+    Modified,
+    /// Most used first
+    Used,
+}
+
+/// How the listed prompts are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// A table with the prompts' names, tags, notes and descriptions
+    #[default]
+    Table,
+    /// A JSON array with the prompts' metadata, times and usage
+    Json,
+    /// Only the names, one per line
+    Names,
+}
+
+pub struct ListOptions {
+    pub tags: Vec<String>,
+    pub query: Option<TagQuery>,
+    pub name_contains: Option<String>,
+    pub sort: Option<ListSort>,
+    pub limit: Option<usize>,
+    pub format: ListFormat,
+}
+
+struct ListedPrompt {
+    prompt: Prompt,
+    times: PromptFileTimes,
+    stats: PromptStats,
+}
+
+pub fn run_list(storage: &FileStorage, options: ListOptions) -> Result<()> {
+    // Every --tag must match, along with the --tags query
+    let mut queries: Vec<TagQuery> = options.tags.into_iter().map(TagQuery::Tag).collect();
+    queries.extend(options.query);
+    let name_contains = options.name_contains.map(|text| text.to_lowercase());
+
+    let stats = storage.get_stats()?;
+    let mut prompts = Vec::new();
+    for prompt in storage.get_prompts_by_query(&TagQuery::All(queries))? {
+        if name_contains
+            .as_ref()
+            .is_some_and(|text| !prompt.metadata.name.to_lowercase().contains(text.as_str()))
+        {
+            continue;
+        }
+        prompts.push(ListedPrompt {
+            times: storage.get_prompt_times(&prompt.metadata.name)?,
+            stats: stats.get(&prompt.metadata.name),
+            prompt,
+        });
+    }
+
+    match options.sort {
+        // Pinned prompts first, in storage order
+        None => prompts.sort_by_key(|listed| !listed.prompt.metadata.pinned),
+        Some(ListSort::Name) => {
+            prompts.sort_by(|a, b| a.prompt.metadata.name.cmp(&b.prompt.metadata.name))
+        }
+        Some(ListSort::Created) => prompts.sort_by_key(|listed| Reverse(listed.times.created)),
+        Some(ListSort::Modified) => prompts.sort_by_key(|listed| Reverse(listed.times.modified)),
+        Some(ListSort::Used) => prompts.sort_by(|a, b| {
+            b.stats
+                .total_count()
+                .cmp(&a.stats.total_count())
+                .then_with(|| a.prompt.metadata.name.cmp(&b.prompt.metadata.name))
+        }),
+    }
+    prompts.truncate(options.limit.unwrap_or(usize::MAX));
+
+    match options.format {
+        ListFormat::Table => print_table(&prompts),
+        ListFormat::Json => print_entries(&prompts)?,
+        ListFormat::Names => {
+            for listed in &prompts {
+                println!("{}", listed.prompt.metadata.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn notes(prompt: &Prompt) -> String {
+    let mut notes = Vec::new();
+    if prompt.metadata.pinned {
+        notes.push("pinned".to_string());
+    }
+    if prompt.metadata.locked {
+        notes.push("locked".to_string());
+    }
+    match (prompt.metadata.deprecated, &prompt.metadata.superseded_by) {
+        (true, Some(replacement)) => notes.push(format!("deprecated, use '{}'", replacement)),
+        (true, None) => notes.push("deprecated".to_string()),
+        (false, _) => {}
+    }
+    notes.join(", ")
+}
+
+fn print_table(prompts: &[ListedPrompt]) {
+    let rows: Vec<[String; 4]> = prompts
+        .iter()
+        .map(|listed| {
+            let metadata = &listed.prompt.metadata;
+            [
+                metadata.name.clone(),
+                metadata.tags.join(","),
+                notes(&listed.prompt),
+                metadata.description.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let header = ["NAME", "TAGS", "NOTES", "DESCRIPTION"].map(String::from);
+
+    let mut widths = [0; 3];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = format!(
+            "{:<name$}  {:<tags$}  {:<notes$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            name = widths[0],
+            tags = widths[1],
+            notes = widths[2]
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+fn print_entries(prompts: &[ListedPrompt]) -> Result<()> {
+    let entries: Vec<_> = prompts
+        .iter()
+        .map(|listed| {
+            json!({
+                "name": listed.prompt.metadata.name,
+                "description": listed.prompt.metadata.description,
+                "tags": listed.prompt.metadata.tags,
+                "pinned": listed.prompt.metadata.pinned,
+                "locked": listed.prompt.metadata.locked,
+                "deprecated": listed.prompt.metadata.deprecated,
+                "superseded_by": listed.prompt.metadata.superseded_by,
+                "created": listed.times.created,
+                "modified": listed.times.modified,
+                "uses": listed.stats.total_count(),
+                "last_used": listed.stats.last_used,
+            })
+        })
+        .collect();
+    print_json(&entries)
+}
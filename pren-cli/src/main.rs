@@ -1,17 +1,121 @@
+mod add;
+mod arguments;
+mod auth;
+mod chat;
+mod check;
+mod clipboard;
+mod completions;
 mod config;
 mod constants;
+mod delete;
+mod doctor;
+mod editor;
+mod errors;
+mod eval;
+mod generate;
+mod history;
+mod hub;
+mod list;
+mod output;
+mod pick;
+mod preset;
+mod profile;
+mod quick;
+mod registry;
+mod render;
+#[cfg(feature = "server")]
+mod rpc;
+#[cfg(feature = "server")]
+mod server;
+mod show;
+mod tagging;
+mod tools;
+mod tour;
+mod transcript;
+#[cfg(feature = "tui")]
+mod tui;
+mod undo;
+mod usage;
+mod wizard;
 
-use crate::config::{PrenCliConfig, get_storage};
-use crate::constants::PREN_CLI;
+use crate::add::{AddOptions, run_add};
+use crate::arguments::{build_args_map, parse_key_val};
+#[cfg(feature = "keyring")]
+use crate::auth::run_auth_command;
+use crate::chat::run_chat;
+use crate::check::{check_content, check_prompt_name, run_check};
+use crate::completions::{Shell, install_completions};
+use crate::config::{ModelPrice, PrenCliConfig, find_project_dir, get_storage, run_config_command};
+#[cfg(feature = "server")]
+use crate::constants::SERVER_TOKEN_ENV;
+use crate::constants::{
+    DEFAULT_HUB_ENDPOINT, HUB_API_KEY_ENV, HUB_ENDPOINT_ENV, NO_CLIPBOARD_ENV, PIPELINES_DIR,
+    PREN_CLI, PROFILE_ENV, REGISTRY_ENV, STORAGE_PATH_ENV,
+};
+use crate::delete::run_delete;
+use crate::doctor::run_doctor;
+use crate::editor::{choose, run_edit};
+use crate::errors::report_error;
+use crate::eval::{EvalModel, run_eval, run_eval_history};
+use crate::generate::{GenerateOptions, run_generate};
+use crate::history::{record_history, record_usage, run_history, run_replay};
+use crate::hub::{HubClient, hub_api_key, run_hub_pull, run_hub_push};
+use crate::list::{ListFormat, ListOptions, ListSort, run_list};
+use crate::output::{OutputMode, RenderFormat, init_logging, print_json, print_warnings};
+use crate::pick::run_pick;
+use crate::preset::{apply_preset, run_preset_command};
+use crate::profile::run_profile_command;
+use crate::quick::{run_quick, run_script_filter};
+use crate::registry::{run_install, run_publish};
+use crate::render::{GetOptions, RenderOptions, run_get, run_render};
+#[cfg(feature = "server")]
+use crate::rpc::run_stdio_server;
+#[cfg(feature = "server")]
+use crate::server::{ServerOptions, run_server};
+use crate::show::{run_args, run_show};
+use crate::tagging::{confirm_tags, print_tag_suggestions, suggest_tags};
+use crate::tour::run_tour;
+use crate::transcript::{
+    run_transcript_export, run_transcript_list, run_transcript_resume, run_transcript_show,
+};
+#[cfg(feature = "tui")]
+use crate::tui::run_tui;
+use crate::undo::{run_undo, run_undo_list};
+use crate::usage::{print_tokens, record_tokens, run_usage, set_price};
+use crate::wizard::run_wizard;
 use anyhow::{Context, Result, bail};
-use arboard::Clipboard;
-use clap::{CommandFactory, Parser, Subcommand, ValueHint};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueHint};
 use clap_complete::CompleteEnv;
 use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
-use pren_core::llm::get_completions_content;
-use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
-use pren_core::storage::PromptStorage;
-use std::collections::{HashMap, HashSet};
+use pren_core::composition::{
+    CompositionNode, NodeKind, composition_tree, reverse_composition_tree,
+};
+use pren_core::diff::{DiffLine, diff_lines, diff_prompts, diff_rendered};
+use pren_core::embeddings::semantic_search;
+use pren_core::export::{ExportFormat, export_prompt};
+use pren_core::file_storage::{FileStorage, FileStorageError};
+use pren_core::history::output_hash;
+use pren_core::import::{ImportFormat, import_prompts};
+use pren_core::improve::{improvement_messages, parse_improvement};
+use pren_core::llm::{ModelSettings, Provider, get_chat_completion};
+use pren_core::messages::{RenderedMessage, Role, has_role_markers, split_messages};
+use pren_core::pack::PromptPack;
+use pren_core::pipeline::Pipeline;
+use pren_core::prompt::{Prompt, PromptTemplate, normalize_tag};
+use pren_core::scaffold::{builtin_scaffolds, scaffold};
+use pren_core::search::{SearchField, search_prompts};
+use pren_core::stats::{PromptStats, UsageKind};
+use pren_core::storage::{EmbeddingStorage, PromptStorage, StatsStorage, TranscriptStorage};
+use pren_core::tag_query::TagQuery;
+use pren_core::tokens::count_tokens;
+use pren_core::transcript::Transcript;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::{info, warn};
 
 // Custom completer for prompt names
 fn prompt_names(_current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
@@ -19,16 +123,138 @@ fn prompt_names(_current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
         return vec![CompletionCandidate::new("")];
     };
 
-    let prompts = storage.get_prompts();
-    match prompts {
-        Ok(prompts) => prompts
-            .iter()
-            .map(|prompt| CompletionCandidate::new(&prompt.metadata.name))
+    // Summaries come from the storage index, so completion stays fast with many prompts
+    match storage.get_prompt_summaries() {
+        Ok(summaries) => summaries
+            .into_iter()
+            .map(|summary| {
+                let mut help = summary.description.unwrap_or_default();
+                if !summary.tags.is_empty() {
+                    help = format!("{} [{}]", help, summary.tags.join(", "))
+                        .trim_start()
+                        .to_string();
+                }
+                // Pinned prompts are offered first
+                CompletionCandidate::new(&summary.name)
+                    .help((!help.is_empty()).then(|| help.into()))
+                    .display_order(Some(usize::from(!summary.pinned)))
+            })
             .collect(),
         Err(_) => vec![CompletionCandidate::new("")],
     }
 }
 
+// Custom completer for config keys
+fn config_keys(_current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
+    confy::load::<PrenCliConfig>(PREN_CLI, None)
+        .ok()
+        .and_then(|config| config.values().ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, _)| CompletionCandidate::new(key))
+        .collect()
+}
+
+// Custom completer for the names of model presets
+fn preset_names(_current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
+    confy::load::<PrenCliConfig>(PREN_CLI, None)
+        .map(|config| config.presets.into_keys().collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+// Custom completer for scaffold kinds, with their descriptions
+fn scaffold_kinds(_current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
+    builtin_scaffolds()
+        .prompts
+        .iter()
+        .map(|prompt| {
+            CompletionCandidate::new(&prompt.metadata.name)
+                .help(prompt.metadata.description.clone().map(Into::into))
+        })
+        .collect()
+}
+
+fn set_pinned(storage: &FileStorage, name: &str, pinned: bool, output: OutputMode) -> Result<()> {
+    let mut prompt = storage.get_prompt(name)?;
+    prompt.metadata.pinned = pinned;
+    storage.save_prompt(&prompt)?;
+    if output == OutputMode::Json {
+        return print_json(&json!({ "name": name, "pinned": pinned }));
+    }
+    println!(
+        "Prompt '{}' {}",
+        name,
+        if pinned { "pinned" } else { "unpinned" }
+    );
+    Ok(())
+}
+
+fn set_locked(storage: &FileStorage, name: &str, locked: bool, output: OutputMode) -> Result<()> {
+    let mut prompt = storage.get_prompt(name)?;
+    prompt.metadata.locked = locked;
+    storage.save_prompt_overriding_lock(&prompt)?;
+    if output == OutputMode::Json {
+        return print_json(&json!({ "name": name, "locked": locked }));
+    }
+    println!(
+        "Prompt '{}' {}",
+        name,
+        if locked { "locked" } else { "unlocked" }
+    );
+    Ok(())
+}
+
+fn print_diff_lines(lines: &[DiffLine]) {
+    for line in lines {
+        match line {
+            DiffLine::Unchanged(text) => println!("  {}", text),
+            DiffLine::Removed(text) => println!("- {}", text),
+            DiffLine::Added(text) => println!("+ {}", text),
+        }
+    }
+}
+
+/// Highlights ranges of a text in bold red, or between brackets if `color` is false.
+fn highlight(text: &str, ranges: &[std::ops::Range<usize>], color: bool) -> String {
+    let (start, end) = if color {
+        ("\x1b[1;31m", "\x1b[0m")
+    } else {
+        ("[", "]")
+    };
+    let mut highlighted = String::new();
+    let mut last_end = 0;
+    for range in ranges {
+        highlighted.push_str(&text[last_end..range.start]);
+        highlighted.push_str(start);
+        highlighted.push_str(&text[range.clone()]);
+        highlighted.push_str(end);
+        last_end = range.end;
+    }
+    highlighted.push_str(&text[last_end..]);
+    highlighted
+}
+
+fn print_composition_tree(nodes: &[CompositionNode], indent: &str) {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index == nodes.len() - 1;
+        let mut label = match node.kind {
+            NodeKind::Prompt => node.name.clone(),
+            NodeKind::Variable => format!("? (from {})", node.name),
+            NodeKind::Missing => format!("{} (missing)", node.name),
+            NodeKind::Cycle => format!("{} (cycle)", node.name),
+        };
+        if node.optional {
+            label.push_str(" (optional)");
+        }
+        println!("{}{} {}", indent, if last { "└──" } else { "├──" }, label);
+        let child_indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+        print_composition_tree(&node.children, &child_indent);
+    }
+}
+
 // Custom completer for template arguments
 fn prompt_args(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
     let current_str = current.to_string_lossy();
@@ -83,7 +309,7 @@ fn prompt_args(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
     // If user is in the middle of typing key=value, provide the key suggestions
     if let Some((partial_key, _)) = current_str.split_once('=') {
         let partial_key_string = partial_key.to_string();
-        if prompt_args.contains(&&partial_key_string) {
+        if prompt_args.contains(&partial_key_string) {
             return vec![CompletionCandidate::new(current_str.to_string())];
         }
     }
@@ -105,9 +331,34 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    // The storage path where pren prompts are stored
-    #[arg(long, short = 'p')]
-    storage_path: Option<String>,
+    /// Directory where prompts are stored, instead of the one of the profile in use
+    #[arg(long, short = 'p', global = true, env = STORAGE_PATH_ENV, value_hint = ValueHint::DirPath)]
+    storage_path: Option<PathBuf>,
+
+    /// Ignore the prompts of the project in the current directory, if any
+    #[arg(long, global = true)]
+    no_project: bool,
+
+    /// Profile to use instead of the active one
+    #[arg(long, global = true, env = PROFILE_ENV)]
+    profile: Option<String>,
+
+    /// Print results as text or as JSON, for scripts and editors
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: OutputMode,
+
+    /// Log what pren does to stderr. Repeat for more details: -vv logs the prompt files
+    /// read and written, templates parsed and model requests sent
+    #[arg(short = 'v', long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Only log errors, without warnings
+    #[arg(short = 'q', long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Never use the clipboard: print what would be copied, and read stdin instead of it
+    #[arg(long, global = true, env = NO_CLIPBOARD_ENV)]
+    no_clipboard: bool,
 }
 
 #[derive(Subcommand)]
@@ -119,158 +370,2278 @@ pub enum Commands {
         description: Option<String>,
         #[arg(short = 't', long, value_delimiter = ',')]
         tags: Vec<String>,
-        #[arg(short = 'c', long)]
-        content: String,
+        /// Content of the prompt. Read from --file or stdin if not given
+        #[arg(short = 'c', long, conflicts_with = "file")]
+        content: Option<String>,
+        /// File to read the content of the prompt from
+        #[arg(short = 'f', long, value_hint = ValueHint::FilePath)]
+        file: Option<PathBuf>,
+        /// Take the content of the prompt from the clipboard
+        #[arg(long, conflicts_with_all = ["content", "file"])]
+        from_clipboard: bool,
+        /// Author of the prompt, defaulting to the configured author
+        #[arg(long, value_hint = ValueHint::Other)]
+        author: Option<String>,
+        /// Where the prompt comes from
+        #[arg(long, value_hint = ValueHint::Url)]
+        source_url: Option<String>,
+        /// License the prompt is shared under
+        #[arg(long, value_hint = ValueHint::Other)]
+        license: Option<String>,
         #[arg(short = 'o', long)]
         overwrite: bool,
+        /// Overwrite the prompt even if it's locked
+        #[arg(long, requires = "overwrite")]
+        override_lock: bool,
+        /// Save the prompt even if it looks like it contains secrets
+        #[arg(long)]
+        skip_checks: bool,
+        /// Create empty prompts for the referenced prompts that don't exist yet
+        #[arg(long)]
+        create_stubs: bool,
+        /// Ask the configured model to suggest tags for the prompt before saving it
+        #[arg(long)]
+        auto_tag: bool,
+        /// Add the suggested tags without asking
+        #[arg(short = 'y', long, requires = "auto_tag")]
+        yes: bool,
     },
+    /// Create a prompt step by step, writing its content in $VISUAL or $EDITOR
+    New,
+    /// Create a prompt from a built-in scaffold, or list the scaffolds if none is given
+    Scaffold {
+        /// The scaffold to start from, e.g. code-review
+        #[arg(add = ArgValueCompleter::new(scaffold_kinds))]
+        kind: Option<String>,
+        /// Name of the new prompt, defaulting to the name of the scaffold
+        #[arg(short = 'n', long, requires = "kind", value_hint = ValueHint::Other)]
+        name: Option<String>,
+        /// Open the new prompt in $VISUAL or $EDITOR
+        #[arg(short = 'e', long, requires = "kind")]
+        edit: bool,
+    },
+    /// Show a prompt's metadata, arguments, references and content
     Show {
         #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
         name: String,
+        /// Also show the prompt rendered with the arguments of its first test case and the
+        /// defaults, leaving placeholders for the other arguments
+        #[arg(short = 'r', long)]
+        rendered: bool,
     },
-    Render {
+    /// List the arguments of a prompt, with their defaults and descriptions
+    Args {
         #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
         name: String,
-        #[arg(short = 'a', long, value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+    },
+    Render {
+        #[arg(short = 'n', long, required_unless_present_any = ["interactive", "names"], add = ArgValueCompleter::new(prompt_names))]
+        name: Option<String>,
+        /// Render several prompts with the same arguments and join them, e.g. a persona,
+        /// a task and its constraints
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["name", "plan", "dry_run", "trace"], add = ArgValueCompleter::new(prompt_names))]
+        names: Vec<String>,
+        /// Separator between the prompts rendered with --names, a blank line by default.
+        /// Accepts \n and \t escapes
+        #[arg(long, requires = "names")]
+        sep: Option<String>,
+        /// Template arguments as KEY=value pairs. Use KEY=@path to read the value from a file,
+        /// KEY=@- to read it from stdin, or KEY=@@text for a value starting with '@'
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
         args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+        /// Use the content piped to stdin as the value of this argument, e.g.
+        /// `git diff | pren generate -g commit-msg --arg-stdin diff`
+        #[arg(long, value_name = "KEY")]
+        arg_stdin: Option<String>,
+        /// File with arguments (JSON, YAML or TOML), overridden by PREN_ARG_* environment
+        /// variables, --args-json and --args
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        context: Option<PathBuf>,
+        /// Pick the prompt with a fuzzy finder if no name is given, and ask for the values
+        /// of missing arguments
+        #[arg(short = 'i', long)]
+        interactive: bool,
         #[arg(short = 'c', long)]
         copy: bool,
+        /// Print a JSON trace of the render (parts, referenced prompts and arguments used)
+        #[arg(long, conflicts_with = "copy")]
+        plan: bool,
+        /// Show the referenced prompts, the provided and missing arguments and the estimated
+        /// token count, without rendering
+        #[arg(long, conflicts_with_all = ["copy", "plan"])]
+        dry_run: bool,
+        /// Show which prompt each part of the output comes from: the tree of prompts resolved,
+        /// in the order they were resolved, with the byte range of the output of each
+        #[arg(long, conflicts_with_all = ["copy", "plan", "dry_run"])]
+        trace: bool,
+        /// Write the result to a file instead of stdout
+        #[arg(long, value_hint = ValueHint::FilePath, conflicts_with_all = ["copy", "plan", "dry_run", "trace"])]
+        out: Option<PathBuf>,
+        /// Print the prompt as it's rendered instead of once it's complete, so large
+        /// compositions show up sooner. A render that fails leaves what was printed so far
+        #[arg(long, conflicts_with_all = ["names", "copy", "plan", "dry_run", "trace", "out", "render_as"])]
+        stream: bool,
+        /// Append the result to the --out file instead of replacing it
+        #[arg(long, requires = "out")]
+        append: bool,
+        /// Render the prompt as text, or as the body of an OpenAI-compatible chat completion
+        /// request, with its messages split by role and its model settings (openai-json)
+        #[arg(long = "as", value_enum, value_name = "FORMAT", default_value_t, conflicts_with_all = ["names", "plan", "dry_run", "trace"])]
+        render_as: RenderFormat,
     },
     Get {
+        #[arg(short = 'n', long, required_unless_present = "interactive", add = ArgValueCompleter::new(prompt_names))]
+        name: Option<String>,
+        /// Template arguments as KEY=value pairs. Use KEY=@path to read the value from a file,
+        /// KEY=@- to read it from stdin, or KEY=@@text for a value starting with '@'
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+        args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+        /// Use the content piped to stdin as the value of this argument, e.g.
+        /// `git diff | pren generate -g commit-msg --arg-stdin diff`
+        #[arg(long, value_name = "KEY")]
+        arg_stdin: Option<String>,
+        /// File with arguments (JSON, YAML or TOML), overridden by PREN_ARG_* environment
+        /// variables, --args-json and --args
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        context: Option<PathBuf>,
+        /// Pick the prompt with a fuzzy finder if no name is given, and ask for the values
+        /// of missing arguments
+        #[arg(short = 'i', long)]
+        interactive: bool,
+    },
+    /// Pick a prompt with a fuzzy finder, fill in its arguments, then print and copy it
+    Pick,
+    /// Render a prompt with positional arguments and print nothing but the result, for
+    /// launchers like Alfred and Raycast
+    Quick {
+        /// The prompt to render, or with --script-filter, the query
+        #[arg(required_unless_present = "script_filter", add = ArgValueCompleter::new(prompt_names))]
+        name: Option<String>,
+        /// Arguments as KEY=value, or values for the prompt's arguments in the order they
+        /// appear in
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Print Alfred script filter JSON for the query: the matching prompts, or the
+        /// prompt it names rendered with the rest of the query as its first argument
+        #[arg(long)]
+        script_filter: bool,
+    },
+    /// Browse, render and generate prompts in a terminal UI
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Serve the prompts over an HTTP API, to list, search, edit and render them, and
+    /// optionally generate with the configured model, along with a web UI using it, over
+    /// gRPC, or over JSON-RPC on stdio for editor plugins
+    #[cfg(feature = "server")]
+    Serve {
+        /// Serve JSON-RPC on stdin and stdout, one message per line, with the list, get,
+        /// render and validate methods, instead of serving over the network
+        #[arg(long, conflicts_with_all = ["http", "grpc", "token", "allow_generate"])]
+        stdio: bool,
+        /// Address to serve the HTTP API on, e.g. 127.0.0.1:8080, or :8080 for every
+        /// interface. Defaults to 127.0.0.1:8080 unless --grpc is given
+        #[arg(long, value_hint = ValueHint::Other)]
+        http: Option<String>,
+        /// Address to serve the gRPC service on, e.g. :50051
+        #[arg(long, value_hint = ValueHint::Other)]
+        grpc: Option<String>,
+        /// Token clients must send as `Authorization: Bearer <token>`
+        #[arg(long, env = SERVER_TOKEN_ENV, hide_env_values = true)]
+        token: Option<String>,
+        /// Let clients generate with the configured model, which uses its API key
+        #[arg(long)]
+        allow_generate: bool,
+        /// Keep clients from editing prompts
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// List prompts, pinned ones first unless sorted otherwise
+    List {
+        /// Only list prompts with this tag. Can be repeated to require several tags
+        #[arg(long)]
+        tag: Vec<String>,
+        /// Only list prompts whose tags match a query, e.g. "rust AND review NOT draft"
+        #[arg(short = 't', long)]
+        tags: Option<TagQuery>,
+        /// Only list prompts whose name contains this text, ignoring case
+        #[arg(long)]
+        name_contains: Option<String>,
+        #[arg(short = 's', long, value_enum)]
+        sort: Option<ListSort>,
+        /// Maximum number of prompts to list
+        #[arg(short = 'l', long)]
+        limit: Option<usize>,
+        #[arg(short = 'f', long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
+    /// Search prompts' names, descriptions, tags and content, ignoring case
+    Search {
+        /// Words to search for. Every word must be found in a matching prompt
+        #[arg(required = true, num_args = 1..)]
+        query: Vec<String>,
+        /// Only search the content of prompts
+        #[arg(long, conflicts_with_all = ["name_only", "semantic"])]
+        content_only: bool,
+        /// Only search the names of prompts
+        #[arg(long, conflicts_with = "semantic")]
+        name_only: bool,
+        /// Find the prompts closest in meaning to the query, comparing their embeddings
+        /// instead of their words
+        #[arg(long)]
+        semantic: bool,
+        /// Maximum number of prompts found by semantic search
+        #[arg(short = 'l', long, default_value_t = 10, requires = "semantic")]
+        limit: usize,
+        /// Model embedding the prompts and the query, overriding the configured one
+        #[arg(long, requires = "semantic")]
+        embedding_model: Option<String>,
+        /// Model preset to use, replacing the model settings of the profile in use
+        #[arg(long, requires = "semantic", add = ArgValueCompleter::new(preset_names))]
+        preset: Option<String>,
+        /// API serving the embedding model, overriding the configured one: openai (or any
+        /// OpenAI-compatible server), ollama or gemini
+        #[arg(
+            long,
+            requires = "semantic",
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// URL of the API serving the embedding model, overriding the configured one
+        #[arg(long, requires = "semantic", value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+    },
+    /// List the tags in use, with the number of prompts using each
+    Tags,
+    /// Manage tags across prompts
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+    /// Pin a prompt, so it's listed and completed first
+    Pin {
         #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
         name: String,
-        #[arg(short = 'a', long, value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
-        args: Vec<(String, String)>,
     },
-    List,
-    Delete {
+    /// Unpin a prompt
+    Unpin {
         #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
         name: String,
+    },
+    /// Lock a prompt, so it can't be overwritten or deleted by accident
+    Lock {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+    },
+    /// Unlock a prompt
+    Unlock {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+    },
+    /// Edit a prompt's file in $VISUAL or $EDITOR, validating it before saving
+    Edit {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        /// Edit the prompt even if it's locked
+        #[arg(long)]
+        override_lock: bool,
+    },
+    /// Rename a prompt, offering to update the prompts referencing it
+    Rename {
+        #[arg(long, add = ArgValueCompleter::new(prompt_names))]
+        from: String,
+        #[arg(long, value_hint = ValueHint::Other)]
+        to: String,
+        /// Update the references to the prompt without asking
+        #[arg(long, conflicts_with = "keep_references")]
+        rewrite_references: bool,
+        /// Leave the references to the prompt unchanged without asking
+        #[arg(long)]
+        keep_references: bool,
+    },
+    /// Show the differences between two prompts, or between a prompt and a previous version
+    Diff {
+        #[arg(add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        #[arg(required_unless_present = "version", add = ArgValueCompleter::new(prompt_names))]
+        other: Option<String>,
+        /// Compare the prompt with a previous version instead, kept when it was overwritten
+        /// or deleted: 1 is the version before the current one, 2 the one before it...
+        #[arg(long, conflicts_with = "other")]
+        version: Option<usize>,
+        /// Also diff the prompts' rendered output, using these arguments
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+        args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, also diffing the rendered output
+        #[arg(long)]
+        args_json: Option<String>,
+    },
+    /// Delete prompts by name, glob pattern or tag
+    Delete {
+        /// Names of the prompts, or glob patterns such as 'draft-*'. Can be repeated
+        #[arg(short = 'n', long, value_delimiter = ',', required_unless_present = "tag", add = ArgValueCompleter::new(prompt_names))]
+        name: Vec<String>,
+        /// Delete the prompts with this tag. Can be repeated to delete the prompts with any
+        /// of the tags
+        #[arg(short = 't', long)]
+        tag: Vec<String>,
         #[arg(short = 'f', long, default_value = "false")]
         force: bool,
+        /// Delete the prompts even if they're locked
+        #[arg(long)]
+        override_lock: bool,
+    },
+    /// Undo the last delete or overwrite of a prompt
+    Undo {
+        /// List the operations that can be undone instead, most recent first
+        #[arg(short = 'l', long)]
+        list: bool,
     },
     Generate {
         #[arg(short = 'g', long, add = ArgValueCompleter::new(prompt_names))]
         generation_prompt: String,
-        #[arg(short = 'a', long, value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+        /// Template arguments as KEY=value pairs. Use KEY=@path to read the value from a file,
+        /// KEY=@- to read it from stdin, or KEY=@@text for a value starting with '@'
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
         args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+        /// Use the content piped to stdin as the value of this argument, e.g.
+        /// `git diff | pren generate -g commit-msg --arg-stdin diff`
+        #[arg(long, value_name = "KEY")]
+        arg_stdin: Option<String>,
+        /// File with arguments (JSON, YAML or TOML), overridden by PREN_ARG_* environment
+        /// variables, --args-json and --args
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        context: Option<PathBuf>,
+        /// Model to use, overriding the prompt's preferred model and the configured one
+        #[arg(short = 'm', long)]
+        model: Option<String>,
+        /// Sampling temperature, overriding the prompt's preference
+        #[arg(long)]
+        temperature: Option<f64>,
+        /// Maximum number of tokens to generate, overriding the prompt's preference
+        #[arg(long)]
+        max_tokens: Option<u64>,
+        /// Stop sequences, overriding the prompt's preference
+        #[arg(long, value_delimiter = ',')]
+        stop: Vec<String>,
+        /// Model preset to use, replacing the model settings of the profile in use
+        #[arg(long, add = ArgValueCompleter::new(preset_names))]
+        preset: Option<String>,
+        /// API serving the model, overriding the configured one: openai (or any
+        /// OpenAI-compatible server), anthropic, ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// URL of the API to send the prompt to, overriding the configured one
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+        /// Validate the response against the prompt's output format, asking the model to fix it
+        /// if it doesn't match
+        #[arg(long)]
+        validate: bool,
+        /// Maximum number of responses to ask the model for when they fail validation or the
+        /// prompt's guardrails, overriding the prompt's `max_attempts`
+        #[arg(long)]
+        max_attempts: Option<usize>,
+        /// Configured tools the model can call, in addition to the ones declared by the prompt
+        #[arg(long, value_delimiter = ',', conflicts_with = "validate")]
+        tool: Vec<String>,
+        /// Run the tool calls of the model with the commands configured for the tools, sending
+        /// their results back until the model replies. Calls of other tools are printed
+        #[arg(long, conflicts_with = "validate")]
+        run_tools: bool,
+        /// Maximum number of responses with tool calls to run before stopping
+        #[arg(long, default_value_t = 10, requires = "run_tools")]
+        max_tool_steps: usize,
+        /// Send the prompt even if it looks like it contains secrets
+        #[arg(long)]
+        skip_checks: bool,
+        /// Write the result to a file instead of stdout
+        #[arg(long, alias = "save-output", value_hint = ValueHint::FilePath)]
+        out: Option<PathBuf>,
+        /// Append the result to the --out file instead of replacing it
+        #[arg(long, requires = "out")]
+        append: bool,
+        /// Save the result as a new prompt with this name instead of printing it
+        #[arg(long, value_hint = ValueHint::Other, conflicts_with = "out")]
+        save_as: Option<String>,
+        /// Tags of the prompt saved with --save-as
+        #[arg(short = 't', long, value_delimiter = ',', requires = "save_as")]
+        tags: Vec<String>,
+        /// Description of the prompt saved with --save-as
+        #[arg(short = 'd', long, value_hint = ValueHint::Other, requires = "save_as")]
+        description: Option<String>,
+        /// Replace the prompt saved with --save-as if it already exists
+        #[arg(short = 'o', long, requires = "save_as")]
+        overwrite: bool,
+        /// Save the prompt and the response as a transcript, to continue the conversation
+        /// later with `pren transcript resume`
+        #[arg(long)]
+        transcript: bool,
+    },
+    /// Chat with the model, using a rendered prompt as the system prompt
+    Chat {
+        /// The prompt to use as the system prompt. Prompts with role markers can also
+        /// start the conversation with example messages
+        #[arg(short = 's', long, add = ArgValueCompleter::new(prompt_names))]
+        system: String,
+        /// Template arguments as KEY=value pairs. Use KEY=@path to read the value from a file,
+        /// or KEY=@@text for a value starting with '@'
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+        args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+        /// File with arguments (JSON, YAML or TOML), overridden by PREN_ARG_* environment
+        /// variables, --args-json and --args
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        context: Option<PathBuf>,
+        /// Model to use, overriding the prompt's preferred model and the configured one
+        #[arg(short = 'm', long)]
+        model: Option<String>,
+        /// Sampling temperature, overriding the prompt's preference
+        #[arg(long)]
+        temperature: Option<f64>,
+        /// Maximum number of tokens to generate per response, overriding the prompt's
+        /// preference
+        #[arg(long)]
+        max_tokens: Option<u64>,
+        /// Model preset to use, replacing the model settings of the profile in use
+        #[arg(long, add = ArgValueCompleter::new(preset_names))]
+        preset: Option<String>,
+        /// API serving the model, overriding the configured one: openai (or any
+        /// OpenAI-compatible server), anthropic, ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// URL of the API to chat with, overriding the configured one
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+        /// Save the conversation to this file when it ends, with role markers
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        save: Option<PathBuf>,
+        /// Don't save the conversation as a transcript when it ends
+        #[arg(long)]
+        no_transcript: bool,
+        /// Send the prompt even if it looks like it contains secrets
+        #[arg(long)]
+        skip_checks: bool,
+    },
+    /// Ask the model to critique a prompt and improve it, showing the changes before
+    /// replacing the prompt
+    Improve {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        /// What to improve, e.g. "more concise" or "better suited to small models"
+        #[arg(long, value_hint = ValueHint::Other)]
+        goal: Option<String>,
+        /// Model to use, overriding the configured one
+        #[arg(short = 'm', long)]
+        model: Option<String>,
+        /// Model preset to use, replacing the model settings of the profile in use
+        #[arg(long, add = ArgValueCompleter::new(preset_names))]
+        preset: Option<String>,
+        /// API serving the model, overriding the configured one: openai (or any
+        /// OpenAI-compatible server), anthropic, ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// URL of the API to send the prompt to, overriding the configured one
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+        /// Save the improved prompt as a new prompt with this name instead of replacing it
+        #[arg(long, value_hint = ValueHint::Other)]
+        save_as: Option<String>,
+        /// Replace the prompt without asking
+        #[arg(short = 'y', long, conflicts_with = "save_as")]
+        yes: bool,
+        /// Send the prompt even if it looks like it contains secrets
+        #[arg(long)]
+        skip_checks: bool,
     },
     Info,
+    /// Show the tree of the prompts a prompt includes through prompt references
+    Tree {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        /// Show the prompts including the prompt instead
+        #[arg(short = 'r', long)]
+        reverse: bool,
+    },
+    /// Check the storage and the model config for problems, suggesting how to fix them
+    Doctor,
+    /// Validate prompt files: frontmatter, template syntax, references and names, with
+    /// file:line diagnostics. Fails on errors, e.g. as a git pre-commit hook
+    Check {
+        /// Check the prompt files staged in the git repo of the current directory, as staged
+        #[arg(long, conflicts_with = "paths")]
+        staged: bool,
+        /// Files to check instead of every prompt
+        #[arg(value_hint = ValueHint::FilePath)]
+        paths: Vec<PathBuf>,
+    },
+    /// Count the tokens of a rendered prompt
+    Tokens {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        /// Template arguments as KEY=value pairs. Use KEY=@path to read the value from a file,
+        /// KEY=@- to read it from stdin, or KEY=@@text for a value starting with '@'
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+        args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+    },
+    /// Run the test cases declared in prompts' frontmatter
+    Test {
+        #[arg(short = 'n', long, required_unless_present = "all", conflicts_with = "all", add = ArgValueCompleter::new(prompt_names))]
+        name: Option<String>,
+        /// Test every prompt that declares test cases
+        #[arg(long)]
+        all: bool,
+    },
+    /// Send the eval cases declared in prompts' frontmatter to the model, grading the
+    /// responses, and show how the pass rates changed since the last run
+    Eval {
+        #[arg(short = 'n', long, required_unless_present_any = ["all", "history"], conflicts_with = "all", add = ArgValueCompleter::new(prompt_names))]
+        name: Option<String>,
+        /// Evaluate every prompt that declares eval cases
+        #[arg(long)]
+        all: bool,
+        /// Prompt scoring responses against the criteria of eval cases, rendered with the
+        /// `criteria`, `input` and `output` arguments, instead of the built-in judge
+        #[arg(long, add = ArgValueCompleter::new(prompt_names))]
+        judge: Option<String>,
+        /// Model to use, overriding the prompts' preferred models and the configured one
+        #[arg(short = 'm', long)]
+        model: Option<String>,
+        /// Model preset to use, replacing the model settings of the profile in use
+        #[arg(long, add = ArgValueCompleter::new(preset_names))]
+        preset: Option<String>,
+        /// API serving the model, overriding the configured one: openai (or any
+        /// OpenAI-compatible server), anthropic, ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// URL of the API to send the prompts to, overriding the configured one
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+        /// Show the past runs, of the prompt if one is given, instead of running the evals
+        #[arg(long, conflicts_with_all = ["all", "judge"])]
+        history: bool,
+        /// Send the prompts even if they look like they contain secrets
+        #[arg(long)]
+        skip_checks: bool,
+    },
+    /// Guided tour of pren's main features in a temporary sandbox
+    Tour,
+    /// Show how often prompts are used, most used first
+    Stats {
+        /// Show the least used prompts first instead
+        #[arg(long)]
+        least: bool,
+        /// Maximum number of prompts to show
+        #[arg(short = 'l', long)]
+        limit: Option<usize>,
+    },
+    /// Show the tokens used by each model, and their cost for models with a price
+    Usage {
+        #[command(subcommand)]
+        command: Option<UsageCommands>,
+    },
+    /// Show the prompts rendered and generated recently, newest first
+    #[command(args_conflicts_with_subcommands = true)]
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
+        /// Only show the history of this prompt
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: Option<String>,
+        /// Maximum number of entries to show
+        #[arg(short = 'l', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// List the saved transcripts of conversations, most recently updated first
+    #[command(args_conflicts_with_subcommands = true)]
+    Transcript {
+        #[command(subcommand)]
+        command: Option<TranscriptCommands>,
+        /// Only list the transcripts started from this prompt
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: Option<String>,
+        /// Maximum number of transcripts to list
+        #[arg(short = 'l', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Run a pipeline, rendering (and optionally generating with) its prompts in order
+    Run {
+        /// Name of a pipeline in the storage's pipelines directory, or path to a pipeline file
+        #[arg(value_hint = ValueHint::FilePath)]
+        pipeline: String,
+        /// Template arguments as KEY=value pairs. Use KEY=@path to read the value from a file,
+        /// KEY=@- to read it from stdin, or KEY=@@text for a value starting with '@'
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',')]
+        args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+    },
+    /// Import prompts from other formats, such as LangChain templates or Fabric patterns
+    Import {
+        /// The format of the prompts
+        #[arg(
+            short = 'f',
+            long,
+            value_parser = PossibleValuesParser::new(ImportFormat::NAMES)
+                .try_map(|format| format.parse::<ImportFormat>())
+        )]
+        format: ImportFormat,
+        /// A prompt file, or a directory searched for prompt files
+        #[arg(value_hint = ValueHint::AnyPath)]
+        path: PathBuf,
+        /// Replace stored prompts with the same names
+        #[arg(short = 'o', long)]
+        overwrite: bool,
+    },
+    /// Export a prompt to another template format, rendering everything but its arguments
+    Export {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        /// The format to export to
+        #[arg(
+            short = 'f',
+            long,
+            value_parser = PossibleValuesParser::new(ExportFormat::NAMES)
+                .try_map(|format| format.parse::<ExportFormat>())
+        )]
+        format: ExportFormat,
+        /// Arguments to render into the prompt as KEY=value pairs, instead of leaving them
+        /// as variables
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+        args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+    },
+    /// Validate and install prompt packs
+    Pack {
+        #[command(subcommand)]
+        command: PackCommands,
+    },
+    /// Install a pack from a registry, at the latest version meeting a requirement. Its
+    /// prompts are namespaced as <pack>.<prompt>
+    Install {
+        /// The pack, as name or name@requirement, e.g. code-review@^1.2
+        pack: String,
+        /// The URL of the registry, or its directory
+        #[arg(long, env = REGISTRY_ENV)]
+        registry: String,
+        #[arg(short = 'o', long)]
+        overwrite: bool,
+    },
+    /// Publish a pack directory to a registry directory, which can then be served over HTTPS
+    Publish {
+        #[arg(value_hint = ValueHint::DirPath, default_value = ".")]
+        path: PathBuf,
+        /// The directory of the registry, created if needed
+        #[arg(long, value_hint = ValueHint::DirPath)]
+        registry_dir: PathBuf,
+    },
+    /// Push prompts to and pull them from the LangChain Hub
+    Hub {
+        #[command(subcommand)]
+        command: HubCommands,
+        /// The LangSmith API key the Hub is used with
+        #[arg(long, global = true, env = HUB_API_KEY_ENV, hide_env_values = true)]
+        api_key: Option<String>,
+        /// The URL of the Hub API
+        #[arg(long, global = true, env = HUB_ENDPOINT_ENV, default_value = DEFAULT_HUB_ENDPOINT)]
+        endpoint: String,
+    },
+    /// Manage profiles: prompt collections with their own storage and model config
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// Store API keys in the OS keyring instead of the config file
+    #[cfg(feature = "keyring")]
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+    /// Manage model presets: named model settings to switch between with --preset
+    Preset {
+        #[command(subcommand)]
+        command: PresetCommands,
+    },
+    /// Set up shell completions
+    Completions {
+        #[command(subcommand)]
+        command: CompletionsCommands,
+    },
+    /// View and change the configuration without editing the config file by hand
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
 }
 
-/// Parse a single key-value pair
-fn parse_key_val(s: &str) -> Result<(String, String), String> {
-    let pos = s
-        .find('=')
-        .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{}`", s))?;
-    Ok((s[..pos].to_string(), s[pos + 1..].to_string()))
+#[derive(Subcommand)]
+pub enum CompletionsCommands {
+    /// Install completions for a shell, or for the current one ($SHELL) if none is given
+    Install {
+        #[arg(value_enum)]
+        shell: Option<Shell>,
+        /// Print the line registering the completions instead, to add it yourself
+        #[arg(long)]
+        print: bool,
+    },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let config: PrenCliConfig = confy::load(PREN_CLI, None)
-        .with_context(|| format!("Unexpected error while loading config for {}", PREN_CLI))?;
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the value of a config key, e.g. model_config.model_name
+    Get {
+        #[arg(add = ArgValueCompleter::new(config_keys))]
+        key: String,
+    },
+    /// Set the value of a config key
+    Set {
+        #[arg(add = ArgValueCompleter::new(config_keys))]
+        key: String,
+        #[arg(value_hint = ValueHint::Other)]
+        value: String,
+    },
+    /// List every config key with its value, hiding API keys
+    List,
+    /// Print the path of the config file
+    Path,
+    /// Edit the config file in $VISUAL or $EDITOR, validating it before saving
+    Edit,
+}
+
+#[cfg(feature = "keyring")]
+#[derive(Subcommand)]
+pub enum AuthCommands {
+    /// Store the API key of a provider, read from a hidden prompt or stdin
+    Set {
+        /// The provider of the key, defaulting to the one of the profile in use
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+    },
+    /// Remove the API key of a provider
+    Remove {
+        /// The provider of the key, defaulting to the one of the profile in use
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+    },
+}
 
+#[derive(Subcommand)]
+pub enum PresetCommands {
+    /// Add a preset, or replace it. Settings that aren't given are taken from the profile
+    /// in use
+    Add {
+        #[arg(value_hint = ValueHint::Other)]
+        name: String,
+        #[arg(long)]
+        model_name: Option<String>,
+        #[arg(long)]
+        api_key: Option<String>,
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+        /// API serving the model: openai (or any OpenAI-compatible server), anthropic,
+        /// ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+    },
+    /// List the presets with their settings, hiding API keys
+    List,
+    /// Remove a preset
+    Remove {
+        #[arg(add = ArgValueCompleter::new(preset_names))]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommands {
+    /// Add a profile. Model settings that aren't given are taken from the default config
+    Add {
+        #[arg(value_hint = ValueHint::Other)]
+        name: String,
+        /// Directory where the profile's prompts are stored
+        #[arg(long, value_hint = ValueHint::DirPath)]
+        path: PathBuf,
+        #[arg(long)]
+        model_name: Option<String>,
+        #[arg(long)]
+        api_key: Option<String>,
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+        /// API serving the model: openai (or any OpenAI-compatible server), anthropic,
+        /// ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// Read the prompts as the notes of an Obsidian vault: frontmatter is optional and
+        /// [[wikilinks]] are prompt references
+        #[arg(long)]
+        vault: bool,
+    },
+    /// List the profiles, marking the active one
+    List,
+    /// Make a profile the active one
+    Use {
+        #[arg(value_hint = ValueHint::Other)]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TagCommands {
+    /// Rename a tag in every prompt using it
+    Rename {
+        #[arg(value_hint = ValueHint::Other)]
+        old: String,
+        #[arg(value_hint = ValueHint::Other)]
+        new: String,
+    },
+    /// Ask the model to suggest tags for a prompt, preferring the tags already in use
+    Suggest {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        /// Model to use, overriding the configured one
+        #[arg(short = 'm', long)]
+        model: Option<String>,
+        /// Model preset to use, replacing the model settings of the profile in use
+        #[arg(long, add = ArgValueCompleter::new(preset_names))]
+        preset: Option<String>,
+        /// API serving the model, overriding the configured one: openai (or any
+        /// OpenAI-compatible server), anthropic, ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// URL of the API to send the prompt to, overriding the configured one
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+        /// Add the suggested tags without asking
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Send the prompt even if it looks like it contains secrets
+        #[arg(long)]
+        skip_checks: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommands {
+    /// Render a history entry again with the same arguments, sending it to the model
+    /// again if it was a generation
+    Replay {
+        /// The id of the entry, as shown by `pren history`
+        id: u64,
+        /// Send the prompt even if it looks like it contains secrets
+        #[arg(long)]
+        skip_checks: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TranscriptCommands {
+    /// Print a transcript as markdown
+    Show {
+        /// The id of the transcript, as shown by `pren transcript`
+        id: u64,
+    },
+    /// Write a transcript to a markdown file
+    Export {
+        /// The id of the transcript, as shown by `pren transcript`
+        id: u64,
+        /// The file to write
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+    },
+    /// Continue the conversation of a transcript, saving the new messages to it
+    Resume {
+        /// The id of the transcript, as shown by `pren transcript`
+        id: u64,
+        /// Model to use, instead of the one the conversation was held with
+        #[arg(short = 'm', long)]
+        model: Option<String>,
+        /// Model preset to use, replacing the model settings of the profile in use
+        #[arg(long, add = ArgValueCompleter::new(preset_names))]
+        preset: Option<String>,
+        /// API serving the model, overriding the configured one: openai (or any
+        /// OpenAI-compatible server), anthropic, ollama or gemini
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(Provider::NAMES)
+                .try_map(|provider| provider.parse::<Provider>())
+        )]
+        provider: Option<Provider>,
+        /// URL of the API to chat with, overriding the configured one
+        #[arg(long, value_hint = ValueHint::Url)]
+        base_url: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UsageCommands {
+    /// Set the price of a model, in dollars per million tokens, to show the cost of the
+    /// tokens it used
+    Price {
+        /// The name of the model, as sent to the provider
+        #[arg(value_hint = ValueHint::Other)]
+        model: String,
+        /// Dollars per million input tokens
+        #[arg(long)]
+        input_price: f64,
+        /// Dollars per million output tokens
+        #[arg(long)]
+        output_price: f64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PackCommands {
+    /// Check that a pack directory is well-formed
+    Validate {
+        #[arg(value_hint = ValueHint::DirPath)]
+        path: PathBuf,
+    },
+    /// Install the prompts of a pack directory into the prompt storage
+    Install {
+        #[arg(value_hint = ValueHint::DirPath)]
+        path: PathBuf,
+        #[arg(short = 'o', long)]
+        overwrite: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HubCommands {
+    /// Push a prompt to a Hub repo as a new commit, creating the repo if needed. Prompts are
+    /// rendered first, leaving the arguments that aren't given as template variables
+    Push {
+        #[arg(short = 'n', long, add = ArgValueCompleter::new(prompt_names))]
+        name: String,
+        /// The repo to push to, as repo or owner/repo. Named after the prompt by default
+        #[arg(long)]
+        repo: Option<String>,
+        /// Arguments to render into the prompt as KEY=value pairs, instead of leaving them
+        /// as variables
+        #[arg(short = 'a', long, alias = "arg", value_parser = parse_key_val, value_delimiter = ',', add = ArgValueCompleter::new(prompt_args))]
+        args: Vec<(String, String)>,
+        /// Structured arguments as a JSON object, accessed in templates with dotted names
+        #[arg(long)]
+        args_json: Option<String>,
+        /// Make the repo public when it's created
+        #[arg(long)]
+        public: bool,
+    },
+    /// Pull the prompt of a Hub repo, given as owner/repo, at its latest commit or at
+    /// owner/repo:commit
+    Pull {
+        repo: String,
+        /// The name to save the prompt as, the repo's name by default
+        #[arg(short = 'n', long)]
+        name: Option<String>,
+        /// Replace the stored prompt with the same name
+        #[arg(short = 'o', long)]
+        overwrite: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
     CompleteEnv::with_factory(Cli::command).complete();
     let cli = Cli::parse();
-    let storage = get_storage()?;
+    init_logging(cli.verbose, cli.quiet);
+    let output = cli.output;
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => report_error(&error, output),
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let config: PrenCliConfig = confy::load(PREN_CLI, None)
+        .with_context(|| format!("Unexpected error while loading config for {}", PREN_CLI))?;
+    let output = cli.output;
+    let use_clipboard = !cli.no_clipboard;
+    let command = match cli.command {
+        // Profiles are managed before resolving one, so a missing profile can be fixed
+        Commands::Profile { command } => return run_profile_command(config, command, output),
+        Commands::Preset { command } => return run_preset_command(config, command, output),
+        Commands::Config { command } => return run_config_command(config, command, output),
+        Commands::Completions {
+            command: CompletionsCommands::Install { shell, print },
+        } => return install_completions(shell, print, output),
+        command => command,
+    };
+    let project_dir = if cli.no_project {
+        None
+    } else {
+        find_project_dir(&std::env::current_dir()?)
+    };
+    let profile = config.resolve_profile(cli.profile.as_deref(), cli.storage_path, project_dir)?;
+    info!(
+        "Using profile '{}' with prompts in {}",
+        profile.name,
+        profile.storage.base_path.display()
+    );
+    for fallback_path in &profile.storage.fallback_paths {
+        info!("Falling back to prompts in {}", fallback_path.display());
+    }
+    let storage = profile.storage;
+    let model_config = profile.model_config;
 
-    match cli.command {
+    match command {
         Commands::Add {
             name,
             description,
             tags,
             content,
+            file,
+            from_clipboard,
+            author,
+            source_url,
+            license,
             overwrite,
+            override_lock,
+            skip_checks,
+            create_stubs,
+            auto_tag,
+            yes,
         } => {
-            if let Ok(_p) = storage.get_prompt(&name) {
-                if !overwrite {
-                    bail!(
-                        "Prompt '{}' already exists. Use --overwrite to replace it.",
-                        name
+            let options = AddOptions {
+                name,
+                description,
+                tags,
+                content,
+                file,
+                from_clipboard,
+                author,
+                source_url,
+                license,
+                overwrite,
+                override_lock,
+                skip_checks,
+                create_stubs,
+                auto_tag,
+                yes,
+            };
+            run_add(
+                &storage,
+                &config,
+                &model_config,
+                options,
+                output,
+                use_clipboard,
+            )
+            .await
+        }
+        Commands::New => run_wizard(&storage, config.author.clone(), config.validate_on_save),
+        Commands::Scaffold { kind, name, edit } => {
+            let Some(kind) = kind else {
+                let scaffolds = &builtin_scaffolds().prompts;
+                if output == OutputMode::Json {
+                    let entries: Vec<_> = scaffolds
+                        .iter()
+                        .map(|prompt| {
+                            json!({
+                                "name": prompt.metadata.name,
+                                "description": prompt.metadata.description
+                            })
+                        })
+                        .collect();
+                    return print_json(&entries);
+                }
+                let width = scaffolds
+                    .iter()
+                    .map(|prompt| prompt.metadata.name.len())
+                    .max()
+                    .unwrap_or(0);
+                for prompt in scaffolds {
+                    println!(
+                        "{:<width$}  {}",
+                        prompt.metadata.name,
+                        prompt.metadata.description.as_deref().unwrap_or_default(),
+                        width = width
                     );
                 }
+                return Ok(());
+            };
+
+            let name = name.unwrap_or_else(|| kind.clone());
+            check_prompt_name(&name)?;
+            if storage.get_prompt(&name).is_ok() {
+                bail!(
+                    "Prompt '{}' already exists. Use --name to choose another name.",
+                    name
+                );
             }
-            Ok(storage.save_prompt(&Prompt::new(
-                PromptMetadata::new(name, description, tags),
-                content,
-            ))?)
+            let mut prompt = scaffold(&kind, &name)?;
+            prompt.metadata.author = config.author.clone();
+            storage.save_prompt(&prompt)?;
+            if edit {
+                run_edit(&storage, &name, false, config.validate_on_save)?;
+            }
+            match output {
+                OutputMode::Json => print_json(&storage.get_prompt(&name)?)?,
+                OutputMode::Text if !edit => println!(
+                    "Prompt '{}' created from the '{}' scaffold. Edit it with `pren edit -n {}`.",
+                    name, kind, name
+                ),
+                OutputMode::Text => {}
+            }
+            Ok(())
         }
-        Commands::Show { name } => {
-            let prompt = storage.get_prompt(&name)?;
-
-            println!("Name: {}", prompt.metadata.name);
-            println!("Tags: {:?}", prompt.metadata.tags);
-            println!("Content:\n{}", prompt.content);
+        Commands::Show { name, rendered } => run_show(&storage, &name, rendered, output),
+        Commands::Args { name } => run_args(&storage, &name, output),
+        Commands::Render {
+            name,
+            names,
+            sep,
+            args,
+            args_json,
+            arg_stdin,
+            context,
+            interactive,
+            copy,
+            plan,
+            dry_run,
+            trace,
+            out,
+            stream,
+            append,
+            render_as,
+        } => {
+            let options = RenderOptions {
+                name,
+                names,
+                sep,
+                args,
+                args_json,
+                arg_stdin,
+                context,
+                interactive,
+                copy,
+                plan,
+                dry_run,
+                trace,
+                out,
+                stream,
+                append,
+                render_as,
+            };
+            run_render(&storage, options, output, use_clipboard)
+        }
+        Commands::Get {
+            name,
+            args,
+            args_json,
+            arg_stdin,
+            context,
+            interactive,
+        } => {
+            let options = GetOptions {
+                name,
+                args,
+                args_json,
+                arg_stdin,
+                context,
+                interactive,
+            };
+            run_get(&storage, options, output, use_clipboard)
+        }
+        #[cfg(feature = "tui")]
+        Commands::Tui => run_tui(&storage, &model_config),
+        #[cfg(feature = "server")]
+        Commands::Serve { stdio: true, .. } => run_stdio_server(&storage),
+        #[cfg(feature = "server")]
+        Commands::Serve {
+            stdio: _,
+            http,
+            grpc,
+            token,
+            allow_generate,
+            read_only,
+        } => {
+            let options = ServerOptions {
+                http,
+                grpc,
+                token,
+                allow_generate,
+                read_only,
+            };
+            run_server(storage, model_config, options).await
+        }
+        #[cfg(feature = "keyring")]
+        Commands::Auth { command } => run_auth_command(command, &model_config, output),
+        Commands::Quick {
+            name,
+            args,
+            script_filter: true,
+        } => {
+            let query: Vec<String> = name.into_iter().chain(args).collect();
+            run_script_filter(&storage, &query.join(" "))
+        }
+        Commands::Quick { name, args, .. } => run_quick(&storage, &name.unwrap_or_default(), &args),
+        Commands::Pick => run_pick(&storage, output, use_clipboard),
+        Commands::List {
+            tag,
+            tags,
+            name_contains,
+            sort,
+            limit,
+            format,
+        } => run_list(
+            &storage,
+            ListOptions {
+                tags: tag,
+                query: tags,
+                name_contains,
+                sort,
+                limit,
+                format: match output {
+                    OutputMode::Json => ListFormat::Json,
+                    OutputMode::Text => format,
+                },
+            },
+        ),
+        Commands::Search {
+            query,
+            semantic: true,
+            limit,
+            embedding_model,
+            preset,
+            provider,
+            base_url,
+            ..
+        } => {
+            let (model_config, _) = apply_preset(&config, &model_config, preset.as_deref(), None)?;
+            let embedding_model = match embedding_model {
+                Some(embedding_model) => embedding_model,
+                None => model_config.embedding_model(provider)?,
+            };
+            let prompts = storage.get_prompts()?;
+            let mut cache = storage.get_embeddings()?;
+            let matches = semantic_search(
+                &model_config.provider_config(provider, base_url.as_deref()),
+                &embedding_model,
+                &prompts,
+                &mut cache,
+                &query.join(" "),
+                limit,
+            )
+            .await?;
+            if let Err(e) = storage.save_embeddings(&cache) {
+                warn!("Couldn't cache the embeddings of prompts: {}", e);
+            }
+            if output == OutputMode::Json {
+                return print_json(&matches);
+            }
+            if matches.is_empty() {
+                println!("No prompts found");
+            }
+            for found in matches {
+                let description = prompts
+                    .iter()
+                    .find(|prompt| prompt.metadata.name == found.name)
+                    .and_then(|prompt| prompt.metadata.description.as_deref())
+                    .map(|description| format!("  {}", description))
+                    .unwrap_or_default();
+                println!("{:.2}  {}{}", found.score, found.name, description);
+            }
             Ok(())
         }
-        Commands::Render { name, args, copy } => {
-            let prompt = storage.get_prompt(&name)?;
-
-            let args_map: HashMap<String, String> = args.iter().cloned().collect();
-            let rendered_prompt = PromptTemplate::new(prompt)
-                .context(format!("Error rendering prompt '{}'", name))?
-                .render(&args_map, &storage)?;
-            println!("{}", rendered_prompt);
-            if copy {
-                Clipboard::new()?.set_text(rendered_prompt)?;
+        Commands::Search {
+            query,
+            content_only,
+            name_only,
+            ..
+        } => {
+            let fields = match (content_only, name_only) {
+                (true, _) => &[SearchField::Content][..],
+                (_, true) => &[SearchField::Name][..],
+                _ => SearchField::ALL,
+            };
+            let results = search_prompts(&storage.get_prompts()?, &query.join(" "), fields);
+            if output == OutputMode::Json {
+                return print_json(&results);
+            }
+            if results.is_empty() {
+                println!("No prompts found");
+            }
+            let color = std::io::stdout().is_terminal();
+            for result in results {
+                println!("{}", result.name);
+                for found in result.matches {
+                    let location = match found.line {
+                        Some(line) => format!("content:{}", line),
+                        None => format!("{:?}", found.field).to_lowercase(),
+                    };
+                    println!(
+                        "  {}: {}",
+                        location,
+                        highlight(&found.text, &found.ranges, color)
+                    );
+                }
             }
             Ok(())
         }
-        Commands::Get { name, args } => {
-            let prompt = storage.get_prompt(&name)?;
-            let args_map: HashMap<String, String> = args.iter().cloned().collect();
-            let rendered_prompt = PromptTemplate::new(prompt)?.render(&args_map, &storage)?;
-            Clipboard::new()?.set_text(rendered_prompt)?;
+        Commands::Tags => {
+            let tags = storage.list_tags()?;
+            if output == OutputMode::Json {
+                let tags: Vec<_> = tags
+                    .iter()
+                    .map(|(tag, count)| json!({ "tag": tag, "count": count }))
+                    .collect();
+                return print_json(&tags);
+            }
+            for (tag, count) in tags {
+                println!("{} ({})", tag, count);
+            }
             Ok(())
         }
-        Commands::List => {
+        Commands::Tag { command } => match command {
+            TagCommands::Rename { old, new } => {
+                let new = normalize_tag(&new);
+                if new.is_empty() {
+                    bail!("The new tag can't be empty");
+                }
+                let updated = storage.rename_tag(&old, &new)?;
+                if output == OutputMode::Json {
+                    return print_json(&json!({
+                        "old": normalize_tag(&old),
+                        "new": new,
+                        "updated": updated
+                    }));
+                }
+                println!(
+                    "Renamed tag '{}' to '{}' in {} prompts",
+                    normalize_tag(&old),
+                    new,
+                    updated
+                );
+                Ok(())
+            }
+            TagCommands::Suggest {
+                name,
+                model,
+                preset,
+                provider,
+                base_url,
+                yes,
+                skip_checks,
+            } => {
+                let mut prompt = storage.get_prompt(&name)?;
+                if !skip_checks {
+                    check_content(&prompt.content)?;
+                }
+                let (model_config, model) =
+                    apply_preset(&config, &model_config, preset.as_deref(), model)?;
+                let settings = ModelSettings {
+                    model,
+                    ..ModelSettings::default()
+                };
+                let provider = model_config.provider_config(provider, base_url.as_deref());
+                let (suggested, vocabulary, usage) = suggest_tags(
+                    &storage,
+                    &provider,
+                    &model_config.model_name,
+                    &settings,
+                    &prompt,
+                )
+                .await?;
+                if output == OutputMode::Text {
+                    print_tag_suggestions(&name, &suggested, &vocabulary);
+                    let used_model = settings
+                        .model
+                        .as_deref()
+                        .unwrap_or(&model_config.model_name);
+                    print_tokens(&config, used_model, usage);
+                }
+                let save = !suggested.is_empty()
+                    && (yes || output == OutputMode::Text && confirm_tags(&name)?);
+                if save {
+                    prompt.metadata.tags.extend(suggested.iter().cloned());
+                    storage.save_prompt(&prompt)?;
+                }
+
+                if output == OutputMode::Json {
+                    return print_json(&json!({
+                        "name": name,
+                        "suggested": suggested,
+                        "tags": prompt.metadata.tags,
+                        "saved": save,
+                        "usage": usage
+                    }));
+                }
+                if save {
+                    println!("Tags of '{}': {}", name, prompt.metadata.tags.join(", "));
+                } else if !suggested.is_empty() {
+                    println!("Prompt '{}' left unchanged", name);
+                }
+                Ok(())
+            }
+        },
+        Commands::Pin { name } => set_pinned(&storage, &name, true, output),
+        Commands::Unpin { name } => set_pinned(&storage, &name, false, output),
+        Commands::Lock { name } => set_locked(&storage, &name, true, output),
+        Commands::Unlock { name } => set_locked(&storage, &name, false, output),
+        Commands::Edit {
+            name,
+            override_lock,
+        } => run_edit(&storage, &name, override_lock, config.validate_on_save),
+        Commands::Rename {
+            from,
+            to,
+            rewrite_references,
+            keep_references,
+        } => {
+            check_prompt_name(&to)?;
+            match storage.rename_prompt(&from, &to) {
+                Err(FileStorageError::PromptLocked(name)) => {
+                    bail!("Prompt '{}' is locked. Unlock it to rename it.", name)
+                }
+                result => result?,
+            }
+            if output == OutputMode::Text {
+                println!("Prompt '{}' renamed to '{}'", from, to);
+            }
+
             let prompts = storage.get_prompts()?;
-            for prompt in prompts {
-                println!("Prompt name: {}", prompt.metadata.name);
+            let referencing: Vec<String> = prompts
+                .iter()
+                .filter(|prompt| Prompt::clone(prompt).replace_prompt_references(&from, &to))
+                .map(|prompt| prompt.metadata.name.clone())
+                .collect();
+            // References chosen by an argument can't be rewritten, but may need updating
+            let variable: Vec<String> = prompts
+                .into_iter()
+                .filter(|prompt| {
+                    PromptTemplate::new(prompt.clone())
+                        .is_ok_and(|template| !template.variable_prompt_references().is_empty())
+                })
+                .map(|prompt| prompt.metadata.name)
+                .collect();
+
+            let rewrite = if referencing.is_empty() || keep_references {
+                false
+            } else if rewrite_references {
+                true
+            } else {
+                println!("Prompts referencing '{}': {}", from, referencing.join(", "));
+                std::io::stdin().is_terminal()
+                    && choose("Update their references? [y/n]", &["yes", "no"])? == 'y'
+            };
+            let updated = if rewrite {
+                storage.replace_prompt_references(&from, &to)?
+            } else {
+                Vec::new()
+            };
+
+            if output == OutputMode::Json {
+                return print_json(&json!({
+                    "from": from,
+                    "to": to,
+                    "updated": updated,
+                    "referencing": if rewrite { Vec::new() } else { referencing },
+                    "variable_references": variable
+                }));
+            }
+            if rewrite {
+                println!("Updated references in: {}", updated.join(", "));
+            } else if !referencing.is_empty() {
+                println!("References left unchanged in: {}", referencing.join(", "));
+            }
+            if !variable.is_empty() {
+                println!(
+                    "Prompts choosing a prompt from an argument, which may need '{}' now: {}",
+                    to,
+                    variable.join(", ")
+                );
             }
             Ok(())
         }
-        Commands::Delete { name, force } => {
-            let _prompt = storage
-                .get_prompt(&name)
-                .context(format!("Couldn't delete prompt: '{}'", name))?;
-            if !force {
-                println!("Are you sure you want to delete prompt '{}'? [y/N]", name);
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input)?;
-                let input = input.trim().to_lowercase();
-                if input != "y" && input != "yes" {
-                    println!("Delete operation cancelled.");
-                    return Ok(());
+        Commands::Diff {
+            name,
+            other,
+            version,
+            args,
+            args_json,
+        } => {
+            // A previous version is the old side of the diff, and the current prompt the new one
+            let (old, new, identical) = match (other, version) {
+                (Some(other), _) => (
+                    storage.get_prompt(&name)?,
+                    storage.get_prompt(&other)?,
+                    format!("Prompts '{}' and '{}' are identical", name, other),
+                ),
+                (None, Some(version)) => (
+                    storage.get_prompt_version(&name, version)?,
+                    storage.get_prompt(&name)?,
+                    format!("Prompt '{}' is identical to its version {}", name, version),
+                ),
+                (None, None) => unreachable!("clap requires the other prompt or a version"),
+            };
+            let diff = if args.is_empty() && args_json.is_none() {
+                diff_prompts(&old, &new)
+            } else {
+                let args_map = build_args_map(&args, args_json.as_deref(), None)?;
+                diff_rendered(
+                    &PromptTemplate::new(old)?,
+                    &PromptTemplate::new(new)?,
+                    &args_map,
+                    &storage,
+                )?
+            };
+
+            if output == OutputMode::Json {
+                return print_json(&diff);
+            }
+            if diff.is_empty() {
+                println!("{}", identical);
+                return Ok(());
+            }
+            if !diff.metadata.is_empty() {
+                println!("Metadata:");
+                for change in &diff.metadata {
+                    let show = |value: &Option<Value>| {
+                        value
+                            .as_ref()
+                            .map_or("(none)".to_string(), |value| value.to_string())
+                    };
+                    println!(
+                        "  {}: {} -> {}",
+                        change.field,
+                        show(&change.old),
+                        show(&change.new)
+                    );
                 }
             }
-            storage.delete_prompt(&name)?;
-            println!("Prompt '{}' deleted successfully.", name);
+            println!("Content:");
+            print_diff_lines(&diff.content);
+            if let Some(rendered) = &diff.rendered {
+                println!("Rendered:");
+                print_diff_lines(rendered);
+            }
             Ok(())
         }
+        Commands::Delete {
+            name,
+            tag,
+            force,
+            override_lock,
+        } => run_delete(&storage, &name, &tag, force, override_lock, output),
+        Commands::Undo { list: true } => run_undo_list(&storage, output),
+        Commands::Undo { list: false } => run_undo(&storage, output),
         Commands::Generate {
             generation_prompt,
             args,
+            args_json,
+            arg_stdin,
+            context,
+            model,
+            temperature,
+            max_tokens,
+            stop,
+            preset,
+            provider,
+            base_url,
+            validate,
+            max_attempts,
+            tool,
+            run_tools,
+            max_tool_steps,
+            skip_checks,
+            out,
+            append,
+            save_as,
+            tags,
+            description,
+            overwrite,
+            transcript,
+        } => {
+            let options = GenerateOptions {
+                generation_prompt,
+                args,
+                args_json,
+                arg_stdin,
+                context,
+                model,
+                temperature,
+                max_tokens,
+                stop,
+                preset,
+                provider,
+                base_url,
+                validate,
+                max_attempts,
+                tool,
+                run_tools,
+                max_tool_steps,
+                skip_checks,
+                out,
+                append,
+                save_as,
+                tags,
+                description,
+                overwrite,
+                transcript,
+            };
+            run_generate(&storage, &config, &model_config, options, output).await
+        }
+        Commands::Chat {
+            system,
+            args,
+            args_json,
+            context,
+            model,
+            temperature,
+            max_tokens,
+            preset,
+            provider,
+            base_url,
+            save,
+            no_transcript,
+            skip_checks,
         } => {
-            let prompt = storage.get_prompt(&generation_prompt)?;
-            let args_map: HashMap<String, String> = args.iter().cloned().collect();
-            let rendered_prompt = PromptTemplate::new(prompt)?.render(&args_map, &storage)?;
-            let response = get_completions_content(
-                &config.model_config.api_key,
-                &config.model_config.base_url,
-                &config.model_config.model_name,
-                &rendered_prompt,
+            let (model_config, model) =
+                apply_preset(&config, &model_config, preset.as_deref(), model)?;
+            let prompt = storage.get_prompt(&system)?;
+            let settings = prompt
+                .metadata
+                .model_settings
+                .with_overrides(&ModelSettings {
+                    model,
+                    temperature,
+                    max_tokens,
+                    stop: Vec::new(),
+                });
+            let args_map = build_args_map(&args, args_json.as_deref(), context.as_deref())?;
+            let rendered =
+                PromptTemplate::new(prompt)?.render_with_warnings(&args_map, &storage)?;
+            if !skip_checks {
+                check_content(&rendered.text)?;
+            }
+            print_warnings(&rendered.warnings);
+            let messages = if has_role_markers(&rendered.text) {
+                split_messages(&rendered.text)
+            } else {
+                vec![RenderedMessage {
+                    role: Role::System,
+                    content: rendered.text.trim().to_string(),
+                }]
+            };
+            record_usage(&storage, &system, UsageKind::Generate);
+            record_history(
+                &storage,
+                &system,
+                UsageKind::Generate,
+                &args_map,
+                output_hash(&rendered.text),
+            );
+            let transcript = Transcript::new(
+                system,
+                args_map.into_iter().collect(),
+                model_config.model_name.clone(),
+                messages,
+            );
+            run_chat(
+                &storage,
+                &model_config.provider_config(provider, base_url.as_deref()),
+                &model_config.model_name,
+                transcript,
+                &settings,
+                save.as_deref(),
+                !no_transcript,
+            )
+            .await
+        }
+        Commands::Improve {
+            name,
+            goal,
+            model,
+            preset,
+            provider,
+            base_url,
+            save_as,
+            yes,
+            skip_checks,
+        } => {
+            let prompt = storage.get_prompt(&name)?;
+            if let Some(new_name) = &save_as {
+                check_prompt_name(new_name)?;
+                if storage.get_prompt(new_name).is_ok() {
+                    bail!("Prompt '{}' already exists", new_name);
+                }
+            }
+            if !skip_checks {
+                check_content(&prompt.content)?;
+            }
+            let (model_config, model) =
+                apply_preset(&config, &model_config, preset.as_deref(), model)?;
+            let settings = ModelSettings {
+                model,
+                ..ModelSettings::default()
+            };
+            let completion = get_chat_completion(
+                &model_config.provider_config(provider, base_url.as_deref()),
+                &model_config.model_name,
+                &improvement_messages(&prompt, goal.as_deref()),
+                &settings,
             )
             .await?;
+            let used_model = settings
+                .model
+                .as_deref()
+                .unwrap_or(&model_config.model_name);
+            record_tokens(&storage, used_model, completion.usage);
+            let improvement = parse_improvement(&completion.text)?;
+
+            let mut improved = prompt.clone();
+            improved.content = improvement.content.clone();
+            if let Some(new_name) = &save_as {
+                improved.metadata.name = new_name.clone();
+                improved.metadata.locked = false;
+            }
+            if let Err(e) = PromptTemplate::new(improved.clone()) {
+                warn!("The improved prompt can't be parsed: {}", e);
+            }
+            let diff = diff_lines(&prompt.content, &improved.content);
+            let changed = diff
+                .iter()
+                .any(|line| !matches!(line, DiffLine::Unchanged(_)));
+
+            if output == OutputMode::Text {
+                if !improvement.critique.is_empty() {
+                    println!("Critique:\n{}\n", improvement.critique);
+                }
+                if changed {
+                    print_diff_lines(&diff);
+                } else {
+                    println!("The model didn't change the prompt");
+                }
+                print_tokens(&config, used_model, completion.usage);
+            }
+            let save = changed
+                && (save_as.is_some()
+                    || yes
+                    || output == OutputMode::Text && {
+                        println!("Replace '{}' with the improved version? [y/N]", name);
+                        let mut input = String::new();
+                        std::io::stdin().read_line(&mut input)?;
+                        matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+                    });
+            if save {
+                storage.save_prompt(&improved)?;
+            }
 
-            println!("{}", response);
+            if output == OutputMode::Json {
+                return print_json(&json!({
+                    "name": name,
+                    "critique": improvement.critique,
+                    "content": improvement.content,
+                    "diff": diff,
+                    "saved_as": save.then_some(&improved.metadata.name),
+                    "usage": completion.usage
+                }));
+            }
+            match (save, &save_as) {
+                (true, Some(new_name)) => println!("Prompt '{}' saved", new_name),
+                (true, None) => println!(
+                    "Prompt '{}' updated. Use `pren undo` to restore the previous version.",
+                    name
+                ),
+                (false, _) if changed => println!("Prompt '{}' left unchanged", name),
+                (false, _) => {}
+            }
             Ok(())
         }
         Commands::Info => {
+            let prompt_count = storage.get_prompts()?.len();
+            if output == OutputMode::Json {
+                return print_json(&json!({
+                    "profile": profile.name,
+                    "storage_path": storage.base_path,
+                    "fallback_paths": storage.fallback_paths,
+                    "prompt_count": prompt_count
+                }));
+            }
+            println!("Profile: {}", profile.name);
             println!("Prompt storage path: {:?}", storage.base_path);
-            println!("Total number of prompts: {}", storage.get_prompts()?.len());
+            for fallback_path in &storage.fallback_paths {
+                println!("Fallback prompt storage path: {:?}", fallback_path);
+            }
+            println!("Total number of prompts: {}", prompt_count);
+            Ok(())
+        }
+        Commands::Doctor => run_doctor(&storage, &model_config, output),
+        Commands::Check { staged, paths } => run_check(&storage, staged, &paths, output),
+        Commands::Tree { name, reverse } => {
+            let tree = if reverse {
+                reverse_composition_tree(&storage, &name)?
+            } else {
+                composition_tree(&storage, &name)?
+            };
+            if output == OutputMode::Json {
+                return print_json(&tree);
+            }
+            println!("{}", tree.name);
+            if reverse && tree.children.is_empty() {
+                println!("No prompts include '{}'", name);
+            }
+            print_composition_tree(&tree.children, "");
             Ok(())
         }
+        Commands::Tokens {
+            name,
+            args,
+            args_json,
+        } => {
+            let prompt = storage.get_prompt(&name)?;
+            let args_map = build_args_map(&args, args_json.as_deref(), None)?;
+            let rendered_prompt = PromptTemplate::new(prompt)
+                .context(format!("Error rendering prompt '{}'", name))?
+                .render(&args_map, &storage)?;
+            let tokens = count_tokens(&rendered_prompt);
+            match output {
+                OutputMode::Json => print_json(&json!({ "name": name, "tokens": tokens }))?,
+                OutputMode::Text => println!("{}", tokens),
+            }
+            Ok(())
+        }
+        Commands::Test { name, .. } => {
+            let prompts = match name {
+                Some(name) => vec![storage.get_prompt(&name)?],
+                None => storage
+                    .get_prompts()?
+                    .into_iter()
+                    .filter(|prompt| !prompt.metadata.tests.is_empty())
+                    .collect(),
+            };
+
+            let mut results = Vec::new();
+            for prompt in prompts {
+                let name = prompt.metadata.name.clone();
+                let template = PromptTemplate::new(prompt)
+                    .context(format!("Error parsing prompt '{}'", name))?;
+                results.extend(template.run_tests(&storage));
+            }
+            let failed = results.iter().filter(|result| !result.passed()).count();
+            if output == OutputMode::Json {
+                print_json(&results)?;
+                if failed > 0 {
+                    bail!("{} test case(s) failed", failed);
+                }
+                return Ok(());
+            }
+
+            for result in &results {
+                if result.passed() {
+                    println!("PASS {} {}", result.prompt, result.case);
+                } else {
+                    println!("FAIL {} {}", result.prompt, result.case);
+                    for failure in &result.failures {
+                        println!("  {}", failure);
+                    }
+                }
+            }
+
+            println!("{} passed, {} failed", results.len() - failed, failed);
+            if failed > 0 {
+                bail!("{} test case(s) failed", failed);
+            }
+            Ok(())
+        }
+        Commands::Eval {
+            name,
+            judge,
+            model,
+            preset,
+            provider,
+            base_url,
+            history,
+            skip_checks,
+            ..
+        } => {
+            if history {
+                return run_eval_history(&storage, name.as_deref(), output);
+            }
+            let prompts = match name {
+                Some(name) => vec![storage.get_prompt(&name)?],
+                None => storage
+                    .get_prompts()?
+                    .into_iter()
+                    .filter(|prompt| !prompt.metadata.evals.is_empty())
+                    .collect(),
+            };
+            let judge = match judge {
+                Some(judge) => Some(PromptTemplate::new(storage.get_prompt(&judge)?)?),
+                None => None,
+            };
+            let (model_config, model) =
+                apply_preset(&config, &model_config, preset.as_deref(), model)?;
+            let provider = model_config.provider_config(provider, base_url.as_deref());
+            let eval_model = EvalModel {
+                provider: &provider,
+                model_name: model.as_deref().unwrap_or(&model_config.model_name),
+                override_model: model.is_some(),
+                judge,
+                skip_checks,
+            };
+            run_eval(&storage, prompts, &eval_model, output).await
+        }
+        Commands::Tour => run_tour(&storage, &model_config).await,
+        Commands::History {
+            command,
+            name,
+            limit,
+        } => match command {
+            Some(HistoryCommands::Replay { id, skip_checks }) => {
+                run_replay(&storage, &model_config, id, skip_checks, output).await
+            }
+            None => run_history(&storage, name.as_deref(), limit, output),
+        },
+        Commands::Transcript {
+            command,
+            name,
+            limit,
+        } => match command {
+            Some(TranscriptCommands::Show { id }) => run_transcript_show(&storage, id, output),
+            Some(TranscriptCommands::Export { id, path }) => {
+                run_transcript_export(&storage, id, &path)
+            }
+            Some(TranscriptCommands::Resume {
+                id,
+                model,
+                preset,
+                provider,
+                base_url,
+            }) => {
+                let transcript = storage.get_transcript(id)?;
+                let (model_config, model) =
+                    apply_preset(&config, &model_config, preset.as_deref(), model)?;
+                let settings = ModelSettings {
+                    model: model.or_else(|| Some(transcript.model.clone())),
+                    ..ModelSettings::default()
+                };
+                run_transcript_resume(
+                    &storage,
+                    &model_config.provider_config(provider, base_url.as_deref()),
+                    &model_config.model_name,
+                    transcript,
+                    &settings,
+                )
+                .await
+            }
+            None => run_transcript_list(&storage, name.as_deref(), limit, output),
+        },
+        Commands::Stats { least, limit } => {
+            let stats = storage.get_stats()?;
+            // Prompts that were never used are listed too, since they're the ones to prune
+            let mut rows: Vec<(String, PromptStats)> = storage
+                .get_prompts()?
+                .into_iter()
+                .map(|prompt| {
+                    let prompt_stats = stats.get(&prompt.metadata.name);
+                    (prompt.metadata.name, prompt_stats)
+                })
+                .collect();
+            rows.sort_by(|(a_name, a), (b_name, b)| {
+                b.total_count()
+                    .cmp(&a.total_count())
+                    .then_with(|| a_name.cmp(b_name))
+            });
+            if least {
+                rows.reverse();
+            }
+
+            rows.truncate(limit.unwrap_or(usize::MAX));
+            if output == OutputMode::Json {
+                let rows: Vec<_> = rows
+                    .iter()
+                    .map(|(name, prompt_stats)| {
+                        json!({
+                            "name": name,
+                            "render_count": prompt_stats.render_count,
+                            "generate_count": prompt_stats.generate_count,
+                            "last_used": prompt_stats.last_used
+                        })
+                    })
+                    .collect();
+                return print_json(&rows);
+            }
+
+            for (name, prompt_stats) in rows {
+                let last_used = prompt_stats
+                    .last_used
+                    .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "never".to_string());
+                println!(
+                    "{}: {} renders, {} generations, last used: {}",
+                    name, prompt_stats.render_count, prompt_stats.generate_count, last_used
+                );
+            }
+            Ok(())
+        }
+        Commands::Usage { command } => match command {
+            Some(UsageCommands::Price {
+                model,
+                input_price,
+                output_price,
+            }) => {
+                let price = ModelPrice {
+                    input: input_price,
+                    output: output_price,
+                };
+                set_price(config, model, price, output)
+            }
+            None => run_usage(&storage, &config, output),
+        },
+        Commands::Run {
+            pipeline,
+            args,
+            args_json,
+        } => {
+            let path = PathBuf::from(&pipeline);
+            let path = if path.is_file() {
+                path
+            } else {
+                storage
+                    .base_path
+                    .join(PIPELINES_DIR)
+                    .join(format!("{}.json", pipeline))
+            };
+            let pipeline = Pipeline::load(&path)
+                .with_context(|| format!("Failed to load pipeline from {:?}", path))?;
+            let args_map = build_args_map(&args, args_json.as_deref(), None)?;
+            let pipeline_output = pipeline
+                .run(&storage, args_map, async |prompt, messages| {
+                    let settings = &prompt.metadata.model_settings;
+                    let completion = get_chat_completion(
+                        &model_config.provider_config(None, None),
+                        &model_config.model_name,
+                        messages,
+                        settings,
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    let used_model = settings
+                        .model
+                        .as_deref()
+                        .unwrap_or(&model_config.model_name);
+                    record_tokens(&storage, used_model, completion.usage);
+                    Ok(completion.text)
+                })
+                .await?;
+
+            match output {
+                OutputMode::Json => print_json(&json!({
+                    "output": pipeline_output.output,
+                    "variables": pipeline_output.variables
+                }))?,
+                OutputMode::Text => println!("{}", pipeline_output.output),
+            }
+            for step in &pipeline.steps {
+                let kind = if step.generate {
+                    UsageKind::Generate
+                } else {
+                    UsageKind::Render
+                };
+                record_usage(&storage, &step.prompt, kind);
+            }
+            Ok(())
+        }
+        Commands::Profile { .. }
+        | Commands::Preset { .. }
+        | Commands::Config { .. }
+        | Commands::Completions { .. } => {
+            unreachable!(
+                "Profile, config and completions commands are run before resolving a profile"
+            )
+        }
+        Commands::Import {
+            format,
+            path,
+            overwrite,
+        } => {
+            let mut imported = Vec::new();
+            let mut skipped = Vec::new();
+            for prompt in import_prompts(format, &path)? {
+                let name = prompt.metadata.name.clone();
+                if !overwrite && storage.get_prompt_path(&name).is_ok() {
+                    skipped.push((
+                        name,
+                        "it already exists. Use --overwrite to replace it.".to_string(),
+                    ));
+                    continue;
+                }
+                match storage.save_prompt(&prompt) {
+                    Ok(()) => imported.push(name),
+                    Err(e) => skipped.push((name, format!("{:#}", anyhow::Error::from(e)))),
+                }
+            }
+
+            if output == OutputMode::Json {
+                let skipped: Vec<_> = skipped
+                    .iter()
+                    .map(|(name, reason)| json!({ "name": name, "reason": reason }))
+                    .collect();
+                return print_json(&json!({ "imported": imported, "skipped": skipped }));
+            }
+            for (name, reason) in &skipped {
+                warn!("Skipped '{}': {}", name, reason);
+            }
+            if imported.is_empty() {
+                println!("No prompts imported");
+            } else {
+                println!(
+                    "Imported {} prompt(s): {}",
+                    imported.len(),
+                    imported.join(", ")
+                );
+            }
+            Ok(())
+        }
+        Commands::Export {
+            name,
+            format,
+            args,
+            args_json,
+        } => {
+            let args_map = build_args_map(&args, args_json.as_deref(), None)?;
+            let template = PromptTemplate::new(storage.get_prompt(&name)?)?;
+            let exported = export_prompt(&template, &args_map, &storage, format)
+                .with_context(|| format!("Couldn't export prompt '{}'", name))?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "name": name, "exported": exported }))?,
+                OutputMode::Text => println!("{}", exported),
+            }
+            Ok(())
+        }
+        Commands::Hub {
+            command,
+            api_key,
+            endpoint,
+        } => {
+            let client = HubClient::new(&endpoint, &hub_api_key(api_key)?);
+            match command {
+                HubCommands::Push {
+                    name,
+                    repo,
+                    args,
+                    args_json,
+                    public,
+                } => {
+                    let args_map = build_args_map(&args, args_json.as_deref(), None)?;
+                    run_hub_push(
+                        &storage,
+                        &client,
+                        &name,
+                        repo.as_deref(),
+                        &args_map,
+                        public,
+                        output,
+                    )
+                    .await
+                }
+                HubCommands::Pull {
+                    repo,
+                    name,
+                    overwrite,
+                } => {
+                    run_hub_pull(&storage, &client, &repo, name.as_deref(), overwrite, output).await
+                }
+            }
+        }
+        Commands::Install {
+            pack,
+            registry,
+            overwrite,
+        } => run_install(&storage, &registry, &pack, overwrite, output).await,
+        Commands::Publish { path, registry_dir } => run_publish(&path, &registry_dir, output),
+        Commands::Pack { command } => match command {
+            PackCommands::Validate { path } => {
+                let pack = PromptPack::load(&path)?;
+                pack.validate()?;
+                if output == OutputMode::Json {
+                    return print_json(&json!({
+                        "name": pack.manifest.name,
+                        "version": pack.manifest.version,
+                        "prompts": pack.prompts.len()
+                    }));
+                }
+                println!(
+                    "Pack '{}' v{} is valid ({} prompts)",
+                    pack.manifest.name,
+                    pack.manifest.version,
+                    pack.prompts.len()
+                );
+                Ok(())
+            }
+            PackCommands::Install { path, overwrite } => {
+                let pack = PromptPack::load(&path)?;
+                pack.validate()?;
+                let installed = pack.install(&storage, overwrite)?;
+                if output == OutputMode::Json {
+                    return print_json(&json!({
+                        "name": pack.manifest.name,
+                        "version": pack.manifest.version,
+                        "installed": installed
+                    }));
+                }
+                println!(
+                    "Installed pack '{}' v{}: {}",
+                    pack.manifest.name,
+                    pack.manifest.version,
+                    installed.join(", ")
+                );
+                Ok(())
+            }
+        },
     }
 }
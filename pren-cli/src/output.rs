@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use pren_core::history::HashingWriter;
+use pren_core::prompt::{PromptTemplate, RenderWarning, TemplateArguments};
+use pren_core::storage::PromptStorage;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use tempfile::NamedTempFile;
+use tracing::{Level, warn};
+
+/// How commands print their results to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputMode {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// Structured JSON, for scripts and editors
+    Json,
+}
+
+/// What `render` turns a rendered prompt into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RenderFormat {
+    /// The rendered text
+    #[default]
+    Text,
+    /// The body of an OpenAI-compatible chat completion request, with the prompt's
+    /// messages and model settings
+    OpenaiJson,
+}
+
+/// Prints a value to stdout as pretty JSON.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Writes a command's result to a file, followed by a newline as when it's printed.
+///
+/// Unless `append` is set, the file is replaced atomically, so an existing file is left
+/// untouched if writing fails.
+pub fn write_output_file(path: &Path, text: &str, append: bool) -> Result<()> {
+    let write = || -> std::io::Result<()> {
+        if append {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            return writeln!(file, "{}", text);
+        }
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut file = NamedTempFile::new_in(directory)?;
+        writeln!(file, "{}", text)?;
+        file.persist(path)?;
+        Ok(())
+    };
+    write().with_context(|| format!("Failed to write the output to {:?}", path))
+}
+
+/// Writes a rendered prompt followed by a newline, returning the warnings found and the
+/// hash of the prompt (see [`output_hash`](pren_core::history::output_hash)).
+///
+/// The prompt is written once it's rendered, so a failed render writes nothing and
+/// `pren render x > file` doesn't leave half a prompt behind. With `stream`, each part is
+/// written as soon as it's rendered instead.
+pub fn write_rendered<A: TemplateArguments, S: PromptStorage, W: Write>(
+    template: &PromptTemplate,
+    arguments: &A,
+    storage: &S,
+    stream: bool,
+    writer: W,
+) -> Result<(Vec<RenderWarning>, String)> {
+    let mut writer = HashingWriter::new(writer);
+    let warnings = if stream {
+        template.render_to_writer(arguments, storage, &mut writer)?
+    } else {
+        let rendered = template.render_with_warnings(arguments, storage)?;
+        writer.write_all(rendered.text.as_bytes())?;
+        rendered.warnings
+    };
+    let hash = writer.hash();
+    writeln!(writer)?;
+    Ok((warnings, hash))
+}
+
+/// Logs render warnings to stderr, so they don't end up in piped output
+pub fn print_warnings(warnings: &[RenderWarning]) {
+    for warning in warnings {
+        warn!("{}", warning);
+    }
+}
+
+/// Sets up the logs printed to stderr: only errors with `--quiet`, warnings by default, and
+/// info, debug and trace logs with each `-v`.
+pub fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => Level::ERROR,
+        (false, 0) => Level::WARN,
+        (false, 1) => Level::INFO,
+        (false, 2) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::history::output_hash;
+    use pren_core::memory_storage::MemoryStorage;
+    use pren_core::prompt::{Prompt, PromptMetadata};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_write_rendered() {
+        let storage = MemoryStorage::new();
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("persona".to_string(), None, vec![]),
+                "You review {{language}}.".to_string(),
+            ))
+            .unwrap();
+        let template = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("review".to_string(), None, vec![]),
+            "Intro. {{prompt:persona}} Review {{code}}".to_string(),
+        ))
+        .unwrap();
+        let arguments = HashMap::from([
+            ("language".to_string(), "Rust".to_string()),
+            ("code".to_string(), "main.rs".to_string()),
+        ]);
+
+        for stream in [false, true] {
+            let mut output = Vec::new();
+            let (warnings, hash) =
+                write_rendered(&template, &arguments, &storage, stream, &mut output).unwrap();
+            let text = "Intro. You review Rust. Review main.rs";
+            assert_eq!(format!("{}\n", text), String::from_utf8(output).unwrap());
+            assert_eq!(output_hash(text), hash);
+            assert!(warnings.is_empty());
+        }
+
+        // A render failing halfway writes nothing, unless streaming
+        let arguments = HashMap::from([("code".to_string(), "main.rs".to_string())]);
+        let mut output = Vec::new();
+        assert!(write_rendered(&template, &arguments, &storage, false, &mut output).is_err());
+        assert!(output.is_empty());
+        assert!(write_rendered(&template, &arguments, &storage, true, &mut output).is_err());
+        assert_eq!("Intro. ", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_write_output_file() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let path = directory.path().join("out.txt");
+
+        write_output_file(&path, "First", false).unwrap();
+        write_output_file(&path, "Second", true).unwrap();
+        assert_eq!("First\nSecond\n", std::fs::read_to_string(&path).unwrap());
+
+        write_output_file(&path, "Replaced", false).unwrap();
+        assert_eq!("Replaced\n", std::fs::read_to_string(&path).unwrap());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::arguments::build_args_map;
+use crate::clipboard::copy_or_warn;
+use crate::history::{record_history, record_usage};
+use crate::output::{OutputMode, print_json, print_warnings};
+use anyhow::{Context, Result, bail};
+use dialoguer::{FuzzySelect, Input};
+use pren_core::file_storage::FileStorage;
+use pren_core::history::output_hash;
+use pren_core::prompt::{Prompt, PromptTemplate};
+use pren_core::stats::UsageKind;
+use pren_core::storage::PromptStorage;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+fn ensure_terminal() -> Result<()> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        bail!("Interactive mode needs a terminal");
+    }
+    Ok(())
+}
+
+/// Opens a fuzzy finder over the names and descriptions of the stored prompts, pinned
+/// ones first.
+///
+/// # Returns
+///
+/// * `Ok(Prompt)` - The picked prompt.
+/// * `Err` - If there are no prompts, or the user cancelled the picker.
+pub fn pick_prompt(storage: &FileStorage) -> Result<Prompt> {
+    ensure_terminal()?;
+    let mut prompts = storage.get_prompts()?;
+    if prompts.is_empty() {
+        bail!("There are no prompts to pick from");
+    }
+    prompts.sort_by_key(|prompt| (!prompt.metadata.pinned, prompt.metadata.name.clone()));
+
+    let items: Vec<String> = prompts
+        .iter()
+        .map(|prompt| match &prompt.metadata.description {
+            Some(description) => format!("{}  {}", prompt.metadata.name, description),
+            None => prompt.metadata.name.clone(),
+        })
+        .collect();
+    let index = FuzzySelect::new()
+        .with_prompt("Prompt")
+        .items(&items)
+        .default(0)
+        .interact_opt()
+        .context("Failed to open the prompt picker")?;
+    match index {
+        Some(index) => Ok(prompts.swap_remove(index)),
+        None => bail!("No prompt picked"),
+    }
+}
+
+/// Asks for the value of every argument the template needs that isn't in `arguments`,
+/// including the arguments of the prompts it references.
+pub fn ask_missing_arguments(
+    template: &PromptTemplate,
+    arguments: &mut HashMap<String, Value>,
+    storage: &FileStorage,
+) -> Result<()> {
+    let missing = template
+        .render_partial(arguments, storage)?
+        .missing_arguments;
+    if missing.is_empty() {
+        return Ok(());
+    }
+    ensure_terminal()?;
+
+    let descriptions: HashMap<String, String> = template
+        .argument_info()
+        .into_iter()
+        .filter_map(|argument| Some((argument.name, argument.description?)))
+        .collect();
+    for name in missing {
+        let label = match descriptions.get(&name) {
+            Some(description) => format!("{} ({})", name, description),
+            None => name.clone(),
+        };
+        let value: String = Input::new()
+            .with_prompt(label)
+            .allow_empty(true)
+            .interact_text()
+            .with_context(|| format!("Failed to read the value of '{}'", name))?;
+        arguments.insert(name, Value::String(value));
+    }
+    Ok(())
+}
+
+/// Picks a prompt with a fuzzy finder, asks for its missing arguments, and prints and
+/// copies it rendered.
+pub fn run_pick(storage: &FileStorage, output: OutputMode, use_clipboard: bool) -> Result<()> {
+    let prompt = pick_prompt(storage)?;
+    let name = prompt.metadata.name.clone();
+    let mut args_map = build_args_map(&[], None, None)?;
+    let template = PromptTemplate::new(prompt)?;
+    ask_missing_arguments(&template, &mut args_map, storage)?;
+    let rendered = template.render_with_warnings(&args_map, storage)?;
+    print_warnings(&rendered.warnings);
+    match output {
+        OutputMode::Json => print_json(&json!({ "name": name, "text": rendered.text }))?,
+        OutputMode::Text => println!("{}", rendered.text),
+    }
+    let rendered_hash = output_hash(&rendered.text);
+    copy_or_warn(&rendered.text, use_clipboard);
+    record_usage(storage, &name, UsageKind::Render);
+    record_history(storage, &name, UsageKind::Render, &args_map, rendered_hash);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::prompt::PromptMetadata;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ask_missing_arguments_without_missing_arguments() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        let template = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("greet".to_string(), None, vec![]),
+            "Hello {{name}}!".to_string(),
+        ))
+        .unwrap();
+        let mut arguments = HashMap::from([("name".to_string(), Value::from("Ada"))]);
+
+        // Nothing is asked, so it doesn't need a terminal
+        ask_missing_arguments(&template, &mut arguments, &storage).unwrap();
+        assert_eq!(1, arguments.len());
+    }
+}
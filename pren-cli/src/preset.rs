@@ -0,0 +1,149 @@
+use crate::PresetCommands;
+use crate::config::{ModelConfig, ModelPreset, PrenCliConfig};
+use crate::constants::PREN_CLI;
+use crate::errors::ConfigError;
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result};
+use serde_json::json;
+use tracing::info;
+
+/// Adds, lists or removes model presets, saving the config when it changes.
+pub fn run_preset_command(
+    mut config: PrenCliConfig,
+    command: PresetCommands,
+    output: OutputMode,
+) -> Result<()> {
+    match command {
+        PresetCommands::Add {
+            name,
+            model_name,
+            api_key,
+            base_url,
+            provider,
+        } => {
+            let preset = ModelPreset {
+                model_name,
+                api_key,
+                base_url,
+                provider,
+            };
+            config.presets.insert(name.clone(), preset);
+            confy::store(PREN_CLI, None, &config).context("Failed to save configuration")?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "name": name, "added": true }))?,
+                OutputMode::Text => println!("Preset '{}' added", name),
+            }
+        }
+        PresetCommands::List => {
+            let presets: Vec<_> = config
+                .presets
+                .iter()
+                .map(|(name, preset)| {
+                    json!({
+                        "name": name,
+                        "model_name": preset.model_name,
+                        "provider": preset.provider,
+                        "base_url": preset.base_url,
+                        "api_key": preset.api_key.as_ref().map(|_| "********"),
+                    })
+                })
+                .collect();
+            if output == OutputMode::Json {
+                return print_json(&presets);
+            }
+            if presets.is_empty() {
+                println!("No presets yet. Use `pren preset add` to create one.");
+            }
+            for (name, preset) in &config.presets {
+                let mut settings = Vec::new();
+                if let Some(model_name) = &preset.model_name {
+                    settings.push(format!("model: {}", model_name));
+                }
+                if let Some(provider) = preset.provider {
+                    settings.push(format!("provider: {}", provider));
+                }
+                if let Some(base_url) = &preset.base_url {
+                    settings.push(format!("base url: {}", base_url));
+                }
+                if preset.api_key.is_some() {
+                    settings.push("api key: ********".to_string());
+                }
+                println!("{} ({})", name, settings.join(", "));
+            }
+        }
+        PresetCommands::Remove { name } => {
+            if config.presets.remove(&name).is_none() {
+                return Err(ConfigError(format!("Preset '{}' doesn't exist", name)).into());
+            }
+            confy::store(PREN_CLI, None, &config).context("Failed to save configuration")?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "name": name, "removed": true }))?,
+                OutputMode::Text => println!("Preset '{}' removed", name),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies `--preset` over the model config of the profile in use. The preset's model also
+/// replaces the prompt's preferred one, unless a model is given with `--model`.
+pub fn apply_preset(
+    config: &PrenCliConfig,
+    model_config: &ModelConfig,
+    preset: Option<&str>,
+    model: Option<String>,
+) -> Result<(ModelConfig, Option<String>)> {
+    let Some(name) = preset else {
+        return Ok((model_config.clone(), model));
+    };
+    let preset = config.preset(name)?;
+    info!("Using preset '{}'", name);
+    Ok((
+        model_config.with_preset(preset),
+        model.or(preset.model_name.clone()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_preset() {
+        let mut config = PrenCliConfig::default();
+        config.presets.insert(
+            "fast".to_string(),
+            ModelPreset {
+                model_name: Some("small".to_string()),
+                api_key: None,
+                base_url: Some("http://localhost:8080/v1".to_string()),
+                provider: None,
+            },
+        );
+        let model_config = config.model_config.clone();
+
+        let (unchanged, model) =
+            apply_preset(&config, &model_config, None, Some("big".to_string())).unwrap();
+        assert_eq!(model_config.base_url, unchanged.base_url);
+        assert_eq!(Some("big".to_string()), model);
+
+        // The preset's model replaces the prompt's one, unless --model is given
+        let (preset_config, model) =
+            apply_preset(&config, &model_config, Some("fast"), None).unwrap();
+        assert_eq!("http://localhost:8080/v1", preset_config.base_url);
+        assert_eq!(Some("small".to_string()), model);
+        let (_, model) = apply_preset(
+            &config,
+            &model_config,
+            Some("fast"),
+            Some("big".to_string()),
+        )
+        .unwrap();
+        assert_eq!(Some("big".to_string()), model);
+
+        let error = apply_preset(&config, &model_config, Some("missing"), None)
+            .err()
+            .unwrap();
+        assert!(error.is::<ConfigError>());
+    }
+}
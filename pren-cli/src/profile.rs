@@ -0,0 +1,177 @@
+use crate::ProfileCommands;
+use crate::config::{ModelConfig, PrenCliConfig, Profile};
+use crate::constants::{DEFAULT_PROFILE, PREN_CLI};
+use crate::errors::ConfigError;
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result, bail};
+use pren_core::llm::Provider;
+use serde_json::json;
+
+/// Adds, lists or switches profiles, saving the config when it changes.
+pub fn run_profile_command(
+    mut config: PrenCliConfig,
+    command: ProfileCommands,
+    output: OutputMode,
+) -> Result<()> {
+    match command {
+        ProfileCommands::Add {
+            name,
+            path,
+            model_name,
+            api_key,
+            base_url,
+            provider,
+            vault,
+        } => {
+            if name == DEFAULT_PROFILE || config.profiles.contains_key(&name) {
+                bail!("Profile '{}' already exists", name);
+            }
+            let model_config = profile_model_config(
+                &config.model_config,
+                model_name,
+                api_key,
+                base_url,
+                provider,
+            );
+            config.profiles.insert(
+                name.clone(),
+                Profile {
+                    base_path: path.display().to_string(),
+                    model_config,
+                    vault,
+                },
+            );
+            confy::store(PREN_CLI, None, &config).context("Failed to save configuration")?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "name": name, "added": true }))?,
+                OutputMode::Text => println!("Profile '{}' added", name),
+            }
+        }
+        ProfileCommands::List => {
+            let active = config.active_profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+            let profiles = std::iter::once((DEFAULT_PROFILE, &config.base_path)).chain(
+                config
+                    .profiles
+                    .iter()
+                    .map(|(name, profile)| (name.as_str(), &profile.base_path)),
+            );
+            if output == OutputMode::Json {
+                let profiles: Vec<_> = profiles
+                    .map(|(name, base_path)| {
+                        json!({ "name": name, "base_path": base_path, "active": name == active })
+                    })
+                    .collect();
+                return print_json(&profiles);
+            }
+            for (name, base_path) in profiles {
+                let marker = if name == active { "*" } else { " " };
+                println!("{} {} ({})", marker, name, base_path);
+            }
+        }
+        ProfileCommands::Use { name } => {
+            use_profile(&mut config, &name)?;
+            confy::store(PREN_CLI, None, &config).context("Failed to save configuration")?;
+            match output {
+                OutputMode::Json => print_json(&json!({ "name": name, "active": true }))?,
+                OutputMode::Text => println!("Using profile '{}'", name),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Gets the model config of a new profile, or `None` to use the default one if no setting
+/// is given. Settings that aren't given are taken from the default config, except the base
+/// URL and the API key when the profile uses another provider, as they belong to the
+/// default one.
+fn profile_model_config(
+    default: &ModelConfig,
+    model_name: Option<String>,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    provider: Option<Provider>,
+) -> Option<ModelConfig> {
+    let overridden =
+        model_name.is_some() || api_key.is_some() || base_url.is_some() || provider.is_some();
+    overridden.then(|| {
+        let other_provider = provider.is_some_and(|provider| provider != default.provider);
+        let base_url = match base_url {
+            None if other_provider => String::new(),
+            base_url => base_url.unwrap_or(default.base_url.clone()),
+        };
+        let api_key = match api_key {
+            None if other_provider => None,
+            api_key => api_key.or(default.api_key.clone()),
+        };
+        ModelConfig {
+            model_name: model_name.unwrap_or(default.model_name.clone()),
+            api_key,
+            base_url,
+            provider: provider.unwrap_or(default.provider),
+            ..default.clone()
+        }
+    })
+}
+
+/// Makes a profile the active one, or the default profile with [`DEFAULT_PROFILE`].
+fn use_profile(config: &mut PrenCliConfig, name: &str) -> Result<()> {
+    if name != DEFAULT_PROFILE && !config.profiles.contains_key(name) {
+        return Err(ConfigError(format!("Profile '{}' doesn't exist", name)).into());
+    }
+    config.active_profile = (name != DEFAULT_PROFILE).then(|| name.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_model_config() {
+        let default = ModelConfig {
+            api_key: Some("default-key".to_string()),
+            ..ModelConfig::default()
+        };
+        assert!(profile_model_config(&default, None, None, None, None).is_none());
+
+        let model_config =
+            profile_model_config(&default, Some("small".to_string()), None, None, None).unwrap();
+        assert_eq!("small", model_config.model_name);
+        assert_eq!(default.base_url, model_config.base_url);
+        assert_eq!(Some("default-key".to_string()), model_config.api_key);
+
+        // The base URL and API key of the default provider aren't used with another one
+        let other = if default.provider == Provider::Ollama {
+            Provider::OpenAi
+        } else {
+            Provider::Ollama
+        };
+        let model_config = profile_model_config(&default, None, None, None, Some(other)).unwrap();
+        assert_eq!(other, model_config.provider);
+        assert_eq!("", model_config.base_url);
+        assert_eq!(None, model_config.api_key);
+        assert_eq!(default.model_name, model_config.model_name);
+    }
+
+    #[test]
+    fn test_use_profile() {
+        let mut config = PrenCliConfig::default();
+        config.profiles.insert(
+            "work".to_string(),
+            Profile {
+                base_path: "/tmp/work".to_string(),
+                model_config: None,
+                vault: false,
+            },
+        );
+
+        use_profile(&mut config, "work").unwrap();
+        assert_eq!(Some("work".to_string()), config.active_profile);
+        use_profile(&mut config, DEFAULT_PROFILE).unwrap();
+        assert_eq!(None, config.active_profile);
+
+        let error = use_profile(&mut config, "missing").unwrap_err();
+        assert!(error.is::<ConfigError>());
+        assert_eq!(None, config.active_profile);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::arguments::build_args_map;
+use crate::history::record_usage;
+use crate::output::print_json;
+use anyhow::{Context, Result, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::prompt::PromptTemplate;
+use pren_core::stats::UsageKind;
+use pren_core::storage::PromptStorage;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// Renders a prompt for launchers like Alfred and Raycast, printing nothing but the
+/// rendered prompt, without a trailing newline so it can be pasted as it is.
+///
+/// Arguments are `KEY=value` pairs, or values filling the arguments of the prompt that
+/// aren't given otherwise, in the order they appear in.
+pub fn run_quick(storage: &FileStorage, name: &str, args: &[String]) -> Result<()> {
+    let template = PromptTemplate::new(storage.get_prompt(name)?)?;
+    let args_map = quick_arguments(&template, args)?;
+    let rendered = template.render(&args_map, storage)?;
+    print!("{}", rendered);
+    record_usage(storage, name, UsageKind::Render);
+    Ok(())
+}
+
+/// Matches the values of `quick` to the arguments of a prompt.
+fn quick_arguments(template: &PromptTemplate, args: &[String]) -> Result<HashMap<String, Value>> {
+    let names: Vec<String> = template
+        .argument_info()
+        .into_iter()
+        .map(|argument| argument.name)
+        .collect();
+    let mut pairs = Vec::new();
+    let mut values = Vec::new();
+    for arg in args {
+        match arg.split_once('=') {
+            Some((key, value)) if names.iter().any(|name| name == key) => {
+                pairs.push((key.to_string(), value.to_string()))
+            }
+            _ => values.push(arg.clone()),
+        }
+    }
+    let unset: Vec<String> = names
+        .into_iter()
+        .filter(|name| !pairs.iter().any(|(key, _)| key == name))
+        .collect();
+    let mut unset = unset.into_iter();
+    for value in values {
+        let Some(name) = unset.next() else {
+            bail!(
+                "Too many arguments: '{}' has no argument left for '{}'",
+                template.prompt.metadata.name,
+                value
+            );
+        };
+        pairs.push((name, value));
+    }
+    build_args_map(&pairs, None, None)
+}
+
+/// Prints the items of an Alfred script filter (which Raycast can use too) for a query.
+///
+/// A query starting with the name of a prompt shows the prompt rendered with the rest of
+/// the query as the value of its first argument, to be copied or pasted. Other queries
+/// list the prompts whose name or description contain every word of the query, each
+/// completing the query with its name.
+pub fn run_script_filter(storage: &FileStorage, query: &str) -> Result<()> {
+    let query = query.trim_start();
+    let (name, rest) = query.split_once(' ').unwrap_or((query, ""));
+    let items = match storage.get_prompt(name) {
+        Ok(prompt) => vec![rendered_item(storage, PromptTemplate::new(prompt)?, rest)?],
+        Err(_) => matching_items(storage, query)?,
+    };
+    print_json(&json!({ "items": items }))
+}
+
+fn rendered_item(storage: &FileStorage, template: PromptTemplate, value: &str) -> Result<Value> {
+    let name = template.prompt.metadata.name.clone();
+    let values: Vec<String> = match value.trim() {
+        "" => Vec::new(),
+        value => vec![value.to_string()],
+    };
+    let rendered =
+        quick_arguments(&template, &values).and_then(|args| Ok(template.render(&args, storage)?));
+    Ok(match rendered {
+        Ok(text) => {
+            record_usage(storage, &name, UsageKind::Render);
+            let title = text.lines().find(|line| !line.trim().is_empty());
+            json!({
+                "uid": name,
+                "title": title.unwrap_or(&name),
+                "subtitle": format!("Copy '{}' as rendered", name),
+                "arg": text,
+                "valid": true,
+                "text": { "copy": text, "largetype": text }
+            })
+        }
+        Err(e) => json!({
+            "uid": name,
+            "title": name,
+            "subtitle": format!("{:#}", e),
+            "autocomplete": format!("{} ", name),
+            "valid": false
+        }),
+    })
+}
+
+fn matching_items(storage: &FileStorage, query: &str) -> Result<Vec<Value>> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let summaries = storage
+        .get_prompt_summaries()
+        .context("Failed to list the prompts")?;
+    Ok(summaries
+        .into_iter()
+        .filter(|summary| {
+            let text = format!(
+                "{} {}",
+                summary.name,
+                summary.description.as_deref().unwrap_or_default()
+            )
+            .to_lowercase();
+            words.iter().all(|word| text.contains(word))
+        })
+        .map(|summary| {
+            json!({
+                "uid": summary.name,
+                "title": summary.name,
+                "subtitle": summary.description.unwrap_or_default(),
+                "autocomplete": format!("{} ", summary.name),
+                "valid": false
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::prompt::{Prompt, PromptMetadata};
+    use tempfile::TempDir;
+
+    fn storage_with(sandbox: &TempDir, prompts: &[(&str, &str, &str)]) -> FileStorage {
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        for (name, description, content) in prompts {
+            storage
+                .save_prompt(&Prompt::new(
+                    PromptMetadata::new(name.to_string(), Some(description.to_string()), vec![]),
+                    content.to_string(),
+                ))
+                .unwrap();
+        }
+        storage
+    }
+
+    #[test]
+    fn test_quick_arguments() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = storage_with(
+            &sandbox,
+            &[(
+                "translate",
+                "Translate text",
+                "Translate {{text}} to {{language}}.",
+            )],
+        );
+        let template = PromptTemplate::new(storage.get_prompt("translate").unwrap()).unwrap();
+
+        let args = quick_arguments(
+            &template,
+            &["language=French".to_string(), "Good morning".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            "Translate Good morning to French.",
+            template.render(&args, &storage).unwrap()
+        );
+
+        let too_many = ["a".to_string(), "b".to_string(), "c".to_string()];
+        assert!(quick_arguments(&template, &too_many).is_err());
+    }
+
+    #[test]
+    fn test_matching_items() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = storage_with(
+            &sandbox,
+            &[
+                ("translate", "Translate text", "Translate {{text}}."),
+                ("summarize", "Summarize a text", "Summarize {{text}}."),
+            ],
+        );
+
+        let items = matching_items(&storage, "TEXT summ").unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!("summarize", items[0]["uid"]);
+        assert_eq!("summarize ", items[0]["autocomplete"]);
+        assert_eq!(2, matching_items(&storage, "").unwrap().len());
+
+        let template = PromptTemplate::new(storage.get_prompt("translate").unwrap()).unwrap();
+        let item = rendered_item(&storage, template, " hello ").unwrap();
+        assert_eq!("Translate hello.", item["arg"]);
+        assert_eq!(true, item["valid"]);
+    }
+}
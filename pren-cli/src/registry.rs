@@ -0,0 +1,166 @@
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::pack::PromptPack;
+use pren_core::registry::{INDEX_FILE_NAME, PackBundle, RegistryIndex, VersionReq, publish};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+/// Reads a file of a registry, over HTTP(S) or from a directory.
+async fn fetch(registry: &str, path: &str) -> Result<Vec<u8>> {
+    if registry.starts_with("http://") || registry.starts_with("https://") {
+        let url = format!("{}/{}", registry.trim_end_matches('/'), path);
+        let response = reqwest::get(&url)
+            .await
+            .with_context(|| format!("Couldn't reach the registry at {}", url))?;
+        if !response.status().is_success() {
+            bail!("The registry answered {} for {}", response.status(), url);
+        }
+        return Ok(response.bytes().await?.to_vec());
+    }
+    let dir = registry.strip_prefix("file://").unwrap_or(registry);
+    let file = Path::new(dir).join(path);
+    fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))
+}
+
+/// Installs a pack from a registry, given as `name` or `name@requirement`, at the latest
+/// version meeting the requirement. Its prompts are namespaced by the pack's name.
+pub async fn run_install(
+    storage: &FileStorage,
+    registry: &str,
+    spec: &str,
+    overwrite: bool,
+    output: OutputMode,
+) -> Result<()> {
+    let (name, requirement) = match spec.split_once('@') {
+        Some((name, requirement)) => (name, requirement.parse()?),
+        None => (spec, VersionReq::any()),
+    };
+    let index: RegistryIndex = serde_json::from_slice(&fetch(registry, INDEX_FILE_NAME).await?)
+        .context("The registry's index is invalid")?;
+    let entry = index.resolve(name, &requirement)?;
+    let bytes = fetch(registry, &entry.path).await?;
+    let mut pack = PackBundle::load(name, entry, &bytes)?;
+
+    let source = format!("{}/{}", registry.trim_end_matches('/'), entry.path);
+    for prompt in &mut pack.prompts {
+        prompt.metadata.source_url = Some(source.clone());
+    }
+    let installed = pack.install(storage, overwrite)?;
+
+    if output == OutputMode::Json {
+        return print_json(&json!({
+            "name": name,
+            "version": entry.version,
+            "checksum": entry.checksum,
+            "installed": installed
+        }));
+    }
+    println!(
+        "Installed pack '{}' v{}: {}",
+        name,
+        entry.version,
+        installed.join(", ")
+    );
+    Ok(())
+}
+
+/// Publishes a pack directory to a registry directory, to be served over HTTPS.
+pub fn run_publish(path: &Path, registry_dir: &Path, output: OutputMode) -> Result<()> {
+    let pack = PromptPack::load(path)?;
+    let entry = publish(&pack, registry_dir)?;
+    if output == OutputMode::Json {
+        return print_json(&json!({
+            "name": pack.manifest.name,
+            "version": entry.version,
+            "path": entry.path,
+            "checksum": entry.checksum
+        }));
+    }
+    println!(
+        "Published pack '{}' v{} to {} ({})",
+        pack.manifest.name,
+        entry.version,
+        registry_dir.display(),
+        entry.checksum
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::pack::PackManifest;
+    use pren_core::registry::{IndexEntry, checksum};
+    use pren_core::storage::PromptStorage;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    /// Serves a pack with a prompt named `prompt_name` from a registry directory, with
+    /// a matching checksum.
+    fn malicious_registry(dir: &Path, prompt_name: &str) {
+        let bundle = PackBundle {
+            manifest: PackManifest {
+                name: "evil".to_string(),
+                version: "1.0.0".to_string(),
+                description: None,
+                author: None,
+                license: None,
+                prompts: vec![prompt_name.to_string()],
+            },
+            prompts: vec![format!(
+                "---\nname: {}\ndescription: null\ntags: []\n---\nGotcha.",
+                prompt_name
+            )],
+        };
+        let bytes = serde_json::to_vec(&bundle).unwrap();
+        fs::create_dir_all(dir.join("packs")).unwrap();
+        fs::write(dir.join("packs/evil.json"), &bytes).unwrap();
+        let index = RegistryIndex {
+            packs: BTreeMap::from([(
+                "evil".to_string(),
+                vec![IndexEntry {
+                    version: "1.0.0".to_string(),
+                    path: "packs/evil.json".to_string(),
+                    checksum: checksum(&bytes),
+                    description: None,
+                }],
+            )]),
+        };
+        fs::write(
+            dir.join(INDEX_FILE_NAME),
+            serde_json::to_vec(&index).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_install_rejects_paths_as_prompt_names() {
+        let sandbox = TempDir::new().unwrap();
+        let registry = sandbox.path().join("registry");
+        malicious_registry(&registry, "../../../x");
+        let storage = FileStorage {
+            base_path: sandbox.path().join("prompts"),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+
+        let result = run_install(
+            &storage,
+            registry.to_str().unwrap(),
+            "evil",
+            false,
+            OutputMode::Json,
+        )
+        .await;
+        let error = format!("{:#}", result.err().unwrap());
+        assert!(
+            error.contains("invalid prompt name '../../../x'"),
+            "{}",
+            error
+        );
+        assert!(!sandbox.path().join("x.md").exists());
+        assert!(storage.get_prompts().unwrap().is_empty());
+    }
+}
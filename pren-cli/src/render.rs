@@ -0,0 +1,499 @@
+use crate::arguments::{add_stdin_arg, build_args_map};
+use crate::clipboard::copy_or_warn;
+use crate::history::{record_history, record_usage};
+use crate::output::{
+    OutputMode, RenderFormat, print_json, print_warnings, write_output_file, write_rendered,
+};
+use crate::pick::{ask_missing_arguments, pick_prompt};
+use anyhow::{Context, Result};
+use pren_core::file_storage::FileStorage;
+use pren_core::history::output_hash;
+use pren_core::messages::ChatRequest;
+use pren_core::prompt::{
+    ArgumentStatus, PromptTemplate, ReferenceNode, RenderDryRun, RenderTrace, RenderWarning,
+    TracedPrompt,
+};
+use pren_core::stats::UsageKind;
+use pren_core::storage::PromptStorage;
+use serde_json::json;
+use std::path::PathBuf;
+
+/// What `pren render` renders and where it writes the result.
+pub struct RenderOptions {
+    pub name: Option<String>,
+    pub names: Vec<String>,
+    pub sep: Option<String>,
+    pub args: Vec<(String, String)>,
+    pub args_json: Option<String>,
+    pub arg_stdin: Option<String>,
+    pub context: Option<PathBuf>,
+    pub interactive: bool,
+    pub copy: bool,
+    pub plan: bool,
+    pub dry_run: bool,
+    pub trace: bool,
+    pub out: Option<PathBuf>,
+    pub stream: bool,
+    pub append: bool,
+    pub render_as: RenderFormat,
+}
+
+/// Which prompt `pren get` copies and the arguments to render it with.
+pub struct GetOptions {
+    pub name: Option<String>,
+    pub args: Vec<(String, String)>,
+    pub args_json: Option<String>,
+    pub arg_stdin: Option<String>,
+    pub context: Option<PathBuf>,
+    pub interactive: bool,
+}
+
+/// Renders one prompt, or several joined by a separator, and prints, copies or writes the
+/// result, or shows how the prompt would be rendered without rendering it.
+pub fn run_render(
+    storage: &FileStorage,
+    options: RenderOptions,
+    output: OutputMode,
+    use_clipboard: bool,
+) -> Result<()> {
+    let RenderOptions {
+        name,
+        names,
+        sep,
+        mut args,
+        args_json,
+        arg_stdin,
+        context,
+        interactive,
+        copy,
+        plan,
+        dry_run,
+        trace,
+        out,
+        stream,
+        append,
+        render_as,
+    } = options;
+    if !names.is_empty() {
+        add_stdin_arg(&mut args, arg_stdin.as_deref())?;
+        let mut args_map = build_args_map(&args, args_json.as_deref(), context.as_deref())?;
+        let mut texts = Vec::new();
+        // Stacked prompts share their arguments, so only those none of them use are unused
+        let mut unused: Option<Vec<String>> = None;
+        for name in &names {
+            let template = PromptTemplate::new(storage.get_prompt(name)?)
+                .context(format!("Error rendering prompt '{}'", name))?;
+            if interactive {
+                ask_missing_arguments(&template, &mut args_map, storage)?;
+            }
+            let mut rendered = template
+                .render_with_warnings(&args_map, storage)
+                .context(format!("Error rendering prompt '{}'", name))?;
+            let mut unused_here = Vec::new();
+            rendered.warnings.retain(|warning| match warning {
+                RenderWarning::UnusedArguments { names } => {
+                    unused_here.clone_from(names);
+                    false
+                }
+                _ => true,
+            });
+            unused = Some(match unused {
+                Some(unused) => unused
+                    .into_iter()
+                    .filter(|name| unused_here.contains(name))
+                    .collect(),
+                None => unused_here,
+            });
+            print_warnings(&rendered.warnings);
+            texts.push(rendered.text);
+        }
+        if let Some(names) = unused.filter(|names| !names.is_empty()) {
+            print_warnings(&[RenderWarning::UnusedArguments { names }]);
+        }
+        let separator = sep
+            .as_deref()
+            .map_or("\n\n".to_string(), unescape_separator);
+        let text = texts.join(&separator);
+
+        if let Some(path) = &out {
+            write_output_file(path, &text, append)?;
+            if output == OutputMode::Json {
+                print_json(&json!({ "names": names, "out": path }))?;
+            }
+        } else {
+            match output {
+                OutputMode::Json => print_json(&json!({ "names": names, "text": text }))?,
+                OutputMode::Text => println!("{}", text),
+            }
+            if copy {
+                copy_or_warn(&text, use_clipboard);
+            }
+        }
+        for (name, text) in names.iter().zip(&texts) {
+            record_usage(storage, name, UsageKind::Render);
+            record_history(
+                storage,
+                name,
+                UsageKind::Render,
+                &args_map,
+                output_hash(text),
+            );
+        }
+        return Ok(());
+    }
+
+    let prompt = match name {
+        Some(name) => storage.get_prompt(&name)?,
+        None => pick_prompt(storage)?,
+    };
+    let name = prompt.metadata.name.clone();
+
+    add_stdin_arg(&mut args, arg_stdin.as_deref())?;
+    let mut args_map = build_args_map(&args, args_json.as_deref(), context.as_deref())?;
+    let template =
+        PromptTemplate::new(prompt).context(format!("Error rendering prompt '{}'", name))?;
+    if interactive {
+        ask_missing_arguments(&template, &mut args_map, storage)?;
+    }
+    if dry_run {
+        let dry_run = template.dry_run(&args_map, storage);
+        return match output {
+            OutputMode::Json => print_json(&dry_run),
+            OutputMode::Text => {
+                print_dry_run(&dry_run);
+                Ok(())
+            }
+        };
+    }
+    if plan {
+        let render_plan = template.render_plan(&args_map, storage)?;
+        return print_json(&render_plan);
+    }
+    if trace {
+        let render_trace = template.render_traced(&args_map, storage)?;
+        return match output {
+            OutputMode::Json => print_json(&render_trace),
+            OutputMode::Text => {
+                print_trace(&render_trace);
+                Ok(())
+            }
+        };
+    }
+    let rendered_hash = if render_as == RenderFormat::OpenaiJson {
+        let rendered = template.render_with_warnings(&args_map, storage)?;
+        print_warnings(&rendered.warnings);
+        let request =
+            ChatRequest::from_rendered(&rendered.text, &template.prompt.metadata.model_settings);
+        let body = serde_json::to_string_pretty(&request)?;
+        match &out {
+            Some(path) => {
+                write_output_file(path, &body, append)?;
+                if output == OutputMode::Json {
+                    print_json(&json!({ "name": name, "out": path }))?;
+                }
+            }
+            None => println!("{}", body),
+        }
+        if copy {
+            copy_or_warn(&body, use_clipboard);
+        }
+        output_hash(&rendered.text)
+    } else if let Some(path) = &out {
+        let rendered = template.render_with_warnings(&args_map, storage)?;
+        print_warnings(&rendered.warnings);
+        write_output_file(path, &rendered.text, append)?;
+        if output == OutputMode::Json {
+            print_json(&json!({ "name": name, "out": path }))?;
+        }
+        output_hash(&rendered.text)
+    } else if output == OutputMode::Json {
+        let rendered = template.render_with_warnings(&args_map, storage)?;
+        print_warnings(&rendered.warnings);
+        print_json(&json!({ "name": name, "text": rendered.text }))?;
+        if copy {
+            copy_or_warn(&rendered.text, use_clipboard);
+        }
+        output_hash(&rendered.text)
+    } else if copy {
+        let output = template.render_with_warnings(&args_map, storage)?;
+        print_warnings(&output.warnings);
+        println!("{}", output.text);
+        copy_or_warn(&output.text, use_clipboard);
+        output_hash(&output.text)
+    } else {
+        let (warnings, rendered_hash) = write_rendered(
+            &template,
+            &args_map,
+            storage,
+            stream,
+            std::io::stdout().lock(),
+        )?;
+        print_warnings(&warnings);
+        rendered_hash
+    };
+    record_usage(storage, &name, UsageKind::Render);
+    record_history(storage, &name, UsageKind::Render, &args_map, rendered_hash);
+    Ok(())
+}
+
+/// Renders a prompt and copies the result to the clipboard, printing it if it can't be
+/// copied.
+pub fn run_get(
+    storage: &FileStorage,
+    options: GetOptions,
+    output: OutputMode,
+    use_clipboard: bool,
+) -> Result<()> {
+    let GetOptions {
+        name,
+        mut args,
+        args_json,
+        arg_stdin,
+        context,
+        interactive,
+    } = options;
+    let prompt = match name {
+        Some(name) => storage.get_prompt(&name)?,
+        None => pick_prompt(storage)?,
+    };
+    let name = prompt.metadata.name.clone();
+    add_stdin_arg(&mut args, arg_stdin.as_deref())?;
+    let mut args_map = build_args_map(&args, args_json.as_deref(), context.as_deref())?;
+    let template = PromptTemplate::new(prompt)?;
+    if interactive {
+        ask_missing_arguments(&template, &mut args_map, storage)?;
+    }
+    let rendered = template.render_with_warnings(&args_map, storage)?;
+    print_warnings(&rendered.warnings);
+    let copied = copy_or_warn(&rendered.text, use_clipboard);
+    match output {
+        OutputMode::Json => {
+            print_json(&json!({ "name": name, "text": rendered.text, "copied": copied }))?
+        }
+        // Print what couldn't be copied so it isn't lost
+        OutputMode::Text if !copied => println!("{}", rendered.text),
+        OutputMode::Text => {}
+    }
+    let rendered_hash = output_hash(&rendered.text);
+    record_usage(storage, &name, UsageKind::Render);
+    record_history(storage, &name, UsageKind::Render, &args_map, rendered_hash);
+    Ok(())
+}
+
+fn print_dry_run(dry_run: &RenderDryRun) {
+    println!("{}", dry_run.prompt);
+    print_reference_tree(&dry_run.references, "");
+
+    if !dry_run.arguments.is_empty() {
+        println!("\nArguments:");
+        let width = dry_run
+            .arguments
+            .iter()
+            .map(|argument| argument.name.len())
+            .max()
+            .unwrap_or(0);
+        for argument in &dry_run.arguments {
+            let status = match argument.status {
+                ArgumentStatus::Provided => "provided".to_string(),
+                ArgumentStatus::Default => format!(
+                    "default: {}",
+                    argument.default.as_deref().unwrap_or_default()
+                ),
+                ArgumentStatus::Missing => "missing".to_string(),
+            };
+            let mut line = format!("  {:<width$}  {}", argument.name, status, width = width);
+            if argument.prompt != dry_run.prompt {
+                line.push_str(&format!(" (in '{}')", argument.prompt));
+            }
+            if let Some(description) = &argument.description {
+                line.push_str(&format!(" - {}", description));
+            }
+            println!("{}", line);
+        }
+    }
+
+    println!();
+    match dry_run.estimated_tokens {
+        Some(tokens) => println!("Estimated tokens: {}", tokens),
+        None => println!("Estimated tokens: unknown"),
+    }
+    if let Some(error) = &dry_run.error {
+        println!("Rendering would fail: {}", error);
+    } else if !dry_run.is_renderable() {
+        println!("Rendering would fail: missing arguments");
+    }
+}
+
+fn print_reference_tree(nodes: &[ReferenceNode], indent: &str) {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index == nodes.len() - 1;
+        let mut label = match (&node.name, &node.variable) {
+            (Some(name), None) => name.clone(),
+            (Some(name), Some(variable)) => format!("{} (from {})", name, variable),
+            (None, Some(variable)) => format!("? (from {})", variable),
+            (None, None) => "?".to_string(),
+        };
+        if node.optional {
+            label.push_str(" (optional)");
+        }
+        if let Some(error) = &node.error {
+            label.push_str(&format!(" - error: {}", error));
+        }
+        println!("{}{} {}", indent, if last { "└──" } else { "├──" }, label);
+        let child_indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+        print_reference_tree(&node.references, &child_indent);
+    }
+}
+
+/// How many characters of the output of each prompt a trace shows.
+const TRACE_PREVIEW_CHARS: usize = 40;
+
+fn print_trace(trace: &RenderTrace) {
+    println!("{}\n", trace.output);
+    println!("{}", traced_prompt_label(&trace.root, &trace.output));
+    print_traced_prompts(&trace.root.references, &trace.output, "");
+}
+
+fn print_traced_prompts(prompts: &[TracedPrompt], output: &str, indent: &str) {
+    for (index, prompt) in prompts.iter().enumerate() {
+        let last = index == prompts.len() - 1;
+        println!(
+            "{}{} {}",
+            indent,
+            if last { "└──" } else { "├──" },
+            traced_prompt_label(prompt, output)
+        );
+        let child_indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+        print_traced_prompts(&prompt.references, output, &child_indent);
+    }
+}
+
+/// Labels a traced prompt with its resolution order, its span and the start of its output.
+fn traced_prompt_label(prompt: &TracedPrompt, output: &str) -> String {
+    let text = output.get(prompt.span.clone()).unwrap_or_default();
+    let mut preview: String = text.chars().take(TRACE_PREVIEW_CHARS).collect();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    format!(
+        "{} #{} [{}..{}] {:?}",
+        prompt.name, prompt.order, prompt.span.start, prompt.span.end, preview
+    )
+}
+
+/// Replaces the `\n`, `\t` and `\\` escapes of a separator typed in a shell, where they
+/// aren't expanded inside quotes.
+fn unescape_separator(separator: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = separator.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::prompt::{Prompt, PromptMetadata};
+    use tempfile::TempDir;
+
+    fn options(names: &[&str], out: PathBuf) -> RenderOptions {
+        RenderOptions {
+            name: None,
+            names: names.iter().map(|name| name.to_string()).collect(),
+            sep: Some("\\n---\\n".to_string()),
+            args: vec![("topic".to_string(), "Rust".to_string())],
+            args_json: None,
+            arg_stdin: None,
+            context: None,
+            interactive: false,
+            copy: false,
+            plan: false,
+            dry_run: false,
+            trace: false,
+            out: Some(out),
+            stream: false,
+            append: false,
+            render_as: RenderFormat::default(),
+        }
+    }
+
+    #[test]
+    fn test_unescape_separator() {
+        assert_eq!("\n---\n", unescape_separator("\\n---\\n"));
+        assert_eq!("a\tb\\c", unescape_separator("a\\tb\\\\c"));
+        assert_eq!("\\x\\", unescape_separator("\\x\\"));
+    }
+
+    #[test]
+    fn test_traced_prompt_label() {
+        let prompt = TracedPrompt {
+            name: "persona".to_string(),
+            order: 1,
+            depth: 1,
+            span: 0..5,
+            references: Vec::new(),
+        };
+        assert_eq!(
+            "persona #1 [0..5] \"Hello\"",
+            traced_prompt_label(&prompt, "Hello world")
+        );
+    }
+
+    #[test]
+    fn test_run_render_stacks_prompts() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().join("prompts"),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        for (name, content) in [
+            ("persona", "You are an expert."),
+            ("task", "Explain {{topic}}."),
+        ] {
+            storage
+                .save_prompt(&Prompt::new(
+                    PromptMetadata::new(name.to_string(), None, vec![]),
+                    content.to_string(),
+                ))
+                .unwrap();
+        }
+
+        let out = sandbox.path().join("out.txt");
+        run_render(
+            &storage,
+            options(&["persona", "task"], out.clone()),
+            OutputMode::Json,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            "You are an expert.\n---\nExplain Rust.",
+            std::fs::read_to_string(&out).unwrap().trim_end()
+        );
+        assert!(
+            run_render(
+                &storage,
+                options(&["persona", "missing"], out),
+                OutputMode::Json,
+                false
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use crate::errors::ErrorKind;
+use crate::history::{record_history, record_usage};
+use anyhow::{Context, Result, anyhow};
+use pren_core::file_storage::{FileStorage, parse_prompt_file};
+use pren_core::history::output_hash;
+use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate, normalize_tag};
+use pren_core::stats::UsageKind;
+use pren_core::storage::PromptStorage;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+
+/// The JSON-RPC error code of requests that aren't valid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// The JSON-RPC error code of requests without a method.
+const INVALID_REQUEST: i64 = -32600;
+/// The JSON-RPC error code of unknown methods.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// The JSON-RPC error code of methods called with the wrong params.
+const INVALID_PARAMS: i64 = -32602;
+/// The JSON-RPC error code of methods that failed, e.g. rendering a missing prompt.
+const SERVER_ERROR: i64 = -32000;
+
+/// A JSON-RPC request. Requests without an id are notifications, which aren't answered.
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// An error answered to a request, with the kind of the error as its data when a method
+/// failed.
+struct RpcError {
+    code: i64,
+    message: String,
+    kind: Option<ErrorKind>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            kind: None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        RpcError {
+            code: SERVER_ERROR,
+            message: format!("{:#}", error),
+            kind: Some(ErrorKind::of(&error)),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ListParams {
+    tag: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GetParams {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RenderParams {
+    name: String,
+    #[serde(default)]
+    arguments: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct ValidateParams {
+    /// The text of a prompt file, with or without its frontmatter.
+    content: String,
+}
+
+/// Serves the prompts of the storage over JSON-RPC 2.0 on stdin and stdout, one message per
+/// line, until stdin is closed. Editor plugins keep it running to list, get, render and
+/// validate prompts without starting pren for every request.
+pub fn run_stdio_server(storage: &FileStorage) -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let line = line.context("Failed to read a request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_line(storage, &line) {
+            writeln!(stdout, "{}", response)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Answers a line of input, or returns `None` for notifications.
+fn handle_line(storage: &FileStorage, line: &str) -> Option<Value> {
+    let message: Value = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, format!("Invalid JSON: {}", e));
+            return Some(response(Value::Null, Err(error)));
+        }
+    };
+    let request: Request = match serde_json::from_value(message.clone()) {
+        Ok(request) => request,
+        Err(e) => {
+            let id = message.get("id").cloned().unwrap_or_default();
+            let error = RpcError::new(INVALID_REQUEST, format!("Invalid request: {}", e));
+            return Some(response(id, Err(error)));
+        }
+    };
+    let result = call(storage, &request.method, request.params);
+    request.id.map(|id| response(id, result))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => {
+            let mut body = json!({ "code": error.code, "message": error.message });
+            if let Some(kind) = error.kind {
+                body["data"] = json!({ "kind": kind });
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": body })
+        }
+    }
+}
+
+fn call(storage: &FileStorage, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "list" => Ok(list(storage, parse_params(params)?)?),
+        "get" => Ok(get(storage, parse_params(params)?)?),
+        "render" => Ok(render(storage, parse_params(params)?)?),
+        "validate" => Ok(validate(storage, parse_params(params)?)?),
+        method => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method '{}'", method),
+        )),
+    }
+}
+
+fn parse_params<P: DeserializeOwned>(params: Value) -> Result<P, RpcError> {
+    // Methods without params take an empty object
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", e)))
+}
+
+fn list(storage: &FileStorage, params: ListParams) -> Result<Value> {
+    let tag = params.tag.as_deref().map(normalize_tag);
+    let summaries: Vec<_> = storage
+        .get_prompt_summaries()?
+        .into_iter()
+        .filter(|summary| {
+            tag.as_ref()
+                .is_none_or(|tag| summary.tags.iter().any(|t| &normalize_tag(t) == tag))
+        })
+        .collect();
+    Ok(json!(summaries))
+}
+
+fn get(storage: &FileStorage, params: GetParams) -> Result<Value> {
+    let prompt = storage.get_prompt(&params.name)?;
+    let arguments = PromptTemplate::new(prompt.clone())?.argument_info();
+    let mut result = serde_json::to_value(&prompt)?;
+    result["arguments"] = json!(arguments);
+    Ok(result)
+}
+
+fn render(storage: &FileStorage, params: RenderParams) -> Result<Value> {
+    let RenderParams { name, arguments } = params;
+    let prompt = storage.get_prompt(&name)?;
+    let rendered = PromptTemplate::new(prompt)?.render_with_warnings(&arguments, storage)?;
+    record_usage(storage, &name, UsageKind::Render);
+    record_history(
+        storage,
+        &name,
+        UsageKind::Render,
+        &arguments,
+        output_hash(&rendered.text),
+    );
+    let warnings: Vec<String> = rendered.warnings.iter().map(|w| w.to_string()).collect();
+    Ok(json!({ "name": name, "text": rendered.text, "warnings": warnings }))
+}
+
+/// Validates the text of a prompt file as it's edited, answering its problems as
+/// diagnostics instead of failing, along with its arguments when it can be parsed.
+fn validate(storage: &FileStorage, params: ValidateParams) -> Result<Value> {
+    let prompt = if params.content.starts_with("---") {
+        parse_prompt_file(&params.content).map_err(|e| anyhow!(e))
+    } else {
+        let metadata = PromptMetadata::new(String::new(), None, Vec::new());
+        Ok(Prompt::new(metadata, params.content))
+    };
+    let template = prompt.and_then(|prompt| PromptTemplate::new(prompt).map_err(|e| anyhow!(e)));
+    let template = match template {
+        Ok(template) => template,
+        Err(e) => {
+            return Ok(json!({
+                "valid": false,
+                "diagnostics": [{ "severity": "error", "message": format!("{:#}", e) }],
+                "arguments": []
+            }));
+        }
+    };
+
+    let known: BTreeSet<String> = storage
+        .get_prompt_summaries()?
+        .into_iter()
+        .map(|summary| summary.name)
+        .collect();
+    let references: BTreeSet<String> = template.prompt_references().into_iter().collect();
+    let diagnostics: Vec<Value> = references
+        .difference(&known)
+        .map(|reference| {
+            json!({
+                "severity": "error",
+                "message": format!("References the missing prompt '{}'", reference)
+            })
+        })
+        .collect();
+    Ok(json!({
+        "valid": diagnostics.is_empty(),
+        "diagnostics": diagnostics,
+        "arguments": template.argument_info()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_handle_line() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("greet".to_string(), None, vec![]),
+                "Hello {{name}}!".to_string(),
+            ))
+            .unwrap();
+
+        let rendered = handle_line(
+            &storage,
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "render", "params": {"name": "greet", "arguments": {"name": "Ada"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(1, rendered["id"]);
+        assert_eq!("Hello Ada!", rendered["result"]["text"]);
+
+        let unknown = handle_line(&storage, r#"{"id": 2, "method": "delete"}"#).unwrap();
+        assert_eq!(METHOD_NOT_FOUND, unknown["error"]["code"]);
+
+        let invalid = handle_line(&storage, "not json").unwrap();
+        assert_eq!(PARSE_ERROR, invalid["error"]["code"]);
+        assert!(handle_line(&storage, r#"{"method": "list"}"#).is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+
+        let valid = validate(
+            &storage,
+            ValidateParams {
+                content: "Explain {{topic}}.".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(true, valid["valid"]);
+        assert_eq!("topic", valid["arguments"][0]["name"]);
+
+        let missing = validate(
+            &storage,
+            ValidateParams {
+                content: "{{prompt:persona}}".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(false, missing["valid"]);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::config::ModelConfig;
+use crate::constants::VALIDATION_RETRIES;
+use crate::errors::ErrorKind;
+use crate::history::{record_history, record_usage};
+use crate::usage::record_tokens;
+use anyhow::{Context, Result};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use pren_core::file_storage::{FileStorage, FileStorageError};
+use pren_core::history::output_hash;
+use pren_core::llm::{ModelSettings, get_chat_completion, get_completion_checked};
+use pren_core::messages::split_messages;
+use pren_core::policy::{ContentChecker, Severity};
+use pren_core::prompt::{PromptTemplate, normalize_tag, normalize_tags};
+use pren_core::search::{SearchField, search_prompts};
+use pren_core::stats::UsageKind;
+use pren_core::storage::PromptStorage;
+use pren_proto::server::PromptLibrary;
+use pren_proto::v1::prompt_service_server::PromptServiceServer;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::warn;
+
+/// The web UI, a single page using the API to browse, edit and render prompts.
+const WEB_UI: &str = include_str!("../web/index.html");
+
+/// What the server shares with its handlers.
+struct ServerState {
+    storage: FileStorage,
+    model_config: ModelConfig,
+    token: Option<String>,
+    allow_generate: bool,
+    read_only: bool,
+}
+
+/// The address the HTTP API is served on when no address is given.
+pub const DEFAULT_HTTP_ADDRESS: &str = "127.0.0.1:8080";
+
+/// The options of the server, besides the storage and model it serves.
+pub struct ServerOptions {
+    /// The address to serve the HTTP API on, e.g. `127.0.0.1:8080`, or `:8080` for every
+    /// interface.
+    pub http: Option<String>,
+    /// The address to serve the gRPC service on.
+    pub grpc: Option<String>,
+    /// The token clients must send, if any.
+    pub token: Option<String>,
+    /// Whether clients can generate with the configured model.
+    pub allow_generate: bool,
+    /// Whether clients are kept from editing prompts.
+    pub read_only: bool,
+}
+
+/// An error answered with a status code and a JSON body like `{"error": {"kind", "message"}}`.
+struct ApiError(StatusCode, anyhow::Error);
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError {
+    fn from(error: E) -> Self {
+        let error = error.into();
+        if let Some(FileStorageError::PromptLocked(_)) = error.downcast_ref::<FileStorageError>() {
+            return ApiError(StatusCode::CONFLICT, error);
+        }
+        let status = match ErrorKind::of(&error) {
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::Parse | ErrorKind::MissingArguments => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::Model => StatusCode::BAD_GATEWAY,
+            ErrorKind::Config | ErrorKind::Other => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let ApiError(status, error) = self;
+        let body = json!({
+            "error": {
+                "kind": ErrorKind::of(&error),
+                "message": format!("{:#}", error)
+            }
+        });
+        (status, Json(body)).into_response()
+    }
+}
+
+type ApiResult = Result<Json<Value>, ApiError>;
+
+#[derive(Deserialize)]
+struct ListQuery {
+    tag: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RenderRequest {
+    arguments: HashMap<String, Value>,
+}
+
+/// The frontmatter fields the web UI edits. Fields left out are left unchanged.
+#[derive(Deserialize)]
+struct UpdateRequest {
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct GenerateRequest {
+    arguments: HashMap<String, Value>,
+    #[serde(flatten)]
+    settings: ModelSettings,
+}
+
+/// Turns an address like `:8080` into one listening on every interface.
+fn listen_address(address: &str) -> String {
+    match address.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => address.to_string(),
+    }
+}
+
+/// Serves the prompts of the storage until the process is stopped, over an HTTP API with a
+/// web UI using it at `/`, over gRPC, or both. Without any address, the HTTP API is served
+/// on [`DEFAULT_HTTP_ADDRESS`].
+///
+/// Every HTTP route but `/health`, and every gRPC call, requires the token, when one is
+/// given, as a bearer token. The web UI asks for it.
+pub async fn run_server(
+    storage: FileStorage,
+    model_config: ModelConfig,
+    options: ServerOptions,
+) -> Result<()> {
+    let ServerOptions {
+        http,
+        grpc,
+        token,
+        allow_generate,
+        read_only,
+    } = options;
+    let http = match (http, &grpc) {
+        (None, None) => Some(DEFAULT_HTTP_ADDRESS.to_string()),
+        (http, _) => http,
+    };
+    let library = PromptLibrary::new(FileStorage {
+        base_path: storage.base_path.clone(),
+        fallback_paths: storage.fallback_paths.clone(),
+        vault: storage.vault,
+    })
+    .read_only(read_only);
+    let state = Arc::new(ServerState {
+        storage,
+        model_config,
+        token: token.clone(),
+        allow_generate,
+        read_only,
+    });
+    tokio::try_join!(
+        async {
+            match &http {
+                Some(address) => serve_http(state, address).await,
+                None => Ok(()),
+            }
+        },
+        async {
+            match &grpc {
+                Some(address) => serve_grpc(library, address, token).await,
+                None => Ok(()),
+            }
+        },
+    )?;
+    Ok(())
+}
+
+/// Warns when serving beyond the local machine without a token.
+fn warn_if_open(address: &str, token: Option<&str>) {
+    if token.is_none() && !address.starts_with("127.0.0.1") && !address.starts_with("localhost") {
+        warn!(
+            "Serving on {} without a token, so anyone who can reach it can use it",
+            address
+        );
+    }
+}
+
+async fn serve_http(state: Arc<ServerState>, address: &str) -> Result<()> {
+    let address = listen_address(address);
+    warn_if_open(&address, state.token.as_deref());
+    let api = Router::new()
+        .route("/prompts", get(list_prompts))
+        .route("/prompts/{name}", get(get_prompt).patch(update_prompt))
+        .route("/prompts/{name}/render", post(render_prompt))
+        .route("/prompts/{name}/generate", post(generate))
+        .route("/search", get(search))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize));
+    let app = Router::new()
+        .route("/", get(|| async { Html(WEB_UI) }))
+        .route("/health", get(|| async { Json(json!({ "status": "ok" })) }))
+        .merge(api)
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(&address)
+        .await
+        .with_context(|| format!("Failed to listen on {}", address))?;
+    eprintln!("Serving prompts on http://{}", listener.local_addr()?);
+    axum::serve(listener, app)
+        .await
+        .context("The HTTP server stopped")
+}
+
+async fn serve_grpc(library: PromptLibrary, address: &str, token: Option<String>) -> Result<()> {
+    let address = listen_address(address);
+    warn_if_open(&address, token.as_deref());
+    let socket = tokio::net::lookup_host(&address)
+        .await
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .with_context(|| format!("Invalid gRPC address: {}", address))?;
+    let authorize = move |request: tonic::Request<()>| {
+        if let Some(token) = &token {
+            let sent = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "));
+            if sent != Some(token.as_str()) {
+                return Err(tonic::Status::unauthenticated(
+                    "Missing or invalid token. Send it as `authorization: Bearer <token>`",
+                ));
+            }
+        }
+        Ok(request)
+    };
+    eprintln!("Serving prompts over gRPC on {}", socket);
+    tonic::transport::Server::builder()
+        .add_service(PromptServiceServer::with_interceptor(library, authorize))
+        .serve(socket)
+        .await
+        .context("The gRPC server stopped")
+}
+
+/// Rejects requests without the server's token, if it has one.
+async fn authorize(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(token) = &state.token {
+        let sent = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if sent != Some(token.as_str()) {
+            return ApiError(
+                StatusCode::UNAUTHORIZED,
+                anyhow::anyhow!(
+                    "Missing or invalid token. Send it as `Authorization: Bearer <token>`"
+                ),
+            )
+            .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+async fn list_prompts(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<ListQuery>,
+) -> ApiResult {
+    let tag = query.tag.as_deref().map(normalize_tag);
+    let summaries: Vec<_> = state
+        .storage
+        .get_prompt_summaries()?
+        .into_iter()
+        .filter(|summary| {
+            tag.as_ref()
+                .is_none_or(|tag| summary.tags.iter().any(|t| &normalize_tag(t) == tag))
+        })
+        .collect();
+    Ok(Json(json!(summaries)))
+}
+
+async fn get_prompt(State(state): State<Arc<ServerState>>, Path(name): Path<String>) -> ApiResult {
+    let prompt = state.storage.get_prompt(&name)?;
+    let arguments = PromptTemplate::new(prompt.clone())?.argument_info();
+    let mut body = serde_json::to_value(&prompt)?;
+    body["arguments"] = json!(arguments);
+    Ok(Json(body))
+}
+
+async fn update_prompt(
+    State(state): State<Arc<ServerState>>,
+    Path(name): Path<String>,
+    Json(request): Json<UpdateRequest>,
+) -> ApiResult {
+    if state.read_only {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("The server is read-only"),
+        ));
+    }
+    let mut prompt = state.storage.get_prompt(&name)?;
+    if let Some(description) = request.description {
+        let description = description.trim();
+        prompt.metadata.description = (!description.is_empty()).then(|| description.to_string());
+    }
+    if let Some(tags) = request.tags {
+        prompt.metadata.tags = normalize_tags(&tags);
+    }
+    state.storage.save_prompt(&prompt)?;
+    get_prompt(State(state), Path(name)).await
+}
+
+async fn search(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<SearchQuery>,
+) -> ApiResult {
+    let prompts = state.storage.get_prompts()?;
+    Ok(Json(json!(search_prompts(
+        &prompts,
+        &query.q,
+        SearchField::ALL
+    ))))
+}
+
+async fn render_prompt(
+    State(state): State<Arc<ServerState>>,
+    Path(name): Path<String>,
+    Json(request): Json<RenderRequest>,
+) -> ApiResult {
+    let prompt = state.storage.get_prompt(&name)?;
+    let rendered =
+        PromptTemplate::new(prompt)?.render_with_warnings(&request.arguments, &state.storage)?;
+    record_usage(&state.storage, &name, UsageKind::Render);
+    record_history(
+        &state.storage,
+        &name,
+        UsageKind::Render,
+        &request.arguments,
+        output_hash(&rendered.text),
+    );
+    let warnings: Vec<String> = rendered.warnings.iter().map(|w| w.to_string()).collect();
+    Ok(Json(json!({
+        "name": name,
+        "text": rendered.text,
+        "warnings": warnings
+    })))
+}
+
+async fn generate(
+    State(state): State<Arc<ServerState>>,
+    Path(name): Path<String>,
+    Json(request): Json<GenerateRequest>,
+) -> ApiResult {
+    if !state.allow_generate {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("Generating isn't allowed. Start the server with --allow-generate."),
+        ));
+    }
+    let prompt = state.storage.get_prompt(&name)?;
+    let settings = prompt
+        .metadata
+        .model_settings
+        .with_overrides(&request.settings);
+    let guardrails = prompt.metadata.guardrails.clone();
+    let rendered =
+        PromptTemplate::new(prompt)?.render_with_warnings(&request.arguments, &state.storage)?;
+    let errors: Vec<String> = ContentChecker::default()
+        .check(&rendered.text)
+        .iter()
+        .filter(|violation| violation.severity == Severity::Error)
+        .map(|violation| violation.to_string())
+        .collect();
+    if !errors.is_empty() {
+        return Err(ApiError(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            anyhow::anyhow!("Content checks failed: {}", errors.join("; ")),
+        ));
+    }
+
+    let model_config = &state.model_config;
+    let provider = model_config.provider_config(None, None);
+    let messages = split_messages(&rendered.text);
+    let completion = match guardrails {
+        Some(guardrails) => {
+            let max_retries = guardrails
+                .max_attempts
+                .map_or(VALIDATION_RETRIES, |attempts| attempts.saturating_sub(1));
+            get_completion_checked(
+                &provider,
+                &model_config.model_name,
+                &messages,
+                &settings,
+                |response| guardrails.validate(response).map(|_| response.to_string()),
+                max_retries,
+            )
+            .await?
+        }
+        None => {
+            get_chat_completion(&provider, &model_config.model_name, &messages, &settings).await?
+        }
+    };
+    let used_model = settings
+        .model
+        .as_deref()
+        .unwrap_or(&model_config.model_name);
+    record_tokens(&state.storage, used_model, completion.usage);
+    record_usage(&state.storage, &name, UsageKind::Generate);
+    record_history(
+        &state.storage,
+        &name,
+        UsageKind::Generate,
+        &request.arguments,
+        output_hash(&rendered.text),
+    );
+    Ok(Json(json!({
+        "name": name,
+        "model": used_model,
+        "text": completion.text,
+        "usage": completion.usage
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_address() {
+        assert_eq!("0.0.0.0:8080", listen_address(":8080"));
+        assert_eq!("127.0.0.1:8080", listen_address("127.0.0.1:8080"));
+    }
+}
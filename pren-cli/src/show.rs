@@ -0,0 +1,176 @@
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result};
+use pren_core::file_storage::FileStorage;
+use pren_core::prompt::{ArgumentInfo, PromptTemplate};
+use pren_core::storage::PromptStorage;
+use pren_core::tokens::count_tokens;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+
+/// Prints a prompt's metadata, arguments, references, estimated tokens and content,
+/// and, if `rendered`, a preview rendered with its first test case's arguments.
+pub fn run_show(
+    storage: &FileStorage,
+    name: &str,
+    rendered: bool,
+    output: OutputMode,
+) -> Result<()> {
+    let prompt = storage.get_prompt(name)?;
+    let times = storage.get_prompt_times(name)?;
+    let template =
+        PromptTemplate::new(prompt.clone()).context(format!("Error parsing prompt '{}'", name))?;
+    let arguments = template.argument_info();
+    let mut references = template.prompt_references();
+    let mut variable_references = template.variable_prompt_references();
+    let mut seen = HashSet::new();
+    references.retain(|reference| seen.insert(reference.clone()));
+    seen.clear();
+    variable_references.retain(|reference| seen.insert(reference.clone()));
+    // The first test case gives sample values for the arguments without defaults
+    let samples: HashMap<String, Value> = prompt
+        .metadata
+        .tests
+        .first()
+        .map(|test| test.args.clone().into_iter().collect())
+        .unwrap_or_default();
+    let preview = template.render_partial(&samples, storage);
+    let tokens = preview
+        .as_ref()
+        .ok()
+        .map(|preview| count_tokens(&preview.text));
+
+    if output == OutputMode::Json {
+        let mut shown = serde_json::to_value(&prompt)?;
+        shown["created"] = json!(times.created);
+        shown["modified"] = json!(times.modified);
+        shown["arguments"] = json!(arguments);
+        shown["references"] = json!(references);
+        shown["variable_references"] = json!(variable_references);
+        shown["estimated_tokens"] = json!(tokens);
+        if rendered {
+            shown["rendered"] = json!(preview?.text);
+        }
+        return print_json(&shown);
+    }
+
+    println!("Name: {}", prompt.metadata.name);
+    if let Some(description) = &prompt.metadata.description {
+        println!("Description: {}", description);
+    }
+    println!("Tags: {:?}", prompt.metadata.tags);
+    if let Some(author) = &prompt.metadata.author {
+        println!("Author: {}", author);
+    }
+    if let Some(source_url) = &prompt.metadata.source_url {
+        println!("Source: {}", source_url);
+    }
+    if let Some(license) = &prompt.metadata.license {
+        println!("License: {}", license);
+    }
+    if let Some(created) = times.created {
+        println!("Created: {}", created.format("%Y-%m-%d %H:%M"));
+    }
+    if let Some(modified) = times.modified {
+        println!("Modified: {}", modified.format("%Y-%m-%d %H:%M"));
+    }
+    if !arguments.is_empty() {
+        println!("Arguments:");
+        for argument in &arguments {
+            println!("  {}", format_argument(argument));
+        }
+    }
+    if !references.is_empty() {
+        println!("References: {}", references.join(", "));
+    }
+    if !variable_references.is_empty() {
+        println!(
+            "References chosen by arguments: {}",
+            variable_references.join(", ")
+        );
+    }
+    match tokens {
+        Some(tokens) => println!("Estimated tokens: {}", tokens),
+        None => println!("Estimated tokens: unknown"),
+    }
+    println!("Content:\n{}", prompt.content);
+    if rendered {
+        println!("Rendered:\n{}", preview?.text);
+    }
+    Ok(())
+}
+
+/// Prints the arguments a prompt takes.
+pub fn run_args(storage: &FileStorage, name: &str, output: OutputMode) -> Result<()> {
+    let template = PromptTemplate::new(storage.get_prompt(name)?)
+        .context(format!("Error parsing prompt '{}'", name))?;
+    let arguments = template.argument_info();
+    if output == OutputMode::Json {
+        return print_json(&arguments);
+    }
+    if arguments.is_empty() {
+        println!("Prompt '{}' has no arguments", name);
+    }
+    for argument in &arguments {
+        println!("{}", format_argument(argument));
+    }
+    Ok(())
+}
+
+/// Formats an argument as its name, whether it's required or its default, and its
+/// description.
+fn format_argument(argument: &ArgumentInfo) -> String {
+    let requirement = match &argument.default {
+        Some(default) => format!("optional, default: {:?}", default),
+        None => "required".to_string(),
+    };
+    match &argument.description {
+        Some(description) => format!("{} ({}): {}", argument.name, requirement, description),
+        None => format!("{} ({})", argument.name, requirement),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::prompt::{Prompt, PromptMetadata};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_format_argument() {
+        let mut argument = ArgumentInfo {
+            name: "topic".to_string(),
+            required: true,
+            default: None,
+            description: None,
+        };
+        assert_eq!("topic (required)", format_argument(&argument));
+
+        argument.required = false;
+        argument.default = Some("Rust".to_string());
+        argument.description = Some("What to explain".to_string());
+        assert_eq!(
+            "topic (optional, default: \"Rust\"): What to explain",
+            format_argument(&argument)
+        );
+    }
+
+    #[test]
+    fn test_run_show() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("explain".to_string(), None, vec![]),
+                "Explain {{topic}}.".to_string(),
+            ))
+            .unwrap();
+
+        run_show(&storage, "explain", true, OutputMode::Json).unwrap();
+        run_args(&storage, "explain", OutputMode::Json).unwrap();
+        assert!(run_show(&storage, "missing", false, OutputMode::Json).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::usage::record_tokens;
+use anyhow::Result;
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::{ModelSettings, ProviderConfig, TokenUsage, get_chat_completion};
+use pren_core::prompt::Prompt;
+use pren_core::storage::PromptStorage;
+use pren_core::tagging::{parse_tag_suggestions, tag_suggestion_messages};
+
+/// Asks the model to suggest tags for a prompt, given the tags in use, recording the
+/// tokens used.
+///
+/// # Returns
+///
+/// * The suggested tags the prompt doesn't have yet.
+/// * The tags in use, with their prompt counts.
+/// * The tokens the request used.
+pub async fn suggest_tags(
+    storage: &FileStorage,
+    provider: &ProviderConfig,
+    model_name: &str,
+    settings: &ModelSettings,
+    prompt: &Prompt,
+) -> Result<(Vec<String>, Vec<(String, usize)>, Option<TokenUsage>)> {
+    let vocabulary = storage.list_tags()?;
+    let completion = get_chat_completion(
+        provider,
+        model_name,
+        &tag_suggestion_messages(prompt, &vocabulary),
+        settings,
+    )
+    .await?;
+    let used_model = settings.model.as_deref().unwrap_or(model_name);
+    record_tokens(storage, used_model, completion.usage);
+    let suggested = parse_tag_suggestions(&completion.text, prompt);
+    Ok((suggested, vocabulary, completion.usage))
+}
+
+/// Prints the suggested tags, marking the ones no prompt uses yet.
+pub fn print_tag_suggestions(name: &str, suggested: &[String], vocabulary: &[(String, usize)]) {
+    if suggested.is_empty() {
+        println!("The model didn't suggest any new tags for '{}'", name);
+        return;
+    }
+    let tags: Vec<String> = suggested
+        .iter()
+        .map(|tag| {
+            if vocabulary.iter().any(|(used, _)| used == tag) {
+                tag.clone()
+            } else {
+                format!("{} (new)", tag)
+            }
+        })
+        .collect();
+    println!("Suggested tags for '{}': {}", name, tags.join(", "));
+}
+
+/// Asks whether to add the suggested tags to a prompt.
+pub fn confirm_tags(name: &str) -> Result<bool> {
+    println!("Add them to '{}'? [y/N]", name);
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,69 @@
+use crate::config::ToolConfig;
+use anyhow::{Result, bail};
+use pren_core::llm::{ToolCall, ToolDefinition};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The tools sent to the model: the prompt's own, followed by the configured ones chosen
+/// by name. A prompt's tool replaces a configured one with the same name.
+pub fn tool_definitions(
+    configured: &[ToolConfig],
+    prompt_tools: &[ToolDefinition],
+    names: &[String],
+) -> Result<Vec<ToolDefinition>> {
+    let mut tools = prompt_tools.to_vec();
+    for name in names {
+        let Some(tool) = configured.iter().find(|tool| &tool.definition.name == name) else {
+            bail!(
+                "Tool '{}' isn't configured. Add it to the `tools` of the config.",
+                name
+            );
+        };
+        if !tools.iter().any(|tool| &tool.name == name) {
+            tools.push(tool.definition.clone());
+        }
+    }
+    Ok(tools)
+}
+
+/// Runs a call of a tool with the command configured for it, passing the arguments of the
+/// call as JSON on stdin. Returns `None` if the tool has no command.
+///
+/// Failures are returned as the result of the call, so the model can recover from them.
+pub fn run_tool(configured: &[ToolConfig], call: &ToolCall) -> Option<String> {
+    let tool = configured
+        .iter()
+        .find(|tool| tool.definition.name == call.name)?;
+    let (program, args) = tool.command.split_first()?;
+    eprintln!("Running tool '{}' with {}", call.name, call.arguments);
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(call.arguments.to_string().as_bytes())?;
+            }
+            child.wait_with_output()
+        });
+    Some(match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(output) => format!(
+            "The tool failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => format!("The tool couldn't be run: {}", e),
+    })
+}
+
+/// Prints the tool calls the model made that weren't run.
+pub fn print_tool_calls(calls: &[ToolCall]) {
+    for call in calls {
+        println!("Tool call: {} {}", call.name, call.arguments);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::config::ModelConfig;
+use crate::editor::ask;
+use crate::pick::ask_missing_arguments;
+use anyhow::{Context, Result};
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::get_completions_content;
+use pren_core::parser::is_valid_prompt_name;
+use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
+use pren_core::storage::PromptStorage;
+use std::collections::HashMap;
+use std::fs;
+
+/// The namespace of the prompts created during the tour, e.g. `tour.persona`.
+const TOUR_NAMESPACE: &str = "tour";
+
+fn wait_for_enter() -> Result<()> {
+    ask("\nPress Enter to continue", "")?;
+    println!();
+    Ok(())
+}
+
+fn namespaced(name: &str) -> String {
+    format!("{}.{}", TOUR_NAMESPACE, name)
+}
+
+fn composed_content(persona_name: &str, task_name: &str) -> String {
+    format!(
+        "{{{{prompt:{}}}}}\n\n{{{{prompt:{}}}}}",
+        persona_name, task_name
+    )
+}
+
+/// The prompts created during the tour, saved in the tour namespace of the user's storage
+/// and deleted when the tour ends.
+struct TourPrompts<'a> {
+    storage: &'a FileStorage,
+    created: Vec<String>,
+}
+
+impl<'a> TourPrompts<'a> {
+    fn new(storage: &'a FileStorage) -> Self {
+        TourPrompts {
+            storage,
+            created: Vec::new(),
+        }
+    }
+
+    /// Gets the first of `base`, `base_2`, `base_3`... whose namespaced name no stored
+    /// prompt has, e.g. left by a tour that was interrupted.
+    fn unused_name(&self, base: &str) -> Result<String> {
+        let mut name = base.to_string();
+        let mut suffix = 1;
+        while self.storage.prompt_exists(&namespaced(&name))? {
+            suffix += 1;
+            name = format!("{}_{}", base, suffix);
+        }
+        Ok(name)
+    }
+
+    /// Explains why a name can't be used for a new tour prompt, if it can't.
+    fn name_problem(&self, name: &str) -> Result<Option<String>> {
+        if !is_valid_prompt_name(name) {
+            return Ok(Some(
+                "Use letters, digits, '-', '_' and '.' in names, so they can be referenced."
+                    .to_string(),
+            ));
+        }
+        if self.storage.prompt_exists(&namespaced(name))? {
+            return Ok(Some(format!(
+                "There's already a prompt named '{}'.",
+                namespaced(name)
+            )));
+        }
+        Ok(None)
+    }
+
+    /// Asks for the name of a new prompt until one that can be used is given, returning
+    /// it namespaced.
+    fn ask_name(&self, question: &str, default: &str) -> Result<String> {
+        let default = self.unused_name(default)?;
+        loop {
+            let name = ask(question, &default)?;
+            match self.name_problem(&name)? {
+                Some(problem) => println!("{}", problem),
+                None => return Ok(namespaced(&name)),
+            }
+        }
+    }
+
+    fn save(&mut self, name: &str, content: String) -> Result<()> {
+        self.storage.save_prompt(&Prompt::new(
+            PromptMetadata::new(name.to_string(), None, vec!["tour".to_string()]),
+            content,
+        ))?;
+        self.created.push(name.to_string());
+        Ok(())
+    }
+
+    /// Deletes the prompts created during the tour. Their files are removed directly
+    /// rather than deleted as prompts, so the tour doesn't fill the undo journal.
+    fn clean_up(self) -> Result<()> {
+        for name in &self.created {
+            let path = self.storage.get_prompt_path(name)?;
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete the tour prompt '{}'", name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks the user through the main pren features.
+///
+/// The prompts created during the tour are saved in the `tour` namespace of the storage,
+/// like `tour.persona`, and deleted when the tour ends, even if it fails.
+pub async fn run_tour(storage: &FileStorage, model_config: &ModelConfig) -> Result<()> {
+    let mut prompts = TourPrompts::new(storage);
+    let result = tour(&mut prompts, model_config).await;
+    let cleaned_up = prompts.clean_up();
+    result?;
+    cleaned_up?;
+    println!("\nTour finished, its prompts have been deleted. Happy prompting!");
+    Ok(())
+}
+
+async fn tour(prompts: &mut TourPrompts<'_>, model_config: &ModelConfig) -> Result<()> {
+    let storage = prompts.storage;
+    println!(
+        "Welcome to pren! The prompts you create in this tour are saved as '{}.*' in your",
+        TOUR_NAMESPACE
+    );
+    println!("prompt library and deleted when it ends, so feel free to experiment.\n");
+
+    // Step 1: a simple prompt
+    println!("Step 1/5: Creating a prompt");
+    println!("A prompt is a named piece of text, optionally with a description and tags.");
+    let persona_name = prompts.ask_name("Name for your first prompt", "persona")?;
+    let persona_content = ask("Its content", "You are a friendly and concise assistant.")?;
+    prompts.save(&persona_name, persona_content)?;
+    println!(
+        "Saved '{}'! Outside the tour: pren add -n {} -c \"...\"",
+        persona_name, persona_name
+    );
+    wait_for_enter()?;
+
+    // Step 2: a prompt with an argument
+    println!("Step 2/5: Adding an argument");
+    println!(
+        "Arguments are placeholders written as {{{{argument}}}} and filled in at render time."
+    );
+    let task_name = prompts.ask_name("Name for a prompt with an argument", "task")?;
+    let task_content = ask("Its content", "Explain {{topic}} in three sentences.")?;
+    prompts.save(&task_name, task_content)?;
+    let task_template = PromptTemplate::new(storage.get_prompt(&task_name)?)?;
+    println!("Detected arguments: {:?}", task_template.arguments());
+    wait_for_enter()?;
+
+    // Step 3: composition
+    println!("Step 3/5: Composing prompts");
+    println!("Prompts can include other prompts with {{{{prompt:name}}}}.");
+    let composed_name = prompts.ask_name("Name for a prompt including both", "composed")?;
+    let composed_content = composed_content(&persona_name, &task_name);
+    println!(
+        "Creating '{}' with content:\n{}",
+        composed_name, composed_content
+    );
+    prompts.save(&composed_name, composed_content)?;
+    wait_for_enter()?;
+
+    // Step 4: rendering
+    println!("Step 4/5: Rendering");
+    let composed_template = PromptTemplate::new(storage.get_prompt(&composed_name)?)?;
+    let mut args_map = HashMap::new();
+    ask_missing_arguments(&composed_template, &mut args_map, storage)?;
+    let rendered_prompt = composed_template
+        .render(&args_map, storage)
+        .context(format!("Error rendering prompt '{}'", composed_name))?;
+    println!("\nRendered prompt:\n{}", rendered_prompt);
+    println!(
+        "\nOutside the tour: pren render -n {} -a key=value",
+        composed_name
+    );
+    wait_for_enter()?;
+
+    // Step 5: generating
+    println!("Step 5/5: Generating");
+    println!(
+        "pren can send a rendered prompt to the configured model ({} at {}).",
+        model_config.model_name,
+        model_config.provider_config(None, None).base_url()
+    );
+    let answer = ask("Send the rendered prompt now? (y/n)", "n")?.to_lowercase();
+    if answer == "y" || answer == "yes" {
+        match get_completions_content(
+            &model_config.provider_config(None, None),
+            &model_config.model_name,
+            &rendered_prompt,
+        )
+        .await
+        {
+            Ok(response) => println!("\n{}", response),
+            Err(e) => println!(
+                "\nThe model couldn't be reached ({}). Check your configuration.",
+                e
+            ),
+        }
+    }
+    println!(
+        "Outside the tour: pren generate -g {} -a key=value",
+        composed_name
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::storage::JournalStorage;
+    use tempfile::TempDir;
+
+    fn library(sandbox: &TempDir) -> FileStorage {
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("persona".to_string(), None, vec![]),
+                "The user's own prompt".to_string(),
+            ))
+            .unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_tour_prompt_names() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = library(&sandbox);
+        let mut prompts = TourPrompts::new(&storage);
+
+        // The user's prompts don't clash with the tour's, but leftover tour prompts do
+        assert_eq!("persona", prompts.unused_name("persona").unwrap());
+        prompts.save("tour.persona", "Taken".to_string()).unwrap();
+        assert_eq!("persona_2", prompts.unused_name("persona").unwrap());
+        assert!(prompts.name_problem("persona").unwrap().is_some());
+        assert!(prompts.name_problem("persona_2").unwrap().is_none());
+
+        for name in ["my persona", "../persona", "a/b", ""] {
+            assert!(prompts.name_problem(name).unwrap().is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_tour_prompts_are_composed_and_cleaned_up() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = library(&sandbox);
+        let mut prompts = TourPrompts::new(&storage);
+        prompts
+            .save("tour.persona", "You are a {{tone}} assistant.".to_string())
+            .unwrap();
+        prompts
+            .save("tour.task", "Explain {{topic}}.".to_string())
+            .unwrap();
+        prompts
+            .save(
+                "tour.composed",
+                composed_content("tour.persona", "tour.task"),
+            )
+            .unwrap();
+
+        let template = PromptTemplate::new(storage.get_prompt("tour.composed").unwrap()).unwrap();
+        let mut missing = template
+            .render_partial(&HashMap::<String, String>::new(), &storage)
+            .unwrap()
+            .missing_arguments;
+        missing.sort();
+        assert_eq!(vec!["tone", "topic"], missing);
+
+        prompts.clean_up().unwrap();
+        let names: Vec<String> = storage
+            .get_prompts()
+            .unwrap()
+            .into_iter()
+            .map(|prompt| prompt.metadata.name)
+            .collect();
+        assert_eq!(vec!["persona"], names);
+        assert!(storage.get_journal().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::chat::run_chat;
+use crate::output::{OutputMode, print_json, write_output_file};
+use anyhow::Result;
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::{ModelSettings, ProviderConfig};
+use pren_core::messages::Role;
+use pren_core::storage::TranscriptStorage;
+use pren_core::transcript::Transcript;
+use std::path::Path;
+use tracing::warn;
+
+/// Saves a transcript, telling how to resume it. Failing to save it doesn't fail the
+/// command.
+pub fn save_transcript(storage: &FileStorage, transcript: Transcript) {
+    match storage.save_transcript(transcript) {
+        Ok(transcript) => eprintln!(
+            "Transcript {} saved. Resume it with `pren transcript resume {}`",
+            transcript.id, transcript.id
+        ),
+        Err(e) => warn!("Couldn't save the transcript: {}", e),
+    }
+}
+
+/// Prints the saved transcripts, most recently updated first.
+pub fn run_transcript_list(
+    storage: &FileStorage,
+    name: Option<&str>,
+    limit: usize,
+    output: OutputMode,
+) -> Result<()> {
+    let mut transcripts: Vec<Transcript> = storage
+        .get_transcripts()?
+        .into_iter()
+        .filter(|transcript| name.is_none_or(|name| transcript.prompt == name))
+        .collect();
+    transcripts.sort_by_key(|transcript| std::cmp::Reverse(transcript.updated));
+    transcripts.truncate(limit);
+    if output == OutputMode::Json {
+        return print_json(&transcripts);
+    }
+    if transcripts.is_empty() {
+        println!("No transcripts saved yet");
+        return Ok(());
+    }
+    for transcript in transcripts {
+        println!(
+            "{}  {}  {}  {}  {} messages  {}",
+            transcript.id,
+            transcript.updated.format("%Y-%m-%d %H:%M"),
+            transcript.prompt,
+            transcript.model,
+            transcript.messages.len(),
+            transcript.title()
+        );
+    }
+    Ok(())
+}
+
+/// Prints a transcript as markdown.
+pub fn run_transcript_show(storage: &FileStorage, id: u64, output: OutputMode) -> Result<()> {
+    let transcript = storage.get_transcript(id)?;
+    match output {
+        OutputMode::Json => print_json(&transcript)?,
+        OutputMode::Text => print!("{}", transcript.to_markdown()),
+    }
+    Ok(())
+}
+
+/// Writes a transcript to a markdown file.
+pub fn run_transcript_export(storage: &FileStorage, id: u64, path: &Path) -> Result<()> {
+    let transcript = storage.get_transcript(id)?;
+    write_output_file(path, transcript.to_markdown().trim_end(), false)?;
+    eprintln!("Transcript {} exported to {}", id, path.display());
+    Ok(())
+}
+
+/// Prints the conversation of a transcript like it was shown when it was held, then
+/// continues it, saving the new messages to the same transcript.
+pub async fn run_transcript_resume(
+    storage: &FileStorage,
+    provider: &ProviderConfig,
+    model_name: &str,
+    transcript: Transcript,
+    settings: &ModelSettings,
+) -> Result<()> {
+    for message in &transcript.messages {
+        match message.role {
+            Role::System => {}
+            Role::User => eprintln!("> {}", message.content),
+            Role::Assistant => println!("{}", message.content),
+        }
+    }
+    run_chat(
+        storage, provider, model_name, transcript, settings, None, true,
+    )
+    .await
+}
@@ -0,0 +1,545 @@
+use crate::clipboard::{Copied, copy_text};
+use crate::config::ModelConfig;
+use anyhow::{Context, Result, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::stream_chat_completions_content;
+use pren_core::messages::split_messages;
+use pren_core::policy::{ContentChecker, Severity};
+use pren_core::prompt::{ArgumentInfo, Prompt, PromptTemplate};
+use pren_core::search::{SearchField, search_prompts};
+use pren_core::stats::UsageKind;
+use pren_core::storage::{PromptStorage, StatsStorage};
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+/// The pane receiving key presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Prompts,
+    Filter,
+    Arguments,
+}
+
+/// An argument of the selected prompt and the value typed for it.
+struct Field {
+    info: ArgumentInfo,
+    value: String,
+}
+
+/// A message from the task generating a response.
+enum Generation {
+    Text(String),
+    Done(Result<(), String>),
+}
+
+struct App<'a> {
+    storage: &'a FileStorage,
+    model_config: &'a ModelConfig,
+    prompts: Vec<Prompt>,
+    filter: String,
+    /// The indices in `prompts` of the prompts matching the filter.
+    visible: Vec<usize>,
+    list: ListState,
+    focus: Focus,
+    fields: Vec<Field>,
+    field: usize,
+    output_title: String,
+    output: String,
+    output_scroll: u16,
+    status: String,
+    generation: Option<Receiver<Generation>>,
+    quit: bool,
+}
+
+/// Opens the terminal UI, to browse, filter and preview prompts, fill in their arguments,
+/// render them and stream the model's response.
+///
+/// Must be called within a Tokio runtime, which runs the model requests.
+pub fn run_tui(storage: &FileStorage, model_config: &ModelConfig) -> Result<()> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        bail!("The TUI needs a terminal");
+    }
+    let mut prompts = storage.get_prompts()?;
+    prompts.sort_by_key(|prompt| (!prompt.metadata.pinned, prompt.metadata.name.clone()));
+
+    let mut app = App::new(storage, model_config, prompts);
+    let mut terminal = ratatui::try_init().context("Failed to set up the terminal")?;
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl<'a> App<'a> {
+    fn new(storage: &'a FileStorage, model_config: &'a ModelConfig, prompts: Vec<Prompt>) -> Self {
+        let mut app = App {
+            storage,
+            model_config,
+            visible: (0..prompts.len()).collect(),
+            prompts,
+            filter: String::new(),
+            list: ListState::default(),
+            focus: Focus::Prompts,
+            fields: Vec::new(),
+            field: 0,
+            output_title: "Output".to_string(),
+            output: String::new(),
+            output_scroll: 0,
+            status: String::new(),
+            generation: None,
+            quit: false,
+        };
+        app.select(Some(0));
+        app
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            self.receive_generation();
+            // Poll with a timeout, so that streamed text shows up without key presses
+            if event::poll(Duration::from_millis(50))?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                self.handle_key(key);
+            }
+        }
+        Ok(())
+    }
+
+    fn selected_prompt(&self) -> Option<&Prompt> {
+        let index = *self.visible.get(self.list.selected()?)?;
+        self.prompts.get(index)
+    }
+
+    /// Selects a visible prompt, loading its arguments into the form.
+    fn select(&mut self, position: Option<usize>) {
+        let position = position.filter(|_| !self.visible.is_empty());
+        self.list
+            .select(position.map(|position| position.min(self.visible.len() - 1)));
+        self.field = 0;
+        self.fields = match self.selected_prompt().cloned().map(PromptTemplate::new) {
+            Some(Ok(template)) => template
+                .argument_info()
+                .into_iter()
+                .map(|info| Field {
+                    info,
+                    value: String::new(),
+                })
+                .collect(),
+            Some(Err(e)) => {
+                self.status = format!("Invalid template: {}", e);
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        let position = self
+            .list
+            .selected()
+            .map_or(0, |position| position.saturating_add_signed(offset));
+        self.select(Some(position));
+    }
+
+    fn apply_filter(&mut self) {
+        self.visible = if self.filter.trim().is_empty() {
+            (0..self.prompts.len()).collect()
+        } else {
+            search_prompts(
+                &self.prompts,
+                &self.filter,
+                &[
+                    SearchField::Name,
+                    SearchField::Description,
+                    SearchField::Tags,
+                ],
+            )
+            .into_iter()
+            .filter_map(|result| {
+                self.prompts
+                    .iter()
+                    .position(|prompt| prompt.metadata.name == result.name)
+            })
+            .collect()
+        };
+        self.select(Some(0));
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        // Messages are shown until the next key press, then the key help comes back
+        self.status.clear();
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => self.quit = true,
+            KeyCode::Char('g') if control => self.generate(),
+            KeyCode::PageUp => self.output_scroll = self.output_scroll.saturating_sub(10),
+            KeyCode::PageDown => self.output_scroll = self.output_scroll.saturating_add(10),
+            _ => match self.focus {
+                Focus::Prompts => self.handle_prompts_key(key),
+                Focus::Filter => self.handle_filter_key(key),
+                Focus::Arguments => self.handle_arguments_key(key),
+            },
+        }
+    }
+
+    fn handle_prompts_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Char('/') => self.focus = Focus::Filter,
+            KeyCode::Enter | KeyCode::Tab if !self.fields.is_empty() => {
+                self.focus = Focus::Arguments
+            }
+            KeyCode::Enter | KeyCode::Char('r') => {
+                self.render();
+            }
+            KeyCode::Char('g') => self.generate(),
+            KeyCode::Char('c') => self.copy_output(),
+            _ => {}
+        }
+    }
+
+    fn handle_filter_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter | KeyCode::Tab => self.focus = Focus::Prompts,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.apply_filter();
+                self.focus = Focus::Prompts;
+            }
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Down => self.move_selection(1),
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.apply_filter();
+            }
+            KeyCode::Char(c) => {
+                self.filter.push(c);
+                self.apply_filter();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_arguments_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => self.focus = Focus::Prompts,
+            KeyCode::Up | KeyCode::BackTab => self.field = self.field.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => {
+                self.field = (self.field + 1).min(self.fields.len().saturating_sub(1))
+            }
+            KeyCode::Enter => {
+                self.render();
+            }
+            KeyCode::Backspace => {
+                if let Some(field) = self.fields.get_mut(self.field) {
+                    field.value.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(field) = self.fields.get_mut(self.field) {
+                    field.value.push(c);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Renders the selected prompt with the arguments in the form into the output pane.
+    /// Empty fields are left out, so that default values apply.
+    fn render(&mut self) -> Option<String> {
+        let prompt = self.selected_prompt()?.clone();
+        let name = prompt.metadata.name.clone();
+        let arguments: HashMap<String, Value> = self
+            .fields
+            .iter()
+            .filter(|field| !field.value.is_empty())
+            .map(|field| (field.info.name.clone(), Value::String(field.value.clone())))
+            .collect();
+
+        let rendered = PromptTemplate::new(prompt)
+            .map_err(|e| e.to_string())
+            .and_then(|template| {
+                template
+                    .render_with_warnings(&arguments, self.storage)
+                    .map_err(|e| e.to_string())
+            });
+        match rendered {
+            Ok(rendered) => {
+                self.output_title = format!("Rendered '{}'", name);
+                self.output = rendered.text.clone();
+                self.output_scroll = 0;
+                self.status = rendered
+                    .warnings
+                    .iter()
+                    .map(|warning| format!("Warning: {}", warning))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                self.record_usage(&name, UsageKind::Render);
+                Some(rendered.text)
+            }
+            Err(e) => {
+                self.status = format!("Error rendering '{}': {}", name, e);
+                None
+            }
+        }
+    }
+
+    /// Renders the selected prompt and streams the model's response into the output pane.
+    fn generate(&mut self) {
+        if self.generation.is_some() {
+            self.status = "A response is already being generated".to_string();
+            return;
+        }
+        let Some(text) = self.render() else {
+            return;
+        };
+        let errors: Vec<String> = ContentChecker::default()
+            .check(&text)
+            .iter()
+            .filter(|violation| violation.severity == Severity::Error)
+            .map(|violation| violation.to_string())
+            .collect();
+        if !errors.is_empty() {
+            self.status = format!("Content checks failed: {}", errors.join("; "));
+            return;
+        }
+        let Some(prompt) = self.selected_prompt() else {
+            return;
+        };
+        let name = prompt.metadata.name.clone();
+        let settings = prompt.metadata.model_settings.clone();
+        let messages = split_messages(&text);
+        let model_config = self.model_config.clone();
+
+        let (sender, receiver) = mpsc::channel();
+        tokio::spawn(async move {
+            let chunks = sender.clone();
+            let result = stream_chat_completions_content(
+                &model_config.provider_config(None, None),
+                &model_config.model_name,
+                &messages,
+                &settings,
+                |text| {
+                    let _ = chunks.send(Generation::Text(text.to_string()));
+                },
+            )
+            .await;
+            let _ = sender.send(Generation::Done(
+                result.map(|_| ()).map_err(|e| e.to_string()),
+            ));
+        });
+
+        self.generation = Some(receiver);
+        self.output_title = format!("Response to '{}'", name);
+        self.output.clear();
+        self.output_scroll = 0;
+        self.status = "Generating...".to_string();
+        self.record_usage(&name, UsageKind::Generate);
+    }
+
+    fn receive_generation(&mut self) {
+        let Some(receiver) = &self.generation else {
+            return;
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(Generation::Text(text)) => self.output.push_str(&text),
+                Ok(Generation::Done(result)) => {
+                    self.status = match result {
+                        Ok(()) => "Response complete".to_string(),
+                        Err(e) => format!("Error generating the response: {}", e),
+                    };
+                    self.generation = None;
+                    return;
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.generation = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn copy_output(&mut self) {
+        if self.output.is_empty() {
+            self.status = "Nothing to copy, render a prompt first".to_string();
+            return;
+        }
+        self.status = match copy_text(&self.output) {
+            Ok(Copied::System) => "Copied to the clipboard".to_string(),
+            Ok(Copied::Terminal) => "Copied to the terminal's clipboard".to_string(),
+            Err(e) => format!("Error copying to the clipboard: {:#}", e),
+        };
+    }
+
+    /// Records a use of a prompt, showing failures in the status line.
+    fn record_usage(&mut self, name: &str, kind: UsageKind) {
+        if let Err(e) = self.storage.record_usage(name, kind) {
+            self.status = format!("Couldn't record the usage of '{}': {}", name, e);
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [filter_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [prompts_area, details_area] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main_area);
+        let [preview_area, arguments_area, output_area] = Layout::vertical([
+            Constraint::Percentage(35),
+            Constraint::Length(self.fields.len().max(1) as u16 + 2),
+            Constraint::Min(3),
+        ])
+        .areas(details_area);
+
+        frame.render_widget(
+            Paragraph::new(self.filter.as_str()).block(self.block("Filter (/)", Focus::Filter)),
+            filter_area,
+        );
+        self.draw_prompts(frame, prompts_area);
+        self.draw_preview(frame, preview_area);
+        self.draw_arguments(frame, arguments_area);
+        frame.render_widget(
+            Paragraph::new(self.output.as_str())
+                .wrap(Wrap { trim: false })
+                .scroll((self.output_scroll, 0))
+                .block(Block::bordered().title(self.output_title.as_str())),
+            output_area,
+        );
+
+        let help = match self.focus {
+            Focus::Prompts => {
+                "j/k move  / filter  enter arguments  r render  g generate  c copy  q quit"
+            }
+            Focus::Filter => "type to filter  enter done  esc clear",
+            Focus::Arguments => "tab/up/down move  enter render  ctrl-g generate  esc back",
+        };
+        let status = if self.status.is_empty() {
+            help
+        } else {
+            self.status.as_str()
+        };
+        frame.render_widget(
+            Paragraph::new(status).style(Style::default().fg(Color::DarkGray)),
+            status_area,
+        );
+    }
+
+    fn block(&self, title: &'static str, focus: Focus) -> Block<'static> {
+        let block = Block::bordered().title(title);
+        if self.focus == focus {
+            block.border_style(Style::default().fg(Color::Cyan))
+        } else {
+            block
+        }
+    }
+
+    fn draw_prompts(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&index| {
+                let metadata = &self.prompts[index].metadata;
+                let mut spans = vec![Span::raw(metadata.name.clone())];
+                if metadata.pinned {
+                    spans.push(Span::styled(" *", Style::default().fg(Color::Yellow)));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(self.block("Prompts", Focus::Prompts))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+
+    fn draw_preview(&self, frame: &mut Frame, area: Rect) {
+        let mut lines = Vec::new();
+        if let Some(prompt) = self.selected_prompt() {
+            if let Some(description) = &prompt.metadata.description {
+                lines.push(Line::styled(
+                    description.clone(),
+                    Style::default().add_modifier(Modifier::ITALIC),
+                ));
+            }
+            if !prompt.metadata.tags.is_empty() {
+                lines.push(Line::styled(
+                    format!("Tags: {}", prompt.metadata.tags.join(", ")),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            if !lines.is_empty() {
+                lines.push(Line::raw(""));
+            }
+            lines.extend(
+                prompt
+                    .content
+                    .lines()
+                    .map(|line| Line::raw(line.to_string())),
+            );
+        }
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Preview")),
+            area,
+        );
+    }
+
+    fn draw_arguments(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = if self.fields.is_empty() {
+            vec![Line::styled(
+                "No arguments",
+                Style::default().fg(Color::DarkGray),
+            )]
+        } else {
+            self.fields
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    let mut label = field.info.name.clone();
+                    if !field.info.required {
+                        label.push('?');
+                    }
+                    let hint = match (&field.info.default, &field.info.description) {
+                        (_, Some(description)) if field.value.is_empty() => description.clone(),
+                        (Some(default), None) if field.value.is_empty() => default.clone(),
+                        _ => String::new(),
+                    };
+                    let mut style = Style::default();
+                    if self.focus == Focus::Arguments && index == self.field {
+                        style = style.add_modifier(Modifier::REVERSED);
+                    }
+                    Line::from(vec![
+                        Span::styled(format!("{}: ", label), style),
+                        Span::raw(field.value.clone()),
+                        Span::styled(hint, Style::default().fg(Color::DarkGray)),
+                    ])
+                })
+                .collect()
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(self.block("Arguments", Focus::Arguments)),
+            area,
+        );
+    }
+}
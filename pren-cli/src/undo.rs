@@ -0,0 +1,98 @@
+use crate::output::{OutputMode, print_json};
+use anyhow::{Result, bail};
+use pren_core::file_storage::{FileStorage, FileStorageError};
+use pren_core::journal::{JournalEntry, JournalOperation};
+use pren_core::storage::JournalStorage;
+use serde_json::json;
+
+/// Prints the changes that can be undone, most recent first.
+pub fn run_undo_list(storage: &FileStorage, output: OutputMode) -> Result<()> {
+    let journal: Vec<JournalEntry> = storage.get_journal()?.into_iter().rev().collect();
+    if output == OutputMode::Json {
+        let entries: Vec<_> = journal
+            .iter()
+            .map(|entry| {
+                json!({
+                    "timestamp": entry.timestamp,
+                    "operation": entry.operation,
+                    "name": entry.prompt,
+                    "path": entry.path
+                })
+            })
+            .collect();
+        return print_json(&entries);
+    }
+    if journal.is_empty() {
+        println!("Nothing to undo");
+    }
+    for entry in journal {
+        println!(
+            "{}  {:<9}  {}",
+            entry.timestamp.format("%Y-%m-%d %H:%M"),
+            format!("{:?}", entry.operation).to_lowercase(),
+            entry.prompt
+        );
+    }
+    Ok(())
+}
+
+/// Restores the prompt changed by the most recent delete, overwrite or rename.
+pub fn run_undo(storage: &FileStorage, output: OutputMode) -> Result<()> {
+    let entry = match storage.undo() {
+        Err(FileStorageError::PromptAlreadyExists(name)) => bail!(
+            "Can't restore prompt '{}', as a prompt with that name was created since. \
+             Rename or delete it first.",
+            name
+        ),
+        result => result?,
+    };
+    match (output, entry) {
+        (OutputMode::Json, None) => print_json(&json!({ "undone": false }))?,
+        (OutputMode::Json, Some(entry)) => print_json(&json!({
+            "undone": true,
+            "operation": entry.operation,
+            "name": entry.prompt
+        }))?,
+        (OutputMode::Text, None) => println!("Nothing to undo"),
+        (OutputMode::Text, Some(entry)) => println!(
+            "Prompt '{}' restored to how it was before it was {}",
+            entry.prompt,
+            match entry.operation {
+                JournalOperation::Delete => "deleted",
+                JournalOperation::Overwrite => "overwritten",
+                JournalOperation::Rename => "renamed",
+            }
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::prompt::{Prompt, PromptMetadata};
+    use pren_core::storage::PromptStorage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_run_undo_restores_a_deleted_prompt() {
+        let sandbox = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: sandbox.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        run_undo(&storage, OutputMode::Json).unwrap();
+
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("draft".to_string(), None, vec![]),
+                "Content".to_string(),
+            ))
+            .unwrap();
+        storage.delete_prompt("draft").unwrap();
+        run_undo_list(&storage, OutputMode::Json).unwrap();
+        run_undo(&storage, OutputMode::Json).unwrap();
+        assert_eq!("Content", storage.get_prompt("draft").unwrap().content);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::config::{ModelPrice, PrenCliConfig};
+use crate::constants::PREN_CLI;
+use crate::output::{OutputMode, print_json};
+use anyhow::{Context, Result};
+use pren_core::file_storage::FileStorage;
+use pren_core::llm::TokenUsage;
+use pren_core::storage::StatsStorage;
+use serde_json::json;
+use tracing::warn;
+
+/// Adds the tokens used by a request to the totals of its model. Failing to record them
+/// doesn't fail the command.
+pub fn record_tokens(storage: &FileStorage, model_name: &str, usage: Option<TokenUsage>) {
+    if let Some(usage) = usage
+        && let Err(e) = storage.record_tokens(model_name, usage)
+    {
+        warn!("Couldn't record the tokens used by '{}': {}", model_name, e);
+    }
+}
+
+/// Prints the tokens used by a request to stderr, with their cost if the model has a price,
+/// unless `show_usage` is disabled in the config.
+pub fn print_tokens(config: &PrenCliConfig, model_name: &str, usage: Option<TokenUsage>) {
+    let Some(usage) = usage.filter(|_| config.show_usage) else {
+        return;
+    };
+    let cost = config
+        .prices
+        .get(model_name)
+        .map(|price| format!(" (${:.4})", price.cost(&usage)))
+        .unwrap_or_default();
+    eprintln!(
+        "Tokens: {} in, {} out{}",
+        usage.input_tokens, usage.output_tokens, cost
+    );
+}
+
+/// Prints the tokens used by every model, most used first, with their cost for the models
+/// with a price.
+pub fn run_usage(storage: &FileStorage, config: &PrenCliConfig, output: OutputMode) -> Result<()> {
+    let stats = storage.get_stats()?;
+    let mut models: Vec<_> = stats.models.into_iter().collect();
+    models.sort_by(|(a_name, a), (b_name, b)| {
+        b.usage
+            .total_tokens()
+            .cmp(&a.usage.total_tokens())
+            .then_with(|| a_name.cmp(b_name))
+    });
+    let cost =
+        |name: &str, usage: &TokenUsage| config.prices.get(name).map(|price| price.cost(usage));
+
+    if output == OutputMode::Json {
+        let models: Vec<_> = models
+            .iter()
+            .map(|(name, model)| {
+                json!({
+                    "model": name,
+                    "requests": model.requests,
+                    "input_tokens": model.usage.input_tokens,
+                    "output_tokens": model.usage.output_tokens,
+                    "cost": cost(name, &model.usage),
+                    "last_used": model.last_used
+                })
+            })
+            .collect();
+        return print_json(&models);
+    }
+    if models.is_empty() {
+        println!("No tokens used yet");
+        return Ok(());
+    }
+
+    let mut total = None;
+    for (name, model) in &models {
+        let model_cost = cost(name, &model.usage);
+        if let Some(model_cost) = model_cost {
+            *total.get_or_insert(0.0) += model_cost;
+        }
+        println!(
+            "{}: {} requests, {} tokens in, {} tokens out{}",
+            name,
+            model.requests,
+            model.usage.input_tokens,
+            model.usage.output_tokens,
+            model_cost
+                .map(|cost| format!(", ${:.4}", cost))
+                .unwrap_or_default()
+        );
+    }
+    if let Some(total) = total {
+        println!("Total cost: ${:.4}", total);
+    }
+    Ok(())
+}
+
+/// Sets the price of a model, used to estimate the cost of its tokens.
+pub fn set_price(
+    mut config: PrenCliConfig,
+    model: String,
+    price: ModelPrice,
+    output: OutputMode,
+) -> Result<()> {
+    config.prices.insert(model.clone(), price);
+    confy::store(PREN_CLI, None, &config).context("Failed to save configuration")?;
+    match output {
+        OutputMode::Json => print_json(&json!({ "model": model, "price": price }))?,
+        OutputMode::Text => println!(
+            "Price of '{}' set to ${} per million input tokens and ${} per million output tokens",
+            model, price.input, price.output
+        ),
+    }
+    Ok(())
+}
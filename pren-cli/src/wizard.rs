@@ -0,0 +1,106 @@
+use crate::check::{check_content, check_references};
+use crate::config::ValidateOnSave;
+use crate::editor::{ask, choose, edit_text};
+use anyhow::{Result, bail};
+use pren_core::file_storage::FileStorage;
+use pren_core::parser::is_valid_prompt_name;
+use pren_core::prompt::{ArgumentDeclaration, Prompt, PromptMetadata, PromptTemplate};
+use pren_core::storage::PromptStorage;
+use std::collections::HashMap;
+
+/// Walks the user through creating a prompt: its name, description and tags, its content
+/// in their editor, and descriptions and defaults for the arguments it uses. The parsed
+/// template is previewed before it's saved.
+pub fn run_wizard(
+    storage: &FileStorage,
+    author: Option<String>,
+    validate_on_save: ValidateOnSave,
+) -> Result<()> {
+    let name = loop {
+        let name = ask("Name", "")?;
+        if !is_valid_prompt_name(&name) {
+            println!("Use letters, digits, '-', '_' and '.' in names, so they can be referenced");
+        } else if storage.get_prompt(&name).is_ok() {
+            println!("Prompt '{}' already exists", name);
+        } else {
+            break name;
+        }
+    };
+    let description = Some(ask("Description (optional)", "")?).filter(|d| !d.is_empty());
+    let tags = ask("Tags, separated by commas (optional)", "")?
+        .split(',')
+        .map(str::to_string)
+        .collect();
+
+    println!("Opening your editor for the content of the prompt...");
+    let mut content = String::new();
+    let template = loop {
+        content = edit_text(&content)?;
+        if content.trim().is_empty() {
+            bail!("The prompt content can't be empty, nothing was saved");
+        }
+        let prompt = Prompt::new(
+            PromptMetadata::new(name.clone(), description.clone(), Vec::new()),
+            content.clone(),
+        );
+        match PromptTemplate::new(prompt) {
+            Ok(template) => break template,
+            Err(e) => {
+                println!("Invalid template: {:#}", anyhow::Error::from(e));
+                if choose("[e]dit again or [d]iscard?", &["edit", "discard"])? == 'd' {
+                    println!("Prompt '{}' discarded", name);
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let mut arguments = Vec::new();
+    let detected: Vec<String> = template
+        .argument_info()
+        .into_iter()
+        .map(|argument| argument.name)
+        .collect();
+    if !detected.is_empty() {
+        println!("Arguments found: {}", detected.join(", "));
+        if choose("Document them? [y/n]", &["yes", "no"])? == 'y' {
+            for argument in detected {
+                let description = ask(&format!("Description of '{}' (optional)", argument), "")?;
+                let default = ask(
+                    &format!("Default of '{}' (optional, required if empty)", argument),
+                    "",
+                )?;
+                arguments.push(ArgumentDeclaration {
+                    name: argument,
+                    description: Some(description).filter(|d| !d.is_empty()),
+                    default: Some(default).filter(|d| !d.is_empty()),
+                });
+            }
+        }
+    }
+
+    let mut metadata = PromptMetadata::new(name.clone(), description, tags);
+    metadata.author = author;
+    metadata.arguments = arguments;
+    let prompt = Prompt::new(metadata, content);
+    let template = PromptTemplate::new(prompt.clone())?;
+
+    println!("\nPreview, with missing arguments left as placeholders:\n");
+    match template.render_partial(&HashMap::<String, String>::new(), storage) {
+        Ok(preview) => println!("{}\n", preview.text),
+        Err(e) => println!(
+            "Couldn't preview the prompt: {:#}\n",
+            anyhow::Error::from(e)
+        ),
+    }
+
+    if choose("Save the prompt? [y/n]", &["yes", "no"])? == 'n' {
+        println!("Prompt '{}' discarded", name);
+        return Ok(());
+    }
+    check_content(&prompt.content)?;
+    check_references(storage, &prompt, validate_on_save)?;
+    storage.save_prompt(&prompt)?;
+    println!("Prompt '{}' saved", name);
+    Ok(())
+}
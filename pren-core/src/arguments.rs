@@ -0,0 +1,187 @@
+//! # Argument Sources
+//!
+//! This module provides the sources template arguments can come from, so that values used
+//! on every render don't have to be typed again each time.
+//!
+//! Arguments can be read from a context file (JSON, YAML or TOML, picked by the file
+//! extension), from environment variables with a prefix, or given explicitly. Sources are
+//! merged with [`merge_arguments`], where later sources take precedence.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::arguments::{ArgumentSource, merge_arguments};
+//! use serde_json::Value;
+//! use std::collections::HashMap;
+//!
+//! let defaults = HashMap::from([
+//!     ("name".to_string(), Value::from("World")),
+//!     ("tone".to_string(), Value::from("formal")),
+//! ]);
+//! let explicit = HashMap::from([("name".to_string(), Value::from("Ana"))]);
+//!
+//! let arguments = merge_arguments(&[
+//!     ArgumentSource::Values(defaults),
+//!     ArgumentSource::Values(explicit),
+//! ])
+//! .unwrap();
+//! assert_eq!(arguments["name"], "Ana");
+//! assert_eq!(arguments["tone"], "formal");
+//! ```
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ArgumentSourceError {
+    #[error("couldn't read context file {path:?}")]
+    ReadError { path: PathBuf, source: io::Error },
+    #[error("invalid context file {path:?}: {message}")]
+    ParseError { path: PathBuf, message: String },
+    #[error("unsupported context file {0:?}, expected a .json, .yaml, .yml or .toml file")]
+    UnsupportedFormat(PathBuf),
+}
+
+/// A source of template arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentSource {
+    /// A context file holding an object of arguments, in JSON, YAML or TOML.
+    File(PathBuf),
+    /// Environment variables starting with the prefix. The rest of the variable name,
+    /// lowercased, is the argument name (e.g., `PREN_ARG_NAME` is `name` for `PREN_ARG_`).
+    Env { prefix: String },
+    /// Explicit argument values.
+    Values(HashMap<String, Value>),
+}
+
+impl ArgumentSource {
+    /// Loads the arguments of the source.
+    pub fn load(&self) -> Result<HashMap<String, Value>, ArgumentSourceError> {
+        match self {
+            ArgumentSource::File(path) => load_context_file(path),
+            ArgumentSource::Env { prefix } => Ok(env_arguments(prefix, env::vars())),
+            ArgumentSource::Values(values) => Ok(values.clone()),
+        }
+    }
+}
+
+/// Loads and merges the arguments of several sources.
+///
+/// When an argument is in more than one source, the value from the last source is used.
+pub fn merge_arguments(
+    sources: &[ArgumentSource],
+) -> Result<HashMap<String, Value>, ArgumentSourceError> {
+    let mut arguments = HashMap::new();
+    for source in sources {
+        arguments.extend(source.load()?);
+    }
+    Ok(arguments)
+}
+
+fn load_context_file(path: &Path) -> Result<HashMap<String, Value>, ArgumentSourceError> {
+    let content = fs::read_to_string(path).map_err(|source| ArgumentSourceError::ReadError {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let parse_error = |message: String| ArgumentSourceError::ParseError {
+        path: path.to_path_buf(),
+        message,
+    };
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(|e| parse_error(e.to_string())),
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str(&content).map_err(|e| parse_error(e.to_string()))
+        }
+        Some("toml") => toml::from_str(&content).map_err(|e| parse_error(e.to_string())),
+        _ => Err(ArgumentSourceError::UnsupportedFormat(path.to_path_buf())),
+    }
+}
+
+fn env_arguments(
+    prefix: &str,
+    vars: impl Iterator<Item = (String, String)>,
+) -> HashMap<String, Value> {
+    vars.filter_map(|(key, value)| {
+        key.strip_prefix(prefix)
+            .filter(|name| !name.is_empty())
+            .map(|name| (name.to_lowercase(), Value::String(value)))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_context_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let files = [
+            ("vars.json", r#"{"name": "Ana", "user": {"age": 30}}"#),
+            ("vars.yaml", "name: Ana\nuser:\n  age: 30\n"),
+            ("vars.toml", "name = \"Ana\"\n[user]\nage = 30\n"),
+        ];
+
+        for (file_name, content) in files {
+            let path = temp_dir.path().join(file_name);
+            fs::write(&path, content).unwrap();
+            let arguments = ArgumentSource::File(path).load().unwrap();
+            assert_eq!(json!("Ana"), arguments["name"], "{}", file_name);
+            assert_eq!(json!({"age": 30}), arguments["user"], "{}", file_name);
+        }
+    }
+
+    #[test]
+    fn test_load_invalid_context_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let list = temp_dir.path().join("list.json");
+        fs::write(&list, "[1, 2]").unwrap();
+        assert!(matches!(
+            ArgumentSource::File(list).load(),
+            Err(ArgumentSourceError::ParseError { .. })
+        ));
+
+        let text = temp_dir.path().join("vars.txt");
+        fs::write(&text, "name=Ana").unwrap();
+        assert!(matches!(
+            ArgumentSource::File(text).load(),
+            Err(ArgumentSourceError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_env_arguments() {
+        let vars = [
+            ("PREN_ARG_NAME", "Ana"),
+            ("PREN_ARG_", "ignored"),
+            ("HOME", "/home/ana"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+
+        assert_eq!(
+            HashMap::from([("name".to_string(), json!("Ana"))]),
+            env_arguments("PREN_ARG_", vars)
+        );
+    }
+
+    #[test]
+    fn test_merge_arguments_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("vars.json");
+        fs::write(&path, r#"{"name": "File", "tone": "formal"}"#).unwrap();
+
+        let arguments = merge_arguments(&[
+            ArgumentSource::File(path),
+            ArgumentSource::Values(HashMap::from([("name".to_string(), json!("Ana"))])),
+        ])
+        .unwrap();
+        assert_eq!(json!("Ana"), arguments["name"]);
+        assert_eq!(json!("formal"), arguments["tone"]);
+    }
+}
@@ -0,0 +1,431 @@
+//! # Prompt Composition
+//!
+//! This module builds trees of how prompts are composed from each other through prompt
+//! references, either the prompts a prompt includes, recursively, or the prompts that
+//! include it.
+//!
+//! Trees are built from the templates alone, without arguments, so prompts referenced
+//! through an argument (`{{prompt_var:name}}`) are shown as such instead of being resolved.
+//!
+//! The same static references are used to find the circular references and too deep
+//! nesting that would make a prompt fail to render, before it's saved, with
+//! [`composition_problems`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::composition::{NodeKind, composition_tree};
+//! use pren_core::file_storage::FileStorage;
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//! use pren_core::storage::PromptStorage;
+//! use tempfile::TempDir;
+//!
+//! let temp_dir = TempDir::new().unwrap();
+//! let storage = FileStorage {
+//!     base_path: temp_dir.path().to_path_buf(),
+//!     fallback_paths: Vec::new(),
+//!     vault: false,
+//! };
+//! storage
+//!     .save_prompt(&Prompt::new(
+//!         PromptMetadata::new("review".to_string(), None, vec![]),
+//!         "{{prompt:persona}} Review {{code}}".to_string(),
+//!     ))
+//!     .unwrap();
+//!
+//! let tree = composition_tree(&storage, "review").unwrap();
+//! assert_eq!("persona", tree.children[0].name);
+//! assert_eq!(NodeKind::Missing, tree.children[0].kind);
+//! ```
+
+use crate::prompt::{MAX_NESTING_DEPTH, PromptTemplate, PromptTemplatePart};
+use crate::storage::PromptStorage;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// What a node of a composition tree stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    /// A stored prompt.
+    Prompt,
+    /// A prompt chosen by an argument, named after the argument.
+    Variable,
+    /// A prompt that can't be loaded, usually because it doesn't exist.
+    Missing,
+    /// A prompt that's already in the tree above this node, which isn't expanded again.
+    Cycle,
+}
+
+/// A prompt in a composition tree.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompositionNode {
+    /// The name of the prompt, or of the argument choosing it for variable references.
+    pub name: String,
+    pub kind: NodeKind,
+    /// Whether the reference can be left out to fit a token budget.
+    pub optional: bool,
+    /// The prompts the prompt includes, or that include it in reverse trees.
+    pub children: Vec<CompositionNode>,
+}
+
+impl CompositionNode {
+    fn new(name: &str, kind: NodeKind, optional: bool) -> Self {
+        CompositionNode {
+            name: name.to_string(),
+            kind,
+            optional,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Builds the tree of the prompts a prompt includes through prompt references, recursively.
+///
+/// Each prompt is listed once under the prompt including it, in the order of their first
+/// references.
+///
+/// # Returns
+///
+/// * `Ok(CompositionNode)` - The tree, rooted at the prompt.
+/// * `Err(S::Error)` - If the prompt itself can't be loaded.
+pub fn composition_tree<S: PromptStorage>(
+    storage: &S,
+    name: &str,
+) -> Result<CompositionNode, S::Error> {
+    let prompt = storage.get_prompt(name)?;
+    let mut root = CompositionNode::new(name, NodeKind::Prompt, false);
+    if let Ok(template) = PromptTemplate::new(prompt) {
+        root.children = reference_nodes(&template, storage, &mut vec![name.to_string()]);
+    }
+    Ok(root)
+}
+
+fn reference_nodes<S: PromptStorage>(
+    template: &PromptTemplate,
+    storage: &S,
+    ancestors: &mut Vec<String>,
+) -> Vec<CompositionNode> {
+    let mut nodes: Vec<CompositionNode> = Vec::new();
+    for part in &template.parts {
+        let (name, optional, variable) = match part {
+            PromptTemplatePart::PromptReference(name) => (name, false, false),
+            PromptTemplatePart::OptionalPromptReference(name) => (name, true, false),
+            PromptTemplatePart::VariablePromptReference(name) => (name, false, true),
+            _ => continue,
+        };
+        let kind = if variable {
+            NodeKind::Variable
+        } else {
+            NodeKind::Prompt
+        };
+        if nodes
+            .iter()
+            .any(|node| &node.name == name && (node.kind == NodeKind::Variable) == variable)
+        {
+            continue;
+        }
+
+        let mut node = CompositionNode::new(name, kind, optional);
+        if !variable {
+            if ancestors.contains(name) {
+                node.kind = NodeKind::Cycle;
+            } else {
+                match storage
+                    .get_prompt(name)
+                    .ok()
+                    .and_then(|prompt| PromptTemplate::new(prompt).ok())
+                {
+                    Some(referenced) => {
+                        ancestors.push(name.clone());
+                        node.children = reference_nodes(&referenced, storage, ancestors);
+                        ancestors.pop();
+                    }
+                    None => node.kind = NodeKind::Missing,
+                }
+            }
+        }
+        nodes.push(node);
+    }
+    nodes
+}
+
+/// Builds the tree of the prompts including a prompt through static prompt references,
+/// recursively, sorted by name.
+///
+/// # Returns
+///
+/// * `Ok(CompositionNode)` - The tree, rooted at the prompt, with the prompts including
+///   it as children. The prompt itself doesn't need to exist.
+/// * `Err(S::Error)` - If the stored prompts can't be loaded.
+pub fn reverse_composition_tree<S: PromptStorage>(
+    storage: &S,
+    name: &str,
+) -> Result<CompositionNode, S::Error> {
+    let mut included_by: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for prompt in storage.get_prompts()? {
+        let including = prompt.metadata.name.clone();
+        if let Ok(template) = PromptTemplate::new(prompt) {
+            for reference in template.prompt_references() {
+                included_by
+                    .entry(reference)
+                    .or_default()
+                    .insert(including.clone());
+            }
+        }
+    }
+
+    let mut root = CompositionNode::new(name, NodeKind::Prompt, false);
+    root.children = including_nodes(name, &included_by, &mut vec![name.to_string()]);
+    Ok(root)
+}
+
+fn including_nodes(
+    name: &str,
+    included_by: &BTreeMap<String, BTreeSet<String>>,
+    descendants: &mut Vec<String>,
+) -> Vec<CompositionNode> {
+    let Some(including) = included_by.get(name) else {
+        return Vec::new();
+    };
+    including
+        .iter()
+        .map(|including| {
+            let mut node = CompositionNode::new(including, NodeKind::Prompt, false);
+            if descendants.contains(including) {
+                node.kind = NodeKind::Cycle;
+            } else {
+                descendants.push(including.clone());
+                node.children = including_nodes(including, included_by, descendants);
+                descendants.pop();
+            }
+            node
+        })
+        .collect()
+}
+
+/// A reason a prompt would fail to render, found from its static prompt references.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CompositionProblem {
+    /// The prompts of `chain` reference each other, starting and ending with the same one.
+    CircularReference { chain: Vec<String> },
+    /// The prompts of `chain` are nested deeper than `limit` prompt references.
+    DepthExceeded { chain: Vec<String>, limit: usize },
+}
+
+impl fmt::Display for CompositionProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompositionProblem::CircularReference { chain } => {
+                write!(f, "Circular reference: {}", chain.join(" -> "))
+            }
+            CompositionProblem::DepthExceeded { chain, limit } => write!(
+                f,
+                "Prompts are nested deeper than {} references: {}",
+                limit,
+                chain.join(" -> ")
+            ),
+        }
+    }
+}
+
+/// Finds the circular references and too deep nesting a prompt would run into when
+/// rendered, following its static prompt references (`prompt` and `prompt_opt`) through
+/// the storage.
+///
+/// The prompt doesn't need to be saved, as the given template is used rather than the
+/// stored prompt with its name, so a prompt can be checked before saving it. Prompts that
+/// don't exist or can't be parsed are skipped, as are references through arguments.
+///
+/// # Returns
+///
+/// Each circular reference once, and the first chain of prompts that is nested too deep,
+/// if any. An empty list means the references of the prompt resolve.
+pub fn composition_problems<S: PromptStorage>(
+    template: &PromptTemplate,
+    storage: &S,
+) -> Vec<CompositionProblem> {
+    let mut problems = Vec::new();
+    let mut chain = vec![template.prompt.metadata.name.clone()];
+    find_problems(template, storage, &mut chain, &mut problems);
+    problems
+}
+
+fn find_problems<S: PromptStorage>(
+    template: &PromptTemplate,
+    storage: &S,
+    chain: &mut Vec<String>,
+    problems: &mut Vec<CompositionProblem>,
+) {
+    let mut seen = BTreeSet::new();
+    for part in &template.parts {
+        let name = match part {
+            PromptTemplatePart::PromptReference(name)
+            | PromptTemplatePart::OptionalPromptReference(name) => name,
+            _ => continue,
+        };
+        if !seen.insert(name) {
+            continue;
+        }
+
+        if let Some(start) = chain.iter().position(|ancestor| ancestor == name) {
+            let mut cycle = chain[start..].to_vec();
+            cycle.push(name.clone());
+            let problem = CompositionProblem::CircularReference { chain: cycle };
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+            continue;
+        }
+        if chain.len() > MAX_NESTING_DEPTH {
+            let too_deep = problems
+                .iter()
+                .any(|problem| matches!(problem, CompositionProblem::DepthExceeded { .. }));
+            if !too_deep {
+                let mut nested = chain.clone();
+                nested.push(name.clone());
+                problems.push(CompositionProblem::DepthExceeded {
+                    chain: nested,
+                    limit: MAX_NESTING_DEPTH,
+                });
+            }
+            continue;
+        }
+
+        // The checked prompt is the first of the chain, so it's never loaded from storage
+        if let Some(referenced) = storage
+            .get_prompt(name)
+            .ok()
+            .and_then(|prompt| PromptTemplate::new(prompt).ok())
+        {
+            chain.push(name.clone());
+            find_problems(&referenced, storage, chain, problems);
+            chain.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_storage::FileStorage;
+    use crate::prompt::{Prompt, PromptMetadata};
+    use tempfile::TempDir;
+
+    fn storage(prompts: &[(&str, &str)]) -> (TempDir, FileStorage) {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        for (name, content) in prompts {
+            storage
+                .save_prompt(&Prompt::new(
+                    PromptMetadata::new(name.to_string(), None, vec![]),
+                    content.to_string(),
+                ))
+                .unwrap();
+        }
+        (temp_dir, storage)
+    }
+
+    /// The tree as `name:kind` labels, children in parentheses.
+    fn labels(node: &CompositionNode) -> String {
+        let mut label = format!("{}:{:?}", node.name, node.kind);
+        if !node.children.is_empty() {
+            let children: Vec<String> = node.children.iter().map(labels).collect();
+            label.push_str(&format!("({})", children.join(" ")));
+        }
+        label
+    }
+
+    #[test]
+    fn test_composition_tree() {
+        let (_temp_dir, storage) = storage(&[
+            (
+                "review",
+                "{{prompt:persona}} {{prompt_var:style}} {{prompt_opt:extra}}",
+            ),
+            (
+                "persona",
+                "{{prompt:tone}} {{prompt:review}} {{prompt:tone}}",
+            ),
+            ("tone", "Be kind"),
+        ]);
+        let tree = composition_tree(&storage, "review").unwrap();
+        assert_eq!(
+            "review:Prompt(persona:Prompt(tone:Prompt review:Cycle) style:Variable extra:Missing)",
+            labels(&tree)
+        );
+        assert!(tree.children[2].optional);
+    }
+
+    #[test]
+    fn test_reverse_composition_tree() {
+        let (_temp_dir, storage) = storage(&[
+            ("review", "{{prompt:persona}}"),
+            ("persona", "{{prompt:tone}} {{prompt:review}}"),
+            ("summary", "{{prompt:tone}}"),
+            ("tone", "Be kind"),
+        ]);
+        assert_eq!(
+            "tone:Prompt(persona:Prompt(review:Prompt(persona:Cycle)) summary:Prompt)",
+            labels(&reverse_composition_tree(&storage, "tone").unwrap())
+        );
+        assert_eq!(
+            "missing:Prompt",
+            labels(&reverse_composition_tree(&storage, "missing").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_composition_problems() {
+        let (_cyclic_dir, cyclic) = storage(&[("tone", "{{prompt_opt:review}}")]);
+        let (_temp_dir, storage) = storage(&[
+            ("persona", "{{prompt:tone}}"),
+            ("tone", "Be kind"),
+            ("a", "{{prompt:b}}"),
+            ("b", "{{prompt:c}}"),
+            ("c", "{{prompt:d}}"),
+            ("d", "{{prompt:e}}"),
+            ("e", "End"),
+        ]);
+        let template = |content: &str| {
+            PromptTemplate::new(Prompt::new(
+                PromptMetadata::new("review".to_string(), None, vec![]),
+                content.to_string(),
+            ))
+            .unwrap()
+        };
+
+        let problems = |content: &str| composition_problems(&template(content), &storage);
+        assert!(problems("{{prompt:persona}} {{prompt_var:style}} {{prompt:missing}}").is_empty());
+        assert!(problems("{{prompt:c}}").is_empty());
+
+        // The stored 'tone' references 'review', which isn't stored yet
+        assert_eq!(
+            vec![CompositionProblem::CircularReference {
+                chain: vec![
+                    "review".to_string(),
+                    "tone".to_string(),
+                    "review".to_string()
+                ]
+            }],
+            composition_problems(&template("{{prompt:tone}} {{prompt:tone}}"), &cyclic)
+        );
+        assert_eq!(
+            vec![CompositionProblem::DepthExceeded {
+                chain: ["review", "a", "b", "c", "d"].map(str::to_string).to_vec(),
+                limit: 3
+            }],
+            problems("{{prompt:a}}")
+        );
+        assert_eq!(
+            "Circular reference: review -> review",
+            problems("{{prompt:review}}")[0].to_string()
+        );
+    }
+}
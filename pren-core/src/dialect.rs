@@ -0,0 +1,413 @@
+//! # Template Dialects
+//!
+//! This module provides the template dialects prompts can be written in, so prompts written
+//! for other tools can be stored as they are. A prompt picks its dialect in its frontmatter:
+//!
+//! ```yaml
+//! dialect: jinja
+//! ```
+//!
+//! - `pren` (default) - pren's own syntax, such as `{{name}}` and `{{prompt:other}}`
+//! - `jinja` - Jinja2 templates, rendered with minijinja (`jinja` feature)
+//! - `handlebars` - Handlebars templates (`handlebars` feature)
+//!
+//! Prompts in other dialects reference prompts through a function instead of the
+//! `{{prompt:name}}` syntax: `{{ prompt("name") }}` in Jinja and `{{prompt "name"}}` in
+//! Handlebars. Referenced prompts are rendered with the same arguments, whatever their
+//! dialect, and references are checked for cycles and depth as in pren templates.
+//!
+//! The arguments of a Jinja prompt are the variables its template uses. The ones of a
+//! Handlebars prompt are the variables used outside blocks changing the context, such as
+//! `{{#each}}`, along with the declared ones.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[cfg(feature = "jinja")]
+//! # {
+//! use pren_core::dialect::Dialect;
+//! use pren_core::memory_storage::MemoryStorage;
+//! use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
+//! use std::collections::HashMap;
+//!
+//! let mut metadata = PromptMetadata::new("greeting".to_string(), None, vec![]);
+//! metadata.dialect = Dialect::Jinja;
+//! let prompt = Prompt::new(metadata, "Hello {{ name | upper }}".to_string());
+//!
+//! let template = PromptTemplate::new(prompt).unwrap();
+//! let arguments = HashMap::from([("name".to_string(), "world".to_string())]);
+//! assert_eq!("Hello WORLD", template.render(&arguments, &MemoryStorage::new()).unwrap());
+//! # }
+//! ```
+
+use crate::prompt::{ParseTemplateError, RenderTemplateError};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// The template syntax a prompt is written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    /// pren's own template syntax.
+    #[default]
+    Pren,
+    /// Jinja2 templates, rendered with minijinja.
+    Jinja,
+    /// Handlebars templates.
+    Handlebars,
+}
+
+impl Dialect {
+    pub fn is_pren(&self) -> bool {
+        *self == Dialect::Pren
+    }
+}
+
+impl std::fmt::Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dialect::Pren => write!(f, "pren"),
+            Dialect::Jinja => write!(f, "jinja"),
+            Dialect::Handlebars => write!(f, "handlebars"),
+        }
+    }
+}
+
+/// The result of rendering a template in a dialect.
+pub(crate) struct DialectRender {
+    /// The rendered text.
+    pub text: String,
+    /// The prompts the template referenced that weren't given, in the order they were
+    /// referenced. The text is only complete when there are none.
+    pub missing_references: Vec<String>,
+}
+
+/// Checks the syntax of a template in a dialect.
+#[cfg_attr(
+    not(any(feature = "jinja", feature = "handlebars")),
+    allow(unused_variables)
+)]
+pub(crate) fn validate(dialect: Dialect, source: &str) -> Result<(), ParseTemplateError> {
+    let result: Result<(), String> = match dialect {
+        #[cfg(feature = "jinja")]
+        Dialect::Jinja => jinja::validate(source),
+        #[cfg(feature = "handlebars")]
+        Dialect::Handlebars => handlebars::validate(source),
+        // Prompts in a dialect whose feature isn't enabled can still be read, just not rendered
+        _ => Ok(()),
+    };
+    result.map_err(|message| ParseTemplateError {
+        message: format!("Failed to parse {} template: {}", dialect, message),
+    })
+}
+
+/// Returns the names of the variables a template in a dialect uses, or none if the
+/// dialect's feature isn't enabled.
+#[cfg_attr(
+    not(any(feature = "jinja", feature = "handlebars")),
+    allow(unused_variables)
+)]
+pub(crate) fn variables(dialect: Dialect, source: &str) -> Vec<String> {
+    let mut names = match dialect {
+        #[cfg(feature = "jinja")]
+        Dialect::Jinja => jinja::variables(source),
+        #[cfg(feature = "handlebars")]
+        Dialect::Handlebars => handlebars::variables(source),
+        _ => Vec::new(),
+    };
+    names.retain(|name| name != REFERENCE_FUNCTION);
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Renders a template in a dialect with the given variables. Referenced prompts are taken
+/// from `references`, and the ones missing from it are reported instead.
+#[cfg_attr(
+    not(any(feature = "jinja", feature = "handlebars")),
+    allow(unused_variables)
+)]
+pub(crate) fn render(
+    dialect: Dialect,
+    source: &str,
+    variables: &Map<String, Value>,
+    references: &HashMap<String, String>,
+) -> Result<DialectRender, RenderTemplateError> {
+    match dialect {
+        #[cfg(feature = "jinja")]
+        Dialect::Jinja => jinja::render(source, variables, references),
+        #[cfg(feature = "handlebars")]
+        Dialect::Handlebars => handlebars::render(source, variables, references),
+        _ => Err::<DialectRender, _>(format!("it needs the '{}' feature of pren-core", dialect)),
+    }
+    .map_err(|message| {
+        RenderTemplateError::Other(format!(
+            "Failed to render {} template: {}",
+            dialect, message
+        ))
+    })
+}
+
+/// The function templates in other dialects reference prompts with.
+const REFERENCE_FUNCTION: &str = "prompt";
+
+/// Looks up a referenced prompt, recording it as missing if it isn't given yet.
+#[cfg(any(feature = "jinja", feature = "handlebars"))]
+fn reference(
+    name: &str,
+    references: &HashMap<String, String>,
+    missing: &std::sync::Mutex<Vec<String>>,
+) -> String {
+    match references.get(name) {
+        Some(text) => text.clone(),
+        None => {
+            let mut missing = missing.lock().unwrap_or_else(|e| e.into_inner());
+            if !missing.iter().any(|missing| missing == name) {
+                missing.push(name.to_string());
+            }
+            String::new()
+        }
+    }
+}
+
+#[cfg(feature = "jinja")]
+mod jinja {
+    use super::{DialectRender, REFERENCE_FUNCTION, reference};
+    use minijinja::{Environment, UndefinedBehavior};
+    use serde_json::{Map, Value};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    fn environment<'source>() -> Environment<'source> {
+        let mut environment = Environment::new();
+        environment.set_keep_trailing_newline(true);
+        // Undefined values can be tested, as in `{% if name %}`, but not printed
+        environment.set_undefined_behavior(UndefinedBehavior::SemiStrict);
+        environment
+    }
+
+    pub(super) fn validate(source: &str) -> Result<(), String> {
+        environment()
+            .template_from_str(source)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    pub(super) fn variables(source: &str) -> Vec<String> {
+        let environment = environment();
+        let Ok(template) = environment.template_from_str(source) else {
+            return Vec::new();
+        };
+        template
+            .undeclared_variables(false)
+            .into_iter()
+            .filter(|name| environment.globals().all(|(global, _)| global != name))
+            .collect()
+    }
+
+    pub(super) fn render(
+        source: &str,
+        variables: &Map<String, Value>,
+        references: &HashMap<String, String>,
+    ) -> Result<DialectRender, String> {
+        let missing = Arc::new(Mutex::new(Vec::new()));
+        let mut environment = environment();
+        let (references, function_missing) = (references.clone(), Arc::clone(&missing));
+        environment.add_function(REFERENCE_FUNCTION, move |name: String| {
+            reference(&name, &references, &function_missing)
+        });
+
+        let text = environment
+            .template_from_str(source)
+            .and_then(|template| template.render(variables))
+            .map_err(|e| e.to_string())?;
+        let missing_references =
+            std::mem::take(&mut *missing.lock().unwrap_or_else(|e| e.into_inner()));
+        Ok(DialectRender {
+            text,
+            missing_references,
+        })
+    }
+}
+
+#[cfg(feature = "handlebars")]
+mod handlebars {
+    use super::{DialectRender, REFERENCE_FUNCTION, reference};
+    use handlebars::template::{Parameter, TemplateElement};
+    use handlebars::{
+        Context, Handlebars, Helper, HelperResult, Output, Path, PathSeg, RenderContext,
+        RenderErrorReason, Template,
+    };
+    use serde_json::{Map, Value};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    pub(super) fn validate(source: &str) -> Result<(), String> {
+        Template::compile(source)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    pub(super) fn variables(source: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        if let Ok(template) = Template::compile(source) {
+            collect_variables(&template, &mut names);
+        }
+        names
+    }
+
+    /// Collects the first segment of the paths used in the template, skipping the ones in
+    /// blocks changing the context, where paths are relative to something else.
+    fn collect_variables(template: &Template, names: &mut Vec<String>) {
+        for element in &template.elements {
+            let helper = match element {
+                TemplateElement::Expression(helper)
+                | TemplateElement::HtmlExpression(helper)
+                | TemplateElement::HelperBlock(helper) => helper,
+                _ => continue,
+            };
+            let parameters = std::iter::once(&helper.name)
+                .chain(&helper.params)
+                .chain(helper.hash.values());
+            for parameter in parameters {
+                if let Parameter::Path(Path::Relative((segments, _))) = parameter
+                    && let Some(PathSeg::Named(name)) = segments.first()
+                {
+                    names.push(name.clone());
+                }
+            }
+
+            let keeps_context =
+                matches!(&helper.name, Parameter::Name(name) if name == "if" || name == "unless");
+            if keeps_context {
+                for block in [&helper.template, &helper.inverse].into_iter().flatten() {
+                    collect_variables(block, names);
+                }
+            }
+        }
+    }
+
+    pub(super) fn render(
+        source: &str,
+        variables: &Map<String, Value>,
+        references: &HashMap<String, String>,
+    ) -> Result<DialectRender, String> {
+        let missing = Mutex::new(Vec::new());
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_helper(
+            REFERENCE_FUNCTION,
+            Box::new(
+                |helper: &Helper,
+                 _: &Handlebars,
+                 _: &Context,
+                 _: &mut RenderContext,
+                 out: &mut dyn Output|
+                 -> HelperResult {
+                    let name = helper
+                        .param(0)
+                        .and_then(|name| name.value().as_str())
+                        .ok_or(RenderErrorReason::ParamNotFoundForIndex(
+                            REFERENCE_FUNCTION,
+                            0,
+                        ))?;
+                    out.write(&reference(name, references, &missing))?;
+                    Ok(())
+                },
+            ),
+        );
+
+        let text = registry
+            .render_template(source, variables)
+            .map_err(|e| e.to_string())?;
+        drop(registry);
+        Ok(DialectRender {
+            text,
+            missing_references: missing.into_inner().unwrap_or_else(|e| e.into_inner()),
+        })
+    }
+}
+
+#[cfg(all(test, any(feature = "jinja", feature = "handlebars")))]
+mod tests {
+    use super::*;
+    use crate::memory_storage::MemoryStorage;
+    use crate::prompt::{Prompt, PromptMetadata, PromptTemplate};
+
+    fn prompt(name: &str, dialect: Dialect, content: &str) -> Prompt {
+        let mut metadata = PromptMetadata::new(name.to_string(), None, vec![]);
+        metadata.dialect = dialect;
+        Prompt::new(metadata, content.to_string())
+    }
+
+    fn render(storage: &MemoryStorage, name: &str, arguments: &HashMap<String, Value>) -> String {
+        let template = PromptTemplate::new(storage.prompt(name).unwrap()).unwrap();
+        match template.render(arguments, storage) {
+            Ok(text) => text,
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[cfg(feature = "jinja")]
+    #[test]
+    fn test_jinja_dialect() {
+        let storage = MemoryStorage::from_prompts([
+            prompt("signature", Dialect::Pren, "-- {{author}}"),
+            prompt(
+                "note",
+                Dialect::Jinja,
+                "{% for item in items %}- {{ item | upper }}\n{% endfor %}{{ prompt(\"signature\") }}",
+            ),
+            prompt("wrapper", Dialect::Pren, "Note:\n{{prompt:note}}"),
+            prompt("loop", Dialect::Jinja, "{{ prompt(\"loop\") }}"),
+        ]);
+        let template = PromptTemplate::new(storage.prompt("note").unwrap()).unwrap();
+        assert_eq!(vec!["items"], template.arguments());
+
+        let mut arguments = HashMap::from([("items".to_string(), serde_json::json!(["a", "b"]))]);
+        let missing = PromptTemplate::new(storage.prompt("note").unwrap())
+            .unwrap()
+            .render(&arguments, &storage)
+            .unwrap_err();
+        assert!(missing.is_missing_argument());
+
+        arguments.insert("author".to_string(), Value::String("Ana".to_string()));
+        assert_eq!("- A\n- B\n-- Ana", render(&storage, "note", &arguments));
+        assert_eq!(
+            "Note:\n- A\n- B\n-- Ana",
+            render(&storage, "wrapper", &arguments)
+        );
+        assert!(render(&storage, "loop", &arguments).contains("Circular reference"));
+
+        let invalid = prompt("invalid", Dialect::Jinja, "{% if x %}");
+        assert!(PromptTemplate::new(invalid).is_err());
+    }
+
+    #[cfg(feature = "handlebars")]
+    #[test]
+    fn test_handlebars_dialect() {
+        let storage = MemoryStorage::from_prompts([
+            prompt("signature", Dialect::Jinja, "-- {{ author }}"),
+            prompt(
+                "note",
+                Dialect::Handlebars,
+                "{{#if title}}# {{title}}\n{{/if}}{{#each items}}- {{this}}\n{{/each}}{{prompt \"signature\"}}",
+            ),
+        ]);
+        let template = PromptTemplate::new(storage.prompt("note").unwrap()).unwrap();
+        assert_eq!(vec!["items", "title"], template.arguments());
+
+        let arguments = HashMap::from([
+            ("title".to_string(), Value::String("<Todo>".to_string())),
+            ("items".to_string(), serde_json::json!(["a", "b"])),
+            ("author".to_string(), Value::String("Ana".to_string())),
+        ]);
+        let expected = if cfg!(feature = "jinja") {
+            "# <Todo>\n- a\n- b\n-- Ana"
+        } else {
+            "Failed to render jinja template: it needs the 'jinja' feature of pren-core"
+        };
+        assert!(render(&storage, "note", &arguments).contains(expected));
+    }
+}
@@ -0,0 +1,273 @@
+//! # Prompt Diffs
+//!
+//! This module provides structured diffs between prompts, covering their metadata, their
+//! content and, optionally, their rendered output for a set of arguments.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::diff::{DiffLine, diff_prompts};
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//!
+//! let old = Prompt::new(
+//!     PromptMetadata::new("greeting".to_string(), None, vec![]),
+//!     "Hello!\nBye.".to_string(),
+//! );
+//! let new = Prompt::new(
+//!     PromptMetadata::new("greeting".to_string(), None, vec!["casual".to_string()]),
+//!     "Hi!\nBye.".to_string(),
+//! );
+//!
+//! let diff = diff_prompts(&old, &new);
+//! assert_eq!(diff.metadata[0].field, "tags");
+//! assert_eq!(diff.content[0], DiffLine::Removed("Hello!".to_string()));
+//! ```
+
+use crate::prompt::{Prompt, PromptTemplate, RenderTemplateError, TemplateArguments};
+use crate::storage::PromptStorage;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// A line of a diff between two texts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "line", rename_all = "lowercase")]
+pub enum DiffLine {
+    /// A line present in both texts.
+    Unchanged(String),
+    /// A line only present in the old text.
+    Removed(String),
+    /// A line only present in the new text.
+    Added(String),
+}
+
+/// A change to a metadata field. Fields missing from one of the prompts are `None`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetadataChange {
+    /// The name of the field, as stored in the frontmatter.
+    pub field: String,
+    /// The value of the field in the old prompt.
+    pub old: Option<Value>,
+    /// The value of the field in the new prompt.
+    pub new: Option<Value>,
+}
+
+/// The differences between two prompts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptDiff {
+    /// The metadata fields that changed, sorted by field name.
+    pub metadata: Vec<MetadataChange>,
+    /// The line diff of the prompts' content.
+    pub content: Vec<DiffLine>,
+    /// The line diff of the prompts' rendered output, if they were rendered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendered: Option<Vec<DiffLine>>,
+}
+
+impl PromptDiff {
+    /// Whether the prompts are identical, including their rendered output if it was diffed.
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+            && !has_changes(&self.content)
+            && !self.rendered.as_deref().is_some_and(has_changes)
+    }
+}
+
+fn has_changes(lines: &[DiffLine]) -> bool {
+    lines
+        .iter()
+        .any(|line| !matches!(line, DiffLine::Unchanged(_)))
+}
+
+/// Diffs the metadata and content of two prompts.
+pub fn diff_prompts(old: &Prompt, new: &Prompt) -> PromptDiff {
+    PromptDiff {
+        metadata: diff_metadata(old, new),
+        content: diff_lines(&old.content, &new.content),
+        rendered: None,
+    }
+}
+
+/// Diffs the metadata and content of two templates, and their output when rendered with
+/// the same arguments.
+///
+/// # Returns
+///
+/// * `Ok(PromptDiff)` - The diff, including the rendered output.
+/// * `Err(RenderTemplateError)` - If either template fails to render.
+pub fn diff_rendered<A: TemplateArguments, S: PromptStorage>(
+    old: &PromptTemplate,
+    new: &PromptTemplate,
+    arguments: &A,
+    storage: &S,
+) -> Result<PromptDiff, RenderTemplateError> {
+    let old_output = old.render(arguments, storage)?;
+    let new_output = new.render(arguments, storage)?;
+    Ok(PromptDiff {
+        rendered: Some(diff_lines(&old_output, &new_output)),
+        ..diff_prompts(&old.prompt, &new.prompt)
+    })
+}
+
+fn diff_metadata(old: &Prompt, new: &Prompt) -> Vec<MetadataChange> {
+    let old = serde_json::to_value(&old.metadata).unwrap_or_default();
+    let new = serde_json::to_value(&new.metadata).unwrap_or_default();
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|field| old.get(*field) != new.get(*field))
+        .map(|field| MetadataChange {
+            field: field.clone(),
+            old: old.get(field).cloned(),
+            new: new.get(field).cloned(),
+        })
+        .collect()
+}
+
+/// Diffs two texts line by line, using their longest common subsequence of lines.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Unchanged(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            lines.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines.extend(
+        old[i..]
+            .iter()
+            .map(|line| DiffLine::Removed(line.to_string())),
+    );
+    lines.extend(
+        new[j..]
+            .iter()
+            .map(|line| DiffLine::Added(line.to_string())),
+    );
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_storage::FileStorage;
+    use crate::prompt::PromptMetadata;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn prompt(name: &str, tags: &[&str], content: &str) -> Prompt {
+        Prompt::new(
+            PromptMetadata::new(
+                name.to_string(),
+                None,
+                tags.iter().map(|tag| tag.to_string()).collect(),
+            ),
+            content.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            vec![
+                DiffLine::Unchanged("a".to_string()),
+                DiffLine::Removed("b".to_string()),
+                DiffLine::Added("x".to_string()),
+                DiffLine::Unchanged("c".to_string()),
+                DiffLine::Added("d".to_string()),
+            ],
+            diff_lines("a\nb\nc", "a\nx\nc\nd")
+        );
+        assert_eq!(
+            vec![DiffLine::Removed("a".to_string())],
+            diff_lines("a", "")
+        );
+    }
+
+    #[test]
+    fn test_diff_prompts() {
+        let old = prompt("review", &["code"], "Review this.");
+        let mut new = prompt("review-v2", &["code"], "Review this.");
+        new.metadata.deprecated = true;
+
+        let diff = diff_prompts(&old, &new);
+        assert_eq!(
+            vec![
+                MetadataChange {
+                    field: "deprecated".to_string(),
+                    old: None,
+                    new: Some(json!(true)),
+                },
+                MetadataChange {
+                    field: "name".to_string(),
+                    old: Some(json!("review")),
+                    new: Some(json!("review-v2")),
+                },
+            ],
+            diff.metadata
+        );
+        assert!(!has_changes(&diff.content));
+        assert!(!diff.is_empty());
+        assert!(diff_prompts(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_diff_rendered() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        storage
+            .save_prompt(&prompt("signature", &[], "Regards"))
+            .unwrap();
+
+        let old = PromptTemplate::new(prompt("letter", &[], "Dear {{name}},\nBye")).unwrap();
+        let new = PromptTemplate::new(prompt(
+            "letter",
+            &[],
+            "Dear {{name}},\n{{prompt:signature}}",
+        ))
+        .unwrap();
+        let args = HashMap::from([("name".to_string(), "Ana".to_string())]);
+
+        let diff = diff_rendered(&old, &new, &args, &storage).unwrap();
+        assert_eq!(
+            Some(vec![
+                DiffLine::Unchanged("Dear Ana,".to_string()),
+                DiffLine::Removed("Bye".to_string()),
+                DiffLine::Added("Regards".to_string()),
+            ]),
+            diff.rendered
+        );
+    }
+}
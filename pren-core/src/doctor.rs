@@ -0,0 +1,433 @@
+//! # Storage Diagnostics
+//!
+//! This module checks a prompt storage for problems that otherwise only show up when a
+//! prompt is used, such as prompt files that can't be parsed, references to prompts that
+//! don't exist, or prompts that include each other.
+//!
+//! Each check produces a [`Check`], with a suggestion to fix it if it found a problem.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::doctor::{CheckStatus, check_storage};
+//! use pren_core::file_storage::FileStorage;
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//! use pren_core::storage::PromptStorage;
+//! use tempfile::TempDir;
+//!
+//! let temp_dir = TempDir::new().unwrap();
+//! let storage = FileStorage {
+//!     base_path: temp_dir.path().to_path_buf(),
+//!     fallback_paths: Vec::new(),
+//!     vault: false,
+//! };
+//! storage
+//!     .save_prompt(&Prompt::new(
+//!         PromptMetadata::new("review".to_string(), None, vec![]),
+//!         "{{prompt:persona}} Review {{code}}".to_string(),
+//!     ))
+//!     .unwrap();
+//!
+//! let checks = check_storage(&storage);
+//! assert!(checks.iter().any(|check| check.status == CheckStatus::Error));
+//! ```
+
+use crate::file_storage::{FileStorage, parse_prompt_file};
+use crate::prompt::PromptTemplate;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+/// The outcome of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    /// Something may not work as expected.
+    Warning,
+    /// Something is broken.
+    Error,
+}
+
+/// The result of a diagnostic check.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    /// What was checked, e.g. `references`.
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found.
+    pub message: String,
+    /// How to fix the problem, if one was found.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    pub fn ok(name: &str, message: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            status: CheckStatus::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    pub fn warning(name: &str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            status: CheckStatus::Warning,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    pub fn error(name: &str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            status: CheckStatus::Error,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Checks a file storage: that its directory is writable, that every prompt file parses,
+/// that prompt references point to stored prompts without forming cycles, and that the
+/// storage index matches the prompt files.
+///
+/// # Returns
+///
+/// The checks, with one check per problem found, or a single passing check for each
+/// kind of check that found none.
+pub fn check_storage(storage: &FileStorage) -> Vec<Check> {
+    let mut checks = vec![check_directory(storage)];
+
+    let files = match storage.get_prompt_files() {
+        Ok(files) => files,
+        Err(e) => {
+            checks.push(Check::error(
+                "prompts",
+                format!("The prompt files can't be listed: {}", e),
+                "Check the permissions of the storage directory",
+            ));
+            return checks;
+        }
+    };
+
+    // Prompts are looked up by the name of their file
+    let mut problems = Vec::new();
+    let mut paths: HashMap<String, PathBuf> = HashMap::new();
+    let mut templates: BTreeMap<String, PromptTemplate> = BTreeMap::new();
+    for path in files {
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let name = name.to_string();
+        if let Some(first_path) = paths.get(&name) {
+            problems.push(Check::warning(
+                "prompts",
+                format!(
+                    "'{}' is stored in both {} and {}, only the first one is used",
+                    name,
+                    first_path.display(),
+                    path.display()
+                ),
+                format!("Rename or delete {}", path.display()),
+            ));
+            continue;
+        }
+        paths.insert(name.clone(), path.clone());
+
+        let template = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| parse_prompt_file(&content).map_err(|e| e.to_string()))
+            .and_then(|prompt| PromptTemplate::new(prompt).map_err(|e| e.to_string()));
+        match template {
+            Ok(template) => {
+                if template.prompt.metadata.name != name {
+                    problems.push(Check::warning(
+                        "prompts",
+                        format!(
+                            "{} is named '{}' in its frontmatter, but it's found as '{}'",
+                            path.display(),
+                            template.prompt.metadata.name,
+                            name
+                        ),
+                        format!(
+                            "Rename the file to {}.md, or set its name to '{}'",
+                            template.prompt.metadata.name, name
+                        ),
+                    ));
+                }
+                templates.insert(name, template);
+            }
+            Err(e) => problems.push(Check::error(
+                "prompts",
+                format!("{} can't be parsed: {}", path.display(), e),
+                format!(
+                    "Fix the frontmatter or the template syntax of {}, or delete it",
+                    path.display()
+                ),
+            )),
+        }
+    }
+    if problems.is_empty() {
+        checks.push(Check::ok(
+            "prompts",
+            format!("{} prompt(s) parse", templates.len()),
+        ));
+    }
+    checks.append(&mut problems);
+
+    checks.extend(check_references(&templates, &paths));
+    checks.extend(check_cycles(&templates));
+    checks.push(check_index(storage, &templates));
+    checks
+}
+
+/// Checks that the storage directory exists and prompts can be saved in it.
+fn check_directory(storage: &FileStorage) -> Check {
+    let path = &storage.base_path;
+    if !path.exists() {
+        return Check::warning(
+            "storage",
+            format!("{} doesn't exist", path.display()),
+            "It's created when the first prompt is saved, or create it yourself",
+        );
+    }
+    if !path.is_dir() {
+        return Check::error(
+            "storage",
+            format!("{} isn't a directory", path.display()),
+            "Move the file away, or store prompts somewhere else",
+        );
+    }
+    match tempfile::NamedTempFile::new_in(path) {
+        Ok(_) => Check::ok("storage", format!("{} is writable", path.display())),
+        Err(e) => Check::error(
+            "storage",
+            format!("{} isn't writable: {}", path.display(), e),
+            format!("Check the permissions of {}", path.display()),
+        ),
+    }
+}
+
+/// Checks that every static prompt reference points to a stored prompt.
+fn check_references(
+    templates: &BTreeMap<String, PromptTemplate>,
+    paths: &HashMap<String, PathBuf>,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for (name, template) in templates {
+        let references: BTreeSet<String> = template.prompt_references().into_iter().collect();
+        for reference in references {
+            if !paths.contains_key(&reference) {
+                checks.push(Check::error(
+                    "references",
+                    format!("'{}' references the missing prompt '{}'", name, reference),
+                    format!(
+                        "Add a prompt named '{}', or remove the reference from '{}'",
+                        reference, name
+                    ),
+                ));
+            }
+        }
+    }
+    if checks.is_empty() {
+        checks.push(Check::ok("references", "Every referenced prompt exists"));
+    }
+    checks
+}
+
+/// Checks that no prompt includes itself through its prompt references.
+fn check_cycles(templates: &BTreeMap<String, PromptTemplate>) -> Vec<Check> {
+    let graph: BTreeMap<&str, Vec<String>> = templates
+        .iter()
+        .map(|(name, template)| (name.as_str(), template.prompt_references()))
+        .collect();
+    let mut cycles = BTreeSet::new();
+    let mut finished = BTreeSet::new();
+    for name in graph.keys() {
+        find_cycles(name, &graph, &mut Vec::new(), &mut finished, &mut cycles);
+    }
+
+    let checks: Vec<Check> = cycles
+        .into_iter()
+        .map(|cycle: Vec<String>| {
+            let mut path = cycle.clone();
+            path.push(cycle[0].clone());
+            Check::error(
+                "cycles",
+                format!("Circular reference: {}", path.join(" -> ")),
+                "Remove one of the references, so the prompts don't include each other",
+            )
+        })
+        .collect();
+    if checks.is_empty() {
+        return vec![Check::ok("cycles", "No prompt includes itself")];
+    }
+    checks
+}
+
+/// Depth-first search for the cycles reachable from `name`. Cycles are rotated to start
+/// at their smallest name, so each one is found once.
+fn find_cycles<'a>(
+    name: &'a str,
+    graph: &'a BTreeMap<&str, Vec<String>>,
+    stack: &mut Vec<&'a str>,
+    finished: &mut BTreeSet<&'a str>,
+    cycles: &mut BTreeSet<Vec<String>>,
+) {
+    if finished.contains(name) {
+        return;
+    }
+    if let Some(start) = stack.iter().position(|entered| *entered == name) {
+        let mut cycle: Vec<String> = stack[start..].iter().map(|s| s.to_string()).collect();
+        let smallest = (0..cycle.len()).min_by_key(|&i| &cycle[i]).unwrap_or(0);
+        cycle.rotate_left(smallest);
+        cycles.insert(cycle);
+        return;
+    }
+    let Some(references) = graph.get(name) else {
+        return;
+    };
+    stack.push(name);
+    for reference in references {
+        if let Some((reference, _)) = graph.get_key_value(reference.as_str()) {
+            find_cycles(reference, graph, stack, finished, cycles);
+        }
+    }
+    stack.pop();
+    finished.insert(name);
+}
+
+/// Checks that the storage index lists the same prompts as the prompt files.
+fn check_index(storage: &FileStorage, templates: &BTreeMap<String, PromptTemplate>) -> Check {
+    let summaries = match storage.get_prompt_summaries() {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            return Check::error(
+                "index",
+                format!("The index can't be updated: {}", e),
+                "Fix the prompt files that can't be parsed",
+            );
+        }
+    };
+    let mut indexed: Vec<&str> = summaries.iter().map(|s| s.name.as_str()).collect();
+    let mut stored: Vec<&str> = templates
+        .values()
+        .map(|template| template.prompt.metadata.name.as_str())
+        .collect();
+    indexed.sort_unstable();
+    stored.sort_unstable();
+    if indexed == stored {
+        Check::ok(
+            "index",
+            format!("The index lists {} prompt(s)", indexed.len()),
+        )
+    } else {
+        Check::error(
+            "index",
+            "The index doesn't match the prompt files",
+            format!(
+                "Delete {}, it's rebuilt the next time prompts are listed",
+                storage.base_path.join(".pren-index.json").display()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::{Prompt, PromptMetadata};
+    use crate::storage::PromptStorage;
+    use tempfile::TempDir;
+
+    fn save(storage: &FileStorage, name: &str, content: &str) {
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new(name.to_string(), None, vec![]),
+                content.to_string(),
+            ))
+            .unwrap();
+    }
+
+    fn problems(checks: &[Check]) -> Vec<(&str, CheckStatus)> {
+        checks
+            .iter()
+            .filter(|check| check.status != CheckStatus::Ok)
+            .map(|check| (check.name.as_str(), check.status))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_healthy_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        save(&storage, "persona", "You are a {{role}}.");
+        save(&storage, "review", "{{prompt:persona}} Review {{code}}");
+
+        let checks = check_storage(&storage);
+        assert!(problems(&checks).is_empty(), "{:?}", checks);
+        assert_eq!(
+            vec!["storage", "prompts", "references", "cycles", "index"],
+            checks
+                .iter()
+                .map(|check| check.name.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_check_broken_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        save(&storage, "a", "{{prompt:b}}");
+        save(&storage, "b", "{{prompt:c}} {{prompt:a}}");
+        save(&storage, "c", "{{prompt:b}} {{prompt:missing}}");
+        fs::write(temp_dir.path().join("broken.md"), "no frontmatter").unwrap();
+        fs::write(
+            temp_dir.path().join("misnamed.md"),
+            fs::read_to_string(temp_dir.path().join("a.md"))
+                .unwrap()
+                .replace("name: a", "name: other"),
+        )
+        .unwrap();
+
+        let checks = check_storage(&storage);
+        let mut found = problems(&checks);
+        found.sort_by_key(|(name, _)| *name);
+        assert_eq!(
+            vec![
+                ("cycles", CheckStatus::Error),
+                ("cycles", CheckStatus::Error),
+                ("index", CheckStatus::Error),
+                ("prompts", CheckStatus::Error),
+                ("prompts", CheckStatus::Warning),
+                ("references", CheckStatus::Error),
+            ],
+            found
+        );
+        let cycles: Vec<&str> = checks
+            .iter()
+            .filter(|check| check.name == "cycles")
+            .map(|check| check.message.as_str())
+            .collect();
+        assert_eq!(
+            vec![
+                "Circular reference: a -> b -> a",
+                "Circular reference: b -> c -> b"
+            ],
+            cycles
+        );
+    }
+}
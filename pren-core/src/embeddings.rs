@@ -0,0 +1,235 @@
+//! # Prompt Embeddings
+//!
+//! This module provides semantic search over prompts, which finds prompts by meaning rather
+//! than by the words they contain, so "fix flaky tests" finds a prompt about intermittent
+//! test failures.
+//!
+//! Every prompt is embedded by an embedding model, from its name, description, tags and
+//! content. Embeddings are cached by model in an [`EmbeddingCache`], along with a hash of
+//! the embedded text, so only new and changed prompts are embedded again. The cache is kept
+//! by implementors of the [`EmbeddingStorage`](crate::storage::EmbeddingStorage) trait.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::embeddings::EmbeddingCache;
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//!
+//! let prompts = vec![
+//!     Prompt::new(PromptMetadata::new("tests".to_string(), None, vec![]), "Fix tests".to_string()),
+//!     Prompt::new(PromptMetadata::new("sql".to_string(), None, vec![]), "Review SQL".to_string()),
+//! ];
+//! let mut cache = EmbeddingCache::default();
+//! cache.insert("model", &prompts[0], vec![1.0, 0.0]);
+//! cache.insert("model", &prompts[1], vec![0.0, 1.0]);
+//!
+//! let matches = cache.rank("model", &[0.9, 0.1], &prompts, 1);
+//! assert_eq!("tests", matches[0].name);
+//! ```
+
+use crate::history::output_hash;
+#[cfg(feature = "llm")]
+use crate::llm::{LlmError, ProviderConfig, get_embeddings};
+use crate::prompt::Prompt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The embedding of a prompt by a model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptEmbedding {
+    /// The hash of the embedded text, telling whether the prompt changed since.
+    pub hash: String,
+    pub vector: Vec<f64>,
+}
+
+/// The embeddings of prompts, by model name and prompt name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingCache {
+    #[serde(default)]
+    pub models: BTreeMap<String, BTreeMap<String, PromptEmbedding>>,
+}
+
+/// A prompt found by semantic search.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SemanticMatch {
+    pub name: String,
+    /// The cosine similarity between the prompt and the query, from -1 to 1.
+    pub score: f64,
+}
+
+impl EmbeddingCache {
+    /// The prompts without an embedding by the model, or that changed since they were
+    /// embedded.
+    pub fn stale<'a>(&self, model_name: &str, prompts: &'a [Prompt]) -> Vec<&'a Prompt> {
+        let embeddings = self.models.get(model_name);
+        prompts
+            .iter()
+            .filter(|prompt| {
+                embeddings
+                    .and_then(|embeddings| embeddings.get(&prompt.metadata.name))
+                    .is_none_or(|embedding| embedding.hash != output_hash(&embedding_text(prompt)))
+            })
+            .collect()
+    }
+
+    /// Caches the embedding of a prompt by the model.
+    pub fn insert(&mut self, model_name: &str, prompt: &Prompt, vector: Vec<f64>) {
+        self.models
+            .entry(model_name.to_string())
+            .or_default()
+            .insert(
+                prompt.metadata.name.clone(),
+                PromptEmbedding {
+                    hash: output_hash(&embedding_text(prompt)),
+                    vector,
+                },
+            );
+    }
+
+    /// Removes the embeddings of the prompts that aren't in `prompts`, e.g. after they
+    /// were deleted.
+    pub fn retain(&mut self, prompts: &[Prompt]) {
+        for embeddings in self.models.values_mut() {
+            embeddings.retain(|name, _| prompts.iter().any(|p| &p.metadata.name == name));
+        }
+    }
+
+    /// Ranks the prompts with an embedding by the model by their similarity to the query,
+    /// most similar first.
+    pub fn rank(
+        &self,
+        model_name: &str,
+        query: &[f64],
+        prompts: &[Prompt],
+        limit: usize,
+    ) -> Vec<SemanticMatch> {
+        let Some(embeddings) = self.models.get(model_name) else {
+            return Vec::new();
+        };
+        let mut matches: Vec<SemanticMatch> = prompts
+            .iter()
+            .filter_map(|prompt| {
+                let embedding = embeddings.get(&prompt.metadata.name)?;
+                Some(SemanticMatch {
+                    name: prompt.metadata.name.clone(),
+                    score: cosine_similarity(query, &embedding.vector),
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        matches.truncate(limit);
+        matches
+    }
+}
+
+/// The text of a prompt that is embedded: its name, description, tags and content.
+pub fn embedding_text(prompt: &Prompt) -> String {
+    let mut text = prompt.metadata.name.clone();
+    if let Some(description) = &prompt.metadata.description {
+        text.push_str(&format!("\n{}", description));
+    }
+    if !prompt.metadata.tags.is_empty() {
+        text.push_str(&format!("\nTags: {}", prompt.metadata.tags.join(", ")));
+    }
+    text.push_str(&format!("\n\n{}", prompt.content.trim()));
+    text
+}
+
+/// The cosine similarity of two vectors, from -1 to 1. Vectors of different lengths, or
+/// without any magnitude, have a similarity of 0.
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let magnitude = norm(a) * norm(b);
+    if magnitude == 0.0 {
+        0.0
+    } else {
+        dot / magnitude
+    }
+}
+
+/// Finds the prompts closest in meaning to a query.
+///
+/// The prompts that aren't in the cache, or changed since they were embedded, are embedded
+/// in the same request as the query, and the cache is updated with them. Embeddings of
+/// prompts that aren't in `prompts` are removed from it.
+///
+/// # Returns
+///
+/// * `Ok(Vec<SemanticMatch>)` - Up to `limit` prompts, most similar first.
+/// * `Err(LlmError)` - If the embeddings can't be computed.
+#[cfg(feature = "llm")]
+pub async fn semantic_search(
+    provider: &ProviderConfig,
+    model_name: &str,
+    prompts: &[Prompt],
+    cache: &mut EmbeddingCache,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SemanticMatch>, LlmError> {
+    let stale = cache.stale(model_name, prompts);
+    let mut texts: Vec<String> = stale.iter().map(|prompt| embedding_text(prompt)).collect();
+    texts.push(query.to_string());
+    let mut vectors = get_embeddings(provider, model_name, &texts).await?;
+    let query_vector = vectors.pop().unwrap_or_default();
+    for (prompt, vector) in stale.into_iter().zip(vectors) {
+        cache.insert(model_name, prompt, vector);
+    }
+    cache.retain(prompts);
+    Ok(cache.rank(model_name, &query_vector, prompts, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptMetadata;
+
+    #[test]
+    fn test_embedding_cache() {
+        let prompt = |name: &str, content: &str| {
+            Prompt::new(
+                PromptMetadata::new(name.to_string(), None, vec![]),
+                content.to_string(),
+            )
+        };
+        let mut prompts = vec![
+            prompt("flaky", "Fix flaky tests"),
+            prompt("sql", "Review SQL"),
+        ];
+        let mut cache = EmbeddingCache::default();
+        assert_eq!(2, cache.stale("model", &prompts).len());
+
+        cache.insert("model", &prompts[0], vec![1.0, 0.0]);
+        cache.insert("model", &prompts[1], vec![0.0, 1.0]);
+        assert!(cache.stale("model", &prompts).is_empty());
+        assert_eq!(2, cache.stale("other", &prompts).len());
+
+        let matches = cache.rank("model", &[0.0, 2.0], &prompts, 5);
+        assert_eq!(
+            vec!["sql", "flaky"],
+            matches.iter().map(|m| m.name.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(1.0, matches[0].score);
+
+        prompts[1].content = "Review SQL migrations".to_string();
+        assert_eq!("sql", cache.stale("model", &prompts)[0].metadata.name);
+
+        cache.retain(&prompts[..1]);
+        assert_eq!(1, cache.models["model"].len());
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert_eq!(0.0, cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]));
+        assert_eq!(-1.0, cosine_similarity(&[3.0, 0.0], &[-2.0, 0.0]));
+        assert_eq!(0.0, cosine_similarity(&[1.0], &[1.0, 0.0]));
+        assert_eq!(0.0, cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]));
+    }
+}
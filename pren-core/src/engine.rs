@@ -0,0 +1,288 @@
+//! # Prompt Engine
+//!
+//! This module provides [`PromptEngine`], which bundles a prompt storage with the options
+//! and hooks of every render and, with the `llm` feature, the model prompts are sent to.
+//! It's the entry point for embedding pren in an application, instead of wiring the
+//! storage, templates, messages and model requests together by hand.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::engine::PromptEngine;
+//! use pren_core::functions::FunctionRegistry;
+//! use pren_core::memory_storage::MemoryStorage;
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//! use std::collections::HashMap;
+//!
+//! let storage = MemoryStorage::from_prompts([Prompt::new(
+//!     PromptMetadata::new("greeting".to_string(), None, vec![]),
+//!     "Hello {{fn:upper(name)}}".to_string(),
+//! )]);
+//! let mut functions = FunctionRegistry::new();
+//! functions.register("upper", |args| Ok(args.join(" ").to_uppercase()));
+//! let engine = PromptEngine::new(storage).with_functions(functions);
+//!
+//! let arguments = HashMap::from([("name".to_string(), "world".to_string())]);
+//! assert_eq!("Hello WORLD", engine.render("greeting", &arguments).unwrap().text);
+//! ```
+//!
+//! With a model, prompts can be generated from too:
+//!
+//! ```rust,no_run
+//! # use pren_core::engine::PromptEngine;
+//! # use pren_core::memory_storage::MemoryStorage;
+//! # use std::collections::HashMap;
+//! # #[cfg(feature = "llm")]
+//! # async fn generate() -> Result<(), pren_core::engine::EngineError> {
+//! use pren_core::llm::{Provider, ProviderConfig};
+//!
+//! let engine = PromptEngine::new(MemoryStorage::new())
+//!     .with_model(ProviderConfig::new(Provider::OpenAi, "sk-...", ""), "gpt-4o-mini");
+//! let arguments = HashMap::from([("name".to_string(), "world".to_string())]);
+//! let completion = engine.generate("greeting", &arguments).await?;
+//! println!("{}", completion.text);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::functions::FunctionRegistry;
+#[cfg(feature = "llm")]
+use crate::llm::{Completion, LlmError, ProviderConfig, get_chat_completion};
+use crate::messages::{RenderedMessage, split_messages};
+use crate::prompt::{
+    ParseTemplateError, Prompt, PromptTemplate, RenderHooks, RenderOptions, RenderOutput,
+    RenderTemplateError, TemplateArguments,
+};
+use crate::storage::PromptStorage;
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("couldn't retrieve prompt '{prompt}': {message}")]
+    StorageError { prompt: String, message: String },
+    #[error("failed to parse the prompt")]
+    ParseError(#[from] ParseTemplateError),
+    #[error("failed to render the prompt")]
+    RenderError(#[from] RenderTemplateError),
+    #[cfg(feature = "llm")]
+    #[error("the engine has no model to generate with")]
+    NoModel,
+    #[cfg(feature = "llm")]
+    #[error(transparent)]
+    ModelError(#[from] LlmError),
+}
+
+/// The model an engine sends prompts to.
+#[cfg(feature = "llm")]
+struct EngineModel {
+    provider: ProviderConfig,
+    name: String,
+}
+
+/// A prompt storage, with the options and hooks of every render and the model prompts are
+/// generated with.
+pub struct PromptEngine<S: PromptStorage> {
+    storage: S,
+    functions: Option<FunctionRegistry>,
+    max_tokens: Option<usize>,
+    hooks: Option<Mutex<Box<dyn RenderHooks + Send>>>,
+    #[cfg(feature = "llm")]
+    model: Option<EngineModel>,
+}
+
+impl<S: PromptStorage> PromptEngine<S> {
+    pub fn new(storage: S) -> Self {
+        PromptEngine {
+            storage,
+            functions: None,
+            max_tokens: None,
+            hooks: None,
+            #[cfg(feature = "llm")]
+            model: None,
+        }
+    }
+
+    /// Returns this engine with the functions templates can call.
+    pub fn with_functions(self, functions: FunctionRegistry) -> Self {
+        PromptEngine {
+            functions: Some(functions),
+            ..self
+        }
+    }
+
+    /// Returns this engine with every prompt fit in a token budget, leaving optional prompt
+    /// references out as with [`PromptTemplate::render_with_budget`].
+    pub fn with_token_budget(self, max_tokens: usize) -> Self {
+        PromptEngine {
+            max_tokens: Some(max_tokens),
+            ..self
+        }
+    }
+
+    /// Returns this engine with hooks called during every render. Renders run one at a
+    /// time while there are hooks, as they share them.
+    pub fn with_hooks(self, hooks: impl RenderHooks + Send + 'static) -> Self {
+        PromptEngine {
+            hooks: Some(Mutex::new(Box::new(hooks))),
+            ..self
+        }
+    }
+
+    /// Returns this engine with the model prompts are generated with. The model settings of
+    /// a prompt, such as its preferred model, apply when generating from it.
+    #[cfg(feature = "llm")]
+    pub fn with_model(self, provider: ProviderConfig, model_name: &str) -> Self {
+        PromptEngine {
+            model: Some(EngineModel {
+                provider,
+                name: model_name.to_string(),
+            }),
+            ..self
+        }
+    }
+
+    /// The storage of the engine, to manage its prompts.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Gets a prompt from the storage.
+    pub fn prompt(&self, name: &str) -> Result<Prompt, EngineError> {
+        self.storage
+            .get_prompt(name)
+            .map_err(|e| EngineError::StorageError {
+                prompt: name.to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    /// Renders a prompt with the engine's options and hooks.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RenderOutput)` - The rendered prompt and its warnings.
+    /// * `Err(EngineError)` - If the prompt can't be retrieved, parsed or rendered.
+    pub fn render<A: TemplateArguments>(
+        &self,
+        name: &str,
+        arguments: &A,
+    ) -> Result<RenderOutput, EngineError> {
+        let template = PromptTemplate::new(self.prompt(name)?)?;
+        let options = RenderOptions {
+            functions: self.functions.as_ref(),
+            max_tokens: self.max_tokens,
+        };
+        let output = match &self.hooks {
+            Some(hooks) => {
+                let mut hooks = hooks.lock().unwrap_or_else(|e| e.into_inner());
+                template.render_with_options(
+                    arguments,
+                    &self.storage,
+                    options,
+                    Some(hooks.as_mut()),
+                )?
+            }
+            None => template.render_with_options(arguments, &self.storage, options, None)?,
+        };
+        Ok(output)
+    }
+
+    /// Renders a prompt as chat messages, split by its role markers.
+    pub fn render_messages<A: TemplateArguments>(
+        &self,
+        name: &str,
+        arguments: &A,
+    ) -> Result<Vec<RenderedMessage>, EngineError> {
+        Ok(split_messages(&self.render(name, arguments)?.text))
+    }
+
+    /// Renders a prompt as chat messages and sends them to the engine's model.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Completion)` - The response of the model, with the tokens it used.
+    /// * `Err(EngineError)` - If the engine has no model, the prompt can't be rendered or
+    ///   the request fails.
+    #[cfg(feature = "llm")]
+    pub async fn generate<A: TemplateArguments>(
+        &self,
+        name: &str,
+        arguments: &A,
+    ) -> Result<Completion, EngineError> {
+        let model = self.model.as_ref().ok_or(EngineError::NoModel)?;
+        let settings = self.prompt(name)?.metadata.model_settings;
+        let messages = self.render_messages(name, arguments)?;
+        Ok(get_chat_completion(&model.provider, &model.name, &messages, &settings).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_storage::MemoryStorage;
+    use crate::messages::Role;
+    use crate::prompt::{PromptMetadata, PromptTemplatePart};
+    use std::collections::HashMap;
+
+    /// Hooks redacting a word from every part.
+    struct Redact;
+
+    impl RenderHooks for Redact {
+        fn on_part(
+            &mut self,
+            _prompt_name: &str,
+            _part: &PromptTemplatePart,
+            rendered: &mut String,
+        ) -> Result<(), RenderTemplateError> {
+            *rendered = rendered.replace("secret", "[redacted]");
+            Ok(())
+        }
+    }
+
+    fn storage() -> MemoryStorage {
+        let prompt = |name: &str, content: &str| {
+            Prompt::new(
+                PromptMetadata::new(name.to_string(), None, vec![]),
+                content.to_string(),
+            )
+        };
+        MemoryStorage::from_prompts([
+            prompt("chat", "<|system|>\nBe brief\n<|user|>\n{{question}}"),
+            prompt("padded", "Answer {{prompt_opt:context}}"),
+            prompt("context", "with a long context about many things"),
+        ])
+    }
+
+    #[test]
+    fn test_engine_render() {
+        let engine = PromptEngine::new(storage()).with_hooks(Redact);
+        let arguments = HashMap::from([("question".to_string(), "a secret?".to_string())]);
+
+        let messages = engine.render_messages("chat", &arguments).unwrap();
+        assert_eq!(Role::System, messages[0].role);
+        assert_eq!("a [redacted]?", messages[1].content);
+        assert!(matches!(
+            engine.render("missing", &arguments),
+            Err(EngineError::StorageError { .. })
+        ));
+        assert!(matches!(
+            engine.render("chat", &HashMap::<String, String>::new()),
+            Err(EngineError::RenderError(_))
+        ));
+
+        let engine = PromptEngine::new(storage()).with_token_budget(3);
+        assert_eq!("Answer ", engine.render("padded", &arguments).unwrap().text);
+    }
+
+    #[cfg(feature = "llm")]
+    #[tokio::test]
+    async fn test_engine_generate_without_model() {
+        let engine = PromptEngine::new(storage());
+        let arguments = HashMap::from([("question".to_string(), "Why?".to_string())]);
+        assert!(matches!(
+            engine.generate("chat", &arguments).await,
+            Err(EngineError::NoModel)
+        ));
+    }
+}
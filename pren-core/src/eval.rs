@@ -0,0 +1,324 @@
+//! # Prompt Evaluations
+//!
+//! This module provides eval cases, which check the responses of a model to a prompt
+//! rather than how the prompt renders, so prompts can be compared across models and
+//! revisions by their pass rates.
+//!
+//! Eval cases are declared in the prompt's frontmatter. Each one renders the prompt with a
+//! set of arguments, sends it to the model and checks the response for substrings and
+//! regular expressions, and against grading criteria scored by a judge model:
+//!
+//! ```yaml
+//! evals:
+//!   - name: deadline
+//!     args:
+//!       text: The report is due on Friday, and the budget is final.
+//!     contains: ["Friday"]
+//!     criteria: The summary is a single sentence mentioning the deadline.
+//! ```
+//!
+//! Evals are run with [`run_evals`], and the runs are kept by implementors of the
+//! [`EvalStorage`](crate::storage::EvalStorage) trait to follow pass rates over time.
+
+use crate::messages::{RenderedMessage, Role, split_messages};
+use crate::prompt::PromptTemplate;
+use crate::storage::PromptStorage;
+use crate::testing::check_expectations;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+/// The lowest score, out of [`MAX_SCORE`], a judge can give a response that passes.
+pub const PASSING_SCORE: u8 = 4;
+
+/// The highest score a judge can give a response.
+pub const MAX_SCORE: u8 = 5;
+
+const JUDGE_PROMPT: &str = "\
+You are a strict evaluator of responses generated by language models. You're given the \
+prompt a model was sent, its response, and the criteria the response must meet.
+
+Score how well the response meets the criteria from 1 (not at all) to 5 (fully), judging \
+only by the criteria. Reply with a short justification between <reason> and </reason>, \
+then with the score between <score> and </score>.";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum EvalError {
+    #[error("the judge's response has no score from 1 to 5 between <score> tags")]
+    MissingScore,
+}
+
+/// An eval case of a prompt, stored in the prompt's frontmatter.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    /// The name of the eval case, used when reporting results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The arguments the prompt is rendered with.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub args: BTreeMap<String, Value>,
+    /// Substrings the response must contain.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contains: Vec<String>,
+    /// Regular expressions the response must match.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<String>,
+    /// What a good response looks like, scored by a judge model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub criteria: Option<String>,
+}
+
+/// A judge's score of a response against the criteria of an eval case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Judgement {
+    /// The score, from 1 to [`MAX_SCORE`].
+    pub score: u8,
+    /// Why the judge gave the score.
+    pub reason: String,
+}
+
+/// The result of running an eval case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalResult {
+    /// The name of the eval case, or its position (e.g., `#2`) if it has no name.
+    pub case: String,
+    /// The model's response. Empty if the prompt couldn't be rendered or sent.
+    pub output: String,
+    /// Why the eval case failed. Empty if it passed.
+    pub failures: Vec<String>,
+    /// The judge's score, for eval cases with criteria.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judgement: Option<Judgement>,
+}
+
+impl EvalResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// A run of the eval cases of a prompt against a model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalRun {
+    /// The id of the run, increasing from 1.
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    /// The name of the evaluated prompt.
+    pub prompt: String,
+    /// The name of the model the prompt was sent to.
+    pub model: String,
+    pub results: Vec<EvalResult>,
+}
+
+impl EvalRun {
+    pub fn passed_count(&self) -> usize {
+        self.results.iter().filter(|result| result.passed()).count()
+    }
+
+    /// The share of eval cases that passed, from 0 to 1. Runs without cases have a pass
+    /// rate of 0.
+    pub fn pass_rate(&self) -> f64 {
+        if self.results.is_empty() {
+            0.0
+        } else {
+            self.passed_count() as f64 / self.results.len() as f64
+        }
+    }
+}
+
+/// Builds the messages asking the built-in judge to score a response against criteria.
+///
+/// # Arguments
+///
+/// * `criteria` - What a good response looks like.
+/// * `input` - The rendered prompt the response answers.
+/// * `output` - The response to score.
+pub fn judge_messages(criteria: &str, input: &str, output: &str) -> Vec<RenderedMessage> {
+    vec![
+        RenderedMessage {
+            role: Role::System,
+            content: JUDGE_PROMPT.to_string(),
+        },
+        RenderedMessage {
+            role: Role::User,
+            content: format!(
+                "<prompt>\n{}\n</prompt>\n\n<response>\n{}\n</response>\n\n<criteria>\n{}\n</criteria>",
+                input.trim_end(),
+                output.trim_end(),
+                criteria.trim_end()
+            ),
+        },
+    ]
+}
+
+/// Parses the response of a judge, which must hold a score between `<score>` tags and
+/// may hold a justification between `<reason>` tags.
+pub fn parse_judgement(response: &str) -> Result<Judgement, EvalError> {
+    let score = between(response, "<score>", "</score>")
+        .and_then(|score| score.parse::<u8>().ok())
+        .filter(|score| (1..=MAX_SCORE).contains(score))
+        .ok_or(EvalError::MissingScore)?;
+    Ok(Judgement {
+        score,
+        reason: between(response, "<reason>", "</reason>")
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+fn between<'a>(text: &'a str, open: &str, close: &str) -> Option<&'a str> {
+    let start = text.find(open)? + open.len();
+    let end = text[start..].find(close)? + start;
+    Some(text[start..end].trim())
+}
+
+/// Runs the eval cases of a prompt in order.
+///
+/// # Arguments
+///
+/// * `template` - The prompt to evaluate.
+/// * `storage` - The storage referenced prompts are retrieved from.
+/// * `generate` - Called with the rendered messages of every case. Returns the model
+///   response.
+/// * `judge` - Called for cases with criteria, with the criteria, the rendered prompt and
+///   the response. Returns the judge's score.
+///
+/// # Returns
+///
+/// * `Vec<EvalResult>` - The result of every case. Cases that can't be rendered, sent or
+///   judged fail with the reason.
+pub async fn run_evals<S, G, J>(
+    template: &PromptTemplate,
+    storage: &S,
+    generate: G,
+    judge: J,
+) -> Vec<EvalResult>
+where
+    S: PromptStorage,
+    G: AsyncFn(&[RenderedMessage]) -> Result<String, String>,
+    J: AsyncFn(&str, &str, &str) -> Result<Judgement, String>,
+{
+    let mut results = Vec::new();
+    for (index, case) in template.prompt.metadata.evals.iter().enumerate() {
+        let mut result = EvalResult {
+            case: case
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("#{}", index + 1)),
+            output: String::new(),
+            failures: Vec::new(),
+            judgement: None,
+        };
+        let arguments: HashMap<String, Value> = case.args.clone().into_iter().collect();
+        let rendered = match template.render(&arguments, storage) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                result.failures.push(format!("failed to render: {}", e));
+                results.push(result);
+                continue;
+            }
+        };
+        match generate(&split_messages(&rendered)).await {
+            Ok(output) => result.output = output,
+            Err(e) => {
+                result.failures.push(format!("failed to generate: {}", e));
+                results.push(result);
+                continue;
+            }
+        }
+
+        result.failures = check_expectations(&case.contains, &case.matches, &result.output);
+        if let Some(criteria) = &case.criteria {
+            match judge(criteria, &rendered, &result.output).await {
+                Ok(judgement) => {
+                    if judgement.score < PASSING_SCORE {
+                        result.failures.push(format!(
+                            "judge scored {}/{}: {}",
+                            judgement.score, MAX_SCORE, judgement.reason
+                        ));
+                    }
+                    result.judgement = Some(judgement);
+                }
+                Err(e) => result.failures.push(format!("failed to judge: {}", e)),
+            }
+        }
+        results.push(result);
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_storage::FileStorage;
+    use crate::prompt::{Prompt, PromptMetadata};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_judgement() {
+        let judgement = parse_judgement("<reason>Mentions Friday.</reason>\n<score> 4 </score>");
+        assert_eq!(
+            Ok(Judgement {
+                score: 4,
+                reason: "Mentions Friday.".to_string()
+            }),
+            judgement
+        );
+        assert_eq!(Err(EvalError::MissingScore), parse_judgement("Score: 4"));
+        assert_eq!(
+            Err(EvalError::MissingScore),
+            parse_judgement("<score>9</score>")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_evals() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        let mut metadata = PromptMetadata::new("summary".to_string(), None, vec![]);
+        metadata.evals = vec![
+            EvalCase {
+                name: Some("short".to_string()),
+                args: BTreeMap::from([("text".to_string(), Value::from("Due Friday"))]),
+                contains: vec!["Friday".to_string()],
+                criteria: Some("Mentions the deadline".to_string()),
+                ..EvalCase::default()
+            },
+            EvalCase {
+                contains: vec!["Monday".to_string()],
+                ..EvalCase::default()
+            },
+        ];
+        let template =
+            PromptTemplate::new(Prompt::new(metadata, "Summarize: {{text}}".to_string())).unwrap();
+
+        let results = run_evals(
+            &template,
+            &storage,
+            async |messages: &[RenderedMessage]| Ok(messages[0].content.replace("Summarize: ", "")),
+            async |_: &str, input: &str, _: &str| {
+                Ok(Judgement {
+                    score: if input.contains("Friday") { 5 } else { 1 },
+                    reason: String::new(),
+                })
+            },
+        )
+        .await;
+
+        assert_eq!("short", results[0].case);
+        assert_eq!("Due Friday", results[0].output);
+        assert!(results[0].passed());
+        assert_eq!(Some(5), results[0].judgement.as_ref().map(|j| j.score));
+        assert_eq!("#2", results[1].case);
+        assert_eq!(
+            vec!["failed to render: Missing argument: text".to_string()],
+            results[1].failures
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! # Prompt Export
+//!
+//! This module converts pren prompts to the template formats of other ecosystems, so they
+//! can be used by services that don't run pren. The supported formats are:
+//!
+//! - [`ExportFormat::Jinja`] - A Jinja template, with `{{ name }}` variables.
+//! - [`ExportFormat::Langchain`] - A LangChain prompt template file, with an f-string template.
+//! - [`ExportFormat::OpenaiChat`] - A JSON array of OpenAI chat messages, split by the
+//!   prompt's role markers, with `{{name}}` placeholders in their content.
+//! - [`ExportFormat::Json`] - The prompt's metadata, arguments and template as JSON.
+//!
+//! Prompts are rendered before they're exported, so prompt references, functions and
+//! examples are resolved, and the arguments that weren't given stay as variables of the
+//! target format.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::export::{ExportFormat, export_prompt};
+//! use pren_core::file_storage::FileStorage;
+//! use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
+//! use std::collections::HashMap;
+//! use tempfile::TempDir;
+//!
+//! let temp_dir = TempDir::new().unwrap();
+//! let storage = FileStorage {
+//!     base_path: temp_dir.path().to_path_buf(),
+//!     fallback_paths: Vec::new(),
+//!     vault: false,
+//! };
+//! let prompt = Prompt::new(
+//!     PromptMetadata::new("greeting".to_string(), None, vec![]),
+//!     "Hello {{name}}!".to_string(),
+//! );
+//! let template = PromptTemplate::new(prompt).unwrap();
+//!
+//! let exported = export_prompt(
+//!     &template,
+//!     &HashMap::<String, String>::new(),
+//!     &storage,
+//!     ExportFormat::Jinja,
+//! )
+//! .unwrap();
+//! assert_eq!("Hello {{ name }}!", exported);
+//! ```
+
+use crate::messages::split_messages;
+use crate::parser::parse_template;
+use crate::prompt::{PromptTemplate, PromptTemplatePart, RenderTemplateError, TemplateArguments};
+use crate::storage::PromptStorage;
+use serde_json::json;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("error found while rendering the prompt")]
+    RenderError(#[from] RenderTemplateError),
+    #[error("prompt reference chosen by argument '{0}' can't be exported, give its value")]
+    VariablePromptReference(String),
+    #[error("unknown export format '{0}'")]
+    UnknownFormat(String),
+}
+
+/// A format prompts can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Jinja,
+    Langchain,
+    OpenaiChat,
+    Json,
+}
+
+impl ExportFormat {
+    /// The names of every format, as accepted by [`ExportFormat::from_str`].
+    pub const NAMES: &'static [&'static str] = &["jinja", "langchain", "openai-chat", "json"];
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jinja" => Ok(ExportFormat::Jinja),
+            "langchain" => Ok(ExportFormat::Langchain),
+            "openai-chat" => Ok(ExportFormat::OpenaiChat),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(ExportError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// A piece of a prompt rendered for export.
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// Exports a prompt to another template format.
+///
+/// # Arguments
+///
+/// * `template` - The prompt to export.
+/// * `arguments` - The arguments to render into the prompt. Arguments without a value or
+///   a declared default become variables of the exported template.
+/// * `storage` - The storage to resolve prompt references from.
+/// * `format` - The format to export to.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The exported prompt.
+/// * `Err(ExportError)` - If the prompt can't be rendered, or it has a prompt reference
+///   chosen by a missing argument, which the other formats can't express.
+pub fn export_prompt<A: TemplateArguments, S: PromptStorage>(
+    template: &PromptTemplate,
+    arguments: &A,
+    storage: &S,
+    format: ExportFormat,
+) -> Result<String, ExportError> {
+    let partial = template.render_partial(arguments, storage)?;
+    let segments = segments(&partial.text)?;
+
+    let metadata = &template.prompt.metadata;
+    let exported = match format {
+        ExportFormat::Jinja => to_template(&segments, escape_jinja, |name| {
+            format!("{{{{ {} }}}}", name)
+        }),
+        ExportFormat::Langchain => {
+            let template = to_f_string(&segments);
+            to_json(&json!({
+                "_type": "prompt",
+                "input_variables": partial.missing_arguments,
+                "template": template,
+                "template_format": "f-string"
+            }))
+        }
+        ExportFormat::OpenaiChat => to_json(&split_messages(&to_placeholder_template(&segments))),
+        ExportFormat::Json => {
+            let arguments: Vec<_> = template
+                .argument_info()
+                .into_iter()
+                .filter(|argument| partial.missing_arguments.contains(&argument.name))
+                .map(|argument| json!({ "name": argument.name, "description": argument.description }))
+                .collect();
+            to_json(&json!({
+                "name": metadata.name,
+                "description": metadata.description,
+                "tags": metadata.tags,
+                "arguments": arguments,
+                "template": partial.text,
+                "model_settings": metadata.model_settings
+            }))
+        }
+    };
+    Ok(exported)
+}
+
+/// Splits a partially rendered prompt into segments.
+fn segments(text: &str) -> Result<Vec<Segment>, ExportError> {
+    // A partial render is a valid template, with only literals and missing arguments left
+    let (_, parts) = parse_template(text).map_err(|e| {
+        RenderTemplateError::Other(format!("Failed to parse the rendered prompt: {:?}", e))
+    })?;
+    parts
+        .into_iter()
+        .map(|part| match part {
+            PromptTemplatePart::Literal(text) => Ok(Segment::Text(text)),
+            PromptTemplatePart::Argument(name) => Ok(Segment::Variable(name)),
+            PromptTemplatePart::VariablePromptReference(name) => {
+                Err(ExportError::VariablePromptReference(name))
+            }
+            part => Err(ExportError::RenderError(RenderTemplateError::Other(
+                format!("Unexpected part in the rendered prompt: {:?}", part),
+            ))),
+        })
+        .collect()
+}
+
+/// Converts a partially rendered prompt, or a message of one, to a Python f-string
+/// template, returning it with its variables in the order they're first used.
+pub(crate) fn f_string_template(text: &str) -> Result<(String, Vec<String>), ExportError> {
+    let segments = segments(text)?;
+    let mut variables: Vec<String> = Vec::new();
+    for segment in &segments {
+        if let Segment::Variable(name) = segment
+            && !variables.contains(name)
+        {
+            variables.push(name.clone());
+        }
+    }
+    Ok((to_f_string(&segments), variables))
+}
+
+/// Joins the segments as a Python f-string template, doubling literal braces.
+fn to_f_string(segments: &[Segment]) -> String {
+    to_template(
+        segments,
+        |text| text.replace('{', "{{").replace('}', "}}"),
+        |name| format!("{{{}}}", name),
+    )
+}
+
+/// Joins the segments, formatting text and variables for the target format.
+fn to_template(
+    segments: &[Segment],
+    text: impl Fn(&str) -> String,
+    variable: impl Fn(&str) -> String,
+) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Text(content) => text(content),
+            Segment::Variable(name) => variable(name),
+        })
+        .collect()
+}
+
+/// Joins the segments with `{{name}}` placeholders and the text as it is.
+fn to_placeholder_template(segments: &[Segment]) -> String {
+    to_template(segments, str::to_string, |name| format!("{{{{{}}}}}", name))
+}
+
+/// Escapes the delimiters of Jinja statements, expressions and comments in literal text.
+fn escape_jinja(text: &str) -> String {
+    text.replace("{{", "{{ '{{' }}")
+        .replace("{%", "{{ '{%' }}")
+        .replace("{#", "{{ '{#' }}")
+}
+
+fn to_json(value: &impl serde::Serialize) -> String {
+    serde_json::to_string_pretty(value).expect("exported prompts are always serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_storage::FileStorage;
+    use crate::prompt::{Prompt, PromptMetadata};
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn export(content: &str, arguments: &[(&str, &str)], format: ExportFormat) -> String {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("persona".to_string(), None, vec![]),
+                "You are a {{role}}.".to_string(),
+            ))
+            .unwrap();
+        let template = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("review".to_string(), None, vec![]),
+            content.to_string(),
+        ))
+        .unwrap();
+        let arguments: HashMap<String, String> = arguments
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        export_prompt(&template, &arguments, &storage, format).unwrap()
+    }
+
+    #[test]
+    fn test_export_jinja() {
+        assert_eq!(
+            "You are a {{ role }}. Review {{ code }} in {{ '{{' }}braces}}",
+            export(
+                "{{prompt:persona}} Review {{code}} in \\{{braces}}",
+                &[],
+                ExportFormat::Jinja
+            )
+        );
+        assert_eq!(
+            "You are a reviewer. Review {{ code }}",
+            export(
+                "{{prompt:persona}} Review {{code}}",
+                &[("role", "reviewer")],
+                ExportFormat::Jinja
+            )
+        );
+    }
+
+    #[test]
+    fn test_export_langchain() {
+        let exported: serde_json::Value = serde_json::from_str(&export(
+            "Review {{code}} as {\"json\": true}",
+            &[],
+            ExportFormat::Langchain,
+        ))
+        .unwrap();
+        assert_eq!(
+            json!({
+                "_type": "prompt",
+                "input_variables": ["code"],
+                "template": "Review {code} as {{\"json\": true}}",
+                "template_format": "f-string"
+            }),
+            exported
+        );
+    }
+
+    #[test]
+    fn test_export_openai_chat() {
+        let exported: serde_json::Value = serde_json::from_str(&export(
+            "<|system|>\n{{prompt:persona}}\n<|user|>\nReview {{code}}",
+            &[],
+            ExportFormat::OpenaiChat,
+        ))
+        .unwrap();
+        assert_eq!(
+            json!([
+                {"role": "system", "content": "You are a {{role}}."},
+                {"role": "user", "content": "Review {{code}}"}
+            ]),
+            exported
+        );
+    }
+
+    #[test]
+    fn test_export_variable_prompt_reference() {
+        let template = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("review".to_string(), None, vec![]),
+            "{{prompt_var:style}}".to_string(),
+        ))
+        .unwrap();
+        let storage = FileStorage {
+            base_path: TempDir::new().unwrap().path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        assert!(matches!(
+            export_prompt(
+                &template,
+                &HashMap::<String, String>::new(),
+                &storage,
+                ExportFormat::Json
+            ),
+            Err(ExportError::VariablePromptReference(name)) if name == "style"
+        ));
+    }
+}
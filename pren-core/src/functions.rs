@@ -0,0 +1,95 @@
+//! # Template Functions
+//!
+//! This module provides the registry of functions that templates can call with the
+//! `{{fn:name(arg1, arg2)}}` syntax.
+//!
+//! Functions are plain Rust closures that receive the resolved arguments as strings and
+//! return the text to insert in the rendered prompt.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::file_storage::FileStorage;
+//! use pren_core::functions::FunctionRegistry;
+//! use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
+//! use std::collections::HashMap;
+//! use tempfile::TempDir;
+//!
+//! let mut functions = FunctionRegistry::new();
+//! functions.register("upper", |args| Ok(args.join(" ").to_uppercase()));
+//!
+//! let metadata = PromptMetadata::new("shout".to_string(), None, vec![]);
+//! let prompt = Prompt::new(metadata, "{{fn:upper(name, \"!\")}}".to_string());
+//! let template = PromptTemplate::new(prompt).unwrap();
+//!
+//! let temp_dir = TempDir::new().unwrap();
+//! let storage = FileStorage {
+//!     base_path: temp_dir.path().to_path_buf(),
+//!     fallback_paths: Vec::new(),
+//!     vault: false,
+//! };
+//! let mut args = HashMap::new();
+//! args.insert("name".to_string(), "world".to_string());
+//!
+//! let rendered = template.render_with_functions(&args, &storage, &functions).unwrap();
+//! assert_eq!("WORLD !", rendered);
+//! ```
+
+use std::collections::HashMap;
+
+/// A function that can be called from templates.
+///
+/// It receives the resolved arguments and returns the rendered text, or an error message.
+pub type TemplateFunction = Box<dyn Fn(&[String]) -> Result<String, String> + Send + Sync>;
+
+/// A registry of named functions available to templates.
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, TemplateFunction>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    /// Registers a function under the given name, replacing any function with the same name.
+    pub fn register<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&[String]) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.functions.insert(name.to_string(), Box::new(function));
+    }
+
+    /// Returns the function registered under the given name, if any.
+    pub fn get(&self, name: &str) -> Option<&TemplateFunction> {
+        self.functions.get(name)
+    }
+
+    /// Returns the names of all registered functions.
+    pub fn names(&self) -> Vec<&str> {
+        self.functions.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_and_call_function() {
+        let mut registry = FunctionRegistry::new();
+        registry.register("join", |args| Ok(args.join("-")));
+
+        let function = registry.get("join").expect("Function should be registered");
+        let result = function(&["a".to_string(), "b".to_string()]);
+        assert_eq!(Ok("a-b".to_string()), result);
+        assert_eq!(vec!["join"], registry.names());
+    }
+
+    #[test]
+    fn test_get_unknown_function() {
+        let registry = FunctionRegistry::new();
+        assert!(registry.get("missing").is_none());
+    }
+}
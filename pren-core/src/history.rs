@@ -0,0 +1,108 @@
+//! # Render History
+//!
+//! This module provides the data structures used to keep a history of the renders and
+//! generations of prompts, so that what was sent can be reproduced later.
+//!
+//! Each entry records the prompt, the arguments it was rendered with and a hash of the
+//! rendered text. Replaying an entry renders the prompt again with the same arguments,
+//! and the hash tells whether the result is still the same.
+//!
+//! History entries are stored by implementors of the
+//! [`HistoryStorage`](crate::storage::HistoryStorage) trait, alongside the prompts themselves.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::history::{HashingWriter, output_hash};
+//! use std::io::Write;
+//!
+//! let mut writer = HashingWriter::new(Vec::new());
+//! write!(writer, "Hello, world!").unwrap();
+//! assert_eq!(output_hash("Hello, world!"), writer.hash());
+//! ```
+
+use crate::stats::UsageKind;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// A render or generation of a prompt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The number of the entry, increasing with every entry recorded.
+    pub id: u64,
+    /// When the prompt was used.
+    pub timestamp: DateTime<Utc>,
+    /// Whether the prompt was rendered or sent to a model.
+    pub kind: UsageKind,
+    /// The name of the prompt.
+    pub prompt: String,
+    /// The arguments the prompt was rendered with.
+    pub arguments: BTreeMap<String, Value>,
+    /// The hash of the rendered prompt, as printed or sent to the model.
+    pub output_hash: String,
+}
+
+/// Computes the hash (64-bit FNV-1a, in hex) of a rendered prompt.
+pub fn output_hash(text: &str) -> String {
+    let mut writer = HashingWriter::new(io::sink());
+    writer
+        .write_all(text.as_bytes())
+        .expect("writing to a sink never fails");
+    writer.hash()
+}
+
+/// A writer hashing everything written through it, for rendered prompts that are
+/// streamed rather than held in memory.
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hash: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hash: 0xcbf29ce484222325,
+        }
+    }
+
+    /// The hash of the bytes written so far, like [`output_hash`].
+    pub fn hash(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for byte in &buf[..written] {
+            self.hash ^= u64::from(*byte);
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_hash() {
+        assert_eq!("cbf29ce484222325", output_hash(""));
+        assert_ne!(output_hash("Hello"), output_hash("Hello!"));
+
+        let mut writer = HashingWriter::new(Vec::new());
+        write!(writer, "Hello, ").unwrap();
+        write!(writer, "world!").unwrap();
+        assert_eq!(output_hash("Hello, world!"), writer.hash());
+        assert_eq!(b"Hello, world!".to_vec(), writer.inner);
+    }
+}
@@ -0,0 +1,244 @@
+//! # LangChain Hub
+//!
+//! This module converts prompts to and from the manifests stored in LangChain Hub repos,
+//! so prompts can be shared with the Python ecosystem. Manifests are LangChain's serialized
+//! prompt templates:
+//!
+//! - A `PromptTemplate`, for prompts without role markers.
+//! - A `ChatPromptTemplate`, for prompts with role markers, with a message template for
+//!   each message of the prompt.
+//!
+//! Prompts are pushed as f-string templates. As with [`export`](crate::export), prompts are
+//! rendered first, so prompt references, functions and examples are resolved, and the
+//! arguments that weren't given become the variables of the templates. Pulled templates
+//! are converted as with [`from_langchain`](crate::import::from_langchain), and their
+//! partial variables become argument defaults.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::hub::from_manifest;
+//! use serde_json::json;
+//!
+//! let manifest = json!({
+//!     "lc": 1,
+//!     "type": "constructor",
+//!     "id": ["langchain", "prompts", "prompt", "PromptTemplate"],
+//!     "kwargs": {
+//!         "input_variables": ["topic"],
+//!         "template": "Tell me about {topic}",
+//!         "template_format": "f-string"
+//!     }
+//! });
+//! let prompt = from_manifest(&manifest, "about").unwrap();
+//! assert_eq!("Tell me about {{topic}}", prompt.content);
+//! ```
+
+use crate::export::{ExportError, f_string_template};
+use crate::import::{ImportError, argument_defaults, convert_template};
+use crate::messages::{RenderedMessage, Role, has_role_markers, join_messages, split_messages};
+use crate::prompt::{Prompt, PromptMetadata, PromptTemplate, TemplateArguments};
+use crate::storage::PromptStorage;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+const PROMPT_TEMPLATE: [&str; 4] = ["langchain", "prompts", "prompt", "PromptTemplate"];
+const CHAT_PROMPT_TEMPLATE: [&str; 4] = ["langchain", "prompts", "chat", "ChatPromptTemplate"];
+
+/// Converts a prompt to a LangChain Hub manifest.
+///
+/// # Arguments
+///
+/// * `template` - The prompt to convert.
+/// * `arguments` - The arguments to render into the prompt. Arguments without a value or
+///   a declared default become variables of the manifest's templates.
+/// * `storage` - The storage to resolve prompt references from.
+///
+/// # Returns
+///
+/// * `Ok(Value)` - The manifest.
+/// * `Err(ExportError)` - If the prompt can't be rendered, or it has a prompt reference
+///   chosen by a missing argument, which manifests can't express.
+pub fn to_manifest<A: TemplateArguments, S: PromptStorage>(
+    template: &PromptTemplate,
+    arguments: &A,
+    storage: &S,
+) -> Result<Value, ExportError> {
+    let partial = template.render_partial(arguments, storage)?;
+    if !has_role_markers(&partial.text) {
+        let (template, _) = prompt_template(&partial.text)?;
+        return Ok(template);
+    }
+
+    let mut input_variables: Vec<String> = Vec::new();
+    let mut messages = Vec::new();
+    for message in split_messages(&partial.text) {
+        let (template, variables) = prompt_template(&message.content)?;
+        for variable in variables {
+            if !input_variables.contains(&variable) {
+                input_variables.push(variable);
+            }
+        }
+        let class = match message.role {
+            Role::System => "SystemMessagePromptTemplate",
+            Role::User => "HumanMessagePromptTemplate",
+            Role::Assistant => "AIMessagePromptTemplate",
+        };
+        messages.push(constructor(
+            &["langchain", "prompts", "chat", class],
+            json!({ "prompt": template }),
+        ));
+    }
+    Ok(constructor(
+        &CHAT_PROMPT_TEMPLATE,
+        json!({ "input_variables": input_variables, "messages": messages }),
+    ))
+}
+
+/// Converts (part of) a partially rendered prompt to a `PromptTemplate` manifest, returning
+/// it with its variables.
+fn prompt_template(text: &str) -> Result<(Value, Vec<String>), ExportError> {
+    let (template, variables) = f_string_template(text)?;
+    let manifest = constructor(
+        &PROMPT_TEMPLATE,
+        json!({
+            "input_variables": variables,
+            "template": template,
+            "template_format": "f-string"
+        }),
+    );
+    Ok((manifest, variables))
+}
+
+fn constructor(id: &[&str], kwargs: Value) -> Value {
+    json!({ "lc": 1, "type": "constructor", "id": id, "kwargs": kwargs })
+}
+
+/// Converts a LangChain Hub manifest to a prompt.
+///
+/// Chat templates become prompts with role markers. Messages without variables are kept
+/// as they are, while `MessagesPlaceholder`s and other messages pren has no equivalent
+/// for are rejected.
+///
+/// # Returns
+///
+/// * `Ok(Prompt)` - The prompt, named `name`.
+/// * `Err(ImportError)` - If the manifest isn't a prompt template pren can represent.
+pub fn from_manifest(manifest: &Value, name: &str) -> Result<Prompt, ImportError> {
+    let invalid = |reason: String| ImportError::InvalidFile(name.to_string(), reason);
+    let (class, kwargs) = class_and_kwargs(manifest)
+        .ok_or_else(|| invalid("expected a serialized LangChain prompt template".to_string()))?;
+
+    let content = match class {
+        "PromptTemplate" => template_content(kwargs)
+            .ok_or_else(|| invalid("expected the 'template' of the prompt template".to_string()))?,
+        "ChatPromptTemplate" => {
+            let messages = kwargs
+                .get("messages")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("expected the 'messages' of the chat template".to_string()))?
+                .iter()
+                .map(|message| chat_message(message).map_err(&invalid))
+                .collect::<Result<Vec<RenderedMessage>, ImportError>>()?;
+            join_messages(&messages)
+        }
+        class => return Err(invalid(format!("unsupported template '{}'", class))),
+    };
+
+    let mut metadata = PromptMetadata::new(name.to_string(), None, Vec::new());
+    let partial_variables: BTreeMap<String, Value> = kwargs
+        .get("partial_variables")
+        .and_then(|variables| serde_json::from_value(variables.clone()).ok())
+        .unwrap_or_default();
+    metadata.arguments = argument_defaults(partial_variables);
+    Ok(Prompt::new(metadata, content))
+}
+
+/// The class name and constructor arguments of a serialized LangChain object.
+fn class_and_kwargs(value: &Value) -> Option<(&str, &Value)> {
+    let class = value.get("id")?.as_array()?.last()?.as_str()?;
+    Some((class, value.get("kwargs")?))
+}
+
+/// The template of a `PromptTemplate`'s arguments, converted to pren's syntax.
+fn template_content(kwargs: &Value) -> Option<String> {
+    let template = kwargs.get("template")?.as_str()?;
+    let format = kwargs.get("template_format").and_then(Value::as_str);
+    Some(convert_template(template, format))
+}
+
+/// Converts a message of a chat template.
+fn chat_message(message: &Value) -> Result<RenderedMessage, String> {
+    let (class, kwargs) =
+        class_and_kwargs(message).ok_or_else(|| "expected a serialized message".to_string())?;
+    let role = match class {
+        "SystemMessagePromptTemplate" | "SystemMessage" => Role::System,
+        "HumanMessagePromptTemplate" | "HumanMessage" => Role::User,
+        "AIMessagePromptTemplate" | "AIMessage" => Role::Assistant,
+        class => return Err(format!("unsupported message '{}'", class)),
+    };
+    let content = if class.ends_with("PromptTemplate") {
+        kwargs
+            .get("prompt")
+            .and_then(class_and_kwargs)
+            .and_then(|(_, kwargs)| template_content(kwargs))
+    } else {
+        // Messages are literal text, so braces that would read as pren syntax are escaped
+        kwargs
+            .get("content")
+            .and_then(Value::as_str)
+            .map(|content| content.replace("{{", "\\{{"))
+    };
+    let content = content.ok_or_else(|| format!("expected the template of the '{}'", class))?;
+    Ok(RenderedMessage { role, content })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_storage::MemoryStorage;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_hub_manifest_round_trip() {
+        let prompt = |name: &str, content: &str| {
+            Prompt::new(
+                PromptMetadata::new(name.to_string(), None, vec![]),
+                content.to_string(),
+            )
+        };
+        let storage = MemoryStorage::from_prompts([prompt("persona", "You are a {{role}}.")]);
+        let template = PromptTemplate::new(prompt(
+            "review",
+            "<|system|>\n{{prompt:persona}}\n<|user|>\nReview {{code}} as {\"json\": true}",
+        ))
+        .unwrap();
+
+        let manifest = to_manifest(&template, &HashMap::<String, String>::new(), &storage).unwrap();
+        assert_eq!(
+            json!(["role", "code"]),
+            manifest["kwargs"]["input_variables"]
+        );
+        let user = &manifest["kwargs"]["messages"][1];
+        assert_eq!("HumanMessagePromptTemplate", user["id"][3]);
+        assert_eq!(
+            "Review {code} as {{\"json\": true}}",
+            user["kwargs"]["prompt"]["kwargs"]["template"]
+        );
+
+        let pulled = from_manifest(&manifest, "review").unwrap();
+        assert_eq!(
+            "<|system|>\nYou are a {{role}}.\n\n<|user|>\nReview {{code}} as {\"json\": true}\n",
+            pulled.content
+        );
+
+        let placeholder = json!({
+            "id": ["langchain", "prompts", "chat", "ChatPromptTemplate"],
+            "kwargs": {"messages": [{
+                "id": ["langchain", "prompts", "chat", "MessagesPlaceholder"],
+                "kwargs": {"variable_name": "history"}
+            }]}
+        });
+        assert!(from_manifest(&placeholder, "chat").is_err());
+    }
+}
@@ -0,0 +1,439 @@
+//! # Prompt Import
+//!
+//! This module converts prompts from other ecosystems into pren prompts, so existing
+//! collections can be migrated. The supported formats are:
+//!
+//! - [`ImportFormat::Langchain`] - LangChain prompt template files (JSON or YAML). Variables
+//!   of f-string templates (`{name}`) become pren arguments (`{{name}}`), and partial
+//!   variables become argument defaults.
+//! - [`ImportFormat::Openai`] - JSON files with an array of chat messages, or a chat
+//!   completion request with `messages` and model settings. Messages become role blocks.
+//! - [`ImportFormat::Fabric`] - Fabric pattern directories, with a `system.md` and an
+//!   optional `user.md`. The input of the pattern becomes the `input` argument.
+//! - [`ImportFormat::RawMd`] - Plain markdown files, imported as they are.
+//!
+//! Prompts are named after their files (or directories, for Fabric patterns), with the
+//! characters pren doesn't allow in names replaced by dashes.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::import::from_langchain;
+//!
+//! let prompt = from_langchain(
+//!     r#"{"_type": "prompt", "input_variables": ["topic"], "template": "Tell me about {topic}"}"#,
+//!     "about",
+//! )
+//! .unwrap();
+//! assert_eq!("Tell me about {{topic}}", prompt.content);
+//! ```
+
+use crate::llm::ModelSettings;
+use crate::parser::is_valid_prompt_name;
+use crate::prompt::{ArgumentDeclaration, Prompt, PromptMetadata};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("i/o Error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("invalid file '{0}': {1}")]
+    InvalidFile(String, String),
+    #[error("unknown import format '{0}'")]
+    UnknownFormat(String),
+}
+
+/// A format prompts can be imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Langchain,
+    Openai,
+    Fabric,
+    RawMd,
+}
+
+impl ImportFormat {
+    /// The names of every format, as accepted by [`ImportFormat::from_str`].
+    pub const NAMES: &'static [&'static str] = &["langchain", "openai", "fabric", "raw-md"];
+}
+
+impl FromStr for ImportFormat {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "langchain" => Ok(ImportFormat::Langchain),
+            "openai" => Ok(ImportFormat::Openai),
+            "fabric" => Ok(ImportFormat::Fabric),
+            "raw-md" => Ok(ImportFormat::RawMd),
+            _ => Err(ImportError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// Imports the prompts in a file or directory.
+///
+/// # Arguments
+///
+/// * `format` - The format of the prompts.
+/// * `path` - A prompt file, or a directory searched for prompt files. For Fabric, a
+///   pattern directory or a directory of patterns.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Prompt>)` - The converted prompts, sorted by their path.
+/// * `Err(ImportError)` - If a prompt file can't be read or converted.
+pub fn import_prompts(format: ImportFormat, path: &Path) -> Result<Vec<Prompt>, ImportError> {
+    if format == ImportFormat::Fabric {
+        return if path.join("system.md").is_file() {
+            Ok(vec![from_fabric(path)?])
+        } else {
+            sorted_entries(path, 1)?
+                .filter(|entry| entry.path().join("system.md").is_file())
+                .map(|entry| from_fabric(entry.path()))
+                .collect()
+        };
+    }
+
+    let extensions: &[&str] = match format {
+        ImportFormat::Langchain => &["json", "yaml", "yml"],
+        ImportFormat::Openai => &["json"],
+        _ => &["md", "markdown", "txt"],
+    };
+    sorted_entries(path, usize::MAX)?
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry
+                    .path()
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| extensions.contains(&ext))
+        })
+        .map(|entry| {
+            let path = entry.path();
+            let content = fs::read_to_string(path)?;
+            let name = prompt_name(path)?;
+            match format {
+                ImportFormat::Langchain => from_langchain(&content, &name),
+                ImportFormat::Openai => from_openai(&content, &name),
+                _ => Ok(from_markdown(&content, &name)),
+            }
+            .map_err(|e| match e {
+                ImportError::InvalidFile(_, reason) => {
+                    ImportError::InvalidFile(path.display().to_string(), reason)
+                }
+                e => e,
+            })
+        })
+        .collect()
+}
+
+/// The entries of a directory up to `max_depth`, sorted by path, or the path itself if it's
+/// a file.
+fn sorted_entries(
+    path: &Path,
+    max_depth: usize,
+) -> Result<impl Iterator<Item = walkdir::DirEntry>, ImportError> {
+    if !path.exists() {
+        return Err(ImportError::IoError(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("'{}' doesn't exist", path.display()),
+        )));
+    }
+    Ok(WalkDir::new(path)
+        .min_depth(if path.is_dir() { 1 } else { 0 })
+        .max_depth(max_depth)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok()))
+}
+
+/// Names a prompt after its file, replacing the characters that aren't allowed in names.
+fn prompt_name(path: &Path) -> Result<String, ImportError> {
+    let stem = path
+        .file_stem()
+        .or_else(|| path.file_name())
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let name: String = stem
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_matches(['.', '-']).to_string();
+    if !is_valid_prompt_name(&name) {
+        return Err(ImportError::InvalidFile(
+            path.display().to_string(),
+            format!("'{}' can't be used as a prompt name", stem),
+        ));
+    }
+    Ok(name)
+}
+
+/// A LangChain prompt template file.
+#[derive(Deserialize)]
+struct LangchainPrompt {
+    template: String,
+    #[serde(default)]
+    template_format: Option<String>,
+    #[serde(default)]
+    partial_variables: BTreeMap<String, Value>,
+}
+
+/// Converts a LangChain prompt template, in JSON or YAML.
+///
+/// F-string templates are converted to pren's syntax, while mustache and jinja2 templates
+/// are kept as they are, as their variables are already written like pren's.
+pub fn from_langchain(content: &str, name: &str) -> Result<Prompt, ImportError> {
+    // JSON is valid YAML, so both are parsed the same way
+    let langchain: LangchainPrompt = serde_yaml::from_str(content)
+        .map_err(|e| ImportError::InvalidFile(name.to_string(), e.to_string()))?;
+    let content = convert_template(&langchain.template, langchain.template_format.as_deref());
+
+    let mut metadata = PromptMetadata::new(name.to_string(), None, Vec::new());
+    metadata.arguments = argument_defaults(langchain.partial_variables);
+    Ok(Prompt::new(metadata, content))
+}
+
+/// Converts a LangChain template in the given `template_format` (f-string by default).
+pub(crate) fn convert_template(template: &str, format: Option<&str>) -> String {
+    match format {
+        None | Some("f-string") => convert_f_string(template),
+        _ => template.to_string(),
+    }
+}
+
+/// Declares the partial variables of a LangChain template as arguments with defaults.
+pub(crate) fn argument_defaults(
+    partial_variables: BTreeMap<String, Value>,
+) -> Vec<ArgumentDeclaration> {
+    partial_variables
+        .into_iter()
+        .map(|(name, value)| ArgumentDeclaration {
+            name,
+            description: None,
+            default: Some(match value {
+                Value::String(text) => text,
+                value => value.to_string(),
+            }),
+        })
+        .collect()
+}
+
+/// Converts a Python f-string template (`{name}`, with `{{` and `}}` for literal braces)
+/// to pren's syntax.
+fn convert_f_string(template: &str) -> String {
+    let mut converted = String::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                literal.push(c);
+            }
+            ('{', _) => {
+                let variable: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                // Literal braces that would read as pren syntax are escaped
+                converted.push_str(&literal.replace("{{", "\\{{"));
+                literal.clear();
+                converted.push_str(&format!("{{{{{}}}}}", variable.trim()));
+            }
+            _ => literal.push(c),
+        }
+    }
+    converted.push_str(&literal.replace("{{", "\\{{"));
+    converted
+}
+
+/// Converts a JSON array of chat messages, or a chat completion request with `messages`
+/// and optionally `model`, `temperature`, `max_tokens` and `stop`.
+pub fn from_openai(content: &str, name: &str) -> Result<Prompt, ImportError> {
+    let invalid = |reason: &str| ImportError::InvalidFile(name.to_string(), reason.to_string());
+    let request: Value = serde_json::from_str(content).map_err(|e| invalid(&e.to_string()))?;
+    let messages = match &request {
+        Value::Array(messages) => messages,
+        Value::Object(request) => match request.get("messages") {
+            Some(Value::Array(messages)) => messages,
+            _ => return Err(invalid("expected a 'messages' array")),
+        },
+        _ => return Err(invalid("expected an array of messages or a request object")),
+    };
+
+    let mut blocks = Vec::new();
+    for message in messages {
+        let role = message["role"]
+            .as_str()
+            .ok_or_else(|| invalid("message without a role"))?;
+        let role = match role {
+            // Developer messages replace system messages in newer models
+            "system" | "developer" => "system",
+            "user" | "assistant" => role,
+            _ => return Err(invalid(&format!("unsupported role '{}'", role))),
+        };
+        let text = match &message["content"] {
+            Value::String(text) => text.clone(),
+            // Content parts, of which only the text ones are kept
+            Value::Array(parts) => parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            _ => String::new(),
+        };
+        blocks.push(format!("<|{}|>\n{}", role, text.trim()));
+    }
+
+    let mut metadata = PromptMetadata::new(name.to_string(), None, Vec::new());
+    metadata.model_settings = ModelSettings {
+        model: request["model"].as_str().map(str::to_string),
+        temperature: request["temperature"].as_f64(),
+        max_tokens: request["max_tokens"]
+            .as_u64()
+            .or(request["max_completion_tokens"].as_u64()),
+        stop: match &request["stop"] {
+            Value::String(stop) => vec![stop.clone()],
+            Value::Array(stops) => stops
+                .iter()
+                .filter_map(|stop| stop.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        },
+    };
+    Ok(Prompt::new(metadata, blocks.join("\n")))
+}
+
+/// Converts a Fabric pattern directory. The system prompt comes from `system.md`, and the
+/// user message from `user.md`, if any, followed by the `input` argument.
+pub fn from_fabric(pattern_dir: &Path) -> Result<Prompt, ImportError> {
+    let name = prompt_name(pattern_dir)?;
+    let system = fs::read_to_string(pattern_dir.join("system.md"))?;
+    let user_path = pattern_dir.join("user.md");
+    let user = if user_path.is_file() {
+        format!("{}\n", fs::read_to_string(user_path)?.trim())
+    } else {
+        String::new()
+    };
+
+    let content = format!(
+        "<|system|>\n{}\n<|user|>\n{}{{{{input}}}}",
+        system.trim(),
+        user
+    );
+    let mut metadata = PromptMetadata::new(name, None, Vec::new());
+    metadata.arguments = vec![ArgumentDeclaration {
+        name: "input".to_string(),
+        description: Some("The input of the pattern".to_string()),
+        default: None,
+    }];
+    Ok(Prompt::new(metadata, content))
+}
+
+/// Converts a plain markdown file, keeping its content as it is.
+pub fn from_markdown(content: &str, name: &str) -> Prompt {
+    Prompt::new(
+        PromptMetadata::new(name.to_string(), None, Vec::new()),
+        content.trim().to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_convert_f_string() {
+        assert_eq!(
+            "Hi {{name}}, {literal} {{topic}}",
+            convert_f_string("Hi {name}, {{literal}} { topic }")
+        );
+        assert_eq!("\\{{json}}", convert_f_string("{{{{json}}}}"));
+    }
+
+    #[test]
+    fn test_from_langchain() {
+        let yaml = "_type: prompt\n\
+                    input_variables: [text]\n\
+                    partial_variables:\n  tone: formal\n\
+                    template: 'Summarize {text} in a {tone} tone'\n";
+        let prompt = from_langchain(yaml, "summary").unwrap();
+        assert_eq!("Summarize {{text}} in a {{tone}} tone", prompt.content);
+        assert_eq!("tone", prompt.metadata.arguments[0].name);
+        assert_eq!(
+            Some("formal".to_string()),
+            prompt.metadata.arguments[0].default
+        );
+
+        let json = r#"{"template": "Hi {{name}}", "template_format": "mustache"}"#;
+        assert_eq!("Hi {{name}}", from_langchain(json, "hi").unwrap().content);
+        assert!(from_langchain("{}", "empty").is_err());
+    }
+
+    #[test]
+    fn test_from_openai() {
+        let request = r#"{
+            "model": "gpt-4o",
+            "temperature": 0.2,
+            "messages": [
+                {"role": "developer", "content": "You are terse."},
+                {"role": "user", "content": [{"type": "text", "text": "Explain {{topic}}"}]}
+            ]
+        }"#;
+        let prompt = from_openai(request, "explain").unwrap();
+        assert_eq!(
+            "<|system|>\nYou are terse.\n<|user|>\nExplain {{topic}}",
+            prompt.content
+        );
+        assert_eq!(
+            Some("gpt-4o".to_string()),
+            prompt.metadata.model_settings.model
+        );
+        assert_eq!(Some(0.2), prompt.metadata.model_settings.temperature);
+
+        assert!(from_openai(r#"[{"role": "tool", "content": ""}]"#, "tool").is_err());
+    }
+
+    #[test]
+    fn test_import_prompts() {
+        let dir = TempDir::new().unwrap();
+        let pattern_dir = dir.path().join("patterns").join("extract_wisdom");
+        fs::create_dir_all(&pattern_dir).unwrap();
+        fs::write(
+            pattern_dir.join("system.md"),
+            "# IDENTITY\nExtract wisdom.\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("My Notes.md"), "Notes about {{topic}}\n").unwrap();
+
+        let prompts = import_prompts(ImportFormat::Fabric, &dir.path().join("patterns")).unwrap();
+        assert_eq!(1, prompts.len());
+        assert_eq!("extract_wisdom", prompts[0].metadata.name);
+        assert_eq!(
+            "<|system|>\n# IDENTITY\nExtract wisdom.\n<|user|>\n{{input}}",
+            prompts[0].content
+        );
+
+        // Only the files of the format are imported, even from nested directories
+        let prompts = import_prompts(ImportFormat::RawMd, dir.path()).unwrap();
+        let names: Vec<&str> = prompts
+            .iter()
+            .map(|prompt| prompt.metadata.name.as_str())
+            .collect();
+        assert_eq!(vec!["My-Notes", "system"], names);
+        assert_eq!("Notes about {{topic}}", prompts[0].content);
+    }
+}
@@ -0,0 +1,142 @@
+//! # Prompt Improvement
+//!
+//! This module provides a built-in meta-prompt asking a model to critique a prompt and
+//! rewrite it, optionally towards a goal such as "more concise", and parses the model's
+//! response into the critique and the improved content.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::improve::{improvement_messages, parse_improvement};
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//!
+//! let prompt = Prompt::new(
+//!     PromptMetadata::new("summary".to_string(), None, vec![]),
+//!     "summarize {{text}}".to_string(),
+//! );
+//! let messages = improvement_messages(&prompt, Some("more specific"));
+//! assert!(messages[1].content.contains("summarize {{text}}"));
+//!
+//! let response = "<critique>Too vague.</critique>\n\
+//!                 <improved_prompt>Summarize {{text}} in three bullet points.</improved_prompt>";
+//! let improvement = parse_improvement(response).unwrap();
+//! assert_eq!("Too vague.", improvement.critique);
+//! assert_eq!("Summarize {{text}} in three bullet points.", improvement.content);
+//! ```
+
+use crate::messages::{RenderedMessage, Role};
+use crate::prompt::Prompt;
+use serde::Serialize;
+use thiserror::Error;
+
+const META_PROMPT: &str = "\
+You are an expert prompt engineer. You critique prompt templates and rewrite them to be \
+clearer, more specific and more effective, without changing what they're for.
+
+Templates contain placeholders between double braces: `{{name}}` inserts an argument, \
+`{{prompt:name}}` includes another prompt, and `{{examples:...}}` or `{{prompts:...}}` \
+include prompts by tag. Keep every placeholder exactly as it is, don't add new ones, and \
+keep role markers such as `<|system|>` on lines of their own.
+
+Reply with your critique between <critique> and </critique>, then with the full improved \
+template, and nothing else, between <improved_prompt> and </improved_prompt>.";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ImproveError {
+    #[error("the model's response has no improved prompt between <improved_prompt> tags")]
+    MissingImprovedPrompt,
+}
+
+/// A model's critique of a prompt, and its improved content.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Improvement {
+    pub critique: String,
+    pub content: String,
+}
+
+/// Builds the messages asking a model to improve a prompt, towards `goal` if it's given.
+///
+/// The prompt's content is sent as it's stored, with its placeholders, rather than rendered.
+pub fn improvement_messages(prompt: &Prompt, goal: Option<&str>) -> Vec<RenderedMessage> {
+    let mut request = String::new();
+    if let Some(description) = &prompt.metadata.description {
+        request.push_str(&format!("The prompt is described as: {}\n\n", description));
+    }
+    if let Some(goal) = goal {
+        request.push_str(&format!("Make the prompt {}.\n\n", goal));
+    }
+    request.push_str(&format!(
+        "<prompt>\n{}\n</prompt>",
+        prompt.content.trim_end()
+    ));
+    vec![
+        RenderedMessage {
+            role: Role::System,
+            content: META_PROMPT.to_string(),
+        },
+        RenderedMessage {
+            role: Role::User,
+            content: request,
+        },
+    ]
+}
+
+/// Parses a response to [`improvement_messages`]. The critique is optional, since models
+/// sometimes leave it out, but the improved prompt isn't.
+pub fn parse_improvement(response: &str) -> Result<Improvement, ImproveError> {
+    let content = between(response, "<improved_prompt>", "</improved_prompt>")
+        .ok_or(ImproveError::MissingImprovedPrompt)?;
+    Ok(Improvement {
+        critique: between(response, "<critique>", "</critique>")
+            .unwrap_or_default()
+            .to_string(),
+        content: content.to_string(),
+    })
+}
+
+/// The trimmed text between two tags. A missing closing tag is tolerated, since responses
+/// cut short by the token limit lose it.
+fn between<'a>(text: &'a str, open: &str, close: &str) -> Option<&'a str> {
+    let start = text.find(open)? + open.len();
+    let rest = &text[start..];
+    let end = rest.find(close).unwrap_or(rest.len());
+    Some(rest[..end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptMetadata;
+
+    #[test]
+    fn test_improvement_messages() {
+        let prompt = Prompt::new(
+            PromptMetadata::new(
+                "review".to_string(),
+                Some("Reviews code".to_string()),
+                vec![],
+            ),
+            "Review {{code}}\n".to_string(),
+        );
+        let messages = improvement_messages(&prompt, Some("more concise"));
+        assert_eq!(Role::System, messages[0].role);
+        assert_eq!(
+            "The prompt is described as: Reviews code\n\n\
+             Make the prompt more concise.\n\n\
+             <prompt>\nReview {{code}}\n</prompt>",
+            messages[1].content
+        );
+    }
+
+    #[test]
+    fn test_parse_improvement() {
+        assert_eq!(
+            Err(ImproveError::MissingImprovedPrompt),
+            parse_improvement("Looks good to me")
+        );
+
+        let improvement = parse_improvement("<improved_prompt>\nReview {{code}}").unwrap();
+        assert_eq!("", improvement.critique);
+        assert_eq!("Review {{code}}", improvement.content);
+    }
+}
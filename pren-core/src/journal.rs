@@ -0,0 +1,46 @@
+//! # Undo Journal
+//!
+//! This module provides the data structures used to keep a journal of destructive
+//! operations on prompts, such as deleting or overwriting them, so they can be undone.
+//!
+//! Each entry keeps the content the prompt had before the operation. Undoing an entry
+//! restores that content and removes the entry from the journal. Only the last
+//! [`MAX_JOURNAL_ENTRIES`] operations are kept.
+//!
+//! The journal is kept by implementors of the
+//! [`JournalStorage`](crate::storage::JournalStorage) trait, alongside the prompts themselves.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The number of operations kept in the journal, older ones are forgotten.
+pub const MAX_JOURNAL_ENTRIES: usize = 50;
+
+/// A destructive operation on a prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalOperation {
+    /// The prompt was deleted.
+    Delete,
+    /// The prompt was saved over a previous version.
+    Overwrite,
+    /// The prompt was renamed, moving its file.
+    Rename,
+}
+
+/// A destructive operation on a prompt, with what's needed to undo it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// When the operation happened.
+    pub timestamp: DateTime<Utc>,
+    pub operation: JournalOperation,
+    /// The name of the prompt.
+    pub prompt: String,
+    /// The path of the prompt file, relative to the storage.
+    pub path: String,
+    /// The content of the prompt file before the operation.
+    pub content: String,
+    /// The path the prompt file was moved to when it was renamed, relative to the storage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_to: Option<String>,
+}
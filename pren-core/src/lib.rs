@@ -7,15 +7,58 @@
 //!
 //! # Modules
 //!
+//! - [`arguments`] - Sources of template arguments, such as context files
+//! - [`composition`] - Trees of the prompts composing a prompt, and of those including it
+//! - [`dialect`] - Template dialects prompts can be written in, such as Jinja and Handlebars
+//! - [`diff`] - Structured diffs between prompts
+//! - [`doctor`] - Diagnostics of prompt storages, such as dangling references and cycles
+//! - [`embeddings`] - Semantic search over prompts, with cached embeddings
+//! - [`engine`] - A prompt engine bundling a storage, render options, hooks and a model
+//! - [`eval`] - Eval cases checking model responses to prompts, optionally scored by a judge
+//! - [`export`] - Conversion of prompts to other template formats, such as Jinja
 //! - [`file_storage`] - File-based storage implementation for prompts
+//! - [`functions`] - Registry of functions callable from templates
+//! - [`history`] - History of the renders and generations of prompts
+//! - [`hub`] - Conversion of prompts to and from LangChain Hub manifests
+//! - [`import`] - Conversion of prompts from other ecosystems, such as LangChain and Fabric
+//! - [`improve`] - Built-in meta-prompt asking a model to critique and improve a prompt
+//! - [`journal`] - Journal of destructive operations on prompts, to undo them
+//! - [`memory_storage`] - In-memory storage for prompts, for targets without a file system
+//! - [`messages`] - Chat messages with roles, split from rendered prompts
+//! - [`output`] - Output formats that model responses are validated against
+//! - [`pack`] - Shareable prompt packs described by a manifest
 //! - [`parser`] - Template parsing functionality
+//! - [`pipeline`] - Pipelines chaining prompts and model calls
+//! - [`policy`] - Content policies, such as secret detection, checked before saving or generating
 //! - [`prompt`] - Core prompt data structures and functionality
+//! - [`registry`] - Static registries of prompt packs, with semantic versions and checksums
+//! - [`scaffold`] - Built-in meta-prompts to start new prompts from
+//! - [`search`] - Full-text search over prompts
+//! - [`stats`] - Usage statistics of prompts
 //! - [`storage`] - Prompt storage traits and file format definitions
+//! - [`tag_query`] - Boolean queries over prompt tags
+//! - [`tagging`] - Built-in prompt asking a model to suggest tags for a prompt
+//! - [`testing`] - Test cases stored alongside prompts
+//! - [`tokens`] - Token counting for rendered prompts
+//! - [`transcript`] - Transcripts of conversations with models, started from prompts
+//! - [`validation`] - Validation of prompt files with line diagnostics, e.g. in git hooks
+//!
+//! # Features
+//!
+//! - `llm` (default) - Requests to models, in [`llm`] and [`embeddings`]
+//! - `tiktoken` - Exact token counts instead of estimates
+//! - `jinja` - Prompts written as Jinja2 templates, see [`dialect`]
+//! - `handlebars` - Prompts written as Handlebars templates, see [`dialect`]
+//!
+//! Without `llm`, the crate compiles to `wasm32-unknown-unknown`, so templates can be
+//! parsed, validated and rendered in a browser. As there's no file system there, prompts
+//! are kept in a [`MemoryStorage`](memory_storage::MemoryStorage) instead of a
+//! [`FileStorage`](file_storage::FileStorage).
 //!
 //! # Examples
 //!
 //! ```rust
-//! use pren_core::prompt::{Prompt, PromptMetadata};
+//! use pren_core::prompt::Prompt;
 //! use pren_core::file_storage::FileStorage;
 //! use pren_core::storage::PromptStorage;
 //! use std::path::PathBuf;
@@ -25,18 +68,55 @@
 //! let temp_dir = TempDir::new().unwrap();
 //!
 //! // Create a prompt
-//! let metadata = PromptMetadata::new("greeting".to_string(), None, vec!["example".to_string()]);
-//! let prompt = Prompt::new(metadata, "Hello, world!".to_string());
+//! let prompt = Prompt::builder()
+//!     .name("greeting")
+//!     .tag("example")
+//!     .content("Hello, world!")
+//!     .build()
+//!     .expect("Invalid prompt");
 //!
 //! // Save it to file storage
 //! let storage = FileStorage {
 //!     base_path: temp_dir.path().to_path_buf(),
+//!     fallback_paths: Vec::new(),
+//!     vault: false,
 //! };
 //! storage.save_prompt(&prompt).expect("Failed to save prompt");
 //! ```
 
+pub mod arguments;
+pub mod composition;
+pub mod dialect;
+pub mod diff;
+pub mod doctor;
+pub mod embeddings;
+pub mod engine;
+pub mod eval;
+pub mod export;
 pub mod file_storage;
+pub mod functions;
+pub mod history;
+pub mod hub;
+pub mod import;
+pub mod improve;
+pub mod journal;
 pub mod llm;
+pub mod memory_storage;
+pub mod messages;
+pub mod output;
+pub mod pack;
 pub mod parser;
+pub mod pipeline;
+pub mod policy;
 pub mod prompt;
+pub mod registry;
+pub mod scaffold;
+pub mod search;
+pub mod stats;
 pub mod storage;
+pub mod tag_query;
+pub mod tagging;
+pub mod testing;
+pub mod tokens;
+pub mod transcript;
+pub mod validation;
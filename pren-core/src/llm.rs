@@ -1,26 +1,331 @@
-use rig::client::CompletionClient;
-use rig::completion::{AssistantContent, CompletionError, CompletionModelDyn, Message};
-use rig::providers::openai::Client;
-
-pub async fn get_completions_content(
-    api_key: &str,
-    base_url: &str,
-    model_name: &str,
-    prompt: &str,
-) -> Result<String, CompletionError> {
-    let client = Client::builder(api_key).base_url(base_url).build().unwrap();
-
-    let model = client.completion_model(model_name).completions_api();
-
-    let response = model
-        .completion_request(Message::from(prompt))
-        .send()
-        .await?;
-
-    match response.choice.first() {
-        AssistantContent::Text(t) => Ok(t.text.clone()),
-        _ => Err(CompletionError::ResponseError(
-            "Expected text response, but got tool call or reasoning".to_string(),
-        )),
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(feature = "llm")]
+mod client;
+
+#[cfg(feature = "llm")]
+pub use client::*;
+
+/// The APIs models can be served by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// OpenAI, or any server with an OpenAI-compatible API, such as LM Studio or vLLM.
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    Anthropic,
+    Ollama,
+    Gemini,
+}
+
+impl Provider {
+    /// The names of the providers, as used in configs and on the command line.
+    pub const NAMES: &'static [&'static str] = &["openai", "anthropic", "ollama", "gemini"];
+
+    /// The embedding model used when none is configured, or `None` for providers that
+    /// don't serve embeddings.
+    pub fn default_embedding_model(&self) -> Option<&'static str> {
+        match self {
+            Provider::OpenAi => Some("text-embedding-3-small"),
+            Provider::Anthropic => None,
+            Provider::Ollama => Some("nomic-embed-text"),
+            Provider::Gemini => Some("text-embedding-004"),
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Provider::OpenAi => "openai",
+            Provider::Anthropic => "anthropic",
+            Provider::Ollama => "ollama",
+            Provider::Gemini => "gemini",
+        })
+    }
+}
+
+/// The error of parsing the name of a provider that doesn't exist.
+#[derive(Debug, Error)]
+#[error("Unknown provider '{0}'. Use one of: openai, anthropic, ollama, gemini")]
+pub struct UnknownProviderError(pub String);
+
+impl FromStr for Provider {
+    type Err = UnknownProviderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(Provider::OpenAi),
+            "anthropic" => Ok(Provider::Anthropic),
+            "ollama" => Ok(Provider::Ollama),
+            "gemini" => Ok(Provider::Gemini),
+            _ => Err(UnknownProviderError(s.to_string())),
+        }
+    }
+}
+
+/// How to reach the API serving a model.
+///
+/// Without a base URL, requests go to the provider's public API, or to a local server
+/// for Ollama.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderConfig {
+    provider: Provider,
+    api_key: String,
+    base_url: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl ProviderConfig {
+    /// Creates the config of a provider. An empty `base_url` stands for the provider's
+    /// default one, and the API key is ignored by providers that don't need one.
+    pub fn new(provider: Provider, api_key: &str, base_url: &str) -> Self {
+        ProviderConfig {
+            provider,
+            api_key: api_key.to_string(),
+            base_url: (!base_url.is_empty()).then(|| base_url.to_string()),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Returns this config with requests timed out and retried according to `retry`.
+    pub fn with_retry_policy(self, retry: RetryPolicy) -> Self {
+        ProviderConfig { retry, ..self }
+    }
+
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// The URL requests are sent to.
+    pub fn base_url(&self) -> &str {
+        let default = match self.provider {
+            Provider::OpenAi => "https://api.openai.com/v1",
+            Provider::Anthropic => "https://api.anthropic.com",
+            Provider::Ollama => "http://localhost:11434",
+            Provider::Gemini => "https://generativelanguage.googleapis.com",
+        };
+        self.base_url.as_deref().unwrap_or(default)
+    }
+}
+
+/// How long to wait for the model, and how to retry requests that fail for reasons that
+/// may go away on their own, such as rate limits, server errors and dropped connections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// How long to wait for a response, or for the next chunk of a streamed one. `None`
+    /// waits forever.
+    pub timeout: Option<Duration>,
+    /// How many times a failed request is retried.
+    pub max_retries: u32,
+    /// How long to wait before the first retry. The wait doubles with every retry.
+    pub initial_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The longest wait between two attempts.
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    /// How long to wait before a retry, after `attempt` failed attempts.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(Self::MAX_BACKOFF)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            timeout: Some(Duration::from_secs(300)),
+            max_retries: 2,
+            initial_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// The number of tokens a request used, as reported by the provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// The tokens of the prompt, including the chat history.
+    pub input_tokens: u64,
+    /// The tokens of the response.
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+        }
+    }
+}
+
+/// The response of the model to a request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    /// The text of the response.
+    pub text: String,
+    /// The tokens the request used, if the provider reported them.
+    pub usage: Option<TokenUsage>,
+    /// The tools the model called instead of, or along with, replying with text.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// A function the model can call, declared in a prompt's frontmatter:
+///
+/// ```yaml
+/// tools:
+///   - name: get_weather
+///     description: Gets the current weather in a city
+///     parameters:
+///       type: object
+///       properties:
+///         city: { type: string }
+///       required: [city]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    /// What the function does, telling the model when to call it.
+    #[serde(default)]
+    pub description: String,
+    /// The JSON schema of the function's arguments. Functions without arguments can leave
+    /// it out.
+    #[serde(default = "empty_parameters")]
+    pub parameters: Value,
+}
+
+fn empty_parameters() -> Value {
+    json!({ "type": "object", "properties": {} })
+}
+
+/// A call of a tool by the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// The id of the call, which its result refers to.
+    pub id: String,
+    /// The provider's second id of the call, for the providers that have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_id: Option<String>,
+    /// The name of the called tool.
+    pub name: String,
+    /// The arguments of the call, which should match the tool's parameters.
+    pub arguments: Value,
+}
+
+/// Preferred model settings for a prompt, stored in its frontmatter.
+///
+/// Unset fields fall back to the model's defaults (or the configured model, for `model`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelSettings {
+    /// The name of the model to use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The sampling temperature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// The maximum number of tokens to generate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    /// Sequences where the model stops generating.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl ModelSettings {
+    /// Returns these settings with every field set in `overrides` replaced.
+    pub fn with_overrides(&self, overrides: &ModelSettings) -> ModelSettings {
+        ModelSettings {
+            model: overrides.model.clone().or_else(|| self.model.clone()),
+            temperature: overrides.temperature.or(self.temperature),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            stop: if overrides.stop.is_empty() {
+                self.stop.clone()
+            } else {
+                overrides.stop.clone()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_config() {
+        let provider = ProviderConfig::new(Provider::Ollama, "key", "");
+        assert_eq!(Provider::Ollama, provider.provider());
+        assert_eq!("http://localhost:11434", provider.base_url());
+
+        let provider = ProviderConfig::new(Provider::OpenAi, "key", "http://localhost:1234/v1");
+        assert_eq!(Provider::OpenAi, provider.provider());
+        assert_eq!("http://localhost:1234/v1", provider.base_url());
+        assert_eq!(
+            Ok(Provider::Anthropic),
+            "anthropic".parse::<Provider>().map_err(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_tool_definitions() {
+        let tools: Vec<ToolDefinition> =
+            serde_yaml::from_str("- name: now\n- name: weather\n  parameters: { type: object }")
+                .unwrap();
+        assert_eq!("", tools[0].description);
+        assert_eq!(empty_parameters(), tools[0].parameters);
+        assert_eq!(json!({ "type": "object" }), tools[1].parameters);
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let retry = RetryPolicy {
+            initial_backoff: Duration::from_millis(500),
+            ..RetryPolicy::default()
+        };
+        assert_eq!(Duration::from_millis(500), retry.backoff(1));
+        assert_eq!(Duration::from_secs(1), retry.backoff(2));
+        assert_eq!(Duration::from_secs(2), retry.backoff(3));
+        assert_eq!(RetryPolicy::MAX_BACKOFF, retry.backoff(20));
+    }
+
+    #[test]
+    fn test_model_settings_with_overrides() {
+        let hints = ModelSettings {
+            model: Some("small".to_string()),
+            temperature: Some(0.2),
+            max_tokens: Some(100),
+            stop: vec!["END".to_string()],
+        };
+        let overrides = ModelSettings {
+            temperature: Some(0.9),
+            ..ModelSettings::default()
+        };
+
+        let settings = hints.with_overrides(&overrides);
+        assert_eq!(Some("small".to_string()), settings.model);
+        assert_eq!(Some(0.9), settings.temperature);
+        assert_eq!(Some(100), settings.max_tokens);
+        assert_eq!(vec!["END".to_string()], settings.stop);
     }
 }
@@ -0,0 +1,753 @@
+//! Requests to the models, through the clients of their providers.
+
+use super::*;
+use crate::messages::{RenderedMessage, Role};
+use crate::output::OutputFormat;
+use futures::StreamExt;
+use rig::OneOrMany;
+use rig::client::{CompletionClient, EmbeddingsClient};
+use rig::completion::message::{ToolResult, ToolResultContent, UserContent};
+use rig::completion::{
+    AssistantContent, CompletionModel, CompletionRequestBuilder, GetTokenUsage, Message, Usage,
+};
+use rig::embeddings::{EmbeddingError, EmbeddingModel};
+use rig::providers::{anthropic, gemini, ollama, openai};
+use rig::streaming::StreamedAssistantContent;
+use tracing::{debug, warn};
+
+/// The error of a failed request to the model.
+pub use rig::completion::CompletionError;
+
+/// The error of a failed request to the model, classified so callers can tell failures
+/// worth retrying from the rest.
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("The model didn't respond within {} seconds", .0.as_secs_f64())]
+    Timeout(Duration),
+    #[error("Couldn't connect to the model: {0}")]
+    Connection(CompletionError),
+    #[error("The model is rate limited: {0}")]
+    RateLimited(CompletionError),
+    #[error("The model's server failed: {0}")]
+    Server(CompletionError),
+    #[error(transparent)]
+    Request(CompletionError),
+}
+
+impl LlmError {
+    /// Whether the request may succeed if sent again.
+    pub fn is_transient(&self) -> bool {
+        !matches!(self, LlmError::Request(_))
+    }
+}
+
+impl From<CompletionError> for LlmError {
+    /// Classifies an error of the model's client. Most providers report failed responses
+    /// with their body only, so rate limits and server errors are told apart by the text.
+    fn from(error: CompletionError) -> Self {
+        let text = match &error {
+            CompletionError::HttpError(e) => {
+                if let Some(status) = e.status() {
+                    return match status.as_u16() {
+                        429 => LlmError::RateLimited(error),
+                        500..=599 => LlmError::Server(error),
+                        _ => LlmError::Request(error),
+                    };
+                }
+                if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() {
+                    return LlmError::Connection(error);
+                }
+                return LlmError::Request(error);
+            }
+            CompletionError::ProviderError(text) => text.to_lowercase(),
+            _ => return LlmError::Request(error),
+        };
+        if ["429", "rate limit", "rate_limit", "too many requests"]
+            .iter()
+            .any(|pattern| text.contains(pattern))
+        {
+            LlmError::RateLimited(error)
+        } else if [
+            "overloaded",
+            "internal server error",
+            "bad gateway",
+            "service unavailable",
+            "gateway timeout",
+        ]
+        .iter()
+        .any(|pattern| text.contains(pattern))
+        {
+            LlmError::Server(error)
+        } else if [
+            "connection reset",
+            "connection refused",
+            "connection closed",
+        ]
+        .iter()
+        .any(|pattern| text.contains(pattern))
+        {
+            LlmError::Connection(error)
+        } else {
+            LlmError::Request(error)
+        }
+    }
+}
+
+/// Runs `$body` with `$model` bound to the completion model of a provider, since each
+/// provider has a model type of its own.
+macro_rules! with_model {
+    ($provider:expr, $model_name:expr, |$model:ident| $body:expr) => {{
+        let base_url = $provider.base_url();
+        let api_key = &$provider.api_key;
+        match $provider.provider {
+            Provider::OpenAi => {
+                let $model = openai::Client::builder(api_key)
+                    .base_url(base_url)
+                    .build()
+                    .map_err(client_error)?
+                    .completion_model($model_name)
+                    .completions_api();
+                $body
+            }
+            Provider::Anthropic => {
+                let $model = anthropic::Client::builder(api_key)
+                    .base_url(base_url)
+                    .build()
+                    .map_err(client_error)?
+                    .completion_model($model_name);
+                $body
+            }
+            Provider::Ollama => {
+                let $model = ollama::Client::builder()
+                    .base_url(base_url)
+                    .build()
+                    .map_err(client_error)?
+                    .completion_model($model_name);
+                $body
+            }
+            Provider::Gemini => {
+                let $model = gemini::Client::builder(api_key)
+                    .base_url(base_url)
+                    .build()
+                    .map_err(client_error)?
+                    .completion_model($model_name);
+                $body
+            }
+        }
+    }};
+}
+
+fn client_error(error: impl fmt::Display) -> CompletionError {
+    CompletionError::ProviderError(format!("Couldn't create the client: {}", error))
+}
+
+impl TokenUsage {
+    /// The usage reported by the model's client, if the provider reported any.
+    fn reported(usage: Usage) -> Option<TokenUsage> {
+        (usage.input_tokens > 0 || usage.output_tokens > 0).then_some(TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+        })
+    }
+}
+
+/// Adds the tokens used by a request to the ones used by previous requests.
+fn add_usage(usage: Option<TokenUsage>, other: Option<TokenUsage>) -> Option<TokenUsage> {
+    match (usage, other) {
+        (Some(usage), Some(other)) => Some(usage + other),
+        (usage, other) => usage.or(other),
+    }
+}
+
+impl From<&ToolDefinition> for rig::completion::ToolDefinition {
+    fn from(tool: &ToolDefinition) -> Self {
+        rig::completion::ToolDefinition {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            parameters: tool.parameters.clone(),
+        }
+    }
+}
+
+impl From<&rig::completion::message::ToolCall> for ToolCall {
+    fn from(call: &rig::completion::message::ToolCall) -> Self {
+        ToolCall {
+            id: call.id.clone(),
+            call_id: call.call_id.clone(),
+            name: call.function.name.clone(),
+            arguments: call.function.arguments.clone(),
+        }
+    }
+}
+
+impl ToolCall {
+    fn to_content(&self) -> AssistantContent {
+        AssistantContent::ToolCall(rig::completion::message::ToolCall {
+            id: self.id.clone(),
+            call_id: self.call_id.clone(),
+            function: rig::completion::message::ToolFunction {
+                name: self.name.clone(),
+                arguments: self.arguments.clone(),
+            },
+        })
+    }
+
+    fn result(&self, output: String) -> UserContent {
+        UserContent::ToolResult(ToolResult {
+            id: self.id.clone(),
+            call_id: self.call_id.clone(),
+            content: OneOrMany::one(ToolResultContent::text(output)),
+        })
+    }
+}
+
+pub async fn get_completions_content(
+    provider: &ProviderConfig,
+    model_name: &str,
+    prompt: &str,
+) -> Result<String, LlmError> {
+    let message = RenderedMessage {
+        role: Role::User,
+        content: prompt.to_string(),
+    };
+    get_chat_completions_content(provider, model_name, &[message], &ModelSettings::default()).await
+}
+
+/// Sends a chat history to the model and returns the text of its response.
+///
+/// See [`get_chat_completion`] for how the messages are sent.
+pub async fn get_chat_completions_content(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+) -> Result<String, LlmError> {
+    get_chat_completion(provider, model_name, messages, settings)
+        .await
+        .map(|completion| completion.text)
+}
+
+/// Sends a chat history to the model and returns its response, with the tokens it used.
+///
+/// System messages are sent as the preamble, and the last message is sent as the prompt,
+/// with the ones before it as the chat history. The model in `settings`, if any, is used
+/// instead of `model_name`. Requests that fail with a transient error are retried
+/// according to the provider's [`RetryPolicy`].
+pub async fn get_chat_completion(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+) -> Result<Completion, LlmError> {
+    send_with_retries(provider, model_name, messages, settings, &[], &[]).await
+}
+
+/// Sends a chat history to the model with tools it can call, running the calls with
+/// `run_tool` and sending their results back until the model replies without calling any.
+///
+/// `run_tool` is called with every call of a response, and returns its result or `None`
+/// if it can't run it. The response is returned with its calls unrun if any of them can't
+/// be run, or after `max_steps` responses with calls. The returned usage is the one of
+/// every request.
+pub async fn get_chat_completion_with_tools(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    tools: &[ToolDefinition],
+    max_steps: usize,
+    mut run_tool: impl AsyncFnMut(&ToolCall) -> Option<String>,
+) -> Result<Completion, LlmError> {
+    let mut turns = Vec::new();
+    let mut usage = None;
+    let mut step = 0;
+    loop {
+        let mut completion =
+            send_with_retries(provider, model_name, messages, settings, tools, &turns).await?;
+        usage = add_usage(usage, completion.usage);
+        completion.usage = usage;
+        if completion.tool_calls.is_empty() || step == max_steps {
+            return Ok(completion);
+        }
+
+        let mut results = Vec::new();
+        for call in &completion.tool_calls {
+            match run_tool(call).await {
+                Some(output) => results.push(call.result(output)),
+                None => return Ok(completion),
+            }
+        }
+        step += 1;
+        debug!("Sending the results of {} tool call(s)", results.len());
+        let mut calls: Vec<AssistantContent> = completion
+            .tool_calls
+            .iter()
+            .map(ToolCall::to_content)
+            .collect();
+        if !completion.text.is_empty() {
+            calls.insert(0, AssistantContent::text(completion.text));
+        }
+        // Neither is empty, since the response called at least one tool
+        if let (Ok(calls), Ok(results)) = (OneOrMany::many(calls), OneOrMany::many(results)) {
+            turns.push(Message::Assistant {
+                id: None,
+                content: calls,
+            });
+            turns.push(Message::User { content: results });
+        }
+    }
+}
+
+async fn send_with_retries(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    tools: &[ToolDefinition],
+    turns: &[Message],
+) -> Result<Completion, LlmError> {
+    let model_name = settings.model.as_deref().unwrap_or(model_name);
+    with_model!(provider, model_name, |model| {
+        let mut attempt = 0;
+        loop {
+            let result = send_request(
+                &model, provider, model_name, messages, settings, tools, turns,
+            );
+            match result.await {
+                Err(e) if retry_after(provider.retry_policy(), &e, &mut attempt).await => {}
+                result => break result,
+            }
+        }
+    })
+}
+
+async fn send_request<M: CompletionModel>(
+    model: &M,
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    tools: &[ToolDefinition],
+    turns: &[Message],
+) -> Result<Completion, LlmError> {
+    let request = completion_request(
+        model, provider, model_name, messages, settings, tools, turns,
+    )?;
+    let response = timed(provider.retry_policy().timeout, request.send()).await??;
+
+    let mut completion = Completion {
+        text: String::new(),
+        usage: TokenUsage::reported(response.usage),
+        tool_calls: Vec::new(),
+    };
+    let mut has_text = false;
+    for content in response.choice.iter() {
+        match content {
+            AssistantContent::Text(text) => {
+                has_text = true;
+                completion.text.push_str(&text.text);
+            }
+            AssistantContent::ToolCall(call) => completion.tool_calls.push(call.into()),
+            _ => {}
+        }
+    }
+    if !has_text && completion.tool_calls.is_empty() {
+        return Err(LlmError::Request(CompletionError::ResponseError(
+            "Expected text or tool call response, but got reasoning".to_string(),
+        )));
+    }
+    Ok(completion)
+}
+
+/// Sends a chat history to the model like [`get_chat_completion`], streaming its response.
+///
+/// `on_text` is called with every chunk of text as it arrives, and the full response is
+/// returned once it's complete. A request is only retried if it fails before any text
+/// arrives, so the text passed to `on_text` is never repeated.
+pub async fn stream_chat_completion(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    mut on_text: impl FnMut(&str),
+) -> Result<Completion, LlmError> {
+    let model_name = settings.model.as_deref().unwrap_or(model_name);
+    with_model!(provider, model_name, |model| {
+        let mut completion = Completion {
+            text: String::new(),
+            usage: None,
+            tool_calls: Vec::new(),
+        };
+        let mut attempt = 0;
+        loop {
+            let result = stream_request(
+                &model,
+                provider,
+                model_name,
+                messages,
+                settings,
+                &mut completion,
+                &mut on_text,
+            )
+            .await;
+            match result {
+                Err(e)
+                    if completion.text.is_empty()
+                        && retry_after(provider.retry_policy(), &e, &mut attempt).await => {}
+                result => break result.map(|()| completion),
+            }
+        }
+    })
+}
+
+/// Streams the response of the model like [`stream_chat_completion`], returning only its
+/// text.
+pub async fn stream_chat_completions_content(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    on_text: impl FnMut(&str),
+) -> Result<String, LlmError> {
+    stream_chat_completion(provider, model_name, messages, settings, on_text)
+        .await
+        .map(|completion| completion.text)
+}
+
+async fn stream_request<M: CompletionModel>(
+    model: &M,
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    completion: &mut Completion,
+    on_text: &mut impl FnMut(&str),
+) -> Result<(), LlmError> {
+    let timeout = provider.retry_policy().timeout;
+    let request = completion_request(model, provider, model_name, messages, settings, &[], &[])?;
+    let mut stream = timed(timeout, request.stream()).await??;
+
+    while let Some(chunk) = timed(timeout, stream.next()).await? {
+        match chunk? {
+            StreamedAssistantContent::Text(text) => {
+                on_text(&text.text);
+                completion.text.push_str(&text.text);
+            }
+            StreamedAssistantContent::Final(response) => {
+                completion.usage = response.token_usage().and_then(TokenUsage::reported);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Waits for a future, failing with [`LlmError::Timeout`] if it takes longer than `timeout`.
+async fn timed<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = T>,
+) -> Result<T, LlmError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| LlmError::Timeout(timeout)),
+        None => Ok(future.await),
+    }
+}
+
+/// Decides whether to retry a request that failed with `error`, after `attempt` retries,
+/// and waits before the retry if so.
+async fn retry_after(retry: &RetryPolicy, error: &LlmError, attempt: &mut u32) -> bool {
+    if !error.is_transient() || *attempt >= retry.max_retries {
+        return false;
+    }
+    *attempt += 1;
+    let backoff = retry.backoff(*attempt);
+    warn!(
+        "{}. Retrying in {:.1}s ({}/{})",
+        error,
+        backoff.as_secs_f64(),
+        attempt,
+        retry.max_retries
+    );
+    tokio::time::sleep(backoff).await;
+    true
+}
+
+fn completion_request<M: CompletionModel>(
+    model: &M,
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    tools: &[ToolDefinition],
+    turns: &[Message],
+) -> Result<CompletionRequestBuilder<M>, CompletionError> {
+    debug!(
+        "Sending {} message(s) to '{}' at {} ({}) with {:?}",
+        messages.len() + turns.len(),
+        model_name,
+        provider.base_url(),
+        provider.provider(),
+        settings
+    );
+
+    let preamble = messages
+        .iter()
+        .filter(|message| message.role == Role::System)
+        .map(|message| message.content.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n\n");
+    let mut history: Vec<Message> = messages
+        .iter()
+        .filter_map(|message| match message.role {
+            Role::System => None,
+            Role::User => Some(Message::user(&message.content)),
+            Role::Assistant => Some(Message::assistant(&message.content)),
+        })
+        .collect();
+    history.extend_from_slice(turns);
+    let prompt = history.pop().ok_or_else(|| {
+        CompletionError::RequestError("Expected at least one user or assistant message".into())
+    })?;
+
+    let mut request = model
+        .completion_request(prompt)
+        .messages(history)
+        .temperature_opt(settings.temperature)
+        .max_tokens_opt(settings.max_tokens);
+    if !settings.stop.is_empty() {
+        // Anthropic and Gemini name stop sequences differently from OpenAI and Ollama
+        let stop = match provider.provider() {
+            Provider::Anthropic => json!({ "stop_sequences": settings.stop }),
+            Provider::Gemini => json!({ "generationConfig": { "stopSequences": settings.stop } }),
+            Provider::OpenAi | Provider::Ollama => json!({ "stop": settings.stop }),
+        };
+        request = request.additional_params(stop);
+    }
+    if !preamble.is_empty() {
+        request = request.preamble(preamble);
+    }
+    if !tools.is_empty() {
+        request = request.tools(tools.iter().map(Into::into).collect());
+    }
+    Ok(request)
+}
+
+/// Computes the embeddings of texts with an embedding model, in as few requests as the
+/// provider allows. Requests that fail with a transient error are retried according to the
+/// provider's [`RetryPolicy`].
+///
+/// # Returns
+///
+/// * `Ok(Vec<Vec<f64>>)` - The embedding of every text, in order.
+/// * `Err(LlmError)` - If a request fails, or the provider doesn't serve embeddings.
+pub async fn get_embeddings(
+    provider: &ProviderConfig,
+    model_name: &str,
+    texts: &[String],
+) -> Result<Vec<Vec<f64>>, LlmError> {
+    let base_url = provider.base_url();
+    let api_key = &provider.api_key;
+    let retry = provider.retry_policy();
+    match provider.provider {
+        Provider::OpenAi => {
+            let model = openai::Client::builder(api_key)
+                .base_url(base_url)
+                .build()
+                .map_err(client_error)?
+                .embedding_model(model_name);
+            embed(&model, retry, texts).await
+        }
+        Provider::Ollama => {
+            let model = ollama::Client::builder()
+                .base_url(base_url)
+                .build()
+                .map_err(client_error)?
+                .embedding_model(model_name);
+            embed(&model, retry, texts).await
+        }
+        Provider::Gemini => {
+            let model = gemini::Client::builder(api_key)
+                .base_url(base_url)
+                .build()
+                .map_err(client_error)?
+                .embedding_model(model_name);
+            embed(&model, retry, texts).await
+        }
+        Provider::Anthropic => Err(LlmError::Request(CompletionError::ProviderError(
+            "Anthropic doesn't serve embedding models".to_string(),
+        ))),
+    }
+}
+
+async fn embed<M: EmbeddingModel>(
+    model: &M,
+    retry: &RetryPolicy,
+    texts: &[String],
+) -> Result<Vec<Vec<f64>>, LlmError> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(M::MAX_DOCUMENTS) {
+        let mut attempt = 0;
+        let embeddings = loop {
+            let result = match timed(retry.timeout, model.embed_texts(batch.to_vec())).await {
+                Ok(result) => result.map_err(|e| LlmError::from(completion_error(e))),
+                Err(e) => Err(e),
+            };
+            match result {
+                Err(e) if retry_after(retry, &e, &mut attempt).await => {}
+                result => break result?,
+            }
+        };
+        vectors.extend(embeddings.into_iter().map(|embedding| embedding.vec));
+    }
+    Ok(vectors)
+}
+
+/// Converts the error of an embedding request, so it's classified like the errors of
+/// completion requests.
+fn completion_error(error: EmbeddingError) -> CompletionError {
+    match error {
+        EmbeddingError::HttpError(e) => CompletionError::HttpError(e),
+        EmbeddingError::JsonError(e) => CompletionError::JsonError(e),
+        EmbeddingError::UrlError(e) => CompletionError::UrlError(e),
+        EmbeddingError::DocumentError(e) => CompletionError::RequestError(e),
+        EmbeddingError::ResponseError(e) => CompletionError::ResponseError(e),
+        EmbeddingError::ProviderError(e) => CompletionError::ProviderError(e),
+    }
+}
+
+/// Sends a chat history to the model and validates its response against an output format.
+///
+/// When the response doesn't match the format, the model is asked to fix it, up to
+/// `max_retries` times, like [`get_completion_checked`] does.
+///
+/// # Returns
+///
+/// * `Ok(Completion)` - The valid (and possibly repaired) response, with the tokens used
+///   by every attempt.
+/// * `Err(LlmError)` - If the request fails or no valid response was produced.
+pub async fn get_completions_validated(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    format: &OutputFormat,
+    max_retries: usize,
+) -> Result<Completion, LlmError> {
+    get_completion_checked(
+        provider,
+        model_name,
+        messages,
+        settings,
+        |response| format.validate(response),
+        max_retries,
+    )
+    .await
+}
+
+/// Sends a chat history to the model and checks its response, e.g. against an output
+/// format or [`Guardrails`](crate::output::Guardrails).
+///
+/// When `check` rejects the response, the model is asked to fix it, up to `max_retries`
+/// times, with the rejected response and the reason it was rejected added to the chat
+/// history.
+///
+/// # Arguments
+///
+/// * `check` - Returns the response to use, possibly repaired, or why the response is
+///   rejected.
+///
+/// # Returns
+///
+/// * `Ok(Completion)` - The accepted response, with the tokens used by every attempt.
+/// * `Err(LlmError)` - If the request fails or every response was rejected.
+pub async fn get_completion_checked(
+    provider: &ProviderConfig,
+    model_name: &str,
+    messages: &[RenderedMessage],
+    settings: &ModelSettings,
+    check: impl Fn(&str) -> Result<String, String>,
+    max_retries: usize,
+) -> Result<Completion, LlmError> {
+    let mut messages = messages.to_vec();
+    let mut usage: Option<TokenUsage> = None;
+    let mut attempt = 0;
+    loop {
+        let completion = get_chat_completion(provider, model_name, &messages, settings).await?;
+        usage = add_usage(usage, completion.usage);
+        let response = completion.text;
+        let error = match check(&response) {
+            Ok(text) => {
+                return Ok(Completion {
+                    text,
+                    usage,
+                    tool_calls: Vec::new(),
+                });
+            }
+            Err(error) => error,
+        };
+
+        if attempt == max_retries {
+            return Err(LlmError::Request(CompletionError::ResponseError(format!(
+                "Response still invalid after {} attempts: {}",
+                attempt + 1,
+                error
+            ))));
+        }
+        attempt += 1;
+        debug!(
+            "Asking the model to fix its response ({}/{}): {}",
+            attempt, max_retries, error
+        );
+        messages.push(RenderedMessage {
+            role: Role::Assistant,
+            content: response,
+        });
+        messages.push(RenderedMessage {
+            role: Role::User,
+            content: format!(
+                "Your response isn't valid: {}. Reply again with only the corrected output.",
+                error
+            ),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_call_content() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            call_id: None,
+            name: "weather".to_string(),
+            arguments: json!({ "city": "Lisbon" }),
+        };
+        let AssistantContent::ToolCall(content) = call.to_content() else {
+            panic!("expected a tool call");
+        };
+        assert_eq!(call, ToolCall::from(&content));
+    }
+
+    #[test]
+    fn test_llm_error_classification() {
+        let error = |text: &str| LlmError::from(CompletionError::ProviderError(text.to_string()));
+        assert!(matches!(
+            error(r#"{"error":{"message":"Rate limit reached","code":"rate_limit_exceeded"}}"#),
+            LlmError::RateLimited(_)
+        ));
+        assert!(matches!(
+            error("<html>502 Bad Gateway</html>"),
+            LlmError::Server(_)
+        ));
+        assert!(matches!(error("Overloaded"), LlmError::Server(_)));
+        assert!(!error("Invalid API key").is_transient());
+        assert!(
+            !LlmError::from(CompletionError::ResponseError("empty".to_string())).is_transient()
+        );
+        assert!(LlmError::Timeout(Duration::from_secs(1)).is_transient());
+    }
+}
@@ -0,0 +1,171 @@
+//! # In-Memory Storage
+//!
+//! This module provides [`MemoryStorage`], a [`PromptStorage`] keeping its prompts in
+//! memory. It needs no file system, so templates can be validated and rendered where there
+//! is none, such as in a browser-based prompt editor compiled to WebAssembly.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::memory_storage::MemoryStorage;
+//! use pren_core::prompt::{Prompt, PromptMetadata, PromptTemplate};
+//! use std::collections::HashMap;
+//!
+//! let prompt = |name: &str, content: &str| {
+//!     Prompt::new(PromptMetadata::new(name.to_string(), None, vec![]), content.to_string())
+//! };
+//! let storage = MemoryStorage::from_prompts([
+//!     prompt("signature", "-- {{author}}"),
+//!     prompt("note", "Hi\n{{prompt:signature}}"),
+//! ]);
+//!
+//! let template = PromptTemplate::new(storage.prompt("note").unwrap()).unwrap();
+//! let arguments = HashMap::from([("author".to_string(), "Ana".to_string())]);
+//! assert_eq!("Hi\n-- Ana", template.render(&arguments, &storage).unwrap());
+//! ```
+
+use crate::prompt::Prompt;
+use crate::storage::PromptStorage;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MemoryStorageError {
+    #[error("prompt '{0}' couldn't be found")]
+    PromptNotFound(String),
+    #[error("prompt '{0}' already exists")]
+    PromptAlreadyExists(String),
+}
+
+/// A prompt storage keeping its prompts in memory, sorted by name.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    prompts: RwLock<BTreeMap<String, Prompt>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    /// Creates a storage with prompts. Prompts with the same name replace the earlier ones.
+    pub fn from_prompts(prompts: impl IntoIterator<Item = Prompt>) -> Self {
+        let prompts = prompts
+            .into_iter()
+            .map(|prompt| (prompt.metadata.name.clone(), prompt))
+            .collect();
+        MemoryStorage {
+            prompts: RwLock::new(prompts),
+        }
+    }
+
+    /// Returns a copy of a prompt, or `None` if there's no prompt with that name.
+    pub fn prompt(&self, name: &str) -> Option<Prompt> {
+        self.read().get(name).cloned()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Prompt>> {
+        self.prompts.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, Prompt>> {
+        self.prompts.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl PromptStorage for MemoryStorage {
+    type Error = MemoryStorageError;
+
+    fn save_prompt(&self, prompt: &Prompt) -> Result<(), Self::Error> {
+        self.write()
+            .insert(prompt.metadata.name.clone(), prompt.clone());
+        Ok(())
+    }
+
+    fn get_prompt(&self, name: &str) -> Result<Prompt, Self::Error> {
+        self.prompt(name)
+            .ok_or_else(|| MemoryStorageError::PromptNotFound(name.to_string()))
+    }
+
+    fn is_not_found(error: &Self::Error) -> bool {
+        matches!(error, MemoryStorageError::PromptNotFound(_))
+    }
+
+    fn get_prompts(&self) -> Result<Vec<Prompt>, Self::Error> {
+        Ok(self.read().values().cloned().collect())
+    }
+
+    fn get_prompts_by_tag(&self, tags: &[String]) -> Result<Vec<Prompt>, Self::Error> {
+        Ok(self
+            .read()
+            .values()
+            .filter(|prompt| prompt.metadata.tags.iter().any(|tag| tags.contains(tag)))
+            .cloned()
+            .collect())
+    }
+
+    fn delete_prompt(&self, name: &str) -> Result<(), Self::Error> {
+        self.write()
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| MemoryStorageError::PromptNotFound(name.to_string()))
+    }
+
+    fn rename_prompt(&self, old: &str, new: &str) -> Result<(), Self::Error> {
+        let mut prompts = self.write();
+        if prompts.contains_key(new) {
+            return Err(MemoryStorageError::PromptAlreadyExists(new.to_string()));
+        }
+        let mut prompt = prompts
+            .remove(old)
+            .ok_or_else(|| MemoryStorageError::PromptNotFound(old.to_string()))?;
+        prompt.metadata.name = new.to_string();
+        prompts.insert(new.to_string(), prompt);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptMetadata;
+
+    #[test]
+    fn test_memory_storage() {
+        let prompt = |name: &str, tags: &[&str]| {
+            Prompt::new(
+                PromptMetadata::new(
+                    name.to_string(),
+                    None,
+                    tags.iter().map(|tag| tag.to_string()).collect(),
+                ),
+                format!("{} content", name),
+            )
+        };
+        let storage = MemoryStorage::from_prompts([prompt("b", &["rust"]), prompt("a", &[])]);
+        storage.save_prompt(&prompt("c", &["rust"])).unwrap();
+
+        let names = |prompts: Vec<Prompt>| -> Vec<String> {
+            prompts.into_iter().map(|p| p.metadata.name).collect()
+        };
+        assert_eq!(vec!["a", "b", "c"], names(storage.get_prompts().unwrap()));
+        assert_eq!(
+            vec!["b", "c"],
+            names(storage.get_prompts_by_tag(&["rust".to_string()]).unwrap())
+        );
+
+        storage.rename_prompt("a", "d").unwrap();
+        assert_eq!("d", storage.get_prompt("d").unwrap().metadata.name);
+        assert_eq!(
+            Err(MemoryStorageError::PromptAlreadyExists("b".to_string())),
+            storage.rename_prompt("c", "b")
+        );
+
+        storage.delete_prompt("d").unwrap();
+        assert_eq!(
+            MemoryStorageError::PromptNotFound("d".to_string()),
+            storage.get_prompt("d").unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! # Chat Messages
+//!
+//! This module provides support for chat prompts made of several messages with roles.
+//!
+//! A prompt becomes a chat prompt when its content has role markers: lines holding only
+//! `<|system|>`, `<|user|>` or `<|assistant|>`. Each marker starts a message with that role,
+//! which lasts until the next marker. Text before the first marker, or in a prompt without
+//! markers, is sent as a user message.
+//!
+//! Markers are split after rendering, so referenced prompts can contribute their own messages.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::messages::{Role, split_messages};
+//!
+//! let messages = split_messages("<|system|>\nYou are terse.\n<|user|>\nHi!");
+//! assert_eq!(messages.len(), 2);
+//! assert_eq!(messages[0].role, Role::System);
+//! assert_eq!(messages[1].content, "Hi!");
+//! ```
+//!
+//! Rendered prompts can also be turned into a [`ChatRequest`], the body of a request to any
+//! OpenAI-compatible chat completions API.
+
+use crate::llm::ModelSettings;
+use serde::{Deserialize, Serialize};
+
+/// The role of the author of a chat message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// Gets the role of a role marker line (e.g., `<|system|>`), if the line is one.
+    fn from_marker(line: &str) -> Option<Role> {
+        match line.trim() {
+            "<|system|>" => Some(Role::System),
+            "<|user|>" => Some(Role::User),
+            "<|assistant|>" => Some(Role::Assistant),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::System => write!(f, "system"),
+            Role::User => write!(f, "user"),
+            Role::Assistant => write!(f, "assistant"),
+        }
+    }
+}
+
+/// A rendered chat message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderedMessage {
+    /// The role of the message author.
+    pub role: Role,
+    /// The text of the message.
+    pub content: String,
+}
+
+/// The body of an OpenAI-compatible chat completion request: the messages of a rendered
+/// prompt along with its preferred model settings.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChatRequest {
+    /// The `model`, `temperature`, `max_tokens` and `stop` fields, when set.
+    #[serde(flatten)]
+    pub settings: ModelSettings,
+    /// The messages, split by their role markers.
+    pub messages: Vec<RenderedMessage>,
+}
+
+impl ChatRequest {
+    /// Creates the request for a rendered prompt, splitting it into messages.
+    pub fn from_rendered(text: &str, settings: &ModelSettings) -> ChatRequest {
+        ChatRequest {
+            settings: settings.clone(),
+            messages: split_messages(text),
+        }
+    }
+}
+
+/// Checks whether the text has any role markers.
+pub fn has_role_markers(text: &str) -> bool {
+    text.lines().any(|line| Role::from_marker(line).is_some())
+}
+
+/// Splits a rendered prompt into chat messages using its role markers.
+///
+/// Messages are trimmed, and messages left empty are skipped.
+///
+/// # Arguments
+///
+/// * `text` - The rendered prompt.
+///
+/// # Returns
+///
+/// * `Vec<RenderedMessage>` - The messages, in order.
+pub fn split_messages(text: &str) -> Vec<RenderedMessage> {
+    let mut messages = Vec::new();
+    let mut role = Role::User;
+    let mut content = String::new();
+
+    for line in text.split_inclusive('\n') {
+        match Role::from_marker(line) {
+            Some(next_role) => {
+                push_message(&mut messages, role, &content);
+                role = next_role;
+                content.clear();
+            }
+            None => content.push_str(line),
+        }
+    }
+    push_message(&mut messages, role, &content);
+
+    messages
+}
+
+/// Joins chat messages into a prompt with role markers, the reverse of [`split_messages`].
+///
+/// # Arguments
+///
+/// * `messages` - The messages, in order.
+///
+/// # Returns
+///
+/// * `String` - The text, with each message after the marker of its role.
+pub fn join_messages(messages: &[RenderedMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| format!("<|{}|>\n{}\n", message.role, message.content))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn push_message(messages: &mut Vec<RenderedMessage>, role: Role, content: &str) {
+    let content = content.trim();
+    if !content.is_empty() {
+        messages.push(RenderedMessage {
+            role,
+            content: content.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_messages_without_markers() {
+        let messages = split_messages("Hello!\nHow are you?");
+        assert_eq!(
+            messages,
+            vec![RenderedMessage {
+                role: Role::User,
+                content: "Hello!\nHow are you?".to_string(),
+            }]
+        );
+        assert!(!has_role_markers("Hello!"));
+    }
+
+    #[test]
+    fn test_split_messages_with_markers() {
+        let text =
+            "<|system|>\nYou are terse.\n\n<|user|>\nHi!\n<|assistant|>\nHello.\n<|user|>\nBye!\n";
+        let messages = split_messages(text);
+        assert!(has_role_markers(text));
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| (message.role, message.content.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Role::System, "You are terse."),
+                (Role::User, "Hi!"),
+                (Role::Assistant, "Hello."),
+                (Role::User, "Bye!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_messages() {
+        let messages = split_messages("<|system|>\nYou are terse.\n<|user|>\nHi!\n\nBye!");
+        let text = join_messages(&messages);
+        assert_eq!(
+            "<|system|>\nYou are terse.\n\n<|user|>\nHi!\n\nBye!\n",
+            text
+        );
+        assert_eq!(messages, split_messages(&text));
+    }
+
+    #[test]
+    fn test_chat_request() {
+        let settings = ModelSettings {
+            model: Some("gpt-4o-mini".to_string()),
+            temperature: Some(0.2),
+            ..ModelSettings::default()
+        };
+        let request = ChatRequest::from_rendered("<|system|>\nBe terse.\n<|user|>\nHi!", &settings);
+        assert_eq!(
+            serde_json::json!({
+                "model": "gpt-4o-mini",
+                "temperature": 0.2,
+                "messages": [
+                    {"role": "system", "content": "Be terse."},
+                    {"role": "user", "content": "Hi!"},
+                ],
+            }),
+            serde_json::to_value(&request).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_split_messages_with_text_before_first_marker() {
+        let messages = split_messages("Context first\n<|assistant|>\nOk");
+        assert_eq!(Role::User, messages[0].role);
+        assert_eq!("Context first", messages[0].content);
+        assert_eq!(Role::Assistant, messages[1].role);
+    }
+
+    #[test]
+    fn test_split_messages_ignores_inline_markers_and_empty_messages() {
+        let messages = split_messages("<|system|>\n<|user|>\nType <|user|> to start");
+        assert_eq!(
+            messages,
+            vec![RenderedMessage {
+                role: Role::User,
+                content: "Type <|user|> to start".to_string(),
+            }]
+        );
+    }
+}
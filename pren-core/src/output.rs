@@ -0,0 +1,345 @@
+//! # Output Formats
+//!
+//! This module provides the output contracts that prompts can declare, so that model
+//! responses can be validated (and, when possible, repaired) before they're used.
+//!
+//! A prompt declares its expected output in its frontmatter, either as JSON (optionally
+//! matching a schema) or as text matching a regular expression:
+//!
+//! ```yaml
+//! output_format:
+//!   type: json
+//!   schema:
+//!     type: object
+//!     required: [title]
+//! ```
+//!
+//! Schemas support a subset of JSON Schema: `type`, `properties`, `required`, `items` and `enum`.
+//!
+//! Prompts can also declare [`Guardrails`], further checks responses must pass whatever their
+//! format, along with how many attempts the model gets to pass them:
+//!
+//! ```yaml
+//! guardrails:
+//!   max_length: 280
+//!   banned_phrases: [As an AI]
+//!   max_attempts: 4
+//! ```
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::output::OutputFormat;
+//!
+//! let format = OutputFormat::Regex {
+//!     pattern: "^(yes|no)$".to_string(),
+//! };
+//! assert!(format.validate("yes").is_ok());
+//! assert!(format.validate("maybe").is_err());
+//! ```
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The output format expected from a model when generating with a prompt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// A JSON value, matching the schema if one is given.
+    Json {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        schema: Option<Value>,
+    },
+    /// Text matching a regular expression.
+    Regex { pattern: String },
+}
+
+impl OutputFormat {
+    /// Validates a model response against the format.
+    ///
+    /// JSON responses wrapped in a markdown code fence are repaired by removing the fence.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The valid (and possibly repaired) output.
+    /// * `Err(String)` - A description of why the output doesn't match the format.
+    pub fn validate(&self, output: &str) -> Result<String, String> {
+        match self {
+            OutputFormat::Json { schema } => {
+                let output = strip_code_fence(output);
+                let value: Value = serde_json::from_str(output)
+                    .map_err(|e| format!("the output isn't valid JSON: {}", e))?;
+                if let Some(schema) = schema {
+                    validate_schema(&value, schema, "$")?;
+                }
+                Ok(output.to_string())
+            }
+            OutputFormat::Regex { pattern } => {
+                let regex = Regex::new(pattern)
+                    .map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+                let output = output.trim();
+                if regex.is_match(output) {
+                    Ok(output.to_string())
+                } else {
+                    Err(format!(
+                        "the output doesn't match the pattern '{}'",
+                        pattern
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Checks a model response must pass when generating with a prompt.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Guardrails {
+    /// The maximum number of characters of the response, ignoring surrounding whitespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// Phrases the response must not contain, matched regardless of case.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub banned_phrases: Vec<String>,
+    /// A regular expression the response must match somewhere.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub must_match: Option<String>,
+    /// Whether the response must be valid JSON, possibly in a markdown code fence.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub json: bool,
+    /// How many responses the model may give in total before generating fails, including
+    /// the first one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<usize>,
+}
+
+impl Guardrails {
+    /// Checks a model response against the guardrails.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the response passes every check.
+    /// * `Err(String)` - A description of the first check the response fails.
+    pub fn validate(&self, output: &str) -> Result<(), String> {
+        let output = output.trim();
+        if let Some(max_length) = self.max_length {
+            let length = output.chars().count();
+            if length > max_length {
+                return Err(format!(
+                    "the output is {} characters long, more than the maximum of {}",
+                    length, max_length
+                ));
+            }
+        }
+        let lowercase = output.to_lowercase();
+        if let Some(phrase) = self
+            .banned_phrases
+            .iter()
+            .find(|phrase| lowercase.contains(&phrase.to_lowercase()))
+        {
+            return Err(format!(
+                "the output contains the banned phrase '{}'",
+                phrase
+            ));
+        }
+        if let Some(pattern) = &self.must_match {
+            let regex =
+                Regex::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+            if !regex.is_match(output) {
+                return Err(format!(
+                    "the output doesn't match the pattern '{}'",
+                    pattern
+                ));
+            }
+        }
+        if self.json {
+            serde_json::from_str::<Value>(strip_code_fence(output))
+                .map_err(|e| format!("the output isn't valid JSON: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes a markdown code fence (e.g. ```` ```json ````) around the output, if there's one.
+fn strip_code_fence(output: &str) -> &str {
+    let trimmed = output.trim();
+    match trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+    {
+        // Skip the language tag on the opening line
+        Some(fenced) => fenced
+            .split_once('\n')
+            .map_or(fenced, |(_, body)| body)
+            .trim(),
+        None => trimmed,
+    }
+}
+
+/// Validates a value against the supported subset of JSON Schema.
+fn validate_schema(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected_type) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected_type {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            other => return Err(format!("unsupported type '{}' in schema", other)),
+        };
+        if !matches {
+            return Err(format!("{} should be of type '{}'", path, expected_type));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return Err(format!(
+            "{} should be one of {}",
+            path,
+            Value::from(allowed.clone())
+        ));
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    return Err(format!(
+                        "{} is missing the required property '{}'",
+                        path, key
+                    ));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, property_schema) in properties {
+                if let Some(property) = object.get(key) {
+                    validate_schema(property, property_schema, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            validate_schema(item, item_schema, &format!("{}[{}]", path, index))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_json() {
+        let format = OutputFormat::Json { schema: None };
+        assert_eq!(
+            Ok("{\"a\": 1}".to_string()),
+            format.validate(" {\"a\": 1} ")
+        );
+        assert!(format.validate("not json").is_err());
+    }
+
+    #[test]
+    fn test_validate_json_repairs_code_fences() {
+        let format = OutputFormat::Json { schema: None };
+        assert_eq!(
+            Ok("{\"a\": 1}".to_string()),
+            format.validate("```json\n{\"a\": 1}\n```")
+        );
+        assert_eq!(Ok("[1]".to_string()), format.validate("```\n[1]\n```"));
+    }
+
+    #[test]
+    fn test_validate_json_schema() {
+        let format = OutputFormat::Json {
+            schema: Some(json!({
+                "type": "object",
+                "required": ["title", "tags"],
+                "properties": {
+                    "title": {"type": "string"},
+                    "tags": {"type": "array", "items": {"type": "string"}},
+                    "level": {"enum": ["low", "high"]}
+                }
+            })),
+        };
+        assert!(format.validate(r#"{"title": "a", "tags": ["x"]}"#).is_ok());
+
+        let error = format.validate(r#"{"title": "a"}"#).unwrap_err();
+        assert!(error.contains("'tags'"), "Unexpected error: {}", error);
+
+        let error = format.validate(r#"{"title": 1, "tags": []}"#).unwrap_err();
+        assert!(error.contains("$.title"), "Unexpected error: {}", error);
+
+        let error = format
+            .validate(r#"{"title": "a", "tags": [1]}"#)
+            .unwrap_err();
+        assert!(error.contains("$.tags[0]"), "Unexpected error: {}", error);
+
+        let error = format
+            .validate(r#"{"title": "a", "tags": [], "level": "mid"}"#)
+            .unwrap_err();
+        assert!(error.contains("$.level"), "Unexpected error: {}", error);
+    }
+
+    #[test]
+    fn test_validate_regex() {
+        let format = OutputFormat::Regex {
+            pattern: "^(yes|no)$".to_string(),
+        };
+        assert_eq!(Ok("yes".to_string()), format.validate("yes\n"));
+        assert!(format.validate("maybe").is_err());
+
+        let invalid = OutputFormat::Regex {
+            pattern: "(".to_string(),
+        };
+        assert!(invalid.validate("anything").is_err());
+    }
+
+    #[test]
+    fn test_guardrails() {
+        let guardrails = Guardrails {
+            max_length: Some(20),
+            banned_phrases: vec!["As an AI".to_string()],
+            must_match: Some(r"\d+".to_string()),
+            json: true,
+            max_attempts: None,
+        };
+        assert_eq!(Ok(()), guardrails.validate("```json\n[1, 2]\n```"));
+
+        let error = guardrails.validate(&"1".repeat(21)).unwrap_err();
+        assert!(
+            error.contains("21 characters"),
+            "Unexpected error: {}",
+            error
+        );
+
+        let error = guardrails.validate("\"as an ai, 42\"").unwrap_err();
+        assert!(error.contains("'As an AI'"), "Unexpected error: {}", error);
+
+        let error = guardrails.validate("[true]").unwrap_err();
+        assert!(error.contains("pattern"), "Unexpected error: {}", error);
+
+        let error = guardrails.validate("42 apples").unwrap_err();
+        assert!(error.contains("JSON"), "Unexpected error: {}", error);
+    }
+
+    #[test]
+    fn test_deserialize_output_format() {
+        let format: OutputFormat =
+            serde_json::from_value(json!({"type": "regex", "pattern": "^ok$"})).unwrap();
+        assert_eq!(
+            OutputFormat::Regex {
+                pattern: "^ok$".to_string()
+            },
+            format
+        );
+    }
+}
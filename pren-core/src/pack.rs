@@ -0,0 +1,439 @@
+//! # Prompt Packs
+//!
+//! This module provides support for prompt packs: shareable collections of prompts
+//! described by a manifest.
+//!
+//! A pack is a directory with a `pack.json` manifest and the pack's prompts, stored as
+//! markdown files with YAML frontmatter (the same format used by [`FileStorage`]):
+//!
+//! ```json
+//! {
+//!   "name": "code-review",
+//!   "version": "1.0.0",
+//!   "author": "Ana",
+//!   "license": "MIT",
+//!   "prompts": ["review", "review-style"]
+//! }
+//! ```
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use pren_core::file_storage::FileStorage;
+//! use pren_core::pack::PromptPack;
+//! use std::path::{Path, PathBuf};
+//!
+//! let pack = PromptPack::load(Path::new("./code-review")).expect("Failed to load pack");
+//! pack.validate().expect("Invalid pack");
+//!
+//! let storage = FileStorage {
+//!     base_path: PathBuf::from("./prompts"),
+//!     fallback_paths: Vec::new(),
+//!     vault: false,
+//! };
+//! pack.install(&storage, false).expect("Failed to install pack");
+//! ```
+
+use crate::file_storage::{FileStorage, parse_prompt_file};
+use crate::parser::is_valid_prompt_name;
+use crate::prompt::{Prompt, PromptTemplate};
+use crate::storage::PromptStorage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+/// The name of the manifest file in a pack directory.
+pub const MANIFEST_FILE_NAME: &str = "pack.json";
+
+#[derive(Error, Debug)]
+pub enum PackError {
+    #[error("i/o Error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("invalid manifest: {0}")]
+    ManifestError(#[from] serde_json::Error),
+    #[error("storage Error: {0}")]
+    StorageError(String),
+    #[error("invalid pack: {0}")]
+    InvalidPack(String),
+    #[error("prompt '{0}' already exists")]
+    PromptExists(String),
+}
+
+/// The manifest describing a prompt pack.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackManifest {
+    /// The name of the pack.
+    pub name: String,
+    /// The version of the pack.
+    pub version: String,
+    /// A brief description of the pack.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The author of the pack.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// The license of the pack's prompts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// The names of the prompts in the pack.
+    pub prompts: Vec<String>,
+}
+
+/// A prompt pack, with its manifest and prompts.
+#[derive(Debug, Clone)]
+pub struct PromptPack {
+    pub manifest: PackManifest,
+    pub prompts: Vec<Prompt>,
+}
+
+impl PromptPack {
+    /// Loads a pack from a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The pack directory, holding the manifest and the prompt files.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(PromptPack)` - The loaded pack.
+    /// * `Err(PackError)` - If the manifest can't be read or a listed prompt can't be loaded.
+    pub fn load(path: &Path) -> Result<PromptPack, PackError> {
+        let manifest: PackManifest =
+            serde_json::from_str(&fs::read_to_string(path.join(MANIFEST_FILE_NAME))?)?;
+
+        let pack_storage = FileStorage {
+            base_path: path.to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        let prompts = manifest
+            .prompts
+            .iter()
+            .map(|name| {
+                pack_storage
+                    .get_prompt(name)
+                    .map_err(|e| PackError::StorageError(format!("prompt '{}': {}", name, e)))
+            })
+            .collect::<Result<Vec<Prompt>, PackError>>()?;
+
+        Ok(PromptPack { manifest, prompts })
+    }
+
+    /// Parses a pack from the contents of its manifest and prompt files, for packs that
+    /// don't live in a directory (e.g. packs embedded in a binary).
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The JSON manifest of the pack.
+    /// * `prompt_files` - The contents of the pack's prompt files, in any order.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(PromptPack)` - The parsed pack, with its prompts in the order of the manifest.
+    /// * `Err(PackError)` - If the manifest or a prompt file can't be parsed, or a listed
+    ///   prompt is missing.
+    pub fn parse(manifest: &str, prompt_files: &[&str]) -> Result<PromptPack, PackError> {
+        PromptPack::from_files(serde_json::from_str(manifest)?, prompt_files)
+    }
+
+    /// Parses a pack from its manifest and the contents of its prompt files, like
+    /// [`PromptPack::parse`].
+    pub(crate) fn from_files(
+        manifest: PackManifest,
+        prompt_files: &[&str],
+    ) -> Result<PromptPack, PackError> {
+        let mut parsed = prompt_files
+            .iter()
+            .map(|content| {
+                parse_prompt_file(content).map_err(|e| PackError::StorageError(e.to_string()))
+            })
+            .collect::<Result<Vec<Prompt>, PackError>>()?;
+
+        let prompts = manifest
+            .prompts
+            .iter()
+            .map(|name| {
+                let index = parsed
+                    .iter()
+                    .position(|prompt| &prompt.metadata.name == name)
+                    .ok_or_else(|| {
+                        PackError::StorageError(format!("prompt '{}' couldn't be found", name))
+                    })?;
+                Ok(parsed.swap_remove(index))
+            })
+            .collect::<Result<Vec<Prompt>, PackError>>()?;
+
+        Ok(PromptPack { manifest, prompts })
+    }
+
+    /// Checks that the pack is well-formed.
+    ///
+    /// The manifest must have a name and a version, prompt names must be valid (see
+    /// [`is_valid_prompt_name`]), prompts can't be listed twice, every prompt must be a
+    /// valid template, and prompts can only reference prompts in the same pack.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the pack is valid.
+    /// * `Err(PackError::InvalidPack)` - With the first problem found.
+    pub fn validate(&self) -> Result<(), PackError> {
+        if self.manifest.name.trim().is_empty() {
+            return Err(PackError::InvalidPack("missing pack name".to_string()));
+        }
+        if self.manifest.version.trim().is_empty() {
+            return Err(PackError::InvalidPack("missing pack version".to_string()));
+        }
+
+        self.check_names()?;
+        let mut names = HashSet::new();
+        for prompt in &self.prompts {
+            if !names.insert(prompt.metadata.name.as_str()) {
+                return Err(PackError::InvalidPack(format!(
+                    "prompt '{}' is listed more than once",
+                    prompt.metadata.name
+                )));
+            }
+        }
+
+        for prompt in &self.prompts {
+            let template = PromptTemplate::new(prompt.clone()).map_err(|e| {
+                PackError::InvalidPack(format!("prompt '{}': {}", prompt.metadata.name, e))
+            })?;
+            if let Some(reference) = template
+                .prompt_references()
+                .into_iter()
+                .find(|reference| !names.contains(reference.as_str()))
+            {
+                return Err(PackError::InvalidPack(format!(
+                    "prompt '{}' references '{}', which isn't part of the pack",
+                    prompt.metadata.name, reference
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a prompt's name can't be used in prompt references, which also keeps
+    /// packs from writing files outside the storage they're installed into.
+    fn check_names(&self) -> Result<(), PackError> {
+        match self
+            .prompts
+            .iter()
+            .find(|prompt| !is_valid_prompt_name(&prompt.metadata.name))
+        {
+            Some(prompt) => Err(PackError::InvalidPack(format!(
+                "invalid prompt name '{}'",
+                prompt.metadata.name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Installs the pack's prompts into a storage.
+    ///
+    /// Prompts without an author or a license get the ones of the pack, so their provenance
+    /// is kept after installing them.
+    /// Nothing is installed if any prompt has an invalid name, or if any prompt already
+    /// exists and `overwrite` is false.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage to install the prompts into.
+    /// * `overwrite` - Whether existing prompts with the same names are replaced.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<String>)` - The names of the installed prompts.
+    /// * `Err(PackError)` - If a prompt has an invalid name, already exists or can't be
+    ///   saved.
+    pub fn install<S: PromptStorage>(
+        &self,
+        storage: &S,
+        overwrite: bool,
+    ) -> Result<Vec<String>, PackError> {
+        self.check_names()?;
+        if !overwrite
+            && let Some(prompt) = self
+                .prompts
+                .iter()
+                .find(|prompt| storage.get_prompt(&prompt.metadata.name).is_ok())
+        {
+            return Err(PackError::PromptExists(prompt.metadata.name.clone()));
+        }
+
+        for prompt in &self.prompts {
+            let mut prompt = prompt.clone();
+            if prompt.metadata.author.is_none() {
+                prompt.metadata.author = self.manifest.author.clone();
+            }
+            if prompt.metadata.license.is_none() {
+                prompt.metadata.license = self.manifest.license.clone();
+            }
+            storage
+                .save_prompt(&prompt)
+                .map_err(|e| PackError::StorageError(e.to_string()))?;
+        }
+        Ok(self
+            .prompts
+            .iter()
+            .map(|prompt| prompt.metadata.name.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptMetadata;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn write_pack(dir: &Path, manifest: &str, prompts: &[(&str, &str)]) {
+        fs::write(dir.join(MANIFEST_FILE_NAME), manifest).unwrap();
+        let storage = FileStorage {
+            base_path: dir.to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        for (name, content) in prompts {
+            storage
+                .save_prompt(&Prompt::new(
+                    PromptMetadata::new(name.to_string(), None, vec![]),
+                    content.to_string(),
+                ))
+                .unwrap();
+        }
+    }
+
+    const MANIFEST: &str = r#"{
+        "name": "code-review",
+        "version": "1.0.0",
+        "author": "Ana",
+        "license": "MIT",
+        "prompts": ["review", "review-style"]
+    }"#;
+
+    #[test]
+    fn test_load_and_validate_pack() {
+        let pack_dir = TempDir::new().unwrap();
+        write_pack(
+            pack_dir.path(),
+            MANIFEST,
+            &[
+                ("review", "Review {{code}}. {{prompt:review-style}}"),
+                ("review-style", "Be concise."),
+            ],
+        );
+
+        let pack = PromptPack::load(pack_dir.path()).unwrap();
+        assert_eq!("code-review", pack.manifest.name);
+        assert_eq!(Some("MIT".to_string()), pack.manifest.license);
+        assert_eq!(2, pack.prompts.len());
+        assert!(pack.validate().is_ok());
+    }
+
+    #[test]
+    fn test_load_pack_with_missing_prompt() {
+        let pack_dir = TempDir::new().unwrap();
+        write_pack(pack_dir.path(), MANIFEST, &[("review", "Review {{code}}.")]);
+
+        let result = PromptPack::load(pack_dir.path());
+        assert!(matches!(result, Err(PackError::StorageError(_))));
+    }
+
+    #[test]
+    fn test_parse_pack() {
+        let review = "---\nname: review\ndescription: null\ntags: []\n---\nReview {{code}}.";
+        let style = "---\nname: review-style\ndescription: null\ntags: []\n---\nBe concise.";
+
+        let pack = PromptPack::parse(MANIFEST, &[style, review]).unwrap();
+        assert_eq!(
+            vec!["review", "review-style"],
+            pack.prompts
+                .iter()
+                .map(|prompt| prompt.metadata.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(pack.validate().is_ok());
+
+        let result = PromptPack::parse(MANIFEST, &[review]);
+        assert!(
+            matches!(result, Err(PackError::StorageError(message)) if message.contains("review-style"))
+        );
+    }
+
+    #[test]
+    fn test_validate_pack_with_external_reference() {
+        let pack_dir = TempDir::new().unwrap();
+        write_pack(
+            pack_dir.path(),
+            MANIFEST,
+            &[
+                ("review", "{{prompt:elsewhere}}"),
+                ("review-style", "Be concise."),
+            ],
+        );
+
+        let pack = PromptPack::load(pack_dir.path()).unwrap();
+        let result = pack.validate();
+        assert!(
+            matches!(result, Err(PackError::InvalidPack(message)) if message.contains("elsewhere"))
+        );
+    }
+
+    #[test]
+    fn test_pack_with_path_as_prompt_name() {
+        let manifest = r#"{"name": "evil", "version": "1.0.0", "prompts": ["../../x"]}"#;
+        let prompt = "---\nname: ../../x\ndescription: null\ntags: []\n---\nGotcha.";
+        let pack = PromptPack::parse(manifest, &[prompt]).unwrap();
+
+        assert!(
+            matches!(pack.validate(), Err(PackError::InvalidPack(message)) if message.contains("../../x"))
+        );
+        let storage_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: storage_dir.path().join("a/b"),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        assert!(matches!(
+            pack.install(&storage, false),
+            Err(PackError::InvalidPack(_))
+        ));
+        assert!(!storage_dir.path().join("x.md").exists());
+    }
+
+    #[test]
+    fn test_install_pack() {
+        let pack_dir = TempDir::new().unwrap();
+        write_pack(
+            pack_dir.path(),
+            MANIFEST,
+            &[
+                ("review", "Review {{code}}."),
+                ("review-style", "Be concise."),
+            ],
+        );
+        let pack = PromptPack::load(pack_dir.path()).unwrap();
+
+        let storage_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: PathBuf::from(storage_dir.path()),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        let installed = pack.install(&storage, false).unwrap();
+        assert_eq!(vec!["review", "review-style"], installed);
+        let installed_prompt = storage.get_prompt("review-style").unwrap();
+        assert_eq!("Be concise.", installed_prompt.content);
+        assert_eq!(Some("Ana".to_string()), installed_prompt.metadata.author);
+        assert_eq!(Some("MIT".to_string()), installed_prompt.metadata.license);
+
+        // Installing again fails unless overwriting
+        let result = pack.install(&storage, false);
+        assert!(matches!(result, Err(PackError::PromptExists(name)) if name == "review"));
+        assert!(pack.install(&storage, true).is_ok());
+    }
+}
@@ -3,9 +3,18 @@
 //! This module provides parsing functionality for prompt templates.
 //!
 //! The parser handles template syntax with the following features:
-//! - Arguments: `{{variable_name}}`
-//! - Prompt references: `{{prompt:prompt_name}}`
-//! - Escaped literals: `{{{{literal_text}}}}`
+//! - Arguments: `{{variable_name}}`, or `{{user.name}}` to access nested values
+//! - Prompt references: `{{prompt:prompt_name}}`, or `{{prompt:reviews.v2}}` for dotted names
+//! - Function calls: `{{fn:name(argument, "literal")}}`
+//! - Few-shot examples: `{{examples:tag=review,count=3,order=random}}`
+//! - All prompts with a tag: `{{prompts:tag=guidelines,sep=\n\n}}`
+//! - Optional prompt references: `{{prompt_opt:prompt_name}}`, dropped first when over a token budget
+//! - Whitespace around names is ignored: `{{ name }}` is the same as `{{name}}`
+//! - Escaped literals: `{{{{literal_text}}}}`, or `\{{` for a single pair of braces
+//!
+//! Braces that don't form a valid tag, like a `{{` that is never closed or the
+//! `{{#if done}}` of a Handlebars snippet, are kept as literal text, so content with stray
+//! braces doesn't need to be escaped.
 //!
 //! # Examples
 //!
@@ -17,14 +26,18 @@
 //! assert!(result.is_ok());
 //! ```
 
-use crate::prompt::PromptTemplatePart;
+use crate::prompt::{
+    ExamplesDirective, FunctionArgument, FunctionCall, PromptTemplatePart, TaggedPromptsDirective,
+};
 use nom::IResult;
 use nom::Parser;
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_until, take_while_m_n};
-use nom::combinator::{all_consuming, map, rest, verify};
-use nom::multi::many0;
-use nom::sequence::delimited;
+use nom::bytes::complete::{tag, take_until, take_while_m_n, take_while1};
+use nom::character::complete::{char, multispace0};
+use nom::combinator::{all_consuming, map, map_res, recognize};
+use nom::error::{Error, ErrorKind};
+use nom::multi::{many0, separated_list0, separated_list1};
+use nom::sequence::{delimited, preceded, separated_pair};
 
 /// Parses a template string into a Vec<PromptTemplatePart>.
 ///
@@ -45,27 +58,48 @@ pub fn parse_element(input: &str) -> IResult<&str, PromptTemplatePart> {
         map(parse_escaped_literal, |text| {
             PromptTemplatePart::Literal(text.to_string())
         }),
+        map(parse_escaped_braces, |text| {
+            PromptTemplatePart::Literal(text.to_string())
+        }),
         map(parse_variable_prompt_reference, |text| {
             PromptTemplatePart::VariablePromptReference(text.to_string())
         }),
+        map(parse_optional_prompt_reference, |name| {
+            PromptTemplatePart::OptionalPromptReference(name.to_string())
+        }),
         map(parse_prompt_reference, |name| {
             PromptTemplatePart::PromptReference(name.to_string())
         }),
+        map(parse_function_call, PromptTemplatePart::Function),
+        map(parse_examples, PromptTemplatePart::Examples),
+        map(parse_tagged_prompts, PromptTemplatePart::TaggedPrompts),
         map(parse_argument, |name| {
             PromptTemplatePart::Argument(name.to_string())
         }),
         map(parse_literal_text, |text| {
             PromptTemplatePart::Literal(text.to_string())
         }),
+        map(parse_stray_braces, |text| {
+            PromptTemplatePart::Literal(text.to_string())
+        }),
     ))
     .parse(input)
 }
 
+/// Parses literal text up to the next `{{` (or `\{{`), or until the end of the input.
 pub fn parse_literal_text(input: &str) -> IResult<&str, &str> {
-    verify(alt((take_until("{{"), rest)), |s: &&str| !s.is_empty()).parse(input)
+    let end = match input.find("{{") {
+        Some(pos) if input[..pos].ends_with('\\') => pos - 1,
+        Some(pos) => pos,
+        None => input.len(),
+    };
+    if end == 0 {
+        return Err(nom::Err::Error(Error::new(input, ErrorKind::TakeUntil)));
+    }
+    Ok((&input[end..], &input[..end]))
 }
 
-/// Parses an argument placeholder (e.g., `{{name}}`).
+/// Parses backslash-escaped braces (e.g., `\{{`), which render as `{{`.
 ///
 /// # Arguments
 ///
@@ -73,10 +107,45 @@ pub fn parse_literal_text(input: &str) -> IResult<&str, &str> {
 ///
 /// # Returns
 ///
-/// * `Ok((remaining, name))` - The parsed argument name.
+/// * `Ok((remaining, text))` - The literal braces.
+/// * `Err` - If parsing fails.
+pub fn parse_escaped_braces(input: &str) -> IResult<&str, &str> {
+    map(tag("\\{{"), |_| "{{").parse(input)
+}
+
+/// Parses an opening `{{` that doesn't start a valid tag, so it can be kept as literal text
+/// along with what follows it, e.g. in `{{#if done}}` or in a `{{` that is never closed.
+///
+/// It's tried after every tag, so any `{{` left matches. Of three or more braces, only the
+/// first one is taken, so the rest can still start a tag, as in `{{{name}}}`.
+///
+/// # Arguments
+///
+/// * `input` - The input string to parse.
+///
+/// # Returns
+///
+/// * `Ok((remaining, text))` - The stray braces.
+/// * `Err` - If the input doesn't start with `{{`.
+pub fn parse_stray_braces(input: &str) -> IResult<&str, &str> {
+    if input.starts_with("{{{") {
+        return tag("{").parse(input);
+    }
+    tag("{{").parse(input)
+}
+
+/// Parses an argument placeholder (e.g., `{{name}}` or `{{user.name}}`).
+///
+/// # Arguments
+///
+/// * `input` - The input string to parse.
+///
+/// # Returns
+///
+/// * `Ok((remaining, name))` - The parsed argument name, including any dotted path.
 /// * `Err` - If parsing fails.
 pub fn parse_argument(input: &str) -> IResult<&str, &str> {
-    delimited(tag("{{"), identifier, tag("}}")).parse(input)
+    template_tag("", argument_path).parse(input)
 }
 
 /// Parses a variable prompt reference (e.g., `{{prompt:name}}`).
@@ -90,7 +159,7 @@ pub fn parse_argument(input: &str) -> IResult<&str, &str> {
 /// * `Ok((remaining, name))` - The parsed prompt reference name.
 /// * `Err` - If parsing fails.
 pub fn parse_variable_prompt_reference(input: &str) -> IResult<&str, &str> {
-    delimited(tag("{{prompt_var:"), identifier, tag("}}")).parse(input)
+    template_tag("prompt_var:", identifier).parse(input)
 }
 
 /// Parses a prompt reference (e.g., `{{prompt:name}}`).
@@ -104,7 +173,160 @@ pub fn parse_variable_prompt_reference(input: &str) -> IResult<&str, &str> {
 /// * `Ok((remaining, name))` - The parsed prompt reference name.
 /// * `Err` - If parsing fails.
 pub fn parse_prompt_reference(input: &str) -> IResult<&str, &str> {
-    delimited(tag("{{prompt:"), identifier, tag("}}")).parse(input)
+    template_tag("prompt:", prompt_name).parse(input)
+}
+
+/// Parses an optional prompt reference (e.g., `{{prompt_opt:name}}`).
+///
+/// # Arguments
+///
+/// * `input` - The input string to parse.
+///
+/// # Returns
+///
+/// * `Ok((remaining, name))` - The parsed prompt reference name.
+/// * `Err` - If parsing fails.
+pub fn parse_optional_prompt_reference(input: &str) -> IResult<&str, &str> {
+    template_tag("prompt_opt:", prompt_name).parse(input)
+}
+
+/// Parses a function call (e.g., `{{fn:name(argument, "literal")}}`).
+///
+/// Arguments are separated by commas and can be quoted strings, passed as-is, or
+/// argument names, whose values are passed at render time. Quotes and backslashes are
+/// escaped with a backslash in quoted strings (e.g., `"say \"hi\""`).
+///
+/// # Arguments
+///
+/// * `input` - The input string to parse.
+///
+/// # Returns
+///
+/// * `Ok((remaining, call))` - The parsed function call.
+/// * `Err` - If parsing fails.
+pub fn parse_function_call(input: &str) -> IResult<&str, FunctionCall> {
+    let arguments = separated_list0(
+        char(','),
+        delimited(multispace0, function_argument, multispace0),
+    );
+    map(
+        template_tag(
+            "fn:",
+            (
+                identifier,
+                delimited(char('('), arguments, preceded(multispace0, char(')'))),
+            ),
+        ),
+        |(name, arguments)| FunctionCall {
+            name: name.to_string(),
+            arguments,
+        },
+    )
+    .parse(input)
+}
+
+/// Parses an examples directive (e.g., `{{examples:tag=review,count=3}}`).
+///
+/// # Arguments
+///
+/// * `input` - The input string to parse.
+///
+/// # Returns
+///
+/// * `Ok((remaining, directive))` - The parsed examples directive.
+/// * `Err` - If parsing fails or the options are invalid.
+pub fn parse_examples(input: &str) -> IResult<&str, ExamplesDirective> {
+    map_res(template_tag("examples:", directive_options), |options| {
+        ExamplesDirective::from_options(&options)
+    })
+    .parse(input)
+}
+
+/// Parses a directive including all prompts with a tag (e.g., `{{prompts:tag=guidelines}}`).
+///
+/// # Arguments
+///
+/// * `input` - The input string to parse.
+///
+/// # Returns
+///
+/// * `Ok((remaining, directive))` - The parsed directive.
+/// * `Err` - If parsing fails or the options are invalid.
+pub fn parse_tagged_prompts(input: &str) -> IResult<&str, TaggedPromptsDirective> {
+    map_res(template_tag("prompts:", directive_options), |options| {
+        TaggedPromptsDirective::from_options(&options)
+    })
+    .parse(input)
+}
+
+/// Parses a `{{keyword body}}` tag, allowing whitespace around the keyword and the body
+/// (e.g., `{{ prompt: greeting }}`).
+fn template_tag<'a, O, P>(
+    keyword: &'static str,
+    body: P,
+) -> impl Parser<&'a str, Output = O, Error = Error<&'a str>>
+where
+    P: Parser<&'a str, Output = O, Error = Error<&'a str>>,
+{
+    delimited(
+        (tag("{{"), multispace0, tag(keyword), multispace0),
+        body,
+        (multispace0, tag("}}")),
+    )
+}
+
+fn directive_options(input: &str) -> IResult<&str, Vec<(&str, &str)>> {
+    // Comma-separated `key=value` pairs, where values can't contain commas or braces.
+    // Whitespace around keys and values is ignored.
+    separated_list1(
+        char(','),
+        separated_pair(
+            delimited(multispace0, identifier, multispace0),
+            char('='),
+            map(
+                preceded(
+                    multispace0,
+                    take_while1(|c: char| c != ',' && c != '{' && c != '}'),
+                ),
+                str::trim_end,
+            ),
+        ),
+    )
+    .parse(input)
+}
+
+fn function_argument(input: &str) -> IResult<&str, FunctionArgument> {
+    alt((
+        map(quoted_literal, FunctionArgument::Literal),
+        map(argument_path, |name| {
+            FunctionArgument::Argument(name.to_string())
+        }),
+    ))
+    .parse(input)
+}
+
+/// Parses a string in double or single quotes, where `\` escapes the quote or another `\`.
+/// Other backslashes are kept as they are.
+fn quoted_literal(input: &str) -> IResult<&str, String> {
+    let mut chars = input.char_indices().peekable();
+    let quote = match chars.next() {
+        Some((_, quote @ ('"' | '\''))) => quote,
+        _ => return Err(nom::Err::Error(Error::new(input, ErrorKind::Char))),
+    };
+    let mut text = String::new();
+    while let Some((index, c)) = chars.next() {
+        if c == quote {
+            return Ok((&input[index + 1..], text));
+        }
+        match chars.peek() {
+            Some(&(_, next)) if c == '\\' && (next == quote || next == '\\') => {
+                text.push(next);
+                chars.next();
+            }
+            _ => text.push(c),
+        }
+    }
+    Err(nom::Err::Error(Error::new(input, ErrorKind::TakeUntil)))
 }
 
 /// Parses an escaped literal (e.g., `{{{{text}}}}`).
@@ -121,8 +343,26 @@ pub fn parse_escaped_literal(input: &str) -> IResult<&str, &str> {
     delimited(tag("{{{{"), take_until("}}}}"), tag("}}}}")).parse(input)
 }
 
+/// Checks whether a prompt name can be used in a prompt reference (e.g., `{{prompt:name}}`).
+///
+/// Valid names are one or more segments of letters, digits, `-` or `_` (in any script),
+/// separated by dots, like `reviews.v2` or `equipo.revisión`.
+pub fn is_valid_prompt_name(name: &str) -> bool {
+    all_consuming(prompt_name).parse(name).is_ok()
+}
+
+fn prompt_name(input: &str) -> IResult<&str, &str> {
+    // Prompt names can be namespaced with dots, e.g. `reviews.v2`
+    recognize(separated_list1(tag("."), identifier)).parse(input)
+}
+
+fn argument_path(input: &str) -> IResult<&str, &str> {
+    // One or more identifiers separated by dots, e.g. `config.model.temperature`
+    recognize(separated_list1(tag("."), identifier)).parse(input)
+}
+
 fn identifier(input: &str) -> IResult<&str, &str> {
-    // Limit identifiers to 1-64 characters with alphanumeric, dash, underscore
+    // Limit identifiers to 1-64 characters with alphanumeric (in any script), dash, underscore
     take_while_m_n(1, 64, |c: char| c.is_alphanumeric() || c == '-' || c == '_').parse(input)
 }
 
@@ -148,6 +388,21 @@ mod tests {
         assert_eq!(result, Ok((" is the subject", "topic")));
     }
 
+    #[test]
+    fn test_parse_argument_with_dotted_path() {
+        let result = parse_argument("{{config.model.temperature}} is the value");
+        assert_eq!(result, Ok((" is the value", "config.model.temperature")));
+
+        let result = parse_argument("{{user.}}");
+        assert!(result.is_err(), "Trailing dot should fail");
+
+        let result = parse_argument("{{.user}}");
+        assert!(result.is_err(), "Leading dot should fail");
+
+        let result = parse_argument("{{user..name}}");
+        assert!(result.is_err(), "Empty path segment should fail");
+    }
+
     #[test]
     fn test_parse_consecutive_variables() {
         let result = parse_template("{{a}}{{b}}{{prompt:c}}");
@@ -168,24 +423,154 @@ mod tests {
 
     #[test]
     fn test_parse_incomplete_templates() {
-        let result = parse_template("Hello {{name"); // Missing closing }}
-        assert!(result.is_err());
+        // Unclosed braces are kept as literal text
+        for input in ["Hello {{name", "{{prompt:test", "{{{{hello"] {
+            let result = parse_template(input);
+            assert!(result.is_ok(), "'{}' should parse", input);
+            let (remaining, parts) = result.unwrap();
+            assert_eq!(remaining, "");
+            assert!(
+                parts
+                    .iter()
+                    .all(|part| matches!(part, PromptTemplatePart::Literal(_)))
+            );
+        }
+    }
 
-        let result = parse_template("{{prompt:test"); // Missing closing }}
-        assert!(result.is_err());
+    #[test]
+    fn test_parse_stray_braces_before_a_tag() {
+        let result = parse_template("Stray {{ before {{name}}");
+        assert!(result.is_ok());
+        let (_, parts) = result.unwrap();
+        assert_eq!(
+            parts,
+            vec![
+                PromptTemplatePart::Literal(String::from("Stray ")),
+                PromptTemplatePart::Literal(String::from("{{")),
+                PromptTemplatePart::Literal(String::from(" before ")),
+                PromptTemplatePart::Argument(String::from("name")),
+            ]
+        );
+    }
 
-        let result = parse_template("{{{{hello"); // Missing closing }}}}
+    #[test]
+    fn test_parse_invalid_tags_as_literals() {
+        for (input, expected) in [
+            ("{{#if done}}", vec!["{{", "#if done}}"]),
+            ("{{ foo bar }}", vec!["{{", " foo bar }}"]),
+            (
+                "function() {{ return x; }} and {{ {{name}}",
+                vec!["function() ", "{{", " return x; }} and ", "{{", " "],
+            ),
+        ] {
+            let (remaining, parts) = parse_template(input).unwrap();
+            assert_eq!("", remaining);
+            let literals: Vec<PromptTemplatePart> = expected
+                .into_iter()
+                .map(|text| PromptTemplatePart::Literal(text.to_string()))
+                .collect();
+            assert_eq!(literals, parts[..literals.len()]);
+        }
+
+        let (_, parts) = parse_template("{{ {{name}}").unwrap();
+        assert_eq!(PromptTemplatePart::Argument(String::from("name")), parts[2]);
+
+        // A brace right before a tag is literal text
+        let (_, parts) = parse_template("{{{prompt:intro}}}").unwrap();
+        assert_eq!(
+            vec![
+                PromptTemplatePart::Literal(String::from("{")),
+                PromptTemplatePart::PromptReference(String::from("intro")),
+                PromptTemplatePart::Literal(String::from("}")),
+            ],
+            parts
+        );
+    }
+
+    #[test]
+    fn test_parse_stray_braces() {
+        let result = parse_stray_braces("{{ no closing");
+        assert_eq!(result, Ok((" no closing", "{{")));
+
+        let result = parse_stray_braces("{{{name}}}");
+        assert_eq!(result, Ok(("{{name}}}", "{")));
+
+        let result = parse_stray_braces("no braces");
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_escaped_braces() {
+        let result = parse_template("\\{{#if done}} {{name}} \\{{/if}}");
+        assert!(result.is_ok());
+        let (remaining, parts) = result.unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(
+            parts,
+            vec![
+                PromptTemplatePart::Literal(String::from("{{")),
+                PromptTemplatePart::Literal(String::from("#if done}} ")),
+                PromptTemplatePart::Argument(String::from("name")),
+                PromptTemplatePart::Literal(String::from(" ")),
+                PromptTemplatePart::Literal(String::from("{{")),
+                PromptTemplatePart::Literal(String::from("/if}}")),
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_with_whitespace() {
-        // Currently your parser doesn't allow whitespace in identifiers
         let result = parse_argument("{{ name }}");
-        assert!(result.is_err(), "Whitespace should not be allowed");
+        assert_eq!(result, Ok(("", "name")));
+
+        let result = parse_argument("{{\n  user.name\n}}");
+        assert_eq!(result, Ok(("", "user.name")));
 
         let result = parse_prompt_reference("{{prompt: test }}");
-        assert!(result.is_err(), "Whitespace should not be allowed");
+        assert_eq!(result, Ok(("", "test")));
+
+        let result = parse_prompt_reference("{{ prompt:test}}");
+        assert_eq!(result, Ok(("", "test")));
+
+        let result = parse_variable_prompt_reference("{{prompt_var: kind }}");
+        assert_eq!(result, Ok(("", "kind")));
+
+        let result = parse_optional_prompt_reference("{{ prompt_opt: extra }}");
+        assert_eq!(result, Ok(("", "extra")));
+
+        let result = parse_function_call("{{ fn: upper(name) }}");
+        assert!(result.is_ok());
+
+        let result = parse_examples("{{ examples: tag=review }}");
+        assert!(
+            matches!(result, Ok(("", ExamplesDirective { ref tag, .. })) if tag == "review"),
+            "Unexpected result: {:?}",
+            result
+        );
+
+        let result = directive_options(" tag = review , count= 3 ,order =random ");
+        assert_eq!(
+            result,
+            Ok((
+                "",
+                vec![("tag", "review"), ("count", "3"), ("order", "random")]
+            ))
+        );
+
+        let result = directive_options("sep=a b ");
+        assert_eq!(result, Ok(("", vec![("sep", "a b")])));
+
+        let result = directive_options("tag= ");
+        assert!(result.is_err(), "Blank values should fail");
+    }
+
+    #[test]
+    fn test_parse_whitespace_inside_identifier() {
+        let result = parse_argument("{{first name}}");
+        assert!(result.is_err(), "Whitespace inside a name should fail");
+
+        let result = parse_prompt_reference("{{prompt :test}}");
+        assert!(result.is_err(), "Whitespace before the colon should fail");
     }
 
     #[test]
@@ -237,6 +622,114 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_optional_prompt_reference() {
+        let result = parse_element("{{prompt_opt:examples}} are optional");
+        assert_eq!(
+            result,
+            Ok((
+                " are optional",
+                PromptTemplatePart::OptionalPromptReference(String::from("examples"))
+            ))
+        );
+
+        let result = parse_optional_prompt_reference("{{prompt_opt:}}");
+        assert!(
+            result.is_err(),
+            "Empty optional prompt reference should fail"
+        );
+    }
+
+    #[test]
+    fn test_parse_function_call() {
+        let result = parse_element("{{fn:choose( tone ,\"a, b\", 'c')}} rest");
+        assert_eq!(
+            result,
+            Ok((
+                " rest",
+                PromptTemplatePart::Function(FunctionCall {
+                    name: String::from("choose"),
+                    arguments: vec![
+                        FunctionArgument::Argument(String::from("tone")),
+                        FunctionArgument::Literal(String::from("a, b")),
+                        FunctionArgument::Literal(String::from("c")),
+                    ],
+                })
+            ))
+        );
+
+        let result = parse_function_call("{{fn:uuid()}}");
+        assert_eq!(
+            result,
+            Ok((
+                "",
+                FunctionCall {
+                    name: String::from("uuid"),
+                    arguments: vec![],
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_function_call_round_trip() {
+        let call = FunctionCall {
+            name: String::from("wrap"),
+            arguments: vec![
+                FunctionArgument::Literal(String::from("say \"hi\" (twice) }} \\")),
+                FunctionArgument::Literal(String::from("C:\\path")),
+                FunctionArgument::Argument(String::from("text")),
+            ],
+        };
+        let source = format!("{{{{{}}}}}", call);
+        assert_eq!(Ok(("", call)), parse_function_call(&source));
+
+        // Backslashes escaping nothing are kept as they are
+        let result = parse_function_call("{{fn:upper('it\\'s', \"a\\b\")}}");
+        assert_eq!(
+            Ok(vec![
+                FunctionArgument::Literal(String::from("it's")),
+                FunctionArgument::Literal(String::from("a\\b")),
+            ]),
+            result.map(|(_, call)| call.arguments)
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_function_call() {
+        let result = parse_function_call("{{fn:uuid}}");
+        assert!(result.is_err(), "Missing parentheses should fail");
+
+        let result = parse_function_call("{{fn:upper(\"unclosed)}}");
+        assert!(result.is_err(), "Unclosed string should fail");
+
+        let result = parse_function_call("{{fn:upper(a b)}}");
+        assert!(result.is_err(), "Arguments must be separated by commas");
+    }
+
+    #[test]
+    fn test_parse_examples() {
+        let result = parse_element("{{examples:tag=review,count=3}}");
+        assert!(
+            matches!(
+                result,
+                Ok(("", PromptTemplatePart::Examples(ExamplesDirective { ref tag, count: Some(3), .. })))
+                    if tag == "review"
+            ),
+            "Unexpected result: {:?}",
+            result
+        );
+
+        let result = parse_examples("{{examples:count=3}}");
+        assert!(result.is_err(), "Missing tag should fail");
+
+        let result = parse_examples("{{examples:tag=review,color=blue}}");
+        assert!(result.is_err(), "Unknown option should fail");
+
+        let result = parse_examples("{{examples:}}");
+        assert!(result.is_err(), "Empty options should fail");
+    }
+
     #[test]
     fn test_parse_variable_prompt_reference() {
         let result = parse_variable_prompt_reference("{{prompt_var:dynamic_prompt}} is the prompt");
@@ -302,9 +795,13 @@ mod tests {
     #[test]
     fn test_parse_element_invalid_argument() {
         let result = parse_element("{{user&name}}");
-        assert!(
-            result.is_err(),
-            "Expected parse to fail due to non-alphanumeric character"
+        assert_eq!(
+            result,
+            Ok((
+                "user&name}}",
+                PromptTemplatePart::Literal(String::from("{{"))
+            )),
+            "Invalid arguments are kept as literal text"
         );
     }
 
@@ -323,9 +820,13 @@ mod tests {
     #[test]
     fn test_parse_element_invalid_prompt_reference() {
         let result = parse_element("{{prompt:u$ername}}");
-        assert!(
-            result.is_err(),
-            "Expected parse to fail due to non-alphanumeric character"
+        assert_eq!(
+            result,
+            Ok((
+                "prompt:u$ername}}",
+                PromptTemplatePart::Literal(String::from("{{"))
+            )),
+            "Invalid prompt references are kept as literal text"
         );
     }
 
@@ -344,9 +845,13 @@ mod tests {
     #[test]
     fn test_parse_element_invalid_variable_prompt_reference() {
         let result = parse_element("{{prompt_var:u$ername}}");
-        assert!(
-            result.is_err(),
-            "Expected parse to fail due to non-alphanumeric character"
+        assert_eq!(
+            result,
+            Ok((
+                "prompt_var:u$ername}}",
+                PromptTemplatePart::Literal(String::from("{{"))
+            )),
+            "Invalid prompt references are kept as literal text"
         );
     }
 
@@ -400,7 +905,18 @@ mod tests {
     #[test]
     fn test_parse_invalid_template() {
         let result = parse_template("Hello {{n@me}}, welcome to {{prompt:greeting}}!");
-        assert!(result.is_err());
+        assert!(result.is_ok());
+        let (_, parts) = result.unwrap();
+        assert_eq!(
+            parts,
+            vec![
+                PromptTemplatePart::Literal(String::from("Hello ")),
+                PromptTemplatePart::Literal(String::from("{{")),
+                PromptTemplatePart::Literal(String::from("n@me}}, welcome to ")),
+                PromptTemplatePart::PromptReference(String::from("greeting")),
+                PromptTemplatePart::Literal(String::from("!")),
+            ]
+        );
     }
 
     #[test]
@@ -517,4 +1033,51 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_parse_prompt_reference_with_dotted_and_unicode_names() {
+        let result = parse_prompt_reference("{{prompt:reviews.v2}}");
+        assert_eq!(result, Ok(("", "reviews.v2")));
+
+        let result = parse_prompt_reference("{{prompt:equipo.revisión}}");
+        assert_eq!(result, Ok(("", "equipo.revisión")));
+
+        let result = parse_optional_prompt_reference("{{prompt_opt:políticas.二}}");
+        assert_eq!(result, Ok(("", "políticas.二")));
+
+        let result = parse_prompt_reference("{{prompt:reviews.}}");
+        assert!(result.is_err(), "Trailing dot should fail");
+    }
+
+    #[test]
+    fn test_is_valid_prompt_name() {
+        assert!(is_valid_prompt_name("greeting"));
+        assert!(is_valid_prompt_name("reviews.v2"));
+        assert!(is_valid_prompt_name("equipo.revisión"));
+        assert!(!is_valid_prompt_name(""));
+        assert!(!is_valid_prompt_name("my prompt"));
+        assert!(!is_valid_prompt_name("a/b"));
+        assert!(!is_valid_prompt_name(".hidden"));
+    }
+
+    #[test]
+    fn test_parse_tagged_prompts() {
+        let result = parse_element("{{prompts:tag=guidelines,sep=\\n---\\n}}");
+        assert_eq!(
+            result,
+            Ok((
+                "",
+                PromptTemplatePart::TaggedPrompts(TaggedPromptsDirective {
+                    tag: "guidelines".to_string(),
+                    separator: "\n---\n".to_string(),
+                })
+            ))
+        );
+
+        let result = parse_tagged_prompts("{{prompts:sep=\\n}}");
+        assert!(result.is_err(), "Missing tag should fail");
+
+        let result = parse_tagged_prompts("{{prompts:tag=guidelines,count=2}}");
+        assert!(result.is_err(), "Unknown option should fail");
+    }
 }
@@ -0,0 +1,305 @@
+//! # Pipelines
+//!
+//! This module provides pipelines: ordered lists of steps where each step renders a prompt,
+//! optionally sends it to a model, and binds the result to a variable that later steps can
+//! use as an argument.
+//!
+//! Pipelines are stored as JSON files:
+//!
+//! ```json
+//! {
+//!   "name": "blog-post",
+//!   "steps": [
+//!     { "prompt": "outline", "output": "outline", "generate": true },
+//!     { "prompt": "draft", "output": "draft", "generate": true }
+//!   ]
+//! }
+//! ```
+//!
+//! Here, the `draft` prompt can use `{{outline}}` to access the first step's output.
+
+use crate::messages::{RenderedMessage, split_messages};
+use crate::prompt::{ParseTemplateError, Prompt, PromptTemplate, RenderTemplateError};
+use crate::storage::PromptStorage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fs, io};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("i/o Error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("invalid pipeline definition: {0}")]
+    DefinitionError(#[from] serde_json::Error),
+    #[error("invalid pipeline: {0}")]
+    InvalidPipeline(String),
+    #[error("step {step} couldn't retrieve prompt '{prompt}': {message}")]
+    StorageError {
+        step: usize,
+        prompt: String,
+        message: String,
+    },
+    #[error("failed to parse the prompt of step {step}")]
+    ParseError {
+        step: usize,
+        source: ParseTemplateError,
+    },
+    #[error("failed to render step {step}")]
+    RenderError {
+        step: usize,
+        source: RenderTemplateError,
+    },
+    #[error("step {step} failed to generate: {message}")]
+    GenerationError { step: usize, message: String },
+}
+
+/// A single step of a pipeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineStep {
+    /// The name of the prompt rendered by the step.
+    pub prompt: String,
+    /// The name of the variable the step's output is bound to.
+    pub output: String,
+    /// Whether the rendered prompt is sent to a model, binding the response instead.
+    #[serde(default)]
+    pub generate: bool,
+}
+
+/// An ordered list of steps, each one able to use the outputs of the previous ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    /// The name of the pipeline.
+    pub name: String,
+    /// A brief description of the pipeline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The steps of the pipeline, run in order.
+    pub steps: Vec<PipelineStep>,
+}
+
+/// The result of running a pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineOutput {
+    /// The output of the last step.
+    pub output: String,
+    /// The initial arguments and every variable bound by the steps.
+    pub variables: HashMap<String, Value>,
+}
+
+impl Pipeline {
+    /// Loads a pipeline from a JSON file.
+    pub fn load(path: &Path) -> Result<Pipeline, PipelineError> {
+        let pipeline: Pipeline = serde_json::from_str(&fs::read_to_string(path)?)?;
+        pipeline.validate()?;
+        Ok(pipeline)
+    }
+
+    /// Checks that the pipeline has steps, and that every step has a prompt and an output.
+    pub fn validate(&self) -> Result<(), PipelineError> {
+        if self.steps.is_empty() {
+            return Err(PipelineError::InvalidPipeline(format!(
+                "pipeline '{}' has no steps",
+                self.name
+            )));
+        }
+        if let Some((index, _)) = self
+            .steps
+            .iter()
+            .enumerate()
+            .find(|(_, step)| step.prompt.trim().is_empty() || step.output.trim().is_empty())
+        {
+            return Err(PipelineError::InvalidPipeline(format!(
+                "step {} needs both a prompt and an output",
+                index + 1
+            )));
+        }
+        Ok(())
+    }
+
+    /// Runs the pipeline's steps in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage the step prompts are retrieved from.
+    /// * `arguments` - The initial arguments, available to every step.
+    /// * `generate` - Called for steps that send their prompt to a model, with the step's
+    ///   prompt and its rendered messages. Returns the model response.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(PipelineOutput)` - The output of the last step and all the bound variables.
+    /// * `Err(PipelineError)` - With the first step that failed.
+    pub async fn run<S, G>(
+        &self,
+        storage: &S,
+        arguments: HashMap<String, Value>,
+        generate: G,
+    ) -> Result<PipelineOutput, PipelineError>
+    where
+        S: PromptStorage,
+        G: AsyncFn(&Prompt, &[RenderedMessage]) -> Result<String, String>,
+    {
+        self.validate()?;
+
+        let mut variables = arguments;
+        let mut output = String::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            let step_number = index + 1;
+            let prompt =
+                storage
+                    .get_prompt(&step.prompt)
+                    .map_err(|e| PipelineError::StorageError {
+                        step: step_number,
+                        prompt: step.prompt.clone(),
+                        message: e.to_string(),
+                    })?;
+            let template = PromptTemplate::new(prompt.clone()).map_err(|source| {
+                PipelineError::ParseError {
+                    step: step_number,
+                    source,
+                }
+            })?;
+            let rendered = template.render(&variables, storage).map_err(|source| {
+                PipelineError::RenderError {
+                    step: step_number,
+                    source,
+                }
+            })?;
+
+            output = if step.generate {
+                generate(&prompt, &split_messages(&rendered))
+                    .await
+                    .map_err(|message| PipelineError::GenerationError {
+                        step: step_number,
+                        message,
+                    })?
+            } else {
+                rendered
+            };
+            variables.insert(step.output.clone(), Value::String(output.clone()));
+        }
+
+        Ok(PipelineOutput { output, variables })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_storage::FileStorage;
+    use crate::prompt::PromptMetadata;
+    use tempfile::TempDir;
+
+    fn storage_with(prompts: &[(&str, &str)]) -> (TempDir, FileStorage) {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        for (name, content) in prompts {
+            storage
+                .save_prompt(&Prompt::new(
+                    PromptMetadata::new(name.to_string(), None, vec![]),
+                    content.to_string(),
+                ))
+                .unwrap();
+        }
+        (temp_dir, storage)
+    }
+
+    fn step(prompt: &str, output: &str, generate: bool) -> PipelineStep {
+        PipelineStep {
+            prompt: prompt.to_string(),
+            output: output.to_string(),
+            generate,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_pipeline() {
+        let (_temp_dir, storage) = storage_with(&[
+            ("outline", "Outline a post about {{topic}}"),
+            ("draft", "Write a post following: {{outline}}"),
+        ]);
+        let pipeline = Pipeline {
+            name: "blog".to_string(),
+            description: None,
+            steps: vec![
+                step("outline", "outline", true),
+                step("draft", "draft", false),
+            ],
+        };
+
+        let mut arguments = HashMap::new();
+        arguments.insert("topic".to_string(), Value::String("Rust".to_string()));
+        let result = pipeline
+            .run(&storage, arguments, async |prompt, messages| {
+                Ok(format!(
+                    "[{}] {}",
+                    prompt.metadata.name,
+                    messages.last().unwrap().content
+                ))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            "Write a post following: [outline] Outline a post about Rust",
+            result.output
+        );
+        assert_eq!(
+            Some(&Value::String(
+                "[outline] Outline a post about Rust".to_string()
+            )),
+            result.variables.get("outline")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_pipeline_reports_failing_step() {
+        let (_temp_dir, storage) = storage_with(&[("first", "Hi"), ("second", "{{missing}}")]);
+        let pipeline = Pipeline {
+            name: "broken".to_string(),
+            description: None,
+            steps: vec![step("first", "a", false), step("second", "b", false)],
+        };
+
+        let result = pipeline
+            .run(&storage, HashMap::new(), async |_, _| Ok(String::new()))
+            .await;
+        assert!(matches!(
+            result,
+            Err(PipelineError::RenderError { step: 2, .. })
+        ));
+
+        let result = pipeline
+            .run(&storage, HashMap::new(), async |_, _| {
+                Err("model unavailable".to_string())
+            })
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_load_pipeline() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("blog.json");
+        fs::write(
+            &path,
+            r#"{"name": "blog", "steps": [{"prompt": "outline", "output": "outline", "generate": true}]}"#,
+        )
+        .unwrap();
+        let pipeline = Pipeline::load(&path).unwrap();
+        assert_eq!(vec![step("outline", "outline", true)], pipeline.steps);
+
+        fs::write(&path, r#"{"name": "empty", "steps": []}"#).unwrap();
+        assert!(matches!(
+            Pipeline::load(&path),
+            Err(PipelineError::InvalidPipeline(_))
+        ));
+    }
+}
@@ -23,17 +23,32 @@
 //! let prompt = Prompt::new(metadata, "Hello {{name}}, welcome to {{prompt:service_name}}!".to_string());
 //! ```
 
-use crate::parser::parse_template;
+use crate::dialect::{self, Dialect};
+use crate::eval::EvalCase;
+use crate::functions::FunctionRegistry;
+use crate::llm::{ModelSettings, ToolDefinition};
+use crate::messages::{ChatRequest, RenderedMessage, split_messages};
+use crate::output::{Guardrails, OutputFormat};
+use crate::parser::{is_valid_prompt_name, parse_template};
 use crate::storage::PromptStorage;
+use crate::testing::{PromptTestCase, PromptTestResult};
+use crate::tokens::count_tokens;
 use nom::Err as NomErr;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::ops::Range;
+use tracing::debug;
 
 use thiserror::Error;
 
-
 /// Maximum allowed nesting depth for prompt templates
-const MAX_NESTING_DEPTH: usize = 3; // TODO: Make this a variable
+pub(crate) const MAX_NESTING_DEPTH: usize = 3; // TODO: Make this a variable
+
+/// The number of tokens over which a rendered prompt is reported as very large
+const LARGE_OUTPUT_TOKENS: usize = 100_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PromptMetadata {
@@ -43,9 +58,86 @@ pub struct PromptMetadata {
     pub description: Option<String>,
     /// Tags used for searching.
     pub tags: Vec<String>,
+    /// Who wrote the prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Where the prompt comes from, if it was taken from somewhere else.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    /// The license the prompt is shared under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Declarations documenting the arguments of the prompt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arguments: Vec<ArgumentDeclaration>,
+    /// Whether the prompt is deprecated. Rendering it produces a warning.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deprecated: bool,
+    /// The name of the prompt replacing this one, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<String>,
+    /// Whether the prompt is pinned, so it's listed before the rest.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Whether the prompt is locked, so storages refuse to overwrite or delete it
+    /// unless the lock is explicitly overridden.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// The preferred model settings (`model`, `temperature`, `max_tokens` and `stop`)
+    /// used when generating with the prompt.
+    #[serde(flatten)]
+    pub model_settings: ModelSettings,
+    /// The format model responses are expected to have when generating with the prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<OutputFormat>,
+    /// Checks model responses must pass when generating with the prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guardrails: Option<Guardrails>,
+    /// Test cases checking how the prompt renders.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<PromptTestCase>,
+    /// Eval cases checking the model's responses to the prompt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evals: Vec<EvalCase>,
+    /// Functions the model can call when generating with the prompt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
+    /// The template syntax of the prompt's content.
+    #[serde(default, skip_serializing_if = "Dialect::is_pren")]
+    pub dialect: Dialect,
+    /// Frontmatter keys pren doesn't know, e.g. those of other tools, kept so they're
+    /// written back when the prompt is saved.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
-#[derive(Debug, Clone)]
+/// The declaration of a prompt argument, stored in the prompt's frontmatter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArgumentDeclaration {
+    /// The name of the argument.
+    pub name: String,
+    /// What the argument is for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The value used when the argument isn't provided. Arguments without a default are required.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// Information about an argument of a template, combining its usage and its declaration.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArgumentInfo {
+    /// The name of the argument.
+    pub name: String,
+    /// Whether the argument must be provided to render the template.
+    pub required: bool,
+    /// The value used when the argument isn't provided.
+    pub default: Option<String>,
+    /// What the argument is for.
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Prompt {
     pub metadata: PromptMetadata,
     pub content: String,
@@ -61,6 +153,172 @@ pub enum PromptTemplatePart {
     PromptReference(String),
     /// A variable reference to another prompt that gets rendered at render time.
     VariablePromptReference(String),
+    /// A reference to another prompt that can be left out to fit a token budget.
+    OptionalPromptReference(String),
+    /// A call to a function from the [`FunctionRegistry`] that gets evaluated at render time.
+    Function(FunctionCall),
+    /// A selection of prompts with a given tag, inlined as few-shot examples.
+    Examples(ExamplesDirective),
+    /// All the prompts with a given tag, concatenated with a separator.
+    TaggedPrompts(TaggedPromptsDirective),
+}
+
+/// A directive that inlines prompts with a given tag as few-shot examples
+/// (e.g., `{{examples:tag=review,count=3,order=random}}`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExamplesDirective {
+    /// The tag of the prompts to use as examples.
+    pub tag: String,
+    /// The maximum number of examples to include, or all of them if `None`.
+    pub count: Option<usize>,
+    /// How the examples are selected.
+    pub order: ExampleOrder,
+    /// The text placed between examples.
+    pub separator: String,
+}
+
+/// How examples are selected from the prompts with the requested tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExampleOrder {
+    /// Examples are sorted by prompt name, so the selection is always the same.
+    Name,
+    /// Examples are shuffled. Using a seed makes the shuffle reproducible.
+    Random { seed: Option<u64> },
+}
+
+impl ExamplesDirective {
+    /// Default text placed between examples.
+    pub const DEFAULT_SEPARATOR: &'static str = "\n\n";
+
+    /// Builds the directive from its `key=value` options.
+    ///
+    /// Supported options are `tag` (required), `count`, `order` (`name` or `random`),
+    /// `seed` and `sep`. Separators can use `\n` and `\t` escapes.
+    pub fn from_options(options: &[(&str, &str)]) -> Result<ExamplesDirective, String> {
+        let mut tag = None;
+        let mut count = None;
+        let mut random = false;
+        let mut seed = None;
+        let mut separator = Self::DEFAULT_SEPARATOR.to_string();
+
+        for (key, value) in options {
+            match *key {
+                "tag" => tag = Some(value.to_string()),
+                "count" => {
+                    count = Some(
+                        value
+                            .parse::<usize>()
+                            .map_err(|_| format!("invalid count '{}'", value))?,
+                    )
+                }
+                "order" => match *value {
+                    "name" => random = false,
+                    "random" => random = true,
+                    other => return Err(format!("invalid order '{}'", other)),
+                },
+                "seed" => {
+                    seed = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| format!("invalid seed '{}'", value))?,
+                    )
+                }
+                "sep" => separator = unescape_separator(value),
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+
+        Ok(ExamplesDirective {
+            tag: tag.ok_or("missing 'tag' option")?,
+            count,
+            order: if random {
+                ExampleOrder::Random { seed }
+            } else {
+                ExampleOrder::Name
+            },
+            separator,
+        })
+    }
+}
+
+/// A directive that inlines every prompt with a given tag
+/// (e.g., `{{prompts:tag=guidelines,sep=\n\n}}`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaggedPromptsDirective {
+    /// The tag of the prompts to include.
+    pub tag: String,
+    /// The text placed between prompts.
+    pub separator: String,
+}
+
+impl TaggedPromptsDirective {
+    /// Default text placed between prompts.
+    pub const DEFAULT_SEPARATOR: &'static str = "\n\n";
+
+    /// Builds the directive from its `key=value` options.
+    ///
+    /// Supported options are `tag` (required) and `sep`, which can use `\n` and `\t` escapes.
+    pub fn from_options(options: &[(&str, &str)]) -> Result<TaggedPromptsDirective, String> {
+        let mut tag = None;
+        let mut separator = Self::DEFAULT_SEPARATOR.to_string();
+
+        for (key, value) in options {
+            match *key {
+                "tag" => tag = Some(value.to_string()),
+                "sep" => separator = unescape_separator(value),
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+
+        Ok(TaggedPromptsDirective {
+            tag: tag.ok_or("missing 'tag' option")?,
+            separator,
+        })
+    }
+}
+
+/// Replaces the `\n`, `\t` and `\s` (space) escapes in a separator option. Spaces around
+/// option values are ignored, so `\s` writes one at the start or end of a separator.
+fn unescape_separator(value: &str) -> String {
+    value
+        .replace("\\n", "\n")
+        .replace("\\t", "\t")
+        .replace("\\s", " ")
+}
+
+/// A function call in a template (e.g., `{{fn:choose(tone, "formal")}}`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCall {
+    /// The name of the function in the registry.
+    pub name: String,
+    /// The arguments passed to the function.
+    pub arguments: Vec<FunctionArgument>,
+}
+
+/// An argument passed to a template function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionArgument {
+    /// A quoted string passed as-is, once `\` escapes of its quote or of `\` are removed.
+    Literal(String),
+    /// The name of a template argument whose value is passed.
+    Argument(String),
+}
+
+impl std::fmt::Display for FunctionCall {
+    /// Formats the call with the template syntax, without the surrounding braces.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arguments: Vec<String> = self
+            .arguments
+            .iter()
+            .map(|argument| match argument {
+                FunctionArgument::Literal(text) => {
+                    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+                }
+                FunctionArgument::Argument(name) => name.clone(),
+            })
+            .collect();
+        write!(f, "fn:{}({})", self.name, arguments.join(", "))
+    }
 }
 
 /// A parsed template with parts that can be literals, arguments, or prompt references.
@@ -78,64 +336,784 @@ pub struct ParseTemplateError {
     pub message: String,
 }
 
+/// Why rendering a template failed. Failures in referenced prompts are returned as they
+/// are, so they can be matched on whatever prompt they happened in.
 #[derive(Error, Debug)]
-#[error("Error found while rendering template: {message}")]
-pub struct RenderTemplateError {
-    pub message: String,
+pub enum RenderTemplateError {
+    /// An argument wasn't given, to this prompt or to a prompt it references.
+    #[error("Missing argument: {name}")]
+    MissingArgument { name: String },
+    /// A referenced prompt couldn't be retrieved from the storage.
+    #[error("Prompt '{name}', referenced from '{referenced_from}', couldn't be found")]
+    PromptNotFound {
+        name: String,
+        referenced_from: String,
+    },
+    /// A referenced prompt exists but couldn't be read from the storage, e.g. because its
+    /// file is corrupt.
+    #[error("Prompt '{name}', referenced from '{referenced_from}', couldn't be read: {source}")]
+    Storage {
+        name: String,
+        referenced_from: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A prompt references itself, directly or indirectly, through the prompts of the chain.
+    #[error("Circular reference detected: {}", chain.join(" -> "))]
+    CircularReference { chain: Vec<String> },
+    /// Prompts are nested deeper than the limit.
+    #[error("Maximum nesting depth of {limit} exceeded")]
+    DepthExceeded { limit: usize },
+    /// A referenced prompt couldn't be parsed.
+    #[error("Error parsing referenced prompt '{name}': {source}")]
+    InvalidPrompt {
+        name: String,
+        source: ParseTemplateError,
+    },
+    /// Any other failure, e.g. of a function, a hook or another template dialect.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl RenderTemplateError {
+    /// Whether the render failed because an argument wasn't given, to this prompt or to a
+    /// prompt it references.
+    pub fn is_missing_argument(&self) -> bool {
+        matches!(self, RenderTemplateError::MissingArgument { .. })
+    }
+}
+
+/// A set of argument values that templates can be rendered with.
+///
+/// Arguments are looked up by the name used in the template, which can be a dotted path
+/// (e.g. `user.name`) when the values are structured.
+pub trait TemplateArguments {
+    /// Returns the value of the argument as text, or `None` if it isn't provided.
+    fn lookup(&self, name: &str) -> Option<String>;
+
+    /// Returns the value of the argument as JSON, for the template dialects working with
+    /// structured values (see [`Dialect`]). Defaults to the text of the argument.
+    fn lookup_value(&self, name: &str) -> Option<Value> {
+        self.lookup(name).map(Value::String)
+    }
+
+    /// Returns the names of the arguments provided, to warn about those no prompt uses.
+    /// Defaults to none, so no warning is given.
+    fn names(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+impl TemplateArguments for HashMap<String, String> {
+    fn lookup(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.keys().cloned().collect()
+    }
+}
+
+impl TemplateArguments for HashMap<String, Value> {
+    /// Looks up an exact key first and then falls back to traversing the dotted path
+    /// through nested objects (and arrays, using numeric segments).
+    ///
+    /// String values are rendered as-is, any other value is rendered as JSON.
+    fn lookup(&self, name: &str) -> Option<String> {
+        let value = match self.get(name) {
+            Some(value) => value,
+            None => {
+                let mut segments = name.split('.');
+                let mut value = self.get(segments.next()?)?;
+                for segment in segments {
+                    value = match value {
+                        Value::Object(map) => map.get(segment)?,
+                        Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+                        _ => return None,
+                    };
+                }
+                value
+            }
+        };
+
+        match value {
+            Value::String(text) => Some(text.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn lookup_value(&self, name: &str) -> Option<Value> {
+        self.get(name).cloned()
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.keys().cloned().collect()
+    }
+}
+
+/// The result of rendering a template, along with the non-fatal issues found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOutput {
+    /// The rendered text.
+    pub text: String,
+    /// The warnings found while rendering, in the order they were found.
+    pub warnings: Vec<RenderWarning>,
+}
+
+/// Options of a render, combining the ones of the specialized render methods.
+#[derive(Default, Clone, Copy)]
+pub struct RenderOptions<'a> {
+    /// The functions the template can call, as with [`PromptTemplate::render_with_functions`].
+    pub functions: Option<&'a FunctionRegistry>,
+    /// The token budget to fit the prompt in, as with [`PromptTemplate::render_with_budget`].
+    pub max_tokens: Option<usize>,
+}
+
+/// A non-fatal issue found while rendering a template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderWarning {
+    /// A deprecated prompt was rendered, directly or through a reference.
+    DeprecatedPrompt {
+        name: String,
+        superseded_by: Option<String>,
+    },
+    /// Arguments were provided that no rendered prompt uses, e.g. because of a typo.
+    UnusedArguments { names: Vec<String> },
+    /// A prompt was rendered at the maximum nesting depth, so it can't reference others.
+    NearDepthLimit { name: String, depth: usize },
+    /// The rendered prompt is very large, and may not fit in a model's context window.
+    LargeOutput { tokens: usize },
+}
+
+impl std::fmt::Display for RenderWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderWarning::DeprecatedPrompt {
+                name,
+                superseded_by: Some(replacement),
+            } => write!(
+                f,
+                "Prompt '{}' is deprecated, use '{}' instead",
+                name, replacement
+            ),
+            RenderWarning::DeprecatedPrompt {
+                name,
+                superseded_by: None,
+            } => write!(f, "Prompt '{}' is deprecated", name),
+            RenderWarning::UnusedArguments { names } => {
+                write!(f, "Arguments not used by the prompt: {}", names.join(", "))
+            }
+            RenderWarning::NearDepthLimit { name, depth } => write!(
+                f,
+                "Prompt '{}' is nested {} levels deep, the maximum, so it can't reference other prompts",
+                name, depth
+            ),
+            RenderWarning::LargeOutput { tokens } => write!(
+                f,
+                "The rendered prompt has about {} tokens, and may not fit in a model's context window",
+                tokens
+            ),
+        }
+    }
+}
+
+/// The result of partially rendering a template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialRender {
+    /// The rendered text, with placeholders left in place for the missing arguments.
+    /// The text is itself a valid template, so it can be rendered again later.
+    pub text: String,
+    /// The arguments that were not provided, in order of first appearance.
+    pub missing_arguments: Vec<String>,
+}
+
+/// Hooks called while a template is rendered, to observe or adjust the composition.
+///
+/// Every method has a no-op default, so implementors only override what they need.
+/// Returning an error from any hook aborts the render.
+pub trait RenderHooks {
+    /// Called after each part is rendered, with the name of the prompt the part belongs to.
+    ///
+    /// The rendered text can be modified in place (e.g. to redact secrets). For prompt
+    /// references, `rendered` holds the whole referenced prompt, whose own parts have
+    /// already been passed to this hook.
+    fn on_part(
+        &mut self,
+        _prompt_name: &str,
+        _part: &PromptTemplatePart,
+        _rendered: &mut String,
+    ) -> Result<(), RenderTemplateError> {
+        Ok(())
+    }
+
+    /// Called before a referenced prompt is resolved, with the current nesting depth.
+    fn on_prompt_reference(
+        &mut self,
+        _referenced_from: &str,
+        _prompt_name: &str,
+        _depth: usize,
+    ) -> Result<(), RenderTemplateError> {
+        Ok(())
+    }
+
+    /// Called once with the final output of the render.
+    fn post_render(&mut self, _output: &mut String) -> Result<(), RenderTemplateError> {
+        Ok(())
+    }
+}
+
+/// A structured trace of a render, meant to be serialized and snapshot-tested.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenderPlan {
+    /// The name of the rendered prompt.
+    pub prompt: String,
+    /// A fingerprint of the rendered prompt's content.
+    pub fingerprint: String,
+    /// Every part rendered, in the order rendering finished. The parts of a referenced
+    /// prompt come before the part referencing it.
+    pub parts: Vec<PlannedPart>,
+    /// The prompts resolved while rendering, in the order they were referenced.
+    pub referenced_prompts: Vec<PlannedReference>,
+    /// The arguments used by the render, sorted by name.
+    pub arguments: BTreeMap<String, String>,
+    /// The final rendered text.
+    pub output: String,
+}
+
+/// A single part rendered as part of a [`RenderPlan`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedPart {
+    /// The name of the prompt the part belongs to.
+    pub prompt: String,
+    /// The kind of part (e.g. `literal`, `argument` or `prompt_reference`).
+    pub kind: String,
+    /// The argument, prompt, function call or tag the part refers to, if any.
+    pub target: Option<String>,
+    /// The text the part rendered to.
+    pub output: String,
+}
+
+/// A prompt resolved as part of a [`RenderPlan`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedReference {
+    /// The name of the referenced prompt.
+    pub name: String,
+    /// The name of the prompt holding the reference.
+    pub referenced_from: String,
+    /// The nesting depth the reference was found at.
+    pub depth: usize,
+    /// A fingerprint of the referenced prompt's content, to notice when it changes.
+    pub fingerprint: String,
+}
+
+/// A trace of a render: the tree of prompts resolved, in the order they were resolved, with
+/// the span of the output each of them rendered to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenderTrace {
+    /// The rendered prompt, with the prompts it referenced.
+    pub root: TracedPrompt,
+    /// The final rendered text.
+    pub output: String,
+}
+
+/// A prompt resolved as part of a [`RenderTrace`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TracedPrompt {
+    /// The name of the prompt.
+    pub name: String,
+    /// The order the prompt was resolved in, from 0 for the rendered prompt.
+    pub order: usize,
+    /// The nesting depth of the prompt, from 0 for the rendered prompt.
+    pub depth: usize,
+    /// The byte range of the output the prompt rendered to, including the prompts it
+    /// references.
+    pub span: Range<usize>,
+    /// The prompts referenced by the prompt, in the order they appear in the output.
+    pub references: Vec<TracedPrompt>,
+}
+
+/// What a render would do, worked out without producing the final output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenderDryRun {
+    /// The name of the prompt.
+    pub prompt: String,
+    /// The prompts referenced by the prompt, each with the prompts it references.
+    pub references: Vec<ReferenceNode>,
+    /// Every argument used by the prompt and the prompts it references.
+    pub arguments: Vec<DryRunArgument>,
+    /// The estimated token count of the rendered prompt, with missing arguments left
+    /// as placeholders. `None` if the prompt can't be rendered.
+    pub estimated_tokens: Option<usize>,
+    /// Why the prompt can't be rendered, other than missing arguments.
+    pub error: Option<String>,
+}
+
+impl RenderDryRun {
+    /// Whether the render would succeed.
+    pub fn is_renderable(&self) -> bool {
+        self.error.is_none()
+            && self
+                .arguments
+                .iter()
+                .all(|argument| argument.status != ArgumentStatus::Missing)
+    }
+}
+
+/// A prompt reference in the tree of a [`RenderDryRun`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReferenceNode {
+    /// The name of the referenced prompt, unless it's selected by a missing argument.
+    pub name: Option<String>,
+    /// The argument selecting the prompt, for variable references.
+    pub variable: Option<String>,
+    /// Whether the reference can be left out to fit a token budget.
+    pub optional: bool,
+    /// Why the reference can't be resolved, if it can't.
+    pub error: Option<String>,
+    /// The prompts referenced by the referenced prompt.
+    pub references: Vec<ReferenceNode>,
+}
+
+/// An argument used by a render, as found by a [`RenderDryRun`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DryRunArgument {
+    /// The name of the argument.
+    pub name: String,
+    /// The prompt where the argument was first found.
+    pub prompt: String,
+    /// Whether the argument was provided.
+    pub status: ArgumentStatus,
+    /// The value used when the argument isn't provided.
+    pub default: Option<String>,
+    /// What the argument is for.
+    pub description: Option<String>,
+}
+
+/// Whether an argument of a [`RenderDryRun`] was provided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgumentStatus {
+    /// The argument was provided.
+    Provided,
+    /// The argument wasn't provided, but has a default value.
+    Default,
+    /// The argument wasn't provided and is required.
+    Missing,
+}
+
+/// Hooks recording the parts and references seen while building a [`RenderPlan`].
+#[derive(Default)]
+struct RenderPlanRecorder {
+    parts: Vec<PlannedPart>,
+    references: Vec<(String, String, usize)>,
+    argument_names: Vec<String>,
+}
+
+impl RenderHooks for RenderPlanRecorder {
+    fn on_part(
+        &mut self,
+        prompt_name: &str,
+        part: &PromptTemplatePart,
+        rendered: &mut String,
+    ) -> Result<(), RenderTemplateError> {
+        let (kind, target) = match part {
+            PromptTemplatePart::Literal(_) => ("literal", None),
+            PromptTemplatePart::Argument(name) => {
+                self.argument_names.push(name.clone());
+                ("argument", Some(name.clone()))
+            }
+            PromptTemplatePart::PromptReference(name) => ("prompt_reference", Some(name.clone())),
+            PromptTemplatePart::VariablePromptReference(name) => {
+                self.argument_names.push(name.clone());
+                ("variable_prompt_reference", Some(name.clone()))
+            }
+            PromptTemplatePart::OptionalPromptReference(name) => {
+                ("optional_prompt_reference", Some(name.clone()))
+            }
+            PromptTemplatePart::Function(call) => {
+                for argument in &call.arguments {
+                    if let FunctionArgument::Argument(name) = argument {
+                        self.argument_names.push(name.clone());
+                    }
+                }
+                ("function", Some(call.to_string()))
+            }
+            PromptTemplatePart::Examples(directive) => ("examples", Some(directive.tag.clone())),
+            PromptTemplatePart::TaggedPrompts(directive) => {
+                ("tagged_prompts", Some(directive.tag.clone()))
+            }
+        };
+        self.parts.push(PlannedPart {
+            prompt: prompt_name.to_string(),
+            kind: kind.to_string(),
+            target,
+            output: rendered.clone(),
+        });
+        Ok(())
+    }
+
+    fn on_prompt_reference(
+        &mut self,
+        referenced_from: &str,
+        prompt_name: &str,
+        depth: usize,
+    ) -> Result<(), RenderTemplateError> {
+        self.references
+            .push((referenced_from.to_string(), prompt_name.to_string(), depth));
+        Ok(())
+    }
+}
+
+/// Hooks building the tree of a [`RenderTrace`]. Spans are relative to the output of the
+/// referencing prompt until the render is finished.
+struct RenderTraceRecorder {
+    /// The prompts being rendered, from the rendered prompt to the innermost reference.
+    stack: Vec<TraceFrame>,
+    resolved: usize,
+}
+
+/// A prompt being rendered while tracing.
+struct TraceFrame {
+    node: TracedPrompt,
+    /// The output of the prompt so far.
+    text: String,
+    /// The referenced prompts rendered, but not placed in the output yet.
+    pending: Vec<TraceFrame>,
+}
+
+impl TraceFrame {
+    fn new(name: &str, order: usize, depth: usize) -> Self {
+        TraceFrame {
+            node: TracedPrompt {
+                name: name.to_string(),
+                order,
+                depth,
+                span: 0..0,
+                references: Vec::new(),
+            },
+            text: String::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Places the pending references in `rendered`, output by this prompt at `base`.
+    /// References are found in order, as they're rendered one after the other.
+    fn place_pending(&mut self, base: usize, rendered: &str) {
+        let mut cursor = 0;
+        for reference in std::mem::take(&mut self.pending) {
+            let start = rendered[cursor..]
+                .find(&reference.text)
+                .map_or(cursor, |position| cursor + position);
+            cursor = (start + reference.text.len()).min(rendered.len());
+            let mut node = reference.finish();
+            node.span = base + start..base + cursor;
+            self.node.references.push(node);
+        }
+    }
+
+    /// Finishes the prompt, placing the references left in its output, e.g. those of
+    /// prompts in other template dialects, whose parts aren't traced.
+    fn finish(mut self) -> TracedPrompt {
+        let text = std::mem::take(&mut self.text);
+        self.place_pending(0, &text);
+        self.node.span = 0..text.len();
+        self.node
+    }
+}
+
+impl RenderTraceRecorder {
+    fn new(name: &str) -> Self {
+        RenderTraceRecorder {
+            stack: vec![TraceFrame::new(name, 0, 0)],
+            resolved: 0,
+        }
+    }
+
+    /// Finishes the prompts rendered after `name`, which are done once `name` goes on.
+    fn unwind(&mut self, name: &str) {
+        while self.stack.len() > 1 && self.stack.last().is_some_and(|f| f.node.name != name) {
+            if let (Some(frame), Some(parent)) = (self.stack.pop(), self.stack.last_mut()) {
+                parent.pending.push(frame);
+            }
+        }
+    }
+
+    /// Finishes the trace of a render with the given output.
+    fn finish(mut self, output: String) -> RenderTrace {
+        self.unwind("");
+        let mut root = self.stack.remove(0);
+        // The parts of prompts in other dialects aren't traced, so only the output is known
+        if root.text != output {
+            root.text.clone_from(&output);
+        }
+        let mut root = root.finish();
+        make_spans_absolute(&mut root, 0);
+        RenderTrace { root, output }
+    }
+}
+
+/// Shifts the spans of the references of a traced prompt, relative to it, by its start.
+fn make_spans_absolute(node: &mut TracedPrompt, offset: usize) {
+    node.span = offset + node.span.start..offset + node.span.end;
+    let start = node.span.start;
+    for reference in &mut node.references {
+        make_spans_absolute(reference, start);
+    }
+}
+
+impl RenderHooks for RenderTraceRecorder {
+    fn on_part(
+        &mut self,
+        prompt_name: &str,
+        part: &PromptTemplatePart,
+        rendered: &mut String,
+    ) -> Result<(), RenderTemplateError> {
+        self.unwind(prompt_name);
+        let Some(frame) = self.stack.last_mut() else {
+            return Ok(());
+        };
+        // A single reference rendered to the whole part, even in another dialect
+        if let [reference] = frame.pending.as_mut_slice()
+            && matches!(
+                part,
+                PromptTemplatePart::PromptReference(_)
+                    | PromptTemplatePart::OptionalPromptReference(_)
+                    | PromptTemplatePart::VariablePromptReference(_)
+            )
+        {
+            reference.text.clone_from(rendered);
+        }
+        let base = frame.text.len();
+        frame.place_pending(base, rendered);
+        frame.text.push_str(rendered);
+        Ok(())
+    }
+
+    fn on_prompt_reference(
+        &mut self,
+        referenced_from: &str,
+        prompt_name: &str,
+        depth: usize,
+    ) -> Result<(), RenderTemplateError> {
+        self.unwind(referenced_from);
+        self.resolved += 1;
+        self.stack
+            .push(TraceFrame::new(prompt_name, self.resolved, depth + 1));
+        Ok(())
+    }
+}
+
+/// Computes a stable fingerprint (64-bit FNV-1a, in hex) of a prompt's content.
+fn fingerprint(content: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// The error of a referenced prompt that couldn't be retrieved: not found if the storage
+/// doesn't have it, a storage error otherwise.
+fn reference_error<S: PromptStorage>(
+    error: S::Error,
+    name: &str,
+    referenced_from: &str,
+) -> RenderTemplateError {
+    if S::is_not_found(&error) {
+        RenderTemplateError::PromptNotFound {
+            name: name.to_string(),
+            referenced_from: referenced_from.to_string(),
+        }
+    } else {
+        RenderTemplateError::Storage {
+            name: name.to_string(),
+            referenced_from: referenced_from.to_string(),
+            source: Box::new(error),
+        }
+    }
 }
 
 /// A context for validating prompt templates during rendering, tracking visited prompts and current depth
-#[derive(Debug, Clone)]
-struct RenderValidationContext {
-    /// The names of prompts visited in the current rendering path (to detect circular references)
-    visited_prompts: HashSet<String>,
+struct RenderValidationContext<'a> {
+    /// The names of prompts visited in the current rendering path, in order (to detect circular references)
+    visited_prompts: Vec<String>,
     /// The current nesting depth
     current_depth: usize,
+    /// Whether missing arguments are left as placeholders instead of failing the render
+    partial: bool,
+    /// The arguments found missing during a partial render
+    missing_arguments: Vec<String>,
+    /// The hooks called during rendering, if any
+    hooks: Option<&'a mut dyn RenderHooks>,
+    /// The functions available to the template, if any
+    functions: Option<&'a FunctionRegistry>,
+    /// The warnings found while rendering
+    warnings: Vec<RenderWarning>,
+    /// The names of the arguments looked up while rendering
+    used_arguments: HashSet<String>,
 }
 
-impl RenderValidationContext {
-    fn new() -> Self {
+impl<'a> RenderValidationContext<'a> {
+    /// Creates a context for rendering the prompt `root`, which starts the chain of visited
+    /// prompts so it can't be referenced either.
+    fn new(root: &str) -> Self {
         RenderValidationContext {
-            visited_prompts: HashSet::new(),
+            visited_prompts: vec![root.to_string()],
             current_depth: 0,
+            partial: false,
+            missing_arguments: Vec::new(),
+            hooks: None,
+            functions: None,
+            warnings: Vec::new(),
+            used_arguments: HashSet::new(),
+        }
+    }
+
+    fn with_functions(root: &str, functions: &'a FunctionRegistry) -> Self {
+        RenderValidationContext {
+            functions: Some(functions),
+            ..Self::new(root)
+        }
+    }
+
+    fn with_hooks(root: &str, hooks: &'a mut dyn RenderHooks) -> Self {
+        RenderValidationContext {
+            hooks: Some(hooks),
+            ..Self::new(root)
+        }
+    }
+
+    fn new_partial(root: &str) -> Self {
+        RenderValidationContext {
+            partial: true,
+            ..Self::new(root)
+        }
+    }
+
+    fn record_missing_argument(&mut self, name: &str) {
+        if !self.missing_arguments.iter().any(|arg| arg == name) {
+            self.missing_arguments.push(name.to_string());
+        }
+    }
+
+    fn use_argument(&mut self, name: &str) {
+        self.used_arguments.insert(name.to_string());
+    }
+
+    /// Adds the warnings that depend on the whole render: arguments that weren't used and
+    /// very large output, given the number of tokens rendered.
+    fn finish<A: TemplateArguments>(&mut self, arguments: &A, tokens: usize) {
+        // Structured arguments are used through any of their paths, e.g. `user.name`
+        let mut unused: Vec<String> = arguments
+            .names()
+            .into_iter()
+            .filter(|name| {
+                !self.used_arguments.iter().any(|used| {
+                    used == name
+                        || used
+                            .strip_prefix(name.as_str())
+                            .is_some_and(|path| path.starts_with('.'))
+                })
+            })
+            .collect();
+        if !unused.is_empty() {
+            unused.sort();
+            self.warnings
+                .push(RenderWarning::UnusedArguments { names: unused });
+        }
+        if tokens > LARGE_OUTPUT_TOKENS {
+            self.warnings.push(RenderWarning::LargeOutput { tokens });
+        }
+    }
+
+    fn check_deprecation(&mut self, metadata: &PromptMetadata) {
+        let warning = RenderWarning::DeprecatedPrompt {
+            name: metadata.name.clone(),
+            superseded_by: metadata.superseded_by.clone(),
+        };
+        if metadata.deprecated && !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
         }
     }
 
     fn enter_prompt(&mut self, prompt_name: &str) -> Result<(), RenderTemplateError> {
         // Check for circular references
-        if self.visited_prompts.contains(prompt_name) {
-            return Err(RenderTemplateError {
-                message: format!(
-                    "Circular reference detected: prompt '{}' references itself (directly or indirectly)",
-                    prompt_name
-                ),
-            });
+        if let Some(start) = self.visited_prompts.iter().position(|p| p == prompt_name) {
+            let mut chain = self.visited_prompts[start..].to_vec();
+            chain.push(prompt_name.to_string());
+            return Err(RenderTemplateError::CircularReference { chain });
         }
 
         // Check depth limit
         if self.current_depth >= MAX_NESTING_DEPTH {
-            return Err(RenderTemplateError {
-                message: format!("Maximum nesting depth of {} exceeded", MAX_NESTING_DEPTH),
+            return Err(RenderTemplateError::DepthExceeded {
+                limit: MAX_NESTING_DEPTH,
             });
         }
 
-        self.visited_prompts.insert(prompt_name.to_string());
+        self.visited_prompts.push(prompt_name.to_string());
         self.current_depth += 1;
+        if self.current_depth == MAX_NESTING_DEPTH {
+            let warning = RenderWarning::NearDepthLimit {
+                name: prompt_name.to_string(),
+                depth: self.current_depth,
+            };
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
         Ok(())
     }
 
     fn exit_prompt(&mut self, prompt_name: &str) {
-        self.visited_prompts.remove(prompt_name);
+        self.visited_prompts.retain(|p| p != prompt_name);
         self.current_depth -= 1;
     }
 }
 
+/// Normalizes a tag, so that tags differing only in case or surrounding whitespace match.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalizes tags with [`normalize_tag`], dropping empty and duplicate ones.
+pub fn normalize_tags<I, T>(tags: I) -> Vec<String>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<str>,
+{
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag.as_ref());
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
 impl PromptMetadata {
     pub fn new(name: String, description: Option<String>, tags: Vec<String>) -> PromptMetadata {
         PromptMetadata {
             name,
             description,
             tags,
+            author: None,
+            source_url: None,
+            license: None,
+            arguments: Vec::new(),
+            deprecated: false,
+            superseded_by: None,
+            pinned: false,
+            locked: false,
+            model_settings: ModelSettings::default(),
+            output_format: None,
+            guardrails: None,
+            tests: Vec::new(),
+            evals: Vec::new(),
+            tools: Vec::new(),
+            dialect: Dialect::Pren,
+            extra: BTreeMap::new(),
         }
     }
 }
@@ -144,28 +1122,193 @@ impl Prompt {
     pub fn new(metadata: PromptMetadata, content: String) -> Prompt {
         Prompt { metadata, content }
     }
-}
 
-impl PromptTemplate {
-    /// Creates a new prompt template.
+    /// Creates a builder to construct a validated prompt.
     ///
-    /// # Arguments
+    /// # Examples
     ///
-    /// * `name` - The name of the prompt.
-    /// * `content` - The content of the prompt with template syntax.
-    /// * `template` - The PromptTemplate resulting of parsing the content.
-    /// * `tags` - A vector of tags associated with the prompt.
+    /// ```rust
+    /// use pren_core::prompt::Prompt;
+    ///
+    /// let prompt = Prompt::builder()
+    ///     .name("greeting")
+    ///     .description("A simple greeting")
+    ///     .tag(" general ")
+    ///     .content("Hello, {{name}}!")
+    ///     .build()
+    ///     .expect("Invalid prompt");
+    /// assert_eq!(prompt.metadata.tags, vec!["general"]);
+    /// ```
+    pub fn builder() -> PromptBuilder {
+        PromptBuilder::default()
+    }
+
+    /// Replaces the references to a prompt with references to another one: the
+    /// `{{prompt:old}}` and `{{prompt_opt:old}}` tags in the content, and `superseded_by`.
+    /// Escaped tags are left as they are.
     ///
     /// # Returns
     ///
-    /// * `Ok(Prompt)` - A new `Prompt::Template` variant.
-    /// * `Err(ParseTemplateError)` - If the template syntax is invalid.
+    /// * `bool` - Whether any reference was replaced.
+    pub fn replace_prompt_references(&mut self, old: &str, new: &str) -> bool {
+        let pattern = Regex::new(&format!(
+            r"\{{\{{\s*(prompt|prompt_opt)\s*:\s*{}\s*\}}\}}",
+            regex::escape(old)
+        ))
+        .expect("Invalid prompt reference pattern");
+        // Escaped literals, like `{{{{prompt:old}}}}`, whose content isn't parsed
+        let escaped: Vec<_> = Regex::new(r"(?s)\{\{\{\{.*?\}\}\}\}")
+            .expect("Invalid escaped literal pattern")
+            .find_iter(&self.content)
+            .map(|found| found.range())
+            .collect();
+
+        let mut content = String::new();
+        let mut last_end = 0;
+        for found in pattern.captures_iter(&self.content) {
+            let (Some(whole), Some(kind)) = (found.get(0), found.get(1)) else {
+                continue;
+            };
+            // Skip escaped tags: `{{{{prompt:old}}}}` and `\{{prompt:old}}`
+            if escaped.iter().any(|range| range.contains(&whole.start()))
+                || self.content[..whole.start()].ends_with('\\')
+            {
+                continue;
+            }
+            content.push_str(&self.content[last_end..whole.start()]);
+            content.push_str(&format!("{{{{{}:{}}}}}", kind.as_str(), new));
+            last_end = whole.end();
+        }
+
+        let mut replaced = last_end > 0;
+        if replaced {
+            content.push_str(&self.content[last_end..]);
+            self.content = content;
+        }
+        if self.metadata.superseded_by.as_deref() == Some(old) {
+            self.metadata.superseded_by = Some(new.to_string());
+            replaced = true;
+        }
+        replaced
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PromptBuilderError {
+    #[error("the prompt needs a name")]
+    MissingName,
+    #[error("invalid prompt name '{0}': use letters, digits, '-', '_' and '.'")]
+    InvalidName(String),
+    #[error("the prompt content can't be empty")]
+    EmptyContent,
+}
+
+/// A builder for [`Prompt`]s, created with [`Prompt::builder`].
+///
+/// Building validates the prompt: the name must be usable in prompt references, tags are
+/// normalized with [`normalize_tags`], and the content can't be empty.
+#[derive(Debug, Clone, Default)]
+pub struct PromptBuilder {
+    name: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+    author: Option<String>,
+    content: String,
+}
+
+impl PromptBuilder {
+    pub fn name(mut self, name: impl Into<String>) -> PromptBuilder {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> PromptBuilder {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Adds a tag to the prompt.
+    pub fn tag(mut self, tag: impl Into<String>) -> PromptBuilder {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Adds several tags to the prompt.
+    pub fn tags<I, T>(mut self, tags: I) -> PromptBuilder
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn author(mut self, author: impl Into<String>) -> PromptBuilder {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn content(mut self, content: impl Into<String>) -> PromptBuilder {
+        self.content = content.into();
+        self
+    }
+
+    /// Validates the prompt and builds it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Prompt)` - The prompt.
+    /// * `Err(PromptBuilderError)` - If the name is missing or invalid, or the content is empty.
+    pub fn build(self) -> Result<Prompt, PromptBuilderError> {
+        let name = self.name.ok_or(PromptBuilderError::MissingName)?;
+        if !is_valid_prompt_name(&name) {
+            return Err(PromptBuilderError::InvalidName(name));
+        }
+        if self.content.trim().is_empty() {
+            return Err(PromptBuilderError::EmptyContent);
+        }
+
+        let mut metadata = PromptMetadata::new(name, self.description, normalize_tags(&self.tags));
+        metadata.author = self.author;
+        Ok(Prompt::new(metadata, self.content))
+    }
+}
+
+impl PromptTemplate {
+    /// Creates a new prompt template.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the prompt.
+    /// * `content` - The content of the prompt with template syntax.
+    /// * `template` - The PromptTemplate resulting of parsing the content.
+    /// * `tags` - A vector of tags associated with the prompt.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Prompt)` - A new `Prompt::Template` variant.
+    /// * `Err(ParseTemplateError)` - If the template syntax is invalid.
     pub fn new(prompt: Prompt) -> Result<PromptTemplate, ParseTemplateError> {
-        match parse_template(&prompt.content) {
-            Ok((_, template_parts)) => Ok(PromptTemplate {
+        // Templates in other dialects are kept whole, and rendered by their own engine
+        if !prompt.metadata.dialect.is_pren() {
+            dialect::validate(prompt.metadata.dialect, &prompt.content)?;
+            return Ok(PromptTemplate {
+                parts: vec![PromptTemplatePart::Literal(prompt.content.clone())],
                 prompt,
-                parts: template_parts,
-            }),
+            });
+        }
+        match parse_template(&prompt.content) {
+            Ok((_, template_parts)) => {
+                debug!(
+                    "Parsed the template of '{}' into {} part(s)",
+                    prompt.metadata.name,
+                    template_parts.len()
+                );
+                Ok(PromptTemplate {
+                    prompt,
+                    parts: template_parts,
+                })
+            }
             Err(NomErr::Error(e)) | Err(NomErr::Failure(e)) => Err(ParseTemplateError {
                 message: format!("Failed to parse template: {:?}", e),
             }),
@@ -175,28 +1318,90 @@ impl PromptTemplate {
         }
     }
 
+    /// Returns the names of the arguments used by the template, including the ones passed
+    /// to function calls.
     pub fn arguments(&self) -> Vec<String> {
+        if !self.prompt.metadata.dialect.is_pren() {
+            return dialect::variables(self.prompt.metadata.dialect, &self.prompt.content);
+        }
         self.parts
             .iter()
-            .filter_map(|part| {
-                if let PromptTemplatePart::Argument(arg) = part {
-                    Some(arg.clone())
-                } else {
-                    None
+            .flat_map(|part| match part {
+                PromptTemplatePart::Argument(arg) => vec![arg.clone()],
+                PromptTemplatePart::Function(call) => call
+                    .arguments
+                    .iter()
+                    .filter_map(|argument| match argument {
+                        FunctionArgument::Argument(arg) => Some(arg.clone()),
+                        FunctionArgument::Literal(_) => None,
+                    })
+                    .collect(),
+                _ => vec![],
+            })
+            .collect()
+    }
+
+    /// Returns information about every argument of the template: the ones used in its content
+    /// (including the ones selecting a prompt in variable references) and the declared ones.
+    ///
+    /// Arguments are required unless their declaration has a default value.
+    pub fn argument_info(&self) -> Vec<ArgumentInfo> {
+        let mut names = self.arguments();
+        names.extend(self.variable_prompt_references());
+        names.extend(
+            self.prompt
+                .metadata
+                .arguments
+                .iter()
+                .map(|declaration| declaration.name.clone()),
+        );
+
+        let mut seen = HashSet::new();
+        names
+            .into_iter()
+            .filter(|name| seen.insert(name.clone()))
+            .map(|name| {
+                let declaration = self.argument_declaration(&name);
+                let default = declaration.and_then(|d| d.default.clone());
+                ArgumentInfo {
+                    required: default.is_none(),
+                    default,
+                    description: declaration.and_then(|d| d.description.clone()),
+                    name,
                 }
             })
             .collect()
     }
 
+    fn argument_declaration(&self, name: &str) -> Option<&ArgumentDeclaration> {
+        self.prompt
+            .metadata
+            .arguments
+            .iter()
+            .find(|declaration| declaration.name == name)
+    }
+
+    /// Looks up an argument, falling back to the default value of its declaration
+    fn lookup_argument<A: TemplateArguments>(
+        &self,
+        arguments: &A,
+        name: &str,
+        context: &mut RenderValidationContext<'_>,
+    ) -> Option<String> {
+        context.use_argument(name);
+        arguments
+            .lookup(name)
+            .or_else(|| self.argument_declaration(name)?.default.clone())
+    }
+
+    /// Returns the names of the prompts referenced statically, including optional references.
     pub fn prompt_references(&self) -> Vec<String> {
         self.parts
             .iter()
-            .filter_map(|part| {
-                if let PromptTemplatePart::PromptReference(prompt) = part {
-                    Some(prompt.clone())
-                } else {
-                    None
-                }
+            .filter_map(|part| match part {
+                PromptTemplatePart::PromptReference(prompt)
+                | PromptTemplatePart::OptionalPromptReference(prompt) => Some(prompt.clone()),
+                _ => None,
             })
             .collect()
     }
@@ -215,662 +1420,2531 @@ impl PromptTemplate {
     }
 
     pub fn is_simple(&self) -> bool {
-        self.arguments().len() == 0
-            && self.prompt_references().len() == 0
-            && self.variable_prompt_references().len() == 0
+        self.parts
+            .iter()
+            .all(|part| matches!(part, PromptTemplatePart::Literal(_)))
     }
 
-    pub fn render<S: PromptStorage>(
+    /// Renders the template with the given arguments.
+    ///
+    /// Warnings found along the way (e.g., deprecated prompts or unused arguments) aren't
+    /// collected, so rendering skips the work of finding them. Use
+    /// [`PromptTemplate::render_with_warnings`] to get them.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The rendered prompt.
+    /// * `Err(RenderTemplateError)` - If rendering fails.
+    pub fn render<A: TemplateArguments, S: PromptStorage>(
         &self,
-        arguments: &HashMap<String, String>,
+        arguments: &A,
         storage: &S,
     ) -> Result<String, RenderTemplateError> {
-        let mut context = RenderValidationContext::new();
+        let mut context = RenderValidationContext::new(&self.prompt.metadata.name);
         self.render_internal(arguments, storage, &mut context)
     }
 
-    /// Internal rendering function with validation context
-    fn render_internal<S: PromptStorage>(
+    /// Renders the template, also returning the warnings found along the way
+    /// (e.g., deprecated prompts being rendered).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RenderOutput)` - The rendered prompt and its warnings.
+    /// * `Err(RenderTemplateError)` - If rendering fails.
+    pub fn render_with_warnings<A: TemplateArguments, S: PromptStorage>(
         &self,
-        arguments: &HashMap<String, String>,
+        arguments: &A,
         storage: &S,
-        context: &mut RenderValidationContext,
-    ) -> Result<String, RenderTemplateError> {
-        let mut result = String::new();
+    ) -> Result<RenderOutput, RenderTemplateError> {
+        let mut context = RenderValidationContext::new(&self.prompt.metadata.name);
+        context.check_deprecation(&self.prompt.metadata);
+        let text = self.render_internal(arguments, storage, &mut context)?;
+        context.finish(arguments, count_tokens(&text));
+        Ok(RenderOutput {
+            text,
+            warnings: context.warnings,
+        })
+    }
 
-        for part in &self.parts {
-            match part {
-                PromptTemplatePart::Literal(text) => result.push_str(text),
-                PromptTemplatePart::Argument(name) => match arguments.get(name) {
-                    Some(value) => result.push_str(value),
-                    None => {
-                        return Err(RenderTemplateError {
-                            message: format!("Missing argument: {}", name),
-                        });
-                    }
-                },
-                PromptTemplatePart::PromptReference(name) => {
-                    self.render_prompt_reference(
-                        name,
-                        arguments,
-                        storage,
-                        context,
-                        &mut result,
-                        false,
-                    )?;
-                }
-                PromptTemplatePart::VariablePromptReference(name) => match arguments.get(name) {
-                    Some(value) => {
-                        self.render_prompt_reference(
-                            value,
-                            arguments,
-                            storage,
-                            context,
-                            &mut result,
-                            true,
-                        )?;
-                    }
-                    None => {
-                        return Err(RenderTemplateError {
-                            message: format!("Missing argument: {}", name),
-                        });
-                    }
-                },
-            }
-        }
-        Ok(result)
+    /// Renders the template as a list of chat messages.
+    ///
+    /// The rendered prompt is split by its role markers (e.g., `<|system|>`), so referenced
+    /// prompts can add messages too. Prompts without markers render to a single user message.
+    /// See the [`messages`](crate::messages) module for the marker syntax.
+    pub fn render_messages<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+    ) -> Result<Vec<RenderedMessage>, RenderTemplateError> {
+        Ok(split_messages(&self.render(arguments, storage)?))
     }
 
-    /// Helper function to render a prompt reference
-    fn render_prompt_reference<S: PromptStorage>(
+    /// Renders the template as the body of an OpenAI-compatible chat completion request,
+    /// with its messages split as in [`PromptTemplate::render_messages`] and the prompt's
+    /// preferred model settings.
+    pub fn render_as_messages<A: TemplateArguments, S: PromptStorage>(
         &self,
-        prompt_name: &str,
-        arguments: &HashMap<String, String>,
+        arguments: &A,
         storage: &S,
-        context: &mut RenderValidationContext,
-        result: &mut String,
-        is_variable_reference: bool,
-    ) -> Result<(), RenderTemplateError> {
-        // Validate before resolving the prompt reference
-        context.enter_prompt(prompt_name)?;
+    ) -> Result<ChatRequest, RenderTemplateError> {
+        Ok(ChatRequest::from_rendered(
+            &self.render(arguments, storage)?,
+            &self.prompt.metadata.model_settings,
+        ))
+    }
 
-        match storage.get_prompt(prompt_name) {
-            Ok(prompt) => match PromptTemplate::new(prompt) {
-                Ok(template) => match template.render_internal(arguments, storage, context) {
-                    Ok(rendered) => result.push_str(&rendered),
-                    Err(e) => {
-                        context.exit_prompt(prompt_name);
-                        return Err(RenderTemplateError {
-                            message: format!(
-                                "Failed to render referenced prompt '{}': {}",
-                                prompt_name, e.message
-                            ),
-                        });
-                    }
-                },
-                Err(e) => {
-                    context.exit_prompt(prompt_name);
-                    return Err(RenderTemplateError {
-                        message: format!(
-                            "Error parsing referenced prompt '{}': {}",
-                            prompt_name, e
-                        ),
-                    });
-                }
-            },
-            Err(e) => {
-                context.exit_prompt(prompt_name);
-                return Err(RenderTemplateError {
-                    message: format!(
-                        "Error retrieving referenced prompt '{}': {}",
-                        prompt_name, e
-                    ),
-                });
+    /// Renders the template with whatever arguments are available.
+    ///
+    /// Missing arguments (including the ones used by variable prompt references) are left
+    /// as placeholders in the output instead of causing an error, so the result can be
+    /// stored and rendered again once the remaining arguments are known.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(PartialRender)` - The partially rendered text and the missing arguments.
+    /// * `Err(RenderTemplateError)` - If a referenced prompt can't be resolved.
+    pub fn render_partial<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+    ) -> Result<PartialRender, RenderTemplateError> {
+        let mut context = RenderValidationContext::new_partial(&self.prompt.metadata.name);
+        let text = self.render_internal(arguments, storage, &mut context)?;
+        Ok(PartialRender {
+            text,
+            missing_arguments: context.missing_arguments,
+        })
+    }
+
+    /// Renders the template so that it fits within a token budget.
+    ///
+    /// If the rendered prompt is over budget, optional prompt references
+    /// (`{{prompt_opt:name}}`) are left out one by one, starting from the last one,
+    /// until the prompt fits. Tokens are counted with [`count_tokens`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The rendered prompt, within budget.
+    /// * `Err(RenderTemplateError)` - If rendering fails or the prompt can't fit the budget.
+    pub fn render_with_budget<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        max_tokens: usize,
+    ) -> Result<String, RenderTemplateError> {
+        let mut context = RenderValidationContext::new(&self.prompt.metadata.name);
+        self.render_within_budget(arguments, storage, &mut context, max_tokens)
+    }
+
+    fn render_within_budget<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+        max_tokens: usize,
+    ) -> Result<String, RenderTemplateError> {
+        let mut rendered_parts = if self.prompt.metadata.dialect.is_pren() {
+            self.parts
+                .iter()
+                .map(|part| self.render_part(part, arguments, storage, context))
+                .collect::<Result<Vec<String>, RenderTemplateError>>()?
+        } else {
+            vec![self.render_dialect(arguments, storage, context)?]
+        };
+
+        let mut tokens = count_tokens(&rendered_parts.concat());
+        for (index, part) in self.parts.iter().enumerate().rev() {
+            if tokens <= max_tokens {
+                break;
+            }
+            if matches!(part, PromptTemplatePart::OptionalPromptReference(_)) {
+                rendered_parts[index].clear();
+                tokens = count_tokens(&rendered_parts.concat());
             }
         }
 
-        // Exit the prompt after successful rendering
-        // For variable references, the caller is responsible for exiting
-        if !is_variable_reference {
-            context.exit_prompt(prompt_name);
+        if tokens > max_tokens {
+            return Err(RenderTemplateError::Other(format!(
+                "Rendered prompt has {} tokens, exceeding the budget of {} tokens",
+                tokens, max_tokens
+            )));
         }
-        Ok(())
+        Ok(rendered_parts.concat())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::storage::PromptStorage;
-
-    #[test]
-    fn test_new_simple_prompt() {
-        let name = "prompt_name";
-        let description = Some("A simple description".to_string());
-        let content = "This is the prompt content";
-        let tags = vec!["tag1".to_string(), "tag2".to_string()];
-
-        let metadata = PromptMetadata::new(name.to_string(), description, tags.clone());
-        let prompt = Prompt::new(metadata, content.to_string());
-
-        let result = PromptTemplate::new(prompt);
 
-        assert!(result.is_ok());
-
-        let prompt_template = result.unwrap();
-
-        assert_eq!(name, prompt_template.prompt.metadata.name);
-        assert_eq!(content, prompt_template.prompt.content);
-        assert_eq!(1, prompt_template.parts.len());
-        assert_eq!(2, prompt_template.prompt.metadata.tags.len());
-        assert_eq!(tags[0], prompt_template.prompt.metadata.tags[0]);
-        assert_eq!(tags[1], prompt_template.prompt.metadata.tags[1]);
+    /// Renders the template, evaluating function calls with the given registry.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The rendered prompt.
+    /// * `Err(RenderTemplateError)` - If rendering fails, including unknown or failing functions.
+    pub fn render_with_functions<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        functions: &FunctionRegistry,
+    ) -> Result<String, RenderTemplateError> {
+        let mut context =
+            RenderValidationContext::with_functions(&self.prompt.metadata.name, functions);
+        self.render_internal(arguments, storage, &mut context)
     }
 
-    #[test]
-    fn test_new_template_prompt() {
-        let name = "complex_prompt";
-        let content = "Hello {{name}}, welcome to {{prompt:greeting}}! {{{{literal_braces}}}}";
-        let tags = vec!["tag1".to_string(), "tag2".to_string()];
-
-        let metadata = PromptMetadata::new(name.to_string(), None, tags.clone());
-        let prompt = Prompt::new(metadata, content.to_string());
-
-        let result = PromptTemplate::new(prompt);
-        assert!(result.is_ok());
-
-        let template = result.unwrap();
-        assert_eq!(name, template.prompt.metadata.name);
-        assert_eq!(content, template.prompt.content);
-
-        assert_eq!(6, template.parts.len());
-
-        // Check each part
-        match &template.parts[0] {
-            PromptTemplatePart::Literal(text) => assert_eq!("Hello ", text),
-            _ => panic!("Expected Literal part"),
-        }
-
-        match &template.parts[1] {
-            PromptTemplatePart::Argument(arg) => assert_eq!("name", arg),
-            _ => panic!("Expected Argument part"),
-        }
+    /// Renders the template into a writer, streaming each part as soon as it's resolved
+    /// instead of building the whole prompt in memory. A referenced prompt is one part,
+    /// written once it's rendered, so the output is the same as with [`PromptTemplate::render`].
+    ///
+    /// If rendering fails, the parts rendered before the error have already been written.
+    ///
+    /// The warnings are the same as with [`PromptTemplate::render_with_warnings`]. As the
+    /// whole prompt is never in memory, its tokens are counted part by part, so the count
+    /// in a [`RenderWarning::LargeOutput`] can differ slightly.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<RenderWarning>)` - The warnings found while rendering.
+    /// * `Err(RenderTemplateError)` - If rendering or writing fails.
+    pub fn render_to_writer<A: TemplateArguments, S: PromptStorage, W: Write>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        writer: &mut W,
+    ) -> Result<Vec<RenderWarning>, RenderTemplateError> {
+        let mut context = RenderValidationContext::new(&self.prompt.metadata.name);
+        context.check_deprecation(&self.prompt.metadata);
+        let tokens = self.render_internal_to_writer(arguments, storage, &mut context, writer)?;
+        context.finish(arguments, tokens);
+        Ok(context.warnings)
+    }
 
-        match &template.parts[2] {
-            PromptTemplatePart::Literal(text) => assert_eq!(", welcome to ", text),
-            _ => panic!("Expected Literal part"),
+    /// Renders the template, calling the given hooks along the way.
+    ///
+    /// See [`RenderHooks`] for the points where hooks are called.
+    pub fn render_with_hooks<A: TemplateArguments, S: PromptStorage, H: RenderHooks>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        hooks: &mut H,
+    ) -> Result<String, RenderTemplateError> {
+        let mut context = RenderValidationContext::with_hooks(&self.prompt.metadata.name, hooks);
+        let mut output = self.render_internal(arguments, storage, &mut context)?;
+        if let Some(hooks) = context.hooks.as_deref_mut() {
+            hooks.post_render(&mut output)?;
         }
+        Ok(output)
+    }
 
-        match &template.parts[3] {
-            PromptTemplatePart::PromptReference(prompt_name) => {
-                assert_eq!("greeting", prompt_name)
+    /// Renders the template with the given options, calling the hooks, if any, along the
+    /// way, and returns the rendered prompt with its warnings.
+    pub fn render_with_options<'a, A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        options: RenderOptions<'a>,
+        hooks: Option<&'a mut dyn RenderHooks>,
+    ) -> Result<RenderOutput, RenderTemplateError> {
+        let mut context = RenderValidationContext {
+            functions: options.functions,
+            hooks,
+            ..RenderValidationContext::new(&self.prompt.metadata.name)
+        };
+        context.check_deprecation(&self.prompt.metadata);
+        let mut text = match options.max_tokens {
+            Some(max_tokens) => {
+                self.render_within_budget(arguments, storage, &mut context, max_tokens)?
             }
-            _ => panic!("Expected PromptReference part"),
+            None => self.render_internal(arguments, storage, &mut context)?,
+        };
+        if let Some(hooks) = context.hooks.as_deref_mut() {
+            hooks.post_render(&mut text)?;
         }
+        context.finish(arguments, count_tokens(&text));
+        Ok(RenderOutput {
+            text,
+            warnings: context.warnings,
+        })
+    }
 
-        match &template.parts[4] {
-            PromptTemplatePart::Literal(text) => assert_eq!("! ", text),
-            _ => panic!("Expected Literal part"),
+    /// Renders the template and returns a structured trace of the render.
+    ///
+    /// The plan lists the rendered parts, the referenced prompts (with a fingerprint of their
+    /// content) and the arguments used. Its output is deterministic for the same prompts and
+    /// arguments, so it can be serialized and compared against a snapshot.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RenderPlan)` - The trace of the render, including the rendered text.
+    /// * `Err(RenderTemplateError)` - If rendering fails.
+    pub fn render_plan<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+    ) -> Result<RenderPlan, RenderTemplateError> {
+        let mut recorder = RenderPlanRecorder::default();
+        let output = self.render_with_hooks(arguments, storage, &mut recorder)?;
+
+        let mut referenced_prompts = Vec::new();
+        for (referenced_from, name, depth) in recorder.references {
+            let prompt = storage
+                .get_prompt(&name)
+                .map_err(|e| reference_error::<S>(e, &name, &referenced_from))?;
+            referenced_prompts.push(PlannedReference {
+                fingerprint: fingerprint(&prompt.content),
+                name,
+                referenced_from,
+                depth,
+            });
         }
 
-        match &template.parts[5] {
-            PromptTemplatePart::Literal(text) => assert_eq!("literal_braces", text),
-            _ => panic!("Expected Literal part"),
-        }
+        let arguments = recorder
+            .argument_names
+            .into_iter()
+            .filter_map(|name| arguments.lookup(&name).map(|value| (name, value)))
+            .collect();
+
+        Ok(RenderPlan {
+            prompt: self.prompt.metadata.name.clone(),
+            fingerprint: fingerprint(&self.prompt.content),
+            parts: recorder.parts,
+            referenced_prompts,
+            arguments,
+            output,
+        })
     }
 
-    struct MockStorage {
-        prompts: HashMap<String, Prompt>,
+    /// Renders the template and returns, alongside the output, the tree of the prompts
+    /// resolved, in the order they were resolved, with the span of the output each of them
+    /// rendered to, to find which prompt a piece of a composition comes from.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RenderTrace)` - The trace of the render, including the rendered text.
+    /// * `Err(RenderTemplateError)` - If rendering fails.
+    pub fn render_traced<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+    ) -> Result<RenderTrace, RenderTemplateError> {
+        let mut recorder = RenderTraceRecorder::new(&self.prompt.metadata.name);
+        let output = self.render_with_hooks(arguments, storage, &mut recorder)?;
+        Ok(recorder.finish(output))
     }
 
-    impl MockStorage {
-        fn new() -> Self {
-            MockStorage {
-                prompts: HashMap::new(),
-            }
-        }
-
-        fn add_prompt(&mut self, prompt: Prompt) {
-            self.prompts.insert(prompt.metadata.name.clone(), prompt);
+    /// Works out what rendering the template would do, without producing the final output:
+    /// the tree of referenced prompts, which arguments are provided or missing, and the
+    /// estimated token count.
+    ///
+    /// Unlike rendering, a dry run doesn't stop at the first error. Unresolvable references
+    /// are reported in the tree, and missing arguments in the argument list.
+    pub fn dry_run<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+    ) -> RenderDryRun {
+        let mut context = RenderValidationContext::new(&self.prompt.metadata.name);
+        let mut argument_infos = Vec::new();
+        let references = self.reference_tree(arguments, storage, &mut context, &mut argument_infos);
+
+        let mut seen = HashSet::new();
+        let arguments_used = argument_infos
+            .into_iter()
+            .filter(|(_, info)| seen.insert(info.name.clone()))
+            .map(|(prompt, info)| DryRunArgument {
+                status: if arguments.lookup(&info.name).is_some() {
+                    ArgumentStatus::Provided
+                } else if info.default.is_some() {
+                    ArgumentStatus::Default
+                } else {
+                    ArgumentStatus::Missing
+                },
+                prompt,
+                name: info.name,
+                default: info.default,
+                description: info.description,
+            })
+            .collect();
+
+        let (estimated_tokens, error) = match self.render_partial(arguments, storage) {
+            Ok(partial) => (Some(count_tokens(&partial.text)), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        RenderDryRun {
+            prompt: self.prompt.metadata.name.clone(),
+            references,
+            arguments: arguments_used,
+            estimated_tokens,
+            error,
+        }
+    }
+
+    /// Builds the tree of the prompts referenced by the template, collecting the arguments
+    /// of every resolved prompt along with the name of the prompt using them.
+    fn reference_tree<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+        argument_infos: &mut Vec<(String, ArgumentInfo)>,
+    ) -> Vec<ReferenceNode> {
+        argument_infos.extend(
+            self.argument_info()
+                .into_iter()
+                .map(|info| (self.prompt.metadata.name.clone(), info)),
+        );
+
+        let mut nodes = Vec::new();
+        for part in &self.parts {
+            let (name, variable, optional) = match part {
+                PromptTemplatePart::PromptReference(name) => (Some(name.clone()), None, false),
+                PromptTemplatePart::OptionalPromptReference(name) => {
+                    (Some(name.clone()), None, true)
+                }
+                PromptTemplatePart::VariablePromptReference(variable) => (
+                    self.lookup_argument(arguments, variable, context),
+                    Some(variable.clone()),
+                    false,
+                ),
+                _ => continue,
+            };
+
+            let mut node = ReferenceNode {
+                name: name.clone(),
+                variable: variable.clone(),
+                optional,
+                error: None,
+                references: Vec::new(),
+            };
+            match (name, variable) {
+                (Some(name), _) => match self.enter_prompt_reference(&name, storage, context) {
+                    Ok(template) => {
+                        node.references =
+                            template.reference_tree(arguments, storage, context, argument_infos);
+                        context.exit_prompt(&name);
+                    }
+                    Err(e) => node.error = Some(e.to_string()),
+                },
+                (None, Some(variable)) => {
+                    node.error = Some(format!("Missing argument: {}", variable))
+                }
+                (None, None) => {}
+            }
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    /// Runs the test cases declared in the prompt's frontmatter.
+    ///
+    /// Each test case renders the prompt with its arguments and checks the output.
+    /// A test case that fails to render fails with the render error.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<PromptTestResult>` - The result of each test case, in order.
+    pub fn run_tests<S: PromptStorage>(&self, storage: &S) -> Vec<PromptTestResult> {
+        self.prompt
+            .metadata
+            .tests
+            .iter()
+            .enumerate()
+            .map(|(index, case)| {
+                let arguments: HashMap<String, Value> = case.args.clone().into_iter().collect();
+                let failures = match self.render(&arguments, storage) {
+                    Ok(rendered) => case.check(&rendered),
+                    Err(e) => vec![format!("failed to render: {}", e)],
+                };
+                PromptTestResult {
+                    prompt: self.prompt.metadata.name.clone(),
+                    case: case
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("#{}", index + 1)),
+                    failures,
+                }
+            })
+            .collect()
+    }
+
+    /// Internal rendering function with validation context
+    fn render_internal<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+    ) -> Result<String, RenderTemplateError> {
+        if !self.prompt.metadata.dialect.is_pren() {
+            return self.render_dialect(arguments, storage, context);
+        }
+        let mut result = String::new();
+        for part in &self.parts {
+            result.push_str(&self.render_part(part, arguments, storage, context)?);
+        }
+        Ok(result)
+    }
+
+    /// Renders a template in another dialect.
+    ///
+    /// The prompts it references are only known once it's rendered, so it's rendered again
+    /// each time it references prompts that weren't rendered yet, until all of them are.
+    /// A partial render with missing arguments leaves the template as it is.
+    fn render_dialect<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+    ) -> Result<String, RenderTemplateError> {
+        let dialect = self.prompt.metadata.dialect;
+        let mut variables = Map::new();
+        let mut missing = Vec::new();
+        for info in self.argument_info() {
+            context.use_argument(&info.name);
+            match arguments
+                .lookup_value(&info.name)
+                .or(info.default.map(Value::String))
+            {
+                Some(value) => {
+                    variables.insert(info.name, value);
+                }
+                None => missing.push(info.name),
+            }
+        }
+        if let Some(name) = missing.first() {
+            if !context.partial {
+                return Err(RenderTemplateError::MissingArgument { name: name.clone() });
+            }
+            for name in &missing {
+                context.record_missing_argument(name);
+            }
+            return Ok(self.prompt.content.clone());
+        }
+
+        let mut references = HashMap::new();
+        loop {
+            let render = dialect::render(dialect, &self.prompt.content, &variables, &references)?;
+            if render.missing_references.is_empty() {
+                return Ok(render.text);
+            }
+            for name in render.missing_references {
+                let mut rendered = String::new();
+                self.render_prompt_reference(&name, arguments, storage, context, &mut rendered)?;
+                references.insert(name, rendered);
+            }
+        }
+    }
+
+    /// Renders a single part of the template
+    fn render_part<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        part: &PromptTemplatePart,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+    ) -> Result<String, RenderTemplateError> {
+        let mut rendered_part = String::new();
+        match part {
+            // Escaped literals are escaped again so a partial render stays a valid template
+            PromptTemplatePart::Literal(text) if context.partial => {
+                rendered_part.push_str(&text.replace("{{", "\\{{"))
+            }
+            PromptTemplatePart::Literal(text) => rendered_part.push_str(text),
+            // Values are escaped too, so a value containing `{{` isn't a tag once rendered again
+            PromptTemplatePart::Argument(name) => {
+                match self.lookup_argument(arguments, name, context) {
+                    Some(value) if context.partial => {
+                        rendered_part.push_str(&value.replace("{{", "\\{{"))
+                    }
+                    Some(value) => rendered_part.push_str(&value),
+                    None if context.partial => {
+                        context.record_missing_argument(name);
+                        rendered_part.push_str(&format!("{{{{{}}}}}", name));
+                    }
+                    None => {
+                        return Err(RenderTemplateError::MissingArgument { name: name.clone() });
+                    }
+                }
+            }
+            PromptTemplatePart::PromptReference(name)
+            | PromptTemplatePart::OptionalPromptReference(name) => {
+                self.render_prompt_reference(
+                    name,
+                    arguments,
+                    storage,
+                    context,
+                    &mut rendered_part,
+                )?;
+            }
+            PromptTemplatePart::VariablePromptReference(name) => {
+                match self.lookup_argument(arguments, name, context) {
+                    Some(value) => {
+                        self.render_prompt_reference(
+                            &value,
+                            arguments,
+                            storage,
+                            context,
+                            &mut rendered_part,
+                        )?;
+                    }
+                    None if context.partial => {
+                        context.record_missing_argument(name);
+                        rendered_part.push_str(&format!("{{{{prompt_var:{}}}}}", name));
+                    }
+                    None => {
+                        return Err(RenderTemplateError::MissingArgument { name: name.clone() });
+                    }
+                }
+            }
+            PromptTemplatePart::Examples(directive) => {
+                self.render_examples(directive, arguments, storage, context, &mut rendered_part)?;
+            }
+            PromptTemplatePart::TaggedPrompts(directive) => {
+                let names = self.tagged_prompt_names(&directive.tag, storage)?;
+                self.render_prompt_list(
+                    &names,
+                    &directive.separator,
+                    arguments,
+                    storage,
+                    context,
+                    &mut rendered_part,
+                )?;
+            }
+            PromptTemplatePart::Function(call) => {
+                let mut values = Vec::new();
+                for argument in &call.arguments {
+                    match argument {
+                        FunctionArgument::Literal(text) => values.push(text.clone()),
+                        FunctionArgument::Argument(name) => {
+                            match self.lookup_argument(arguments, name, context) {
+                                Some(value) => values.push(value),
+                                None if context.partial => {
+                                    context.record_missing_argument(name);
+                                }
+                                None => {
+                                    return Err(RenderTemplateError::MissingArgument {
+                                        name: name.clone(),
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+
+                let function = context.functions.and_then(|f| f.get(&call.name));
+                match function {
+                    // Keep the call for a later render if some of its arguments are missing,
+                    // or if the function isn't known to this render
+                    _ if values.len() < call.arguments.len() => {
+                        rendered_part.push_str(&format!("{{{{{}}}}}", call))
+                    }
+                    None if context.partial => rendered_part.push_str(&format!("{{{{{}}}}}", call)),
+                    None => {
+                        return Err(RenderTemplateError::Other(format!(
+                            "Unknown function: {}",
+                            call.name
+                        )));
+                    }
+                    Some(function) => {
+                        let output = function(&values).map_err(|e| {
+                            RenderTemplateError::Other(format!(
+                                "Function '{}' failed: {}",
+                                call.name, e
+                            ))
+                        })?;
+                        if context.partial {
+                            rendered_part.push_str(&output.replace("{{", "\\{{"));
+                        } else {
+                            rendered_part.push_str(&output);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(hooks) = context.hooks.as_deref_mut() {
+            hooks.on_part(&self.prompt.metadata.name, part, &mut rendered_part)?;
+        }
+        Ok(rendered_part)
+    }
+
+    /// Helper function to render the prompts selected by an examples directive
+    fn render_examples<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        directive: &ExamplesDirective,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+        result: &mut String,
+    ) -> Result<(), RenderTemplateError> {
+        let mut names = self.tagged_prompt_names(&directive.tag, storage)?;
+        if let ExampleOrder::Random { seed } = directive.order {
+            let mut rng = match seed {
+                Some(seed) => fastrand::Rng::with_seed(seed),
+                None => fastrand::Rng::new(),
+            };
+            rng.shuffle(&mut names);
+        }
+        if let Some(count) = directive.count {
+            names.truncate(count);
+        }
+
+        self.render_prompt_list(
+            &names,
+            &directive.separator,
+            arguments,
+            storage,
+            context,
+            result,
+        )
+    }
+
+    /// Helper function to get the names of the prompts with a tag, sorted and excluding this prompt
+    fn tagged_prompt_names<S: PromptStorage>(
+        &self,
+        tag: &str,
+        storage: &S,
+    ) -> Result<Vec<String>, RenderTemplateError> {
+        let mut names: Vec<String> = storage
+            .get_prompts_by_tag(&[tag.to_string()])
+            .map_err(|e| {
+                RenderTemplateError::Other(format!(
+                    "Error retrieving prompts with tag '{}': {}",
+                    tag, e
+                ))
+            })?
+            .into_iter()
+            .map(|prompt| prompt.metadata.name)
+            .filter(|name| *name != self.prompt.metadata.name)
+            .collect();
+        names.sort();
+        debug!("Prompts tagged '{}': {}", tag, names.join(", "));
+        Ok(names)
+    }
+
+    /// Helper function to render a list of prompts, joined by a separator
+    fn render_prompt_list<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        names: &[String],
+        separator: &str,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+        result: &mut String,
+    ) -> Result<(), RenderTemplateError> {
+        for (index, name) in names.iter().enumerate() {
+            if index > 0 {
+                result.push_str(separator);
+            }
+            self.render_prompt_reference(name, arguments, storage, context, result)?;
+        }
+        Ok(())
+    }
+
+    /// Helper function to render a prompt reference
+    fn render_prompt_reference<A: TemplateArguments, S: PromptStorage>(
+        &self,
+        prompt_name: &str,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+        result: &mut String,
+    ) -> Result<(), RenderTemplateError> {
+        let template = self.enter_prompt_reference(prompt_name, storage, context)?;
+        let rendered = template.render_internal(arguments, storage, context);
+        // Exit the prompt whether it rendered or not, so it can be referenced again
+        context.exit_prompt(prompt_name);
+        result.push_str(&rendered?);
+        Ok(())
+    }
+
+    /// Helper function to resolve a referenced prompt, entering it in the validation context.
+    /// The caller is responsible for exiting the prompt once it's rendered.
+    fn enter_prompt_reference<S: PromptStorage>(
+        &self,
+        prompt_name: &str,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+    ) -> Result<PromptTemplate, RenderTemplateError> {
+        if let Some(hooks) = context.hooks.as_deref_mut() {
+            hooks.on_prompt_reference(
+                &self.prompt.metadata.name,
+                prompt_name,
+                context.current_depth,
+            )?;
+        }
+
+        // Validate before resolving the prompt reference
+        context.enter_prompt(prompt_name)?;
+        debug!(
+            "Including '{}' in '{}'",
+            prompt_name, self.prompt.metadata.name
+        );
+
+        let template = storage
+            .get_prompt(prompt_name)
+            .map_err(|e| {
+                debug!("Error retrieving prompt '{}': {}", prompt_name, e);
+                reference_error::<S>(e, prompt_name, &self.prompt.metadata.name)
+            })
+            .and_then(|prompt| {
+                PromptTemplate::new(prompt).map_err(|source| RenderTemplateError::InvalidPrompt {
+                    name: prompt_name.to_string(),
+                    source,
+                })
+            });
+        match &template {
+            Ok(template) => context.check_deprecation(&template.prompt.metadata),
+            Err(_) => context.exit_prompt(prompt_name),
+        }
+        template
+    }
+
+    /// Internal streaming function, writing each part as soon as it's rendered. Parts are
+    /// rendered like in [`PromptTemplate::render_internal`], so the output is the same.
+    fn render_internal_to_writer<A: TemplateArguments, S: PromptStorage, W: Write>(
+        &self,
+        arguments: &A,
+        storage: &S,
+        context: &mut RenderValidationContext<'_>,
+        writer: &mut W,
+    ) -> Result<usize, RenderTemplateError> {
+        let write_error = |e: std::io::Error| {
+            RenderTemplateError::Other(format!("Failed to write rendered prompt: {}", e))
+        };
+        if !self.prompt.metadata.dialect.is_pren() {
+            let rendered = self.render_dialect(arguments, storage, context)?;
+            writer.write_all(rendered.as_bytes()).map_err(write_error)?;
+            return Ok(count_tokens(&rendered));
+        }
+        // The tokens written, counted part by part
+        let mut tokens = 0;
+        for part in &self.parts {
+            let rendered_part = self.render_part(part, arguments, storage, context)?;
+            writer
+                .write_all(rendered_part.as_bytes())
+                .map_err(write_error)?;
+            tokens += count_tokens(&rendered_part);
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_storage::{FileStorage, FileStorageError};
+    use crate::messages::Role;
+    use crate::storage::PromptStorage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_prompt_builder() {
+        let prompt = Prompt::builder()
+            .name("reviews.code")
+            .description("Reviews code")
+            .tags(["rust", " Review", "Rust ", ""])
+            .author("Ana")
+            .content("Review {{code}}")
+            .build()
+            .expect("Failed to build prompt");
+
+        assert_eq!("reviews.code", prompt.metadata.name);
+        assert_eq!(
+            Some("Reviews code".to_string()),
+            prompt.metadata.description
+        );
+        assert_eq!(vec!["rust", "review"], prompt.metadata.tags);
+        assert_eq!(Some("Ana".to_string()), prompt.metadata.author);
+        assert_eq!("Review {{code}}", prompt.content);
+    }
+
+    #[test]
+    fn test_replace_prompt_references() {
+        let mut prompt = Prompt::new(
+            PromptMetadata::new("letter".to_string(), None, vec![]),
+            "{{prompt:intro}}{{ prompt_opt: intro }} {{prompt:intro2}} {{{{prompt:intro}}}} \\{{prompt:intro}}"
+                .to_string(),
+        );
+        prompt.metadata.superseded_by = Some("intro".to_string());
+
+        assert!(prompt.replace_prompt_references("intro", "greeting"));
+        assert_eq!(
+            "{{prompt:greeting}}{{prompt_opt:greeting}} {{prompt:intro2}} {{{{prompt:intro}}}} \\{{prompt:intro}}",
+            prompt.content
+        );
+        assert_eq!(Some("greeting"), prompt.metadata.superseded_by.as_deref());
+        assert!(!prompt.replace_prompt_references("intro", "greeting"));
+    }
+
+    #[test]
+    fn test_replace_prompt_references_next_to_literal_braces() {
+        // A single brace before a reference is literal text, but escaped literals aren't
+        // parsed, even when they hold more than the reference
+        let mut prompt = Prompt::new(
+            PromptMetadata::new("letter".to_string(), None, vec![]),
+            "{{{prompt:intro}}} {{{{ {{prompt:intro}} }}}} {{{{x}}}}{{prompt:intro}}".to_string(),
+        );
+
+        assert!(prompt.replace_prompt_references("intro", "greeting"));
+        assert_eq!(
+            "{{{prompt:greeting}}} {{{{ {{prompt:intro}} }}}} {{{{x}}}}{{prompt:greeting}}",
+            prompt.content
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+        assert_eq!(vec!["greeting", "greeting"], template.prompt_references());
+    }
+
+    #[test]
+    fn test_prompt_builder_validation() {
+        assert_eq!(
+            Some(PromptBuilderError::MissingName),
+            Prompt::builder().content("Hi").build().err()
+        );
+        assert_eq!(
+            Some(PromptBuilderError::InvalidName("my prompt".to_string())),
+            Prompt::builder()
+                .name("my prompt")
+                .content("Hi")
+                .build()
+                .err()
+        );
+        assert_eq!(
+            Some(PromptBuilderError::EmptyContent),
+            Prompt::builder().name("empty").content(" \n").build().err()
+        );
+    }
+
+    #[test]
+    fn test_new_simple_prompt() {
+        let name = "prompt_name";
+        let description = Some("A simple description".to_string());
+        let content = "This is the prompt content";
+        let tags = vec!["tag1".to_string(), "tag2".to_string()];
+
+        let metadata = PromptMetadata::new(name.to_string(), description, tags.clone());
+        let prompt = Prompt::new(metadata, content.to_string());
+
+        let result = PromptTemplate::new(prompt);
+
+        assert!(result.is_ok());
+
+        let prompt_template = result.unwrap();
+
+        assert_eq!(name, prompt_template.prompt.metadata.name);
+        assert_eq!(content, prompt_template.prompt.content);
+        assert_eq!(1, prompt_template.parts.len());
+        assert_eq!(2, prompt_template.prompt.metadata.tags.len());
+        assert_eq!(tags[0], prompt_template.prompt.metadata.tags[0]);
+        assert_eq!(tags[1], prompt_template.prompt.metadata.tags[1]);
+    }
+
+    #[test]
+    fn test_new_template_prompt() {
+        let name = "complex_prompt";
+        let content = "Hello {{name}}, welcome to {{prompt:greeting}}! {{{{literal_braces}}}}";
+        let tags = vec!["tag1".to_string(), "tag2".to_string()];
+
+        let metadata = PromptMetadata::new(name.to_string(), None, tags.clone());
+        let prompt = Prompt::new(metadata, content.to_string());
+
+        let result = PromptTemplate::new(prompt);
+        assert!(result.is_ok());
+
+        let template = result.unwrap();
+        assert_eq!(name, template.prompt.metadata.name);
+        assert_eq!(content, template.prompt.content);
+
+        assert_eq!(6, template.parts.len());
+
+        // Check each part
+        match &template.parts[0] {
+            PromptTemplatePart::Literal(text) => assert_eq!("Hello ", text),
+            _ => panic!("Expected Literal part"),
+        }
+
+        match &template.parts[1] {
+            PromptTemplatePart::Argument(arg) => assert_eq!("name", arg),
+            _ => panic!("Expected Argument part"),
         }
+
+        match &template.parts[2] {
+            PromptTemplatePart::Literal(text) => assert_eq!(", welcome to ", text),
+            _ => panic!("Expected Literal part"),
+        }
+
+        match &template.parts[3] {
+            PromptTemplatePart::PromptReference(prompt_name) => {
+                assert_eq!("greeting", prompt_name)
+            }
+            _ => panic!("Expected PromptReference part"),
+        }
+
+        match &template.parts[4] {
+            PromptTemplatePart::Literal(text) => assert_eq!("! ", text),
+            _ => panic!("Expected Literal part"),
+        }
+
+        match &template.parts[5] {
+            PromptTemplatePart::Literal(text) => assert_eq!("literal_braces", text),
+            _ => panic!("Expected Literal part"),
+        }
+    }
+
+    struct MockStorage {
+        prompts: HashMap<String, Prompt>,
+    }
+
+    impl MockStorage {
+        fn new() -> Self {
+            MockStorage {
+                prompts: HashMap::new(),
+            }
+        }
+
+        fn add_prompt(&mut self, prompt: Prompt) {
+            self.prompts.insert(prompt.metadata.name.clone(), prompt);
+        }
+    }
+
+    use std::error::Error;
+
+    #[derive(Debug)]
+    pub struct MockStorageError {
+        message: String,
+    }
+
+    impl std::fmt::Display for MockStorageError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.message)
+        }
+    }
+
+    impl Error for MockStorageError {}
+
+    impl From<String> for MockStorageError {
+        fn from(message: String) -> Self {
+            MockStorageError { message }
+        }
+    }
+
+    impl PromptStorage for MockStorage {
+        type Error = MockStorageError;
+
+        fn save_prompt(&self, _prompt: &Prompt) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn get_prompt(&self, name: &str) -> Result<Prompt, Self::Error> {
+            match self.prompts.get(name) {
+                Some(prompt) => Ok(prompt.clone()),
+                None => Err("Prompt not found".to_string().into()),
+            }
+        }
+
+        fn get_prompts(&self) -> Result<Vec<Prompt>, Self::Error> {
+            Ok(self.prompts.values().cloned().collect())
+        }
+
+        fn get_prompts_by_tag(&self, tags: &[String]) -> Result<Vec<Prompt>, Self::Error> {
+            Ok(self
+                .prompts
+                .values()
+                .filter(|prompt| prompt.metadata.tags.iter().any(|tag| tags.contains(tag)))
+                .cloned()
+                .collect())
+        }
+
+        fn delete_prompt(&self, _name: &str) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_render_simple_prompt() {
+        let metadata = PromptMetadata::new("simple".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "This is a simple prompt".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "World".to_string());
+
+        let storage = MockStorage::new();
+        let rendered = template
+            .render(&args, &storage)
+            .expect("Failed to render simple prompt");
+        assert_eq!("This is a simple prompt", rendered);
+    }
+
+    #[test]
+    fn test_render_template_prompt() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "Hello {{name}}, welcome!".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "World".to_string());
+
+        let storage = MockStorage::new();
+        let rendered = template
+            .render(&args, &storage)
+            .expect("Failed to render template prompt");
+        assert_eq!("Hello World, welcome!", rendered);
+    }
+
+    #[test]
+    fn test_render_template_prompt_missing_argument() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "Hello {{name}}, welcome!".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let args: HashMap<String, String> = HashMap::new();
+
+        let storage = MockStorage::new();
+        let error = template.render(&args, &storage).unwrap_err();
+        assert_eq!("Missing argument: name", error.to_string());
+        assert!(error.is_missing_argument());
+    }
+
+    #[test]
+    fn test_render_template_prompt_multiple_arguments() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(
+            metadata,
+            "Dear {{name}}, you are {{age}} years old!".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "Alice".to_string());
+        args.insert("age".to_string(), "30".to_string());
+
+        let storage = MockStorage::new();
+        let rendered = template
+            .render(&args, &storage)
+            .expect("Failed to render template prompt");
+        assert_eq!("Dear Alice, you are 30 years old!", rendered);
+    }
+
+    #[test]
+    fn test_render_template_prompt_with_escaped_literals() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(
+            metadata,
+            "Hello {{{{{{name}}}}}}, you are {{age}} years old!".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("age".to_string(), "30".to_string());
+
+        let storage = MockStorage::new();
+        let rendered = template
+            .render(&args, &storage)
+            .expect("Failed to render template prompt");
+        assert_eq!("Hello {{name}}, you are 30 years old!", rendered);
+    }
+
+    #[test]
+    fn test_render_template_with_prompt_reference() {
+        let greeting_metadata = PromptMetadata::new("greeting".to_string(), None, vec![]);
+        let greeting_prompt = Prompt::new(greeting_metadata, "Hello!".to_string());
+
+        let main_metadata = PromptMetadata::new("main".to_string(), None, vec![]);
+        let main_prompt = Prompt::new(
+            main_metadata,
+            "{{prompt:greeting}} Nice to meet you {{name}}!".to_string(),
+        );
+        let main_template = PromptTemplate::new(main_prompt).expect("Failed to create template");
+
+        let mut storage = MockStorage::new();
+        storage.add_prompt(greeting_prompt);
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "Alice".to_string());
+
+        let rendered = main_template
+            .render(&args, &storage)
+            .expect("Failed to render template prompt with reference");
+        assert_eq!("Hello! Nice to meet you Alice!", rendered);
+    }
+
+    #[test]
+    fn test_render_template_with_missing_prompt_reference() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "Message: {{prompt:missing}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "Alice".to_string());
+
+        let storage = MockStorage::new();
+        let result = template.render(&args, &storage);
+        assert!(matches!(
+            result,
+            Err(RenderTemplateError::PromptNotFound { name, referenced_from })
+                if name == "missing" && referenced_from == "template"
+        ));
+    }
+
+    #[test]
+    fn test_render_template_with_unreadable_prompt_reference() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        std::fs::write(
+            temp_dir.path().join("broken.md"),
+            "---\nname: [broken\n---\nContent",
+        )
+        .unwrap();
+        let metadata = PromptMetadata::new("main".to_string(), None, vec![]);
+        let template =
+            PromptTemplate::new(Prompt::new(metadata, "{{prompt:broken}}".to_string())).unwrap();
+
+        match template.render(&HashMap::<String, String>::new(), &storage) {
+            Err(RenderTemplateError::Storage {
+                name,
+                referenced_from,
+                source,
+            }) => {
+                assert_eq!("broken", name);
+                assert_eq!("main", referenced_from);
+                assert!(matches!(
+                    source.downcast_ref::<FileStorageError>(),
+                    Some(FileStorageError::DeserializationError(_))
+                ));
+            }
+            other => panic!("Expected a storage error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_render_template_with_nested_template_success() {
+        // Create a template prompt that will be referenced
+        let nested_metadata = PromptMetadata::new("nested_template".to_string(), None, vec![]);
+        let nested_template_prompt = Prompt::new(
+            nested_metadata,
+            "This is a nested template with {{variable}}".to_string(),
+        );
+        let nested_template =
+            PromptTemplate::new(nested_template_prompt).expect("Failed to create nested template");
+
+        // Create a main template that references the nested template
+        let main_metadata = PromptMetadata::new("main".to_string(), None, vec![]);
+        let main_prompt = Prompt::new(
+            main_metadata,
+            "Referencing: {{prompt:nested_template}}".to_string(),
+        );
+        let main_template =
+            PromptTemplate::new(main_prompt).expect("Failed to create main template");
+
+        // Set up storage with the nested template prompt
+        let mut storage = MockStorage::new();
+        storage.add_prompt(nested_template.prompt); // Store the original prompt
+
+        let mut args = HashMap::new();
+        args.insert("variable".to_string(), "value".to_string());
+
+        // Attempt to render, which should succeed with our new implementation
+        let result = main_template.render(&args, &storage);
+        assert!(result.is_ok());
+        assert_eq!(
+            "Referencing: This is a nested template with value",
+            result.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_template_with_circular_reference() {
+        // Create prompts that reference each other
+        let prompt_a_metadata = PromptMetadata::new("prompt_a".to_string(), None, vec![]);
+        let prompt_a = Prompt::new(prompt_a_metadata, "A {{prompt:prompt_b}}".to_string());
+        let template_a = PromptTemplate::new(prompt_a.clone()).expect("Failed to create template");
+
+        let prompt_b_metadata = PromptMetadata::new("prompt_b".to_string(), None, vec![]);
+        let prompt_b = Prompt::new(prompt_b_metadata, "B {{prompt:prompt_a}}".to_string());
+
+        // Set up storage with both prompts
+        let mut storage = MockStorage::new();
+        storage.add_prompt(prompt_a);
+        storage.add_prompt(prompt_b);
+
+        let args: HashMap<String, String> = HashMap::new();
+
+        // Try to render prompt_a, which should fail due to circular reference
+        let error = template_a.render(&args, &storage).unwrap_err();
+        assert_eq!(
+            "Circular reference detected: prompt_a -> prompt_b -> prompt_a",
+            error.to_string()
+        );
+        assert!(matches!(
+            error,
+            RenderTemplateError::CircularReference { chain } if chain.len() == 3
+        ));
+    }
+
+    #[test]
+    fn test_render_template_with_max_depth_exceeded() {
+        // Create prompts with nesting that exceeds the maximum depth
+        let prompt_level_0_metadata =
+            PromptMetadata::new("prompt_level_0".to_string(), None, vec![]);
+        let prompt_level_0 = Prompt::new(
+            prompt_level_0_metadata,
+            "Level 0 {{prompt:prompt_level_1}}".to_string(),
+        );
+        let template_level_0 =
+            PromptTemplate::new(prompt_level_0.clone()).expect("Failed to create template");
+
+        let prompt_level_1_metadata =
+            PromptMetadata::new("prompt_level_1".to_string(), None, vec![]);
+        let prompt_level_1 = Prompt::new(
+            prompt_level_1_metadata,
+            "Level 1 {{prompt:prompt_level_2}}".to_string(),
+        );
+
+        let prompt_level_2_metadata =
+            PromptMetadata::new("prompt_level_2".to_string(), None, vec![]);
+        let prompt_level_2 = Prompt::new(
+            prompt_level_2_metadata,
+            "Level 2 {{prompt:prompt_level_3}}".to_string(),
+        );
+
+        let prompt_level_3_metadata =
+            PromptMetadata::new("prompt_level_3".to_string(), None, vec![]);
+        let prompt_level_3 = Prompt::new(
+            prompt_level_3_metadata,
+            "Level 3 {{prompt:prompt_level_4}}".to_string(),
+        );
+
+        let prompt_level_4_metadata =
+            PromptMetadata::new("prompt_level_4".to_string(), None, vec![]);
+        let prompt_level_4 = Prompt::new(prompt_level_4_metadata, "Level 4".to_string());
+
+        // Set up storage with all prompts
+        let mut storage = MockStorage::new();
+        storage.add_prompt(prompt_level_0);
+        storage.add_prompt(prompt_level_1);
+        storage.add_prompt(prompt_level_2);
+        storage.add_prompt(prompt_level_3);
+        storage.add_prompt(prompt_level_4);
+
+        let args: HashMap<String, String> = HashMap::new();
+
+        // Try to render prompt_level_0, which should fail due to exceeding max depth
+        let result = template_level_0.render(&args, &storage);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            RenderTemplateError::DepthExceeded { limit: 3 }
+        ));
+    }
+
+    #[test]
+    fn test_render_template_with_valid_depth() {
+        // Create prompts with nesting that is within the maximum depth
+        let prompt_level_0_metadata =
+            PromptMetadata::new("prompt_level_0".to_string(), None, vec![]);
+        let prompt_level_0 = Prompt::new(
+            prompt_level_0_metadata,
+            "Level 0 {{prompt:prompt_level_1}}".to_string(),
+        );
+        let template_level_0 =
+            PromptTemplate::new(prompt_level_0.clone()).expect("Failed to create template");
+
+        let prompt_level_1_metadata =
+            PromptMetadata::new("prompt_level_1".to_string(), None, vec![]);
+        let prompt_level_1 = Prompt::new(
+            prompt_level_1_metadata,
+            "Level 1 {{prompt:prompt_level_2}}".to_string(),
+        );
+
+        let prompt_level_2_metadata =
+            PromptMetadata::new("prompt_level_2".to_string(), None, vec![]);
+        let prompt_level_2 = Prompt::new(prompt_level_2_metadata, "Level 2".to_string());
+
+        // Set up storage with all prompts
+        let mut storage = MockStorage::new();
+        storage.add_prompt(prompt_level_0);
+        storage.add_prompt(prompt_level_1);
+        storage.add_prompt(prompt_level_2);
+
+        let args: HashMap<String, String> = HashMap::new();
+
+        // Try to render prompt_level_0, which should succeed
+        let result = template_level_0.render(&args, &storage);
+        assert!(result.is_ok());
+        assert_eq!("Level 0 Level 1 Level 2", result.unwrap());
+    }
+
+    #[test]
+    fn test_render_template_with_variable_prompt_reference() {
+        // Create a prompt that will be referenced dynamically
+        let dynamic_metadata = PromptMetadata::new("greeting".to_string(), None, vec![]);
+        let dynamic_prompt = Prompt::new(dynamic_metadata, "Hello {{name}}!".to_string());
+        let _dynamic_template =
+            PromptTemplate::new(dynamic_prompt.clone()).expect("Failed to create template");
+
+        // Create a main template that uses a variable prompt reference
+        let main_metadata = PromptMetadata::new("main".to_string(), None, vec![]);
+        let main_prompt = Prompt::new(
+            main_metadata,
+            "Message: {{prompt_var:prompt_name}}".to_string(),
+        );
+        let main_template = PromptTemplate::new(main_prompt)
+            .expect("Failed to create template with variable reference");
+
+        // Set up storage with the dynamic prompt
+        let mut storage = MockStorage::new();
+        storage.add_prompt(dynamic_prompt);
+
+        // Provide the argument that specifies which prompt to reference
+        let mut args = HashMap::new();
+        args.insert("prompt_name".to_string(), "greeting".to_string());
+        args.insert("name".to_string(), "Alice".to_string());
+
+        let rendered = main_template
+            .render(&args, &storage)
+            .expect("Failed to render template prompt with variable reference");
+        assert_eq!("Message: Hello Alice!", rendered);
+    }
+
+    #[test]
+    fn test_render_template_with_repeated_variable_prompt_reference() {
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("greeting".to_string(), None, vec![]),
+            "Hello {{name}}!".to_string(),
+        ));
+        let prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "{{prompt_var:x}} {{prompt_var:x}} {{prompt:greeting}}".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("x".to_string(), "greeting".to_string());
+        args.insert("name".to_string(), "Alice".to_string());
+
+        let rendered = template
+            .render_with_warnings(&args, &storage)
+            .expect("A prompt referenced twice isn't a circular reference");
+        assert_eq!("Hello Alice! Hello Alice! Hello Alice!", rendered.text);
+        assert!(rendered.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_variable_prompt_references() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(
+            metadata,
+            "Use {{prompt_var:first}} and {{prompt_var:second}} for dynamic content".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let variable_refs = template.variable_prompt_references();
+        assert_eq!(variable_refs.len(), 2);
+        assert!(variable_refs.contains(&"first".to_string()));
+        assert!(variable_refs.contains(&"second".to_string()));
+    }
+
+    #[test]
+    fn test_render_template_with_missing_variable_prompt_reference() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(
+            metadata,
+            "Message: {{prompt_var:missing_prompt}}".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("missing_prompt".to_string(), "nonexistent".to_string());
+
+        let storage = MockStorage::new();
+        let result = template.render(&args, &storage);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_render_template_with_variable_prompt_reference_missing_argument() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "Message: {{prompt_var:prompt_name}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let args: HashMap<String, String> = HashMap::new(); // Missing the "prompt_name" argument
+
+        let storage = MockStorage::new();
+        let result = template.render(&args, &storage);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            RenderTemplateError::MissingArgument { name } if name == "prompt_name"
+        ));
+    }
+
+    #[test]
+    fn test_render_template_with_variable_prompt_reference_circular_reference() {
+        // Create prompts that reference each other circularly
+        let prompt_a_metadata = PromptMetadata::new("prompt_a".to_string(), None, vec![]);
+        let prompt_a = Prompt::new(prompt_a_metadata, "A {{prompt_var:ref_prompt}}".to_string());
+        let template_a = PromptTemplate::new(prompt_a.clone()).expect("Failed to create template");
+
+        let prompt_b_metadata = PromptMetadata::new("prompt_b".to_string(), None, vec![]);
+        let prompt_b = Prompt::new(prompt_b_metadata, "B {{name}}".to_string());
+
+        // Set up storage with both prompts
+        let mut storage = MockStorage::new();
+        storage.add_prompt(prompt_a);
+        storage.add_prompt(prompt_b);
+
+        // Set up arguments where prompt_name refers back to prompt_a (circular)
+        let mut args = HashMap::new();
+        args.insert("ref_prompt".to_string(), "prompt_a".to_string()); // Circular reference
+        args.insert("name".to_string(), "Alice".to_string());
+
+        // Try to render prompt_a, which should fail due to circular reference
+        let result = template_a.render(&args, &storage);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            RenderTemplateError::CircularReference { .. }
+        ));
     }
 
-    use std::error::Error;
+    #[test]
+    fn test_render_partial_with_missing_arguments() {
+        let greeting_metadata = PromptMetadata::new("greeting".to_string(), None, vec![]);
+        let greeting_prompt = Prompt::new(greeting_metadata, "Hello {{name}}!".to_string());
 
-    #[derive(Debug)]
-    pub struct MockStorageError {
-        message: String,
+        let main_metadata = PromptMetadata::new("main".to_string(), None, vec![]);
+        let main_prompt = Prompt::new(
+            main_metadata,
+            "{{prompt:greeting}} Today we talk about {{topic}} in {{language}}. {{name}}?"
+                .to_string(),
+        );
+        let main_template = PromptTemplate::new(main_prompt).expect("Failed to create template");
+
+        let mut storage = MockStorage::new();
+        storage.add_prompt(greeting_prompt);
+
+        let mut args = HashMap::new();
+        args.insert("topic".to_string(), "lifetimes".to_string());
+
+        let result = main_template
+            .render_partial(&args, &storage)
+            .expect("Failed to partially render template");
+        assert_eq!(
+            "Hello {{name}}! Today we talk about lifetimes in {{language}}. {{name}}?",
+            result.text
+        );
+        assert_eq!(
+            vec!["name".to_string(), "language".to_string()],
+            result.missing_arguments
+        );
     }
 
-    impl std::fmt::Display for MockStorageError {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}", self.message)
-        }
+    #[test]
+    fn test_render_partial_escapes_argument_values() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "Fix {{code}} for {{user}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+        let storage = MockStorage::new();
+
+        let mut args = HashMap::new();
+        args.insert(
+            "code".to_string(),
+            "{{user}} and {{prompt:secret}}".to_string(),
+        );
+        let partial = template
+            .render_partial(&args, &storage)
+            .expect("Failed to partially render template");
+        assert_eq!(vec!["user".to_string()], partial.missing_arguments);
+
+        // The value stays text when the partial render is rendered again
+        let baked = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("baked".to_string(), None, vec![]),
+            partial.text,
+        ))
+        .expect("Failed to create template");
+        assert_eq!(vec!["user".to_string()], baked.arguments());
+        assert!(baked.prompt_references().is_empty());
+        let mut args = HashMap::new();
+        args.insert("user".to_string(), "Ana".to_string());
+        assert_eq!(
+            "Fix {{user}} and {{prompt:secret}} for Ana",
+            baked.render(&args, &storage).unwrap()
+        );
     }
 
-    impl Error for MockStorageError {}
+    #[test]
+    fn test_render_partial_output_is_a_valid_template() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(
+            metadata,
+            "{{{{{{literal}}}}}} {{prompt_var:persona}} {{context}} {{question}}".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+        let storage = MockStorage::new();
 
-    impl From<String> for MockStorageError {
-        fn from(message: String) -> Self {
-            MockStorageError { message }
-        }
+        let mut args = HashMap::new();
+        args.insert("context".to_string(), "Shared context".to_string());
+        let partial = template
+            .render_partial(&args, &storage)
+            .expect("Failed to partially render template");
+        assert_eq!(
+            vec!["persona".to_string(), "question".to_string()],
+            partial.missing_arguments
+        );
+
+        // Render the remaining placeholders later on
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("teacher".to_string(), None, vec![]),
+            "You are a teacher.".to_string(),
+        ));
+        let baked_prompt = Prompt::new(
+            PromptMetadata::new("baked".to_string(), None, vec![]),
+            partial.text,
+        );
+        let baked_template = PromptTemplate::new(baked_prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("persona".to_string(), "teacher".to_string());
+        args.insert("question".to_string(), "Why?".to_string());
+        let rendered = baked_template
+            .render(&args, &storage)
+            .expect("Failed to render baked template");
+        assert_eq!(
+            "{{literal}} You are a teacher. Shared context Why?",
+            rendered
+        );
     }
 
-    impl PromptStorage for MockStorage {
-        type Error = MockStorageError;
+    #[test]
+    fn test_render_template_with_structured_arguments() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(
+            metadata,
+            "Hello {{user.name}}, use {{config.model.temperature}} for {{tasks.1}}. {{user}}"
+                .to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        fn save_prompt(&self, _prompt: &Prompt) -> Result<(), Self::Error> {
-            Ok(())
-        }
+        let mut args: HashMap<String, Value> = HashMap::new();
+        args.insert("user".to_string(), serde_json::json!({"name": "Ana"}));
+        args.insert(
+            "config".to_string(),
+            serde_json::json!({"model": {"temperature": 0.7}}),
+        );
+        args.insert(
+            "tasks".to_string(),
+            serde_json::json!(["review", "summary"]),
+        );
 
-        fn get_prompt(&self, name: &str) -> Result<Prompt, Self::Error> {
-            match self.prompts.get(name) {
-                Some(prompt) => Ok(prompt.clone()),
-                None => Err("Prompt not found".to_string().into()),
+        let storage = MockStorage::new();
+        let rendered = template
+            .render(&args, &storage)
+            .expect("Failed to render template with structured arguments");
+        assert_eq!(
+            "Hello Ana, use 0.7 for summary. {\"name\":\"Ana\"}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_render_template_with_missing_nested_argument() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "Hello {{user.email}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let mut args: HashMap<String, Value> = HashMap::new();
+        args.insert("user".to_string(), serde_json::json!({"name": "Ana"}));
+
+        let storage = MockStorage::new();
+        let result = template.render(&args, &storage);
+        assert!(result.is_err());
+        assert_eq!(
+            "Missing argument: user.email",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_render_template_with_dotted_key() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "Hello {{user.name}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        // Plain string arguments can use the dotted name as a key
+        let mut args = HashMap::new();
+        args.insert("user.name".to_string(), "Ana".to_string());
+
+        let storage = MockStorage::new();
+        let rendered = template
+            .render(&args, &storage)
+            .expect("Failed to render template with dotted key");
+        assert_eq!("Hello Ana", rendered);
+    }
+
+    #[derive(Default)]
+    struct RecordingHooks {
+        references: Vec<(String, String, usize)>,
+        post_render_calls: usize,
+    }
+
+    impl RenderHooks for RecordingHooks {
+        fn on_part(
+            &mut self,
+            _prompt_name: &str,
+            part: &PromptTemplatePart,
+            rendered: &mut String,
+        ) -> Result<(), RenderTemplateError> {
+            // Redact the value of any "secret" argument
+            if matches!(part, PromptTemplatePart::Argument(name) if name == "secret") {
+                *rendered = "[REDACTED]".to_string();
             }
+            Ok(())
         }
 
-        fn get_prompts(&self) -> Result<Vec<Prompt>, Self::Error> {
-            Ok(self.prompts.values().cloned().collect())
+        fn on_prompt_reference(
+            &mut self,
+            referenced_from: &str,
+            prompt_name: &str,
+            depth: usize,
+        ) -> Result<(), RenderTemplateError> {
+            self.references
+                .push((referenced_from.to_string(), prompt_name.to_string(), depth));
+            Ok(())
         }
 
-        fn get_prompts_by_tag(&self, _tags: &[String]) -> Result<Vec<Prompt>, Self::Error> {
-            Ok(vec![])
+        fn post_render(&mut self, output: &mut String) -> Result<(), RenderTemplateError> {
+            self.post_render_calls += 1;
+            output.push_str(" [end]");
+            Ok(())
         }
+    }
 
-        fn delete_prompt(&self, _name: &str) -> Result<(), Self::Error> {
+    #[test]
+    fn test_render_with_hooks() {
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("credentials".to_string(), None, vec![]),
+            "Use the key {{secret}}".to_string(),
+        ));
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("task".to_string(), None, vec![]),
+            "{{prompt:credentials}} to call {{service}}.".to_string(),
+        ));
+        let main_prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "Task: {{prompt:task}}".to_string(),
+        );
+        let template = PromptTemplate::new(main_prompt).expect("Failed to create template");
+
+        let mut args = HashMap::new();
+        args.insert("secret".to_string(), "sk-123".to_string());
+        args.insert("service".to_string(), "the API".to_string());
+
+        let mut hooks = RecordingHooks::default();
+        let rendered = template
+            .render_with_hooks(&args, &storage, &mut hooks)
+            .expect("Failed to render template with hooks");
+
+        assert_eq!(
+            "Task: Use the key [REDACTED] to call the API. [end]",
+            rendered
+        );
+        assert_eq!(
+            vec![
+                ("main".to_string(), "task".to_string(), 0),
+                ("task".to_string(), "credentials".to_string(), 1),
+            ],
+            hooks.references
+        );
+        assert_eq!(1, hooks.post_render_calls);
+    }
+
+    struct BudgetHooks {
+        max_chars: usize,
+    }
+
+    impl RenderHooks for BudgetHooks {
+        fn post_render(&mut self, output: &mut String) -> Result<(), RenderTemplateError> {
+            if output.len() > self.max_chars {
+                return Err(RenderTemplateError::Other(format!(
+                    "Output exceeds {} characters",
+                    self.max_chars
+                )));
+            }
             Ok(())
         }
     }
 
     #[test]
-    fn test_render_simple_prompt() {
-        let metadata = PromptMetadata::new("simple".to_string(), None, vec![]);
-        let prompt = Prompt::new(metadata, "This is a simple prompt".to_string());
+    fn test_render_with_hooks_error_aborts_render() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "A long prompt about {{topic}}".to_string());
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
         let mut args = HashMap::new();
-        args.insert("name".to_string(), "World".to_string());
+        args.insert("topic".to_string(), "everything".to_string());
 
         let storage = MockStorage::new();
-        let rendered = template
-            .render(&args, &storage)
-            .expect("Failed to render simple prompt");
-        assert_eq!("This is a simple prompt", rendered);
+        let result =
+            template.render_with_hooks(&args, &storage, &mut BudgetHooks { max_chars: 10 });
+        assert!(result.is_err());
+        assert_eq!(
+            "Output exceeds 10 characters",
+            result.unwrap_err().to_string()
+        );
     }
 
     #[test]
-    fn test_render_template_prompt() {
-        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
-        let prompt = Prompt::new(metadata, "Hello {{name}}, welcome!".to_string());
+    fn test_render_with_budget_drops_optional_references() {
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("examples".to_string(), None, vec![]),
+            "Example: ".repeat(50),
+        ));
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("style".to_string(), None, vec![]),
+            "Be concise.".to_string(),
+        ));
+        let prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "Review {{code}}. {{prompt_opt:style}} {{prompt_opt:examples}}".to_string(),
+        );
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
         let mut args = HashMap::new();
-        args.insert("name".to_string(), "World".to_string());
+        args.insert("code".to_string(), "main.rs".to_string());
 
-        let storage = MockStorage::new();
+        // Everything fits within a large budget
         let rendered = template
-            .render(&args, &storage)
-            .expect("Failed to render template prompt");
-        assert_eq!("Hello World, welcome!", rendered);
+            .render_with_budget(&args, &storage, 10_000)
+            .expect("Failed to render within budget");
+        assert!(rendered.contains("Example:"));
+        assert!(rendered.contains("Be concise."));
+
+        // The last optional section is dropped first
+        let budget = count_tokens("Review main.rs. Be concise. ");
+        let rendered = template
+            .render_with_budget(&args, &storage, budget)
+            .expect("Failed to render within budget");
+        assert_eq!("Review main.rs. Be concise. ", rendered);
     }
 
     #[test]
-    fn test_render_template_prompt_missing_argument() {
+    fn test_render_with_budget_exceeded() {
         let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
-        let prompt = Prompt::new(metadata, "Hello {{name}}, welcome!".to_string());
+        let prompt = Prompt::new(metadata, "This prompt has no optional sections".to_string());
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        let args = HashMap::new();
-
+        let args: HashMap<String, String> = HashMap::new();
         let storage = MockStorage::new();
-        let result = template.render(&args, &storage);
+        let result = template.render_with_budget(&args, &storage, 1);
         assert!(result.is_err());
-        assert_eq!("Missing argument: name", result.unwrap_err().message);
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("exceeding the budget")
+        );
     }
 
     #[test]
-    fn test_render_template_prompt_multiple_arguments() {
+    fn test_render_with_functions() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("uuid", |_| Ok("1234".to_string()));
+        functions.register("upper", |args| Ok(args.join(" ").to_uppercase()));
+
         let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
         let prompt = Prompt::new(
             metadata,
-            "Dear {{name}}, you are {{age}} years old!".to_string(),
+            "Request {{fn:uuid()}}: {{fn:upper(user.name, \"hi\")}}".to_string(),
         );
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        let mut args = HashMap::new();
-        args.insert("name".to_string(), "Alice".to_string());
-        args.insert("age".to_string(), "30".to_string());
+        let mut args: HashMap<String, Value> = HashMap::new();
+        args.insert("user".to_string(), serde_json::json!({"name": "ana"}));
 
         let storage = MockStorage::new();
         let rendered = template
-            .render(&args, &storage)
-            .expect("Failed to render template prompt");
-        assert_eq!("Dear Alice, you are 30 years old!", rendered);
+            .render_with_functions(&args, &storage, &functions)
+            .expect("Failed to render template with functions");
+        assert_eq!("Request 1234: ANA HI", rendered);
     }
 
     #[test]
-    fn test_render_template_prompt_with_escaped_literals() {
+    fn test_render_with_unknown_or_failing_function() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("fail", |_| Err("boom".to_string()));
+        let storage = MockStorage::new();
+        let args: HashMap<String, String> = HashMap::new();
+
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "{{fn:missing()}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+        let result = template.render_with_functions(&args, &storage, &functions);
+        assert_eq!("Unknown function: missing", result.unwrap_err().to_string());
+
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "{{fn:fail()}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+        let result = template.render_with_functions(&args, &storage, &functions);
+        assert_eq!(
+            "Function 'fail' failed: boom",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_render_partial_keeps_function_calls_with_missing_arguments() {
+        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+        let prompt = Prompt::new(metadata, "{{fn:upper(name, \"!\")}}".to_string());
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+
+        let args: HashMap<String, String> = HashMap::new();
+        let storage = MockStorage::new();
+        let result = template
+            .render_partial(&args, &storage)
+            .expect("Failed to partially render template");
+        assert_eq!("{{fn:upper(name, \"!\")}}", result.text);
+        assert_eq!(vec!["name".to_string()], result.missing_arguments);
+    }
+
+    #[test]
+    fn test_render_partial_keeps_unknown_function_calls() {
         let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
         let prompt = Prompt::new(
             metadata,
-            "Hello {{{{{{name}}}}}}, you are {{age}} years old!".to_string(),
+            "{{fn:upper(name, \"say \\\"hi\\\")\")}} {{name}}".to_string(),
         );
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
         let mut args = HashMap::new();
-        args.insert("age".to_string(), "30".to_string());
-
+        args.insert("name".to_string(), "Ana".to_string());
         let storage = MockStorage::new();
-        let rendered = template
-            .render(&args, &storage)
-            .expect("Failed to render template prompt");
-        assert_eq!("Hello {{name}}, you are 30 years old!", rendered);
+        let result = template
+            .render_partial(&args, &storage)
+            .expect("Failed to partially render template");
+        assert_eq!("{{fn:upper(name, \"say \\\"hi\\\")\")}} Ana", result.text);
+        assert!(result.missing_arguments.is_empty());
+
+        // The call renders once a registry with the function is given
+        let mut functions = FunctionRegistry::new();
+        functions.register("upper", |values: &[String]| {
+            Ok(values.concat().to_uppercase())
+        });
+        let baked = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("baked".to_string(), None, vec![]),
+            result.text,
+        ))
+        .expect("Failed to create template");
+        assert_eq!(
+            "ANASAY \"HI\") Ana",
+            baked
+                .render_with_functions(&args, &storage, &functions)
+                .unwrap()
+        );
+    }
+
+    fn add_examples(storage: &mut MockStorage) {
+        for (name, content) in [
+            ("review_c", "Input: c"),
+            ("review_a", "Input: a"),
+            ("review_b", "Input: {{language}}"),
+        ] {
+            storage.add_prompt(Prompt::new(
+                PromptMetadata::new(name.to_string(), None, vec!["review".to_string()]),
+                content.to_string(),
+            ));
+        }
     }
 
     #[test]
-    fn test_render_template_with_prompt_reference() {
-        let greeting_metadata = PromptMetadata::new("greeting".to_string(), None, vec![]);
-        let greeting_prompt = Prompt::new(greeting_metadata, "Hello!".to_string());
+    fn test_render_examples_by_name() {
+        let mut storage = MockStorage::new();
+        add_examples(&mut storage);
 
-        let main_metadata = PromptMetadata::new("main".to_string(), None, vec![]);
-        let main_prompt = Prompt::new(
-            main_metadata,
-            "{{prompt:greeting}} Nice to meet you {{name}}!".to_string(),
+        let metadata = PromptMetadata::new("main".to_string(), None, vec!["review".to_string()]);
+        let prompt = Prompt::new(
+            metadata,
+            "Examples:\n{{examples:tag=review,count=2,sep=\\n---\\n}}".to_string(),
         );
-        let main_template = PromptTemplate::new(main_prompt).expect("Failed to create template");
-
-        let mut storage = MockStorage::new();
-        storage.add_prompt(greeting_prompt);
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
         let mut args = HashMap::new();
-        args.insert("name".to_string(), "Alice".to_string());
+        args.insert("language".to_string(), "rust".to_string());
 
-        let rendered = main_template
+        let rendered = template
             .render(&args, &storage)
-            .expect("Failed to render template prompt with reference");
-        assert_eq!("Hello! Nice to meet you Alice!", rendered);
+            .expect("Failed to render template with examples");
+        assert_eq!("Examples:\nInput: a\n---\nInput: rust", rendered);
     }
 
     #[test]
-    fn test_render_template_with_missing_prompt_reference() {
-        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
-        let prompt = Prompt::new(metadata, "Message: {{prompt:missing}}".to_string());
+    fn test_render_examples_random_with_seed() {
+        let mut storage = MockStorage::new();
+        add_examples(&mut storage);
+
+        let metadata = PromptMetadata::new("main".to_string(), None, vec![]);
+        let prompt = Prompt::new(
+            metadata,
+            "{{examples:tag=review,order=random,seed=42}}".to_string(),
+        );
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
         let mut args = HashMap::new();
-        args.insert("name".to_string(), "Alice".to_string());
+        args.insert("language".to_string(), "rust".to_string());
 
-        let storage = MockStorage::new();
-        let result = template.render(&args, &storage);
-        assert!(result.is_err());
+        let first = template
+            .render(&args, &storage)
+            .expect("Failed to render template with examples");
+        let second = template
+            .render(&args, &storage)
+            .expect("Failed to render template with examples");
+        assert_eq!(first, second, "Seeded selection should be deterministic");
+        assert_eq!(3, first.split("\n\n").count());
     }
 
-    #[test]
-    fn test_render_template_with_nested_template_success() {
-        // Create a template prompt that will be referenced
-        let nested_metadata = PromptMetadata::new("nested_template".to_string(), None, vec![]);
-        let nested_template_prompt = Prompt::new(
-            nested_metadata,
-            "This is a nested template with {{variable}}".to_string(),
-        );
-        let nested_template =
-            PromptTemplate::new(nested_template_prompt).expect("Failed to create nested template");
-
-        // Create a main template that references the nested template
-        let main_metadata = PromptMetadata::new("main".to_string(), None, vec![]);
-        let main_prompt = Prompt::new(
-            main_metadata,
-            "Referencing: {{prompt:nested_template}}".to_string(),
+    #[test]
+    fn test_examples_directive_from_options() {
+        let directive =
+            ExamplesDirective::from_options(&[("tag", "review"), ("count", "3")]).unwrap();
+        assert_eq!(
+            ExamplesDirective {
+                tag: "review".to_string(),
+                count: Some(3),
+                order: ExampleOrder::Name,
+                separator: "\n\n".to_string(),
+            },
+            directive
         );
-        let main_template =
-            PromptTemplate::new(main_prompt).expect("Failed to create main template");
 
-        // Set up storage with the nested template prompt
+        assert!(ExamplesDirective::from_options(&[("count", "3")]).is_err());
+        assert!(ExamplesDirective::from_options(&[("tag", "a"), ("count", "x")]).is_err());
+        assert!(ExamplesDirective::from_options(&[("tag", "a"), ("foo", "bar")]).is_err());
+    }
+
+    #[test]
+    fn test_render_to_writer() {
         let mut storage = MockStorage::new();
-        storage.add_prompt(nested_template.prompt); // Store the original prompt
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("greeting".to_string(), None, vec![]),
+            "Hello {{name}}!".to_string(),
+        ));
+        let prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "{{prompt:greeting}} Welcome to {{place}}.".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
         let mut args = HashMap::new();
-        args.insert("variable".to_string(), "value".to_string());
+        args.insert("name".to_string(), "Alice".to_string());
+        args.insert("place".to_string(), "pren".to_string());
 
-        // Attempt to render, which should succeed with our new implementation
-        let result = main_template.render(&args, &storage);
-        assert!(result.is_ok());
+        let mut output = Vec::new();
+        template
+            .render_to_writer(&args, &storage, &mut output)
+            .expect("Failed to render template to writer");
         assert_eq!(
-            "Referencing: This is a nested template with value",
-            result.unwrap()
+            template.render(&args, &storage).unwrap(),
+            String::from_utf8(output).unwrap()
         );
     }
 
     #[test]
-    fn test_render_template_with_circular_reference() {
-        // Create prompts that reference each other
-        let prompt_a_metadata = PromptMetadata::new("prompt_a".to_string(), None, vec![]);
-        let prompt_a = Prompt::new(prompt_a_metadata, "A {{prompt:prompt_b}}".to_string());
-        let template_a = PromptTemplate::new(prompt_a.clone()).expect("Failed to create template");
-
-        let prompt_b_metadata = PromptMetadata::new("prompt_b".to_string(), None, vec![]);
-        let prompt_b = Prompt::new(prompt_b_metadata, "B {{prompt:prompt_a}}".to_string());
+    fn test_render_warns_about_large_output() {
+        let storage = MockStorage::new();
+        let prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "Summarize:\n{{text}}".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
+        let mut args = HashMap::new();
+        args.insert("text".to_string(), "lorem ".repeat(120_000));
+
+        let rendered = template.render_with_warnings(&args, &storage).unwrap();
+        assert!(matches!(
+            rendered.warnings.as_slice(),
+            [RenderWarning::LargeOutput { tokens }] if *tokens > LARGE_OUTPUT_TOKENS
+        ));
+
+        let mut output = Vec::new();
+        let warnings = template
+            .render_to_writer(&args, &storage, &mut output)
+            .unwrap();
+        assert_eq!(rendered.text, String::from_utf8(output).unwrap());
+        assert!(matches!(
+            warnings.as_slice(),
+            [RenderWarning::LargeOutput { tokens }] if *tokens > LARGE_OUTPUT_TOKENS
+        ));
+
+        args.insert("text".to_string(), "lorem".to_string());
+        let mut output = Vec::new();
+        let warnings = template
+            .render_to_writer(&args, &storage, &mut output)
+            .unwrap();
+        assert!(warnings.is_empty());
+    }
 
-        // Set up storage with both prompts
+    #[test]
+    fn test_render_to_writer_circular_reference() {
         let mut storage = MockStorage::new();
-        storage.add_prompt(prompt_a);
-        storage.add_prompt(prompt_b);
-
-        let args = HashMap::new();
-
-        // Try to render prompt_a, which should fail due to circular reference
-        let result = template_a.render(&args, &storage);
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("prompt_a".to_string(), None, vec![]),
+            "A {{prompt:prompt_b}}".to_string(),
+        ));
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("prompt_b".to_string(), None, vec![]),
+            "B {{prompt:prompt_a}}".to_string(),
+        ));
+        let template = PromptTemplate::new(storage.get_prompt("prompt_a").unwrap())
+            .expect("Failed to create template");
+
+        let args: HashMap<String, String> = HashMap::new();
+        let mut output = Vec::new();
+        let result = template.render_to_writer(&args, &storage, &mut output);
         assert!(result.is_err());
-        assert!(
-            result
-                .unwrap_err()
-                .message
-                .contains("Circular reference detected")
-        );
+        assert!(matches!(
+            result.unwrap_err(),
+            RenderTemplateError::CircularReference { .. }
+        ));
     }
 
     #[test]
-    fn test_render_template_with_max_depth_exceeded() {
-        // Create prompts with nesting that exceeds the maximum depth
-        let prompt_level_0_metadata =
-            PromptMetadata::new("prompt_level_0".to_string(), None, vec![]);
-        let prompt_level_0 = Prompt::new(
-            prompt_level_0_metadata,
-            "Level 0 {{prompt:prompt_level_1}}".to_string(),
+    fn test_render_plan() {
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("greeting".to_string(), None, vec![]),
+            "Hello {{name}}!".to_string(),
+        ));
+        let prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "{{prompt:greeting}} Bye.".to_string(),
         );
-        let template_level_0 =
-            PromptTemplate::new(prompt_level_0.clone()).expect("Failed to create template");
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        let prompt_level_1_metadata =
-            PromptMetadata::new("prompt_level_1".to_string(), None, vec![]);
-        let prompt_level_1 = Prompt::new(
-            prompt_level_1_metadata,
-            "Level 1 {{prompt:prompt_level_2}}".to_string(),
-        );
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "Alice".to_string());
+        args.insert("unused".to_string(), "value".to_string());
 
-        let prompt_level_2_metadata =
-            PromptMetadata::new("prompt_level_2".to_string(), None, vec![]);
-        let prompt_level_2 = Prompt::new(
-            prompt_level_2_metadata,
-            "Level 2 {{prompt:prompt_level_3}}".to_string(),
+        let plan = template
+            .render_plan(&args, &storage)
+            .expect("Failed to build render plan");
+        assert_eq!("Hello Alice! Bye.", plan.output);
+        assert_eq!(template.render(&args, &storage).unwrap(), plan.output);
+        assert_eq!(
+            vec![
+                ("greeting", "literal"),
+                ("greeting", "argument"),
+                ("greeting", "literal"),
+                ("main", "prompt_reference"),
+                ("main", "literal"),
+            ],
+            plan.parts
+                .iter()
+                .map(|part| (part.prompt.as_str(), part.kind.as_str()))
+                .collect::<Vec<_>>()
         );
-
-        let prompt_level_3_metadata =
-            PromptMetadata::new("prompt_level_3".to_string(), None, vec![]);
-        let prompt_level_3 = Prompt::new(
-            prompt_level_3_metadata,
-            "Level 3 {{prompt:prompt_level_4}}".to_string(),
+        assert_eq!(1, plan.referenced_prompts.len());
+        assert_eq!("greeting", plan.referenced_prompts[0].name);
+        assert_eq!("main", plan.referenced_prompts[0].referenced_from);
+        assert_eq!(
+            fingerprint("Hello {{name}}!"),
+            plan.referenced_prompts[0].fingerprint
+        );
+        assert_eq!(
+            BTreeMap::from([("name".to_string(), "Alice".to_string())]),
+            plan.arguments
         );
+    }
 
-        let prompt_level_4_metadata =
-            PromptMetadata::new("prompt_level_4".to_string(), None, vec![]);
-        let prompt_level_4 = Prompt::new(prompt_level_4_metadata, "Level 4".to_string());
+    #[test]
+    fn test_run_tests() {
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("signature".to_string(), None, vec![]),
+            "Regards".to_string(),
+        ));
+        let mut metadata = PromptMetadata::new("letter".to_string(), None, vec![]);
+        metadata.tests = vec![
+            PromptTestCase {
+                name: Some("formal".to_string()),
+                args: [("name".to_string(), Value::String("Ana".to_string()))].into(),
+                contains: vec!["Dear Ana".to_string()],
+                matches: vec!["Regards$".to_string()],
+            },
+            PromptTestCase {
+                contains: vec!["Hi".to_string()],
+                ..Default::default()
+            },
+        ];
+        let template = PromptTemplate::new(Prompt::new(
+            metadata,
+            "Dear {{name}}, {{prompt:signature}}".to_string(),
+        ))
+        .expect("Failed to create template");
+
+        let results = template.run_tests(&storage);
+        assert_eq!(2, results.len());
+        assert!(results[0].passed());
+        assert_eq!("formal", results[0].case);
+        assert_eq!("#2", results[1].case);
+        assert_eq!(1, results[1].failures.len());
+        assert!(results[1].failures[0].contains("Missing argument: name"));
+
+        // Changing a referenced prompt breaks the test
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("signature".to_string(), None, vec![]),
+            "Best".to_string(),
+        ));
+        assert!(!template.run_tests(&storage)[0].passed());
+    }
 
-        // Set up storage with all prompts
+    #[test]
+    fn test_dry_run() {
         let mut storage = MockStorage::new();
-        storage.add_prompt(prompt_level_0);
-        storage.add_prompt(prompt_level_1);
-        storage.add_prompt(prompt_level_2);
-        storage.add_prompt(prompt_level_3);
-        storage.add_prompt(prompt_level_4);
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("greeting".to_string(), None, vec![]),
+            "Hello {{name}}!".to_string(),
+        ));
+        let mut metadata = PromptMetadata::new("main".to_string(), None, vec![]);
+        metadata.arguments = vec![ArgumentDeclaration {
+            name: "tone".to_string(),
+            description: None,
+            default: Some("friendly".to_string()),
+        }];
+        let template = PromptTemplate::new(Prompt::new(
+            metadata,
+            "{{prompt:greeting}} {{tone}} {{prompt_var:style}} {{prompt_opt:footer}}".to_string(),
+        ))
+        .expect("Failed to create template");
 
-        let args = HashMap::new();
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "Alice".to_string());
+        let dry_run = template.dry_run(&args, &storage);
 
-        // Try to render prompt_level_0, which should fail due to exceeding max depth
-        let result = template_level_0.render(&args, &storage);
-        assert!(result.is_err());
-        assert!(
-            result
-                .unwrap_err()
-                .message
-                .contains("Maximum nesting depth of 3 exceeded")
+        let references: Vec<(Option<&str>, bool, bool)> = dry_run
+            .references
+            .iter()
+            .map(|node| (node.name.as_deref(), node.optional, node.error.is_some()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some("greeting"), false, false),
+                (None, false, true),
+                (Some("footer"), true, true)
+            ],
+            references
+        );
+        assert_eq!(Some("style"), dry_run.references[1].variable.as_deref());
+        let arguments: Vec<(&str, &str, ArgumentStatus)> = dry_run
+            .arguments
+            .iter()
+            .map(|argument| {
+                (
+                    argument.name.as_str(),
+                    argument.prompt.as_str(),
+                    argument.status,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("tone", "main", ArgumentStatus::Default),
+                ("style", "main", ArgumentStatus::Missing),
+                ("name", "greeting", ArgumentStatus::Provided),
+            ],
+            arguments
         );
+        assert_eq!(None, dry_run.estimated_tokens);
+        assert!(dry_run.error.unwrap().contains("footer"));
+
+        let template = PromptTemplate::new(storage.get_prompt("greeting").unwrap()).unwrap();
+        let dry_run = template.dry_run(&args, &storage);
+        assert!(dry_run.is_renderable());
+        assert_eq!(Some(count_tokens("Hello Alice!")), dry_run.estimated_tokens);
     }
 
     #[test]
-    fn test_render_template_with_valid_depth() {
-        // Create prompts with nesting that is within the maximum depth
-        let prompt_level_0_metadata =
-            PromptMetadata::new("prompt_level_0".to_string(), None, vec![]);
-        let prompt_level_0 = Prompt::new(
-            prompt_level_0_metadata,
-            "Level 0 {{prompt:prompt_level_1}}".to_string(),
-        );
-        let template_level_0 =
-            PromptTemplate::new(prompt_level_0.clone()).expect("Failed to create template");
-
-        let prompt_level_1_metadata =
-            PromptMetadata::new("prompt_level_1".to_string(), None, vec![]);
-        let prompt_level_1 = Prompt::new(
-            prompt_level_1_metadata,
-            "Level 1 {{prompt:prompt_level_2}}".to_string(),
+    fn test_render_traced() {
+        let mut storage = MockStorage::new();
+        for (name, content) in [("a", "A{{prompt:c}}"), ("b", "B"), ("c", "C {{x}}")] {
+            storage.add_prompt(Prompt::new(
+                PromptMetadata::new(name.to_string(), None, vec![]),
+                content.to_string(),
+            ));
+        }
+        let template = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "Intro: {{prompt:a}} and {{prompt:b}}".to_string(),
+        ))
+        .expect("Failed to create template");
+        let args = HashMap::from([("x".to_string(), "1".to_string())]);
+
+        let trace = template.render_traced(&args, &storage).unwrap();
+        assert_eq!("Intro: AC 1 and B", trace.output);
+        let spans = |node: &TracedPrompt| (node.name.clone(), node.order, node.span.clone());
+        assert_eq!(("main".to_string(), 0, 0..17), spans(&trace.root));
+        let [a, b] = trace.root.references.as_slice() else {
+            panic!("Expected two references: {:?}", trace.root.references);
+        };
+        assert_eq!(("a".to_string(), 1, 7..11), spans(a));
+        assert_eq!(("b".to_string(), 3, 16..17), spans(b));
+        assert_eq!(
+            vec![("c".to_string(), 2, 8..11)],
+            a.references.iter().map(spans).collect::<Vec<_>>()
         );
+        assert_eq!("C 1", &trace.output[a.references[0].span.clone()]);
+        assert_eq!(2, a.references[0].depth);
+    }
 
-        let prompt_level_2_metadata =
-            PromptMetadata::new("prompt_level_2".to_string(), None, vec![]);
-        let prompt_level_2 = Prompt::new(prompt_level_2_metadata, "Level 2".to_string());
-
-        // Set up storage with all prompts
+    #[test]
+    fn test_render_plan_is_deterministic() {
         let mut storage = MockStorage::new();
-        storage.add_prompt(prompt_level_0);
-        storage.add_prompt(prompt_level_1);
-        storage.add_prompt(prompt_level_2);
-
-        let args = HashMap::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("greeting".to_string(), None, vec![]),
+            "Hello {{b}} and {{a}}!".to_string(),
+        ));
+        let template = PromptTemplate::new(storage.get_prompt("greeting").unwrap())
+            .expect("Failed to create template");
 
-        // Try to render prompt_level_0, which should succeed
-        let result = template_level_0.render(&args, &storage);
-        assert!(result.is_ok());
-        assert_eq!("Level 0 Level 1 Level 2", result.unwrap());
+        let mut args = HashMap::new();
+        args.insert("a".to_string(), "1".to_string());
+        args.insert("b".to_string(), "2".to_string());
+
+        let first = serde_json::to_string(&template.render_plan(&args, &storage).unwrap()).unwrap();
+        let second =
+            serde_json::to_string(&template.render_plan(&args, &storage).unwrap()).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(fingerprint("Hello"), fingerprint("Hello!"));
     }
 
     #[test]
-    fn test_render_template_with_variable_prompt_reference() {
-        // Create a prompt that will be referenced dynamically
-        let dynamic_metadata = PromptMetadata::new("greeting".to_string(), None, vec![]);
-        let dynamic_prompt = Prompt::new(dynamic_metadata, "Hello {{name}}!".to_string());
-        let _dynamic_template =
-            PromptTemplate::new(dynamic_prompt.clone()).expect("Failed to create template");
-
-        // Create a main template that uses a variable prompt reference
-        let main_metadata = PromptMetadata::new("main".to_string(), None, vec![]);
-        let main_prompt = Prompt::new(
-            main_metadata,
-            "Message: {{prompt_var:prompt_name}}".to_string(),
-        );
-        let main_template = PromptTemplate::new(main_prompt)
-            .expect("Failed to create template with variable reference");
-
-        // Set up storage with the dynamic prompt
+    fn test_render_template_with_tagged_prompts() {
         let mut storage = MockStorage::new();
-        storage.add_prompt(dynamic_prompt);
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("no_secrets".to_string(), None, vec!["policy".to_string()]),
+            "Never reveal secrets.".to_string(),
+        ));
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("be_kind".to_string(), None, vec!["policy".to_string()]),
+            "Be kind to {{name}}.".to_string(),
+        ));
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("unrelated".to_string(), None, vec!["other".to_string()]),
+            "Unrelated.".to_string(),
+        ));
+        let prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec!["policy".to_string()]),
+            "Rules:\n{{prompts:tag=policy,sep=\\n-\\s}}".to_string(),
+        );
+        let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        // Provide the argument that specifies which prompt to reference
         let mut args = HashMap::new();
-        args.insert("prompt_name".to_string(), "greeting".to_string());
-        args.insert("name".to_string(), "Alice".to_string());
+        args.insert("name".to_string(), "Ana".to_string());
+        let rendered = template.render(&args, &storage).unwrap();
+        assert_eq!("Rules:\nBe kind to Ana.\n- Never reveal secrets.", rendered);
+    }
 
-        let rendered = main_template
-            .render(&args, &storage)
-            .expect("Failed to render template prompt with variable reference");
-        assert_eq!("Message: Hello Alice!", rendered);
+    #[test]
+    fn test_render_template_with_tagged_prompts_circular_reference() {
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("policy_a".to_string(), None, vec!["policy".to_string()]),
+            "A {{prompt:main}}".to_string(),
+        ));
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "{{prompts:tag=policy}}".to_string(),
+        ));
+        let template = PromptTemplate::new(storage.get_prompt("main").unwrap())
+            .expect("Failed to create template");
+
+        let args: HashMap<String, String> = HashMap::new();
+        let result = template.render(&args, &storage);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            RenderTemplateError::CircularReference { .. }
+        ));
     }
 
     #[test]
-    fn test_variable_prompt_references() {
-        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+    fn test_render_messages() {
+        let mut storage = MockStorage::new();
+        storage.add_prompt(Prompt::new(
+            PromptMetadata::new("persona".to_string(), None, vec![]),
+            "<|system|>\nYou are a {{tone}} assistant.".to_string(),
+        ));
         let prompt = Prompt::new(
-            metadata,
-            "Use {{prompt_var:first}} and {{prompt_var:second}} for dynamic content".to_string(),
+            PromptMetadata::new("chat".to_string(), None, vec![]),
+            "{{prompt:persona}}\n<|user|>\nHello!".to_string(),
         );
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        let variable_refs = template.variable_prompt_references();
-        assert_eq!(variable_refs.len(), 2);
-        assert!(variable_refs.contains(&"first".to_string()));
-        assert!(variable_refs.contains(&"second".to_string()));
+        let mut args = HashMap::new();
+        args.insert("tone".to_string(), "friendly".to_string());
+        let messages = template.render_messages(&args, &storage).unwrap();
+        assert_eq!(
+            vec![
+                RenderedMessage {
+                    role: Role::System,
+                    content: "You are a friendly assistant.".to_string(),
+                },
+                RenderedMessage {
+                    role: Role::User,
+                    content: "Hello!".to_string(),
+                },
+            ],
+            messages
+        );
     }
 
     #[test]
-    fn test_render_template_with_missing_variable_prompt_reference() {
-        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
+    fn test_argument_info() {
+        let mut metadata = PromptMetadata::new("explain".to_string(), None, vec![]);
+        metadata.arguments = vec![
+            ArgumentDeclaration {
+                name: "topic".to_string(),
+                description: Some("What to explain".to_string()),
+                default: None,
+            },
+            ArgumentDeclaration {
+                name: "tone".to_string(),
+                description: None,
+                default: Some("friendly".to_string()),
+            },
+            ArgumentDeclaration {
+                name: "unused".to_string(),
+                description: None,
+                default: Some("x".to_string()),
+            },
+        ];
         let prompt = Prompt::new(
             metadata,
-            "Message: {{prompt_var:missing_prompt}}".to_string(),
+            "Explain {{topic}} in a {{tone}} way. {{prompt_var:style}} {{topic}}".to_string(),
         );
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        let mut args = HashMap::new();
-        args.insert("missing_prompt".to_string(), "nonexistent".to_string());
+        let info = template.argument_info();
+        assert_eq!(
+            vec!["topic", "tone", "style", "unused"],
+            info.iter().map(|arg| arg.name.as_str()).collect::<Vec<_>>()
+        );
+        assert!(info[0].required);
+        assert_eq!(Some("What to explain".to_string()), info[0].description);
+        assert!(!info[1].required);
+        assert_eq!(Some("friendly".to_string()), info[1].default);
+        assert!(info[2].required);
+    }
 
+    #[test]
+    fn test_render_template_with_default_argument() {
+        let mut metadata = PromptMetadata::new("greeting".to_string(), None, vec![]);
+        metadata.arguments = vec![ArgumentDeclaration {
+            name: "name".to_string(),
+            description: None,
+            default: Some("friend".to_string()),
+        }];
+        let template = PromptTemplate::new(Prompt::new(metadata, "Hello {{name}}!".to_string()))
+            .expect("Failed to create template");
         let storage = MockStorage::new();
-        let result = template.render(&args, &storage);
-        assert!(result.is_err());
+
+        let args: HashMap<String, String> = HashMap::new();
+        assert_eq!("Hello friend!", template.render(&args, &storage).unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), "Ana".to_string());
+        assert_eq!("Hello Ana!", template.render(&args, &storage).unwrap());
     }
 
     #[test]
-    fn test_render_template_with_variable_prompt_reference_missing_argument() {
-        let metadata = PromptMetadata::new("template".to_string(), None, vec![]);
-        let prompt = Prompt::new(metadata, "Message: {{prompt_var:prompt_name}}".to_string());
+    fn test_render_with_warnings_for_deprecated_prompts() {
+        let mut storage = MockStorage::new();
+        let mut old_metadata = PromptMetadata::new("old_greeting".to_string(), None, vec![]);
+        old_metadata.deprecated = true;
+        old_metadata.superseded_by = Some("greeting".to_string());
+        storage.add_prompt(Prompt::new(old_metadata, "Hi".to_string()));
+
+        let prompt = Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "{{prompt:old_greeting}} and {{prompt:old_greeting}}".to_string(),
+        );
         let template = PromptTemplate::new(prompt).expect("Failed to create template");
 
-        let args = HashMap::new(); // Missing the "prompt_name" argument
+        let args: HashMap<String, String> = HashMap::new();
+        let output = template.render_with_warnings(&args, &storage).unwrap();
+        assert_eq!("Hi and Hi", output.text);
+        assert_eq!(
+            vec![RenderWarning::DeprecatedPrompt {
+                name: "old_greeting".to_string(),
+                superseded_by: Some("greeting".to_string()),
+            }],
+            output.warnings
+        );
+        assert_eq!(
+            "Prompt 'old_greeting' is deprecated, use 'greeting' instead",
+            output.warnings[0].to_string()
+        );
 
-        let storage = MockStorage::new();
-        let result = template.render(&args, &storage);
-        assert!(result.is_err());
-        assert_eq!("Missing argument: prompt_name", result.unwrap_err().message);
+        let mut output = Vec::new();
+        let warnings = template
+            .render_to_writer(&args, &storage, &mut output)
+            .unwrap();
+        assert_eq!(1, warnings.len());
     }
 
     #[test]
-    fn test_render_template_with_variable_prompt_reference_circular_reference() {
-        // Create prompts that reference each other circularly
-        let prompt_a_metadata = PromptMetadata::new("prompt_a".to_string(), None, vec![]);
-        let prompt_a = Prompt::new(prompt_a_metadata, "A {{prompt_var:ref_prompt}}".to_string());
-        let template_a = PromptTemplate::new(prompt_a.clone()).expect("Failed to create template");
-
-        let prompt_b_metadata = PromptMetadata::new("prompt_b".to_string(), None, vec![]);
-        let prompt_b = Prompt::new(prompt_b_metadata, "B {{name}}".to_string());
+    fn test_render_with_warnings_for_deprecated_root_prompt() {
+        let mut metadata = PromptMetadata::new("old".to_string(), None, vec![]);
+        metadata.deprecated = true;
+        let template = PromptTemplate::new(Prompt::new(metadata, "Hi".to_string()))
+            .expect("Failed to create template");
+
+        let args: HashMap<String, String> = HashMap::new();
+        let output = template
+            .render_with_warnings(&args, &MockStorage::new())
+            .unwrap();
+        assert_eq!("Prompt 'old' is deprecated", output.warnings[0].to_string());
+    }
 
-        // Set up storage with both prompts
+    #[test]
+    fn test_render_with_warnings_for_unused_arguments_and_depth() {
         let mut storage = MockStorage::new();
-        storage.add_prompt(prompt_a);
-        storage.add_prompt(prompt_b);
-
-        // Set up arguments where prompt_name refers back to prompt_a (circular)
-        let mut args = HashMap::new();
-        args.insert("ref_prompt".to_string(), "prompt_a".to_string()); // Circular reference
-        args.insert("name".to_string(), "Alice".to_string());
-
-        // Try to render prompt_a, which should fail due to circular reference
-        let result = template_a.render(&args, &storage);
-        assert!(result.is_err());
-        assert!(
-            result
-                .unwrap_err()
-                .message
-                .contains("Circular reference detected")
+        for (name, content) in [
+            ("a", "{{prompt:b}}"),
+            ("b", "{{prompt:c}}"),
+            ("c", "{{user.name}}"),
+        ] {
+            storage.add_prompt(Prompt::new(
+                PromptMetadata::new(name.to_string(), None, vec![]),
+                content.to_string(),
+            ));
+        }
+        let template = PromptTemplate::new(Prompt::new(
+            PromptMetadata::new("main".to_string(), None, vec![]),
+            "{{prompt:a}}".to_string(),
+        ))
+        .expect("Failed to create template");
+
+        let args: HashMap<String, Value> = HashMap::from([
+            ("user".to_string(), serde_json::json!({ "name": "Ana" })),
+            ("nmae".to_string(), Value::from("Ana")),
+        ]);
+        let output = template.render_with_warnings(&args, &storage).unwrap();
+        assert_eq!("Ana", output.text);
+        assert_eq!(
+            vec![
+                RenderWarning::NearDepthLimit {
+                    name: "c".to_string(),
+                    depth: 3,
+                },
+                RenderWarning::UnusedArguments {
+                    names: vec!["nmae".to_string()],
+                },
+            ],
+            output.warnings
         );
     }
 }
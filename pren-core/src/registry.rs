@@ -0,0 +1,578 @@
+//! # Pack Registry
+//!
+//! This module implements a simple registry of [prompt packs](crate::pack): a static
+//! directory that can be served over HTTPS by any web server, with an index of the
+//! published versions of each pack and a bundle file for each version.
+//!
+//! ```text
+//! index.json
+//! packs/code-review/1.0.0.json
+//! packs/code-review/1.1.0.json
+//! ```
+//!
+//! The index lists the versions of each pack with the path of their bundle, relative to the
+//! index, and its SHA-256 checksum, checked when the bundle is downloaded:
+//!
+//! ```json
+//! {
+//!   "packs": {
+//!     "code-review": [
+//!       {"version": "1.0.0", "path": "packs/code-review/1.0.0.json", "checksum": "sha256:…"}
+//!     ]
+//!   }
+//! }
+//! ```
+//!
+//! A bundle is the pack's manifest with the contents of its prompt files. Published
+//! versions are immutable, and versions are chosen by semantic versioning requirements
+//! like `^1.2`, `~1.2.3`, `>=1.0` or `=1.0.0`.
+//!
+//! Packs installed from a registry are namespaced by their name, so packs can't overwrite
+//! each other's prompts: the `review` prompt of the `code-review` pack is installed as
+//! `code-review.review`, with the references between the pack's prompts renamed.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::registry::{Version, VersionReq};
+//!
+//! let requirement: VersionReq = "^1.2".parse().unwrap();
+//! assert!(requirement.matches(&"1.4.0".parse::<Version>().unwrap()));
+//! assert!(!requirement.matches(&"2.0.0".parse::<Version>().unwrap()));
+//! ```
+
+use crate::file_storage::format_prompt_file;
+use crate::pack::{PackError, PackManifest, PromptPack};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+use thiserror::Error;
+
+/// The name of the index file at the root of a registry.
+pub const INDEX_FILE_NAME: &str = "index.json";
+
+#[derive(Error, Debug)]
+pub enum RegistryError {
+    #[error("i/o error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("invalid registry file: {0}")]
+    FormatError(#[from] serde_json::Error),
+    #[error(transparent)]
+    PackError(#[from] PackError),
+    #[error("invalid version '{0}': expected major.minor.patch")]
+    InvalidVersion(String),
+    #[error("invalid version requirement '{0}'")]
+    InvalidVersionReq(String),
+    #[error("invalid pack name '{0}': use letters, digits, '-' and '_'")]
+    InvalidPackName(String),
+    #[error("pack '{0}' isn't in the registry")]
+    PackNotFound(String),
+    #[error("no version of pack '{name}' matches '{requirement}'")]
+    NoMatchingVersion { name: String, requirement: String },
+    #[error("version {version} of pack '{name}' is already published")]
+    VersionExists { name: String, version: String },
+    #[error("checksum mismatch for pack '{name}': expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// A semantic version, like `1.2.3` or `2.0.0-beta.1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// The pre-release identifiers, e.g. `beta.1`.
+    pub pre: Option<String>,
+}
+
+impl FromStr for Version {
+    type Err = RegistryError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || RegistryError::InvalidVersion(value.to_string());
+        // Build metadata doesn't take part in comparisons
+        let version = value.trim().split('+').next().unwrap_or_default();
+        let (version, pre) = match version.split_once('-') {
+            Some((version, pre)) if !pre.is_empty() => (version, Some(pre.to_string())),
+            Some(_) => return Err(invalid()),
+            None => (version, None),
+        };
+        let numbers = version
+            .split('.')
+            .map(|number| number.parse::<u64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<u64>, RegistryError>>()?;
+        let [major, minor, patch] = numbers[..] else {
+            return Err(invalid());
+        };
+        Ok(Version {
+            major,
+            minor,
+            patch,
+            pre,
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                // Pre-releases come before their release
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(pre), Some(other)) => compare_pre(pre, other),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares pre-release identifiers, numeric ones as numbers.
+fn compare_pre(pre: &str, other: &str) -> Ordering {
+    let mut identifiers = pre.split('.');
+    let mut others = other.split('.');
+    loop {
+        let ordering = match (identifiers.next(), others.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// How a [`VersionReq`] compares versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    /// Compatible versions, the default: `^1.2` matches `1.x` from `1.2.0`.
+    Caret,
+    /// Patch updates: `~1.2` matches `1.2.x`.
+    Tilde,
+    Exact,
+    AtLeast,
+}
+
+/// A semantic versioning requirement choosing the versions of a pack: `*`, `^1.2` (or
+/// just `1.2`), `~1.2.3`, `>=1.0` or `=1.0.0`. Pre-releases only match exact requirements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    operator: Option<Operator>,
+    version: Version,
+    /// How many of major, minor and patch the requirement gives.
+    parts: usize,
+}
+
+impl VersionReq {
+    /// The requirement matching every release.
+    pub fn any() -> Self {
+        VersionReq {
+            operator: None,
+            version: Version {
+                major: 0,
+                minor: 0,
+                patch: 0,
+                pre: None,
+            },
+            parts: 0,
+        }
+    }
+
+    /// Whether a version meets the requirement.
+    pub fn matches(&self, version: &Version) -> bool {
+        let Some(operator) = self.operator else {
+            return version.pre.is_none();
+        };
+        if operator == Operator::Exact {
+            return version == &self.version;
+        }
+        if version.pre.is_some() || version < &self.version {
+            return false;
+        }
+        let required = &self.version;
+        match operator {
+            Operator::AtLeast | Operator::Exact => true,
+            Operator::Tilde if self.parts >= 2 => {
+                (version.major, version.minor) == (required.major, required.minor)
+            }
+            Operator::Tilde => version.major == required.major,
+            // The first non-zero part given can't change
+            Operator::Caret if required.major > 0 || self.parts == 1 => {
+                version.major == required.major
+            }
+            Operator::Caret if required.minor > 0 || self.parts == 2 => {
+                (version.major, version.minor) == (required.major, required.minor)
+            }
+            Operator::Caret => version == required,
+        }
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = RegistryError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value.is_empty() || value == "*" {
+            return Ok(VersionReq::any());
+        }
+        let (operator, version) = [
+            (">=", Operator::AtLeast),
+            ("=", Operator::Exact),
+            ("^", Operator::Caret),
+            ("~", Operator::Tilde),
+        ]
+        .into_iter()
+        .find_map(|(prefix, operator)| Some((operator, value.strip_prefix(prefix)?)))
+        .unwrap_or((Operator::Caret, value));
+
+        let version = version.trim();
+        let parts = version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .count();
+        // Partial versions like `1.2` are completed with zeros
+        let complete = match parts {
+            1 => format!("{}.0.0", version),
+            2 => format!("{}.0", version),
+            _ => version.to_string(),
+        };
+        let version = complete
+            .parse()
+            .map_err(|_| RegistryError::InvalidVersionReq(value.to_string()))?;
+        Ok(VersionReq {
+            operator: Some(operator),
+            version,
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self.operator {
+            None => return write!(f, "*"),
+            Some(Operator::Caret) => "^",
+            Some(Operator::Tilde) => "~",
+            Some(Operator::Exact) => "=",
+            Some(Operator::AtLeast) => ">=",
+        };
+        write!(f, "{}{}", operator, self.version)
+    }
+}
+
+/// A published version of a pack in the index of a registry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub version: String,
+    /// The path of the bundle, relative to the index.
+    pub path: String,
+    /// The checksum of the bundle, as `sha256:<hex digest>`.
+    pub checksum: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// The index of a registry, with the published versions of each pack.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegistryIndex {
+    #[serde(default)]
+    pub packs: BTreeMap<String, Vec<IndexEntry>>,
+}
+
+impl RegistryIndex {
+    /// Finds the latest version of a pack meeting a requirement.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(&IndexEntry)` - The latest matching version.
+    /// * `Err(RegistryError)` - If the pack isn't in the index, or no version matches.
+    pub fn resolve(
+        &self,
+        name: &str,
+        requirement: &VersionReq,
+    ) -> Result<&IndexEntry, RegistryError> {
+        let entries = self
+            .packs
+            .get(name)
+            .ok_or_else(|| RegistryError::PackNotFound(name.to_string()))?;
+        entries
+            .iter()
+            .filter_map(|entry| Some((entry.version.parse::<Version>().ok()?, entry)))
+            .filter(|(version, _)| requirement.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, entry)| entry)
+            .ok_or_else(|| RegistryError::NoMatchingVersion {
+                name: name.to_string(),
+                requirement: requirement.to_string(),
+            })
+    }
+}
+
+/// A published version of a pack: its manifest with the contents of its prompt files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackBundle {
+    pub manifest: PackManifest,
+    pub prompts: Vec<String>,
+}
+
+impl PackBundle {
+    pub fn from_pack(pack: &PromptPack) -> Result<PackBundle, RegistryError> {
+        let prompts = pack
+            .prompts
+            .iter()
+            .map(|prompt| {
+                format_prompt_file(prompt).map_err(|e| PackError::StorageError(e.to_string()))
+            })
+            .collect::<Result<Vec<String>, PackError>>()?;
+        Ok(PackBundle {
+            manifest: pack.manifest.clone(),
+            prompts,
+        })
+    }
+
+    /// Loads a downloaded bundle, checking it against its entry in the index.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(PromptPack)` - The valid pack, with its prompts namespaced by its name.
+    /// * `Err(RegistryError)` - If the checksum doesn't match, or the bundle isn't a valid
+    ///   pack of the version in the index, e.g. if its name or the names of its prompts
+    ///   are paths.
+    pub fn load(name: &str, entry: &IndexEntry, bytes: &[u8]) -> Result<PromptPack, RegistryError> {
+        let actual = checksum(bytes);
+        if actual != entry.checksum {
+            return Err(RegistryError::ChecksumMismatch {
+                name: name.to_string(),
+                expected: entry.checksum.clone(),
+                actual,
+            });
+        }
+        let bundle: PackBundle = serde_json::from_slice(bytes)?;
+        // The checksum comes from the same registry, so the names are checked too
+        if !is_valid_pack_name(&bundle.manifest.name) {
+            return Err(RegistryError::InvalidPackName(bundle.manifest.name));
+        }
+        if bundle.manifest.name != name || bundle.manifest.version != entry.version {
+            return Err(PackError::InvalidPack(format!(
+                "expected {} {}, got {} {}",
+                name, entry.version, bundle.manifest.name, bundle.manifest.version
+            ))
+            .into());
+        }
+        let files: Vec<&str> = bundle.prompts.iter().map(String::as_str).collect();
+        let pack = PromptPack::from_files(bundle.manifest, &files)?;
+        pack.validate()?;
+        Ok(namespaced(pack))
+    }
+}
+
+/// Checks whether a pack name can namespace the names of its prompts.
+fn is_valid_pack_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Computes the checksum of a bundle, as `sha256:<hex digest>`.
+pub fn checksum(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", hex)
+}
+
+/// Renames the prompts of a pack to `<pack>.<prompt>`, along with the references between
+/// them.
+fn namespaced(mut pack: PromptPack) -> PromptPack {
+    let namespace = |name: &str| format!("{}.{}", pack.manifest.name, name);
+    let renames: Vec<(String, String)> = pack
+        .manifest
+        .prompts
+        .iter()
+        .map(|name| (name.clone(), namespace(name)))
+        .collect();
+    for prompt in &mut pack.prompts {
+        for (old, new) in &renames {
+            prompt.replace_prompt_references(old, new);
+        }
+        prompt.metadata.name = namespace(&prompt.metadata.name);
+    }
+    pack.manifest.prompts = renames.into_iter().map(|(_, new)| new).collect();
+    pack
+}
+
+/// Publishes a pack to a registry directory, adding its bundle and its version to the
+/// index. The directory is created if needed, and can then be served as it is.
+///
+/// # Returns
+///
+/// * `Ok(IndexEntry)` - The published version.
+/// * `Err(RegistryError)` - If the pack is invalid, or its version is already published.
+pub fn publish(pack: &PromptPack, registry_dir: &Path) -> Result<IndexEntry, RegistryError> {
+    pack.validate()?;
+    let name = &pack.manifest.name;
+    if !is_valid_pack_name(name) {
+        return Err(RegistryError::InvalidPackName(name.clone()));
+    }
+    let version: Version = pack.manifest.version.parse()?;
+
+    let index_path = registry_dir.join(INDEX_FILE_NAME);
+    let mut index: RegistryIndex = match fs::read_to_string(&index_path) {
+        Ok(content) => serde_json::from_str(&content)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => RegistryIndex::default(),
+        Err(e) => return Err(e.into()),
+    };
+    let entries = index.packs.entry(name.clone()).or_default();
+    if entries
+        .iter()
+        .any(|entry| entry.version.parse::<Version>().ok().as_ref() == Some(&version))
+    {
+        return Err(RegistryError::VersionExists {
+            name: name.clone(),
+            version: version.to_string(),
+        });
+    }
+
+    let path = format!("packs/{}/{}.json", name, version);
+    let bundle = serde_json::to_string_pretty(&PackBundle::from_pack(pack)?)?;
+    let bundle_path = registry_dir.join(&path);
+    if let Some(parent) = bundle_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&bundle_path, &bundle)?;
+
+    let entry = IndexEntry {
+        version: version.to_string(),
+        path,
+        checksum: checksum(bundle.as_bytes()),
+        description: pack.manifest.description.clone(),
+    };
+    entries.push(entry.clone());
+    entries.sort_by_cached_key(|entry| entry.version.parse::<Version>().ok());
+    fs::write(&index_path, serde_json::to_string_pretty(&index)?)?;
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::{Prompt, PromptMetadata};
+    use tempfile::TempDir;
+
+    fn version(value: &str) -> Version {
+        value.parse().unwrap()
+    }
+
+    fn matches(requirement: &str, value: &str) -> bool {
+        requirement
+            .parse::<VersionReq>()
+            .unwrap()
+            .matches(&version(value))
+    }
+
+    #[test]
+    fn test_version_requirements() {
+        assert!(version("1.0.0-beta.2") < version("1.0.0-beta.10"));
+        assert!(version("1.0.0-beta") < version("1.0.0"));
+        assert!("1.0".parse::<Version>().is_err());
+
+        assert!(matches("1.2", "1.9.0"));
+        assert!(!matches("^1.2", "1.1.0"));
+        assert!(!matches("^1.2", "2.0.0"));
+        assert!(matches("^0.2", "0.2.5"));
+        assert!(!matches("^0.2", "0.3.0"));
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3.0"));
+        assert!(matches(">=1.0", "3.0.0"));
+        assert!(!matches("*", "1.0.0-rc.1"));
+        assert!(matches("=1.0.0-rc.1", "1.0.0-rc.1"));
+        assert!("^x".parse::<VersionReq>().is_err());
+    }
+
+    #[test]
+    fn test_publish_and_load_pack() {
+        let pack = |version: &str| PromptPack {
+            manifest: PackManifest {
+                name: "code-review".to_string(),
+                version: version.to_string(),
+                description: None,
+                author: Some("Ana".to_string()),
+                license: None,
+                prompts: vec!["review".to_string(), "style".to_string()],
+            },
+            prompts: vec![
+                Prompt::new(
+                    PromptMetadata::new("review".to_string(), None, vec![]),
+                    "Review {{code}}. {{prompt:style}}".to_string(),
+                ),
+                Prompt::new(
+                    PromptMetadata::new("style".to_string(), None, vec![]),
+                    "Be concise.".to_string(),
+                ),
+            ],
+        };
+        let registry_dir = TempDir::new().unwrap();
+        publish(&pack("1.2.0"), registry_dir.path()).unwrap();
+        publish(&pack("1.10.0"), registry_dir.path()).unwrap();
+        assert!(matches!(
+            publish(&pack("1.2.0"), registry_dir.path()),
+            Err(RegistryError::VersionExists { .. })
+        ));
+
+        let index: RegistryIndex = serde_json::from_str(
+            &fs::read_to_string(registry_dir.path().join(INDEX_FILE_NAME)).unwrap(),
+        )
+        .unwrap();
+        let entry = index
+            .resolve("code-review", &"^1".parse().unwrap())
+            .unwrap();
+        assert_eq!("1.10.0", entry.version);
+
+        let bytes = fs::read(registry_dir.path().join(&entry.path)).unwrap();
+        let installed = PackBundle::load("code-review", entry, &bytes).unwrap();
+        assert_eq!("code-review.review", installed.prompts[0].metadata.name);
+        assert_eq!(
+            "Review {{code}}. {{prompt:code-review.style}}",
+            installed.prompts[0].content
+        );
+        assert!(matches!(
+            PackBundle::load("code-review", entry, b"{}"),
+            Err(RegistryError::ChecksumMismatch { .. })
+        ));
+    }
+}
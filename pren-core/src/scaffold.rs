@@ -0,0 +1,117 @@
+//! # Scaffolds
+//!
+//! This module provides built-in meta-prompts to start new prompts from, such as a code
+//! review or a summarizer. Each scaffold is a pre-structured prompt whose arguments are
+//! declared and documented, ready to be edited.
+//!
+//! The scaffolds are a [`PromptPack`] embedded in the crate, so they're always available
+//! and validated like any other pack.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::scaffold::scaffold;
+//!
+//! let prompt = scaffold("code-review", "rust-review").unwrap();
+//! assert_eq!("rust-review", prompt.metadata.name);
+//! assert!(!prompt.metadata.arguments.is_empty());
+//! ```
+
+use crate::pack::PromptPack;
+use crate::prompt::Prompt;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+const MANIFEST: &str = include_str!("../scaffolds/pack.json");
+
+const PROMPT_FILES: &[&str] = &[
+    include_str!("../scaffolds/code-review.md"),
+    include_str!("../scaffolds/summarizer.md"),
+    include_str!("../scaffolds/extraction.md"),
+    include_str!("../scaffolds/chain-of-thought.md"),
+];
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ScaffoldError {
+    #[error("unknown scaffold '{0}', available scaffolds are: {1}")]
+    UnknownScaffold(String, String),
+}
+
+/// The pack of built-in scaffolds, one prompt per kind of scaffold.
+pub fn builtin_scaffolds() -> &'static PromptPack {
+    static SCAFFOLDS: OnceLock<PromptPack> = OnceLock::new();
+    SCAFFOLDS.get_or_init(|| {
+        PromptPack::parse(MANIFEST, PROMPT_FILES).expect("built-in scaffolds are valid")
+    })
+}
+
+/// Creates a prompt from a built-in scaffold.
+///
+/// # Arguments
+///
+/// * `kind` - The scaffold to use, e.g. `code-review`.
+/// * `name` - The name of the new prompt.
+///
+/// # Returns
+///
+/// * `Ok(Prompt)` - A copy of the scaffold, named `name`.
+/// * `Err(ScaffoldError::UnknownScaffold)` - If there's no scaffold of that kind.
+pub fn scaffold(kind: &str, name: &str) -> Result<Prompt, ScaffoldError> {
+    let scaffolds = builtin_scaffolds();
+    let mut prompt = scaffolds
+        .prompts
+        .iter()
+        .find(|prompt| prompt.metadata.name == kind)
+        .cloned()
+        .ok_or_else(|| {
+            ScaffoldError::UnknownScaffold(kind.to_string(), scaffolds.manifest.prompts.join(", "))
+        })?;
+    prompt.metadata.name = name.to_string();
+    Ok(prompt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptTemplate;
+
+    #[test]
+    fn test_builtin_scaffolds_are_valid() {
+        let scaffolds = builtin_scaffolds();
+        assert!(scaffolds.validate().is_ok());
+        assert_eq!(
+            vec![
+                "code-review",
+                "summarizer",
+                "extraction",
+                "chain-of-thought"
+            ],
+            scaffolds.manifest.prompts
+        );
+
+        // Every argument is documented
+        for prompt in &scaffolds.prompts {
+            let template = PromptTemplate::new(prompt.clone()).unwrap();
+            for argument in template.argument_info() {
+                assert!(
+                    argument.description.is_some(),
+                    "{}: {}",
+                    prompt.metadata.name,
+                    argument.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_scaffold() {
+        let prompt = scaffold("summarizer", "meeting-notes").unwrap();
+        assert_eq!("meeting-notes", prompt.metadata.name);
+        assert_eq!(builtin_scaffolds().prompts[1].content, prompt.content);
+
+        assert!(matches!(
+            scaffold("poem", "poem"),
+            Err(ScaffoldError::UnknownScaffold(kind, _)) if kind == "poem"
+        ));
+    }
+}
@@ -0,0 +1,315 @@
+//! # Prompt Search
+//!
+//! This module provides case-insensitive full-text search over prompts' names,
+//! descriptions, tags and content.
+//!
+//! A query is split into words, and a prompt matches if every word is found in at least
+//! one of the searched fields. Each match records where the words were found, so that
+//! they can be highlighted.
+//!
+//! Prompt names can also be matched against glob patterns with [`matches_glob`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//! use pren_core::search::{SearchField, search_prompts};
+//!
+//! let prompt = Prompt::new(
+//!     PromptMetadata::new("migrations".to_string(), None, vec!["sql".to_string()]),
+//!     "Review this SQL migration for locking issues.".to_string(),
+//! );
+//!
+//! let results = search_prompts(&[prompt], "sql migration", SearchField::ALL);
+//! assert_eq!(results[0].name, "migrations");
+//! assert_eq!(results[0].matches[0].field, SearchField::Name);
+//! ```
+
+use crate::prompt::Prompt;
+use regex::Regex;
+use serde::Serialize;
+use std::ops::Range;
+
+/// A part of a prompt that can be searched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField {
+    Name,
+    Description,
+    Tags,
+    Content,
+}
+
+impl SearchField {
+    /// Every searchable field.
+    pub const ALL: &'static [SearchField] = &[
+        SearchField::Name,
+        SearchField::Description,
+        SearchField::Tags,
+        SearchField::Content,
+    ];
+}
+
+/// Text of a prompt where the query was found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchMatch {
+    pub field: SearchField,
+    /// The line number, starting at 1, for matches in the content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The text of the field, or the matching line of the content. Tags are joined with ", ".
+    pub text: String,
+    /// The byte ranges of `text` where query words were found.
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// A prompt matching a search, with every match found in it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub name: String,
+    pub matches: Vec<SearchMatch>,
+}
+
+/// Searches prompts for every word of a query, ignoring case.
+///
+/// # Arguments
+///
+/// * `prompts` - The prompts to search.
+/// * `query` - The words to search for.
+/// * `fields` - The fields of the prompts to search in.
+///
+/// # Returns
+///
+/// * `Vec<SearchResult>` - The matching prompts, those with the most matches first.
+///   Empty if the query has no words.
+pub fn search_prompts(
+    prompts: &[Prompt],
+    query: &str,
+    fields: &[SearchField],
+) -> Vec<SearchResult> {
+    let words: Vec<Regex> = query
+        .split_whitespace()
+        .map(|word| {
+            Regex::new(&format!("(?i){}", regex::escape(word))).expect("Invalid search pattern")
+        })
+        .collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    let mut results: Vec<SearchResult> = prompts
+        .iter()
+        .filter_map(|prompt| {
+            let texts = searched_texts(prompt, fields);
+            let all_found = words
+                .iter()
+                .all(|word| texts.iter().any(|(_, _, text)| word.is_match(text)));
+            if !all_found {
+                return None;
+            }
+
+            let matches = texts
+                .into_iter()
+                .filter_map(|(field, line, text)| {
+                    let ranges = find_ranges(&words, &text);
+                    (!ranges.is_empty()).then_some(SearchMatch {
+                        field,
+                        line,
+                        text,
+                        ranges,
+                    })
+                })
+                .collect();
+            Some(SearchResult {
+                name: prompt.metadata.name.clone(),
+                matches,
+            })
+        })
+        .collect();
+
+    let count = |result: &SearchResult| -> usize {
+        result.matches.iter().map(|found| found.ranges.len()).sum()
+    };
+    results.sort_by(|a, b| count(b).cmp(&count(a)).then_with(|| a.name.cmp(&b.name)));
+    results
+}
+
+/// The texts of a prompt in the searched fields, with their line numbers for content lines.
+fn searched_texts(
+    prompt: &Prompt,
+    fields: &[SearchField],
+) -> Vec<(SearchField, Option<usize>, String)> {
+    let mut texts = Vec::new();
+    for field in fields {
+        match field {
+            SearchField::Name => texts.push((*field, None, prompt.metadata.name.clone())),
+            SearchField::Description => {
+                if let Some(description) = &prompt.metadata.description {
+                    texts.push((*field, None, description.clone()));
+                }
+            }
+            SearchField::Tags => {
+                if !prompt.metadata.tags.is_empty() {
+                    texts.push((*field, None, prompt.metadata.tags.join(", ")));
+                }
+            }
+            SearchField::Content => texts.extend(
+                prompt
+                    .content
+                    .lines()
+                    .enumerate()
+                    .map(|(index, line)| (*field, Some(index + 1), line.to_string())),
+            ),
+        }
+    }
+    texts
+}
+
+/// Whether a pattern has glob wildcards, rather than being a plain name.
+pub fn is_glob_pattern(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Whether a name matches a glob pattern, where `*` matches any number of characters and
+/// `?` matches exactly one, e.g. `draft-*` matches `draft-intro`.
+pub fn matches_glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // The position after the last `*`, and the position of the name it was matched up to
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character
+                Some((after_star, matched)) => {
+                    star = Some((after_star, matched + 1));
+                    p = after_star;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The sorted, merged ranges of the text where any of the words are found.
+fn find_ranges(words: &[Regex], text: &str) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = words
+        .iter()
+        .flat_map(|word| word.find_iter(text).map(|found| found.range()))
+        .collect();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptMetadata;
+
+    fn prompt(name: &str, description: Option<&str>, tags: &[&str], content: &str) -> Prompt {
+        Prompt::new(
+            PromptMetadata::new(
+                name.to_string(),
+                description.map(str::to_string),
+                tags.iter().map(|tag| tag.to_string()).collect(),
+            ),
+            content.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_search_prompts() {
+        let prompts = [
+            prompt(
+                "migrations",
+                Some("Reviews SQL migrations"),
+                &["sql"],
+                "Check the migration.\nLook for table locks.",
+            ),
+            prompt("queries", None, &["sql"], "Optimize this query."),
+            prompt("greeting", None, &[], "Hello!"),
+        ];
+
+        let results = search_prompts(&prompts, "SQL migration", SearchField::ALL);
+        assert_eq!(1, results.len());
+        assert_eq!(
+            vec![
+                SearchMatch {
+                    field: SearchField::Name,
+                    line: None,
+                    text: "migrations".to_string(),
+                    ranges: vec![Range { start: 0, end: 9 }],
+                },
+                SearchMatch {
+                    field: SearchField::Description,
+                    line: None,
+                    text: "Reviews SQL migrations".to_string(),
+                    ranges: vec![8..11, 12..21],
+                },
+                SearchMatch {
+                    field: SearchField::Tags,
+                    line: None,
+                    text: "sql".to_string(),
+                    ranges: vec![Range { start: 0, end: 3 }],
+                },
+                SearchMatch {
+                    field: SearchField::Content,
+                    line: Some(1),
+                    text: "Check the migration.".to_string(),
+                    ranges: vec![Range { start: 10, end: 19 }],
+                },
+            ],
+            results[0].matches
+        );
+
+        let names: Vec<String> = search_prompts(&prompts, "sql", SearchField::ALL)
+            .into_iter()
+            .map(|result| result.name)
+            .collect();
+        assert_eq!(vec!["migrations", "queries"], names);
+    }
+
+    #[test]
+    fn test_search_selected_fields() {
+        let prompts = [prompt("sql-review", None, &[], "Review this code.")];
+        assert!(search_prompts(&prompts, "sql", &[SearchField::Content]).is_empty());
+        assert_eq!(
+            1,
+            search_prompts(&prompts, "sql", &[SearchField::Name]).len()
+        );
+        assert!(search_prompts(&prompts, "  ", SearchField::ALL).is_empty());
+    }
+
+    #[test]
+    fn test_matches_glob() {
+        assert!(matches_glob("draft-*", "draft-intro"));
+        assert!(matches_glob("draft-*", "draft-"));
+        assert!(matches_glob("*-review-*", "sql-review-v2"));
+        assert!(matches_glob("v?", "v2"));
+        assert!(matches_glob("greeting", "greeting"));
+        assert!(!matches_glob("draft-*", "final-draft"));
+        assert!(!matches_glob("v?", "v10"));
+        assert!(!matches_glob("*-review", "sql-review-v2"));
+        assert!(is_glob_pattern("draft-*"));
+        assert!(!is_glob_pattern("draft"));
+    }
+}
@@ -0,0 +1,128 @@
+//! # Usage Statistics
+//!
+//! This module provides the data structures used to track how often prompts are used,
+//! so that unused prompts can be found and pruned, and how many tokens each model used.
+//!
+//! Statistics are stored by implementors of the [`StatsStorage`](crate::storage::StatsStorage)
+//! trait, alongside the prompts themselves.
+
+use crate::llm::TokenUsage;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How a prompt was used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageKind {
+    /// The prompt was rendered (e.g., with `render` or `get`).
+    Render,
+    /// The prompt was rendered and sent to a model.
+    Generate,
+}
+
+/// The usage statistics of a single prompt.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PromptStats {
+    /// The number of times the prompt was rendered.
+    pub render_count: u64,
+    /// The number of times the prompt was sent to a model.
+    pub generate_count: u64,
+    /// When the prompt was last used.
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl PromptStats {
+    /// The number of times the prompt was used in any way.
+    pub fn total_count(&self) -> u64 {
+        self.render_count + self.generate_count
+    }
+}
+
+/// The tokens used by the requests sent to a model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelStats {
+    /// The number of requests that reported their usage.
+    pub requests: u64,
+    /// The tokens used by those requests.
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+    /// When the model was last used.
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+/// The usage statistics of every prompt that has been used, by prompt name, and of every
+/// model that has been sent requests, by model name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageStats {
+    pub prompts: BTreeMap<String, PromptStats>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<String, ModelStats>,
+}
+
+impl UsageStats {
+    /// Records a use of a prompt at the given time.
+    pub fn record(&mut self, prompt_name: &str, kind: UsageKind, at: DateTime<Utc>) {
+        let stats = self.prompts.entry(prompt_name.to_string()).or_default();
+        match kind {
+            UsageKind::Render => stats.render_count += 1,
+            UsageKind::Generate => stats.generate_count += 1,
+        }
+        stats.last_used = Some(at);
+    }
+
+    /// Records the tokens used by a request to a model at the given time.
+    pub fn record_tokens(&mut self, model_name: &str, usage: TokenUsage, at: DateTime<Utc>) {
+        let stats = self.models.entry(model_name.to_string()).or_default();
+        stats.requests += 1;
+        stats.usage = stats.usage + usage;
+        stats.last_used = Some(at);
+    }
+
+    /// Gets the statistics of a prompt, which are empty if the prompt was never used.
+    pub fn get(&self, prompt_name: &str) -> PromptStats {
+        self.prompts.get(prompt_name).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_usage() {
+        let mut stats = UsageStats::default();
+        let now = Utc::now();
+        stats.record("greeting", UsageKind::Render, now);
+        stats.record("greeting", UsageKind::Render, now);
+        stats.record("greeting", UsageKind::Generate, now);
+
+        let greeting = stats.get("greeting");
+        assert_eq!(2, greeting.render_count);
+        assert_eq!(1, greeting.generate_count);
+        assert_eq!(3, greeting.total_count());
+        assert_eq!(Some(now), greeting.last_used);
+        assert_eq!(PromptStats::default(), stats.get("unused"));
+    }
+
+    #[test]
+    fn test_record_tokens() {
+        let mut stats = UsageStats::default();
+        let now = Utc::now();
+        let usage = TokenUsage {
+            input_tokens: 10,
+            output_tokens: 5,
+        };
+        stats.record_tokens("gpt-4o", usage, now);
+        stats.record_tokens("gpt-4o", usage, now);
+
+        let model = &stats.models["gpt-4o"];
+        assert_eq!(2, model.requests);
+        assert_eq!(30, model.usage.total_tokens());
+        assert_eq!(Some(now), model.last_used);
+
+        // Stats recorded before models were tracked still parse
+        let stats: UsageStats = serde_json::from_str(r#"{"prompts": {}}"#).unwrap();
+        assert!(stats.models.is_empty());
+    }
+}
@@ -4,9 +4,24 @@
 //!
 //! The main components are:
 //! - [`PromptStorage`] trait - Defines the interface for storing and retrieving prompts
+//! - [`StatsStorage`] trait - Defines the interface for tracking prompt and token usage
+//! - [`HistoryStorage`] trait - Defines the interface for keeping a history of prompt uses
+//! - [`EvalStorage`] trait - Defines the interface for keeping the runs of prompt evals
+//! - [`JournalStorage`] trait - Defines the interface for undoing destructive operations
+//! - [`TranscriptStorage`] trait - Defines the interface for keeping conversation transcripts
+//! - [`EmbeddingStorage`] trait - Defines the interface for caching prompt embeddings
 
-use crate::prompt::Prompt;
-// Required for Error trait implementation
+use crate::embeddings::EmbeddingCache;
+use crate::eval::{EvalResult, EvalRun};
+use crate::history::HistoryEntry;
+use crate::journal::JournalEntry;
+use crate::llm::TokenUsage;
+use crate::prompt::{Prompt, normalize_tag, normalize_tags};
+use crate::stats::{UsageKind, UsageStats};
+use crate::tag_query::TagQuery;
+use crate::transcript::Transcript;
+use serde_json::Value;
+use std::collections::BTreeMap;
 
 /// A trait for storing and retrieving prompts.
 ///
@@ -14,7 +29,7 @@ use crate::prompt::Prompt;
 /// Implementors can store prompts in various backends such as files, databases, etc.
 pub trait PromptStorage {
     /// The error type for storage operations.
-    type Error: std::error::Error + Send + Sync;
+    type Error: std::error::Error + Send + Sync + 'static;
 
     /// Saves a prompt to the storage.
     fn save_prompt(&self, prompt: &Prompt) -> Result<(), Self::Error>;
@@ -27,12 +42,282 @@ pub trait PromptStorage {
     /// * `Err(Self::Error)` - If there was an error reading or parsing the prompt, or if the prompt doesn't exist.
     fn get_prompt(&self, name: &str) -> Result<Prompt, Self::Error>;
 
+    /// Whether an error of [`PromptStorage::get_prompt`] means the prompt doesn't exist, as
+    /// opposed to a prompt that exists but can't be read. Defaults to every error, for
+    /// storages that can't tell them apart.
+    fn is_not_found(_error: &Self::Error) -> bool {
+        true
+    }
+
     /// Retrieves all prompts.
     fn get_prompts(&self) -> Result<Vec<Prompt>, Self::Error>;
 
     /// Retrieves prompts that have any of the specified tags.
     fn get_prompts_by_tag(&self, tags: &[String]) -> Result<Vec<Prompt>, Self::Error>;
 
+    /// Retrieves prompts matching a tag query.
+    fn get_prompts_by_query(&self, query: &TagQuery) -> Result<Vec<Prompt>, Self::Error> {
+        Ok(self
+            .get_prompts()?
+            .into_iter()
+            .filter(|prompt| query.matches(&prompt.metadata.tags))
+            .collect())
+    }
+
     /// Deletes a prompt by name.
     fn delete_prompt(&self, name: &str) -> Result<(), Self::Error>;
+
+    /// Renames a prompt. References to it from other prompts are left unchanged, see
+    /// [`PromptStorage::replace_prompt_references`].
+    ///
+    /// The default implementation saves the prompt under the new name and then deletes the
+    /// old one. It can't tell whether a prompt already has the new name, which is replaced,
+    /// so storages should override it to check that and to rename in a single step.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the prompt was renamed.
+    /// * `Err(Self::Error)` - If the prompt doesn't exist or, in storages checking it, a
+    ///   prompt already has the new name.
+    fn rename_prompt(&self, old: &str, new: &str) -> Result<(), Self::Error> {
+        let mut prompt = self.get_prompt(old)?;
+        prompt.metadata.name = new.to_string();
+        self.save_prompt(&prompt)?;
+        self.delete_prompt(old)
+    }
+
+    /// Replaces the references to a prompt in every stored prompt, e.g. after renaming it.
+    /// See [`Prompt::replace_prompt_references`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<String>)` - The names of the prompts that were updated.
+    /// * `Err(Self::Error)` - If the prompts can't be retrieved or saved.
+    fn replace_prompt_references(&self, old: &str, new: &str) -> Result<Vec<String>, Self::Error> {
+        let mut updated = Vec::new();
+        for mut prompt in self.get_prompts()? {
+            if prompt.replace_prompt_references(old, new) {
+                self.save_prompt(&prompt)?;
+                updated.push(prompt.metadata.name);
+            }
+        }
+        Ok(updated)
+    }
+
+    /// Lists the tags in use, after normalizing them, with the number of prompts using each.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<(String, usize)>)` - The tags and their prompt counts, sorted by tag.
+    /// * `Err(Self::Error)` - If the prompts can't be retrieved.
+    fn list_tags(&self) -> Result<Vec<(String, usize)>, Self::Error> {
+        let mut counts = BTreeMap::new();
+        for prompt in self.get_prompts()? {
+            for tag in normalize_tags(&prompt.metadata.tags) {
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Renames a tag in every prompt using it. Tags are compared after normalizing them,
+    /// so renaming `rust` also renames `Rust ` and merges it with existing `new` tags.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of prompts updated.
+    /// * `Err(Self::Error)` - If the prompts can't be retrieved or saved.
+    fn rename_tag(&self, old: &str, new: &str) -> Result<usize, Self::Error> {
+        let old = normalize_tag(old);
+        let mut updated = 0;
+        for mut prompt in self.get_prompts()? {
+            if !prompt
+                .metadata
+                .tags
+                .iter()
+                .any(|tag| normalize_tag(tag) == old)
+            {
+                continue;
+            }
+            let tags = prompt.metadata.tags.iter().map(|tag| {
+                if normalize_tag(tag) == old {
+                    new
+                } else {
+                    tag.as_str()
+                }
+            });
+            prompt.metadata.tags = normalize_tags(tags);
+            self.save_prompt(&prompt)?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+}
+
+/// A trait for tracking how prompts are used, and the tokens used by models, alongside
+/// their storage.
+pub trait StatsStorage {
+    /// The error type for statistics operations.
+    type Error: std::error::Error + Send + Sync;
+
+    /// Records a use of a prompt.
+    fn record_usage(&self, prompt_name: &str, kind: UsageKind) -> Result<(), Self::Error>;
+
+    /// Records the tokens used by a request to a model.
+    fn record_tokens(&self, model_name: &str, usage: TokenUsage) -> Result<(), Self::Error>;
+
+    /// Retrieves the usage statistics of all prompts.
+    fn get_stats(&self) -> Result<UsageStats, Self::Error>;
+}
+
+/// A trait for keeping a history of the renders and generations of prompts, alongside
+/// their storage.
+pub trait HistoryStorage {
+    /// The error type for history operations.
+    type Error: std::error::Error + Send + Sync;
+
+    /// Records a use of a prompt, giving it the next entry id.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(HistoryEntry)` - The recorded entry.
+    fn record_history(
+        &self,
+        kind: UsageKind,
+        prompt_name: &str,
+        arguments: BTreeMap<String, Value>,
+        output_hash: String,
+    ) -> Result<HistoryEntry, Self::Error>;
+
+    /// Retrieves every history entry, oldest first.
+    fn get_history(&self) -> Result<Vec<HistoryEntry>, Self::Error>;
+}
+
+/// A trait for keeping the runs of the evals of prompts, alongside their storage, to follow
+/// their pass rates over time.
+pub trait EvalStorage {
+    /// The error type for eval operations.
+    type Error: std::error::Error + Send + Sync;
+
+    /// Records a run of the evals of a prompt, giving it the next run id.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(EvalRun)` - The recorded run.
+    fn record_eval(
+        &self,
+        prompt_name: &str,
+        model_name: &str,
+        results: Vec<EvalResult>,
+    ) -> Result<EvalRun, Self::Error>;
+
+    /// Retrieves every eval run, oldest first.
+    fn get_evals(&self) -> Result<Vec<EvalRun>, Self::Error>;
+}
+
+/// A trait for keeping the transcripts of conversations with models, alongside the
+/// prompts they started from.
+pub trait TranscriptStorage {
+    /// The error type for transcript operations.
+    type Error: std::error::Error + Send + Sync;
+
+    /// Saves a transcript, giving it the next id if it hasn't been saved yet, or replacing
+    /// the saved one otherwise, e.g. after resuming the conversation.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Transcript)` - The saved transcript, with its id and update time.
+    fn save_transcript(&self, transcript: Transcript) -> Result<Transcript, Self::Error>;
+
+    /// Retrieves a transcript by id.
+    fn get_transcript(&self, id: u64) -> Result<Transcript, Self::Error>;
+
+    /// Retrieves every transcript, oldest first.
+    fn get_transcripts(&self) -> Result<Vec<Transcript>, Self::Error>;
+}
+
+/// A trait for caching the embeddings of prompts alongside their storage, so they're only
+/// computed again when prompts change.
+pub trait EmbeddingStorage {
+    /// The error type for embedding operations.
+    type Error: std::error::Error + Send + Sync;
+
+    /// Retrieves the cached embeddings. Empty if none were cached.
+    fn get_embeddings(&self) -> Result<EmbeddingCache, Self::Error>;
+
+    /// Replaces the cached embeddings.
+    fn save_embeddings(&self, cache: &EmbeddingCache) -> Result<(), Self::Error>;
+}
+
+/// A trait for undoing the destructive operations, such as deletes and overwrites, done
+/// to the stored prompts.
+pub trait JournalStorage {
+    /// The error type for journal operations.
+    type Error: std::error::Error + Send + Sync;
+
+    /// Retrieves the operations that can be undone, oldest first.
+    fn get_journal(&self) -> Result<Vec<JournalEntry>, Self::Error>;
+
+    /// Undoes the last operation, restoring the prompt as it was before it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(JournalEntry))` - The operation that was undone.
+    /// * `Ok(None)` - If there's nothing to undo.
+    fn undo(&self) -> Result<Option<JournalEntry>, Self::Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_storage::{MemoryStorage, MemoryStorageError};
+    use crate::prompt::PromptMetadata;
+
+    /// A storage implementing only the required methods, to test the default ones.
+    struct MinimalStorage(MemoryStorage);
+
+    impl PromptStorage for MinimalStorage {
+        type Error = MemoryStorageError;
+
+        fn save_prompt(&self, prompt: &Prompt) -> Result<(), Self::Error> {
+            self.0.save_prompt(prompt)
+        }
+
+        fn get_prompt(&self, name: &str) -> Result<Prompt, Self::Error> {
+            self.0.get_prompt(name)
+        }
+
+        fn get_prompts(&self) -> Result<Vec<Prompt>, Self::Error> {
+            self.0.get_prompts()
+        }
+
+        fn get_prompts_by_tag(&self, tags: &[String]) -> Result<Vec<Prompt>, Self::Error> {
+            self.0.get_prompts_by_tag(tags)
+        }
+
+        fn delete_prompt(&self, name: &str) -> Result<(), Self::Error> {
+            self.0.delete_prompt(name)
+        }
+    }
+
+    #[test]
+    fn test_default_rename_prompt() {
+        let storage = MinimalStorage(MemoryStorage::from_prompts([Prompt::new(
+            PromptMetadata::new("intro".to_string(), None, vec!["letters".to_string()]),
+            "Dear {{name}},".to_string(),
+        )]));
+
+        storage.rename_prompt("intro", "greeting").unwrap();
+        let renamed = storage.get_prompt("greeting").unwrap();
+        assert_eq!("greeting", renamed.metadata.name);
+        assert_eq!(vec!["letters"], renamed.metadata.tags);
+        assert_eq!("Dear {{name}},", renamed.content);
+        assert_eq!(
+            Err(MemoryStorageError::PromptNotFound("intro".to_string())),
+            storage.get_prompt("intro").map(|_| ())
+        );
+
+        assert!(storage.rename_prompt("intro", "other").is_err());
+        assert!(storage.get_prompt("other").is_err());
+    }
 }
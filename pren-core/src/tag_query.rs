@@ -0,0 +1,236 @@
+//! # Tag Queries
+//!
+//! This module provides boolean queries over prompt tags, for finding prompts with
+//! combinations of tags rather than any of a list of tags.
+//!
+//! Queries can be built from [`TagQuery`] variants or parsed from text, where `AND`, `OR`
+//! and `NOT` are the operators (in decreasing order of precedence: `NOT`, `AND`, `OR`),
+//! parentheses group sub-queries and tags next to each other are joined with `AND`.
+//! Operators must be uppercase, as tags are lowercased when prompts are saved.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::tag_query::TagQuery;
+//!
+//! let query: TagQuery = "rust AND review NOT draft".parse().unwrap();
+//! assert!(query.matches(&["rust".to_string(), "review".to_string()]));
+//! assert!(!query.matches(&["rust".to_string(), "review".to_string(), "draft".to_string()]));
+//! ```
+
+use crate::prompt::{normalize_tag, normalize_tags};
+use nom::IResult;
+use nom::Parser;
+use nom::branch::alt;
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{char, multispace0, multispace1};
+use nom::combinator::{all_consuming, map, verify};
+use nom::error::Error;
+use nom::multi::{many0, separated_list1};
+use nom::sequence::{delimited, preceded, terminated};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TagQueryError {
+    #[error("invalid tag query '{0}'")]
+    InvalidQuery(String),
+}
+
+/// A boolean query over the tags of a prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagQuery {
+    /// Matches prompts with the tag.
+    Tag(String),
+    /// Matches prompts matching every sub-query.
+    All(Vec<TagQuery>),
+    /// Matches prompts matching any of the sub-queries.
+    Any(Vec<TagQuery>),
+    /// Matches prompts not matching the sub-query.
+    Not(Box<TagQuery>),
+}
+
+impl TagQuery {
+    /// Whether a prompt with the given tags matches the query. Tags are compared after
+    /// normalizing them.
+    pub fn matches(&self, tags: &[String]) -> bool {
+        self.matches_normalized(&normalize_tags(tags))
+    }
+
+    fn matches_normalized(&self, tags: &[String]) -> bool {
+        match self {
+            TagQuery::Tag(tag) => tags.contains(&normalize_tag(tag)),
+            TagQuery::All(queries) => queries.iter().all(|query| query.matches_normalized(tags)),
+            TagQuery::Any(queries) => queries.iter().any(|query| query.matches_normalized(tags)),
+            TagQuery::Not(query) => !query.matches_normalized(tags),
+        }
+    }
+}
+
+impl FromStr for TagQuery {
+    type Err = TagQueryError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        all_consuming(delimited(multispace0, parse_any, multispace0))
+            .parse(input)
+            .map(|(_, query)| query)
+            .map_err(|_| TagQueryError::InvalidQuery(input.to_string()))
+    }
+}
+
+impl fmt::Display for TagQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, queries: &[TagQuery], operator: &str| {
+            write!(f, "(")?;
+            for (index, query) in queries.iter().enumerate() {
+                if index > 0 {
+                    write!(f, " {} ", operator)?;
+                }
+                write!(f, "{}", query)?;
+            }
+            write!(f, ")")
+        };
+        match self {
+            TagQuery::Tag(tag) => write!(f, "{}", tag),
+            TagQuery::All(queries) => join(f, queries, "AND"),
+            TagQuery::Any(queries) => join(f, queries, "OR"),
+            TagQuery::Not(query) => write!(f, "NOT {}", query),
+        }
+    }
+}
+
+fn is_operator(word: &str) -> bool {
+    matches!(word, "AND" | "OR" | "NOT")
+}
+
+fn parse_word(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| !c.is_whitespace() && c != '(' && c != ')').parse(input)
+}
+
+fn keyword<'a>(
+    operator: &'static str,
+) -> impl Parser<&'a str, Output = &'a str, Error = Error<&'a str>> {
+    verify(parse_word, move |word: &str| word == operator)
+}
+
+fn parse_tag(input: &str) -> IResult<&str, TagQuery> {
+    map(
+        verify(parse_word, |word: &str| !is_operator(word)),
+        |word| TagQuery::Tag(normalize_tag(word)),
+    )
+    .parse(input)
+}
+
+fn parse_not(input: &str) -> IResult<&str, TagQuery> {
+    alt((
+        map(
+            preceded(terminated(keyword("NOT"), multispace0), parse_not),
+            |query| TagQuery::Not(Box::new(query)),
+        ),
+        delimited(
+            terminated(char('('), multispace0),
+            parse_any,
+            preceded(multispace0, char(')')),
+        ),
+        parse_tag,
+    ))
+    .parse(input)
+}
+
+fn parse_all(input: &str) -> IResult<&str, TagQuery> {
+    let (input, first) = parse_not(input)?;
+    let (input, rest) = many0(preceded(
+        // An explicit AND, or just whitespace between operands
+        alt((
+            delimited(multispace1, keyword("AND"), multispace0),
+            multispace1,
+        )),
+        parse_not,
+    ))
+    .parse(input)?;
+    Ok((input, flatten(first, rest, TagQuery::All)))
+}
+
+fn parse_any(input: &str) -> IResult<&str, TagQuery> {
+    let (input, mut queries) = separated_list1(
+        delimited(multispace0, keyword("OR"), multispace0),
+        parse_all,
+    )
+    .parse(input)?;
+    let first = queries.remove(0);
+    Ok((input, flatten(first, queries, TagQuery::Any)))
+}
+
+fn flatten(
+    first: TagQuery,
+    rest: Vec<TagQuery>,
+    combine: fn(Vec<TagQuery>) -> TagQuery,
+) -> TagQuery {
+    if rest.is_empty() {
+        first
+    } else {
+        combine(std::iter::once(first).chain(rest).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str) -> TagQuery {
+        TagQuery::Tag(name.to_string())
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(Ok(tag("rust")), "Rust".parse());
+        assert_eq!(
+            Ok(TagQuery::All(vec![
+                tag("rust"),
+                tag("review"),
+                TagQuery::Not(Box::new(tag("draft")))
+            ])),
+            "rust AND review NOT draft".parse()
+        );
+        assert_eq!(
+            Ok(TagQuery::Any(vec![
+                TagQuery::All(vec![tag("a"), tag("b")]),
+                TagQuery::Not(Box::new(TagQuery::Any(vec![tag("c"), tag("d")])))
+            ])),
+            " a b OR NOT (c OR d) ".parse()
+        );
+        assert_eq!(
+            Ok(TagQuery::All(vec![tag("notes"), tag("orange")])),
+            "NOTES ORANGE".parse()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_query() {
+        for query in ["", "AND rust", "rust OR", "(rust", "rust)", "NOT"] {
+            assert!(query.parse::<TagQuery>().is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let query: TagQuery = "(rust OR go) NOT draft".parse().unwrap();
+        assert!(query.matches(&tags(&["Go", "review"])));
+        assert!(!query.matches(&tags(&["rust", "draft"])));
+        assert!(!query.matches(&tags(&["python"])));
+        assert!(TagQuery::All(vec![]).matches(&[]));
+        assert!(!TagQuery::Any(vec![]).matches(&tags(&["rust"])));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let query: TagQuery = "a AND NOT (b OR c)".parse().unwrap();
+        assert_eq!("(a AND NOT (b OR c))", query.to_string());
+        assert_eq!(Ok(query.clone()), query.to_string().parse());
+    }
+}
@@ -0,0 +1,155 @@
+//! # Tag Suggestions
+//!
+//! This module provides a built-in prompt asking a model to propose tags for a prompt,
+//! preferring the tags already in use so the vocabulary doesn't drift, and parses the
+//! model's response into normalized tags.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::prompt::{Prompt, PromptMetadata};
+//! use pren_core::tagging::{parse_tag_suggestions, tag_suggestion_messages};
+//!
+//! let prompt = Prompt::new(
+//!     PromptMetadata::new("sql-review".to_string(), None, vec!["sql".to_string()]),
+//!     "Review this SQL migration: {{migration}}".to_string(),
+//! );
+//! let vocabulary = vec![("sql".to_string(), 3), ("review".to_string(), 5)];
+//! let messages = tag_suggestion_messages(&prompt, &vocabulary);
+//! assert!(messages[1].content.contains("review (5)"));
+//!
+//! let tags = parse_tag_suggestions("<tags>SQL, review, migrations</tags>", &prompt);
+//! assert_eq!(vec!["review", "migrations"], tags);
+//! ```
+
+use crate::messages::{RenderedMessage, Role};
+use crate::prompt::{Prompt, normalize_tags};
+
+/// The maximum number of tags taken from a suggestion.
+pub const MAX_SUGGESTED_TAGS: usize = 5;
+
+const TAGGING_PROMPT: &str = "\
+You are a librarian organizing a collection of prompt templates. You propose tags that \
+describe what a prompt is for, such as its task, domain or output, so it can be found \
+among the others.
+
+Prefer tags already in use in the collection, and only propose a new tag when none of \
+them fits. Tags are short, lowercase and use hyphens instead of spaces. Don't propose \
+tags the prompt already has.
+
+Reply with 1 to 5 tags separated by commas between <tags> and </tags>, and nothing else.";
+
+/// Builds the messages asking a model to suggest tags for a prompt.
+///
+/// # Arguments
+///
+/// * `prompt` - The prompt to tag. Its content is sent as it's stored, with its
+///   placeholders, rather than rendered.
+/// * `vocabulary` - The tags in use, with the number of prompts using each, as returned
+///   by [`PromptStorage::list_tags`](crate::storage::PromptStorage::list_tags).
+pub fn tag_suggestion_messages(
+    prompt: &Prompt,
+    vocabulary: &[(String, usize)],
+) -> Vec<RenderedMessage> {
+    let mut request = String::new();
+    if vocabulary.is_empty() {
+        request.push_str("The collection has no tags yet.\n\n");
+    } else {
+        let tags: Vec<String> = vocabulary
+            .iter()
+            .map(|(tag, count)| format!("{} ({})", tag, count))
+            .collect();
+        request.push_str(&format!(
+            "Tags in use, with the number of prompts using each: {}\n\n",
+            tags.join(", ")
+        ));
+    }
+    request.push_str(&format!("Prompt name: {}\n", prompt.metadata.name));
+    if let Some(description) = &prompt.metadata.description {
+        request.push_str(&format!("Description: {}\n", description));
+    }
+    if !prompt.metadata.tags.is_empty() {
+        request.push_str(&format!(
+            "Current tags: {}\n",
+            prompt.metadata.tags.join(", ")
+        ));
+    }
+    request.push_str(&format!(
+        "\n<prompt>\n{}\n</prompt>",
+        prompt.content.trim_end()
+    ));
+    vec![
+        RenderedMessage {
+            role: Role::System,
+            content: TAGGING_PROMPT.to_string(),
+        },
+        RenderedMessage {
+            role: Role::User,
+            content: request,
+        },
+    ]
+}
+
+/// Parses a response to [`tag_suggestion_messages`] into normalized tags, leaving out the
+/// ones the prompt already has, up to [`MAX_SUGGESTED_TAGS`].
+///
+/// The tags are taken from between `<tags>` tags, or from the whole response if the model
+/// left them out. Spaces within tags are replaced with hyphens. Tags with characters other
+/// than letters, digits, `-`, `_`, `.` and `/` are left out, as they're more likely part of
+/// a sentence than a tag.
+pub fn parse_tag_suggestions(response: &str, prompt: &Prompt) -> Vec<String> {
+    let tags = match response.find("<tags>") {
+        Some(start) => {
+            let rest = &response[start + "<tags>".len()..];
+            &rest[..rest.find("</tags>").unwrap_or(rest.len())]
+        }
+        None => response,
+    };
+    let current = normalize_tags(&prompt.metadata.tags);
+    normalize_tags(
+        tags.split([',', '\n'])
+            .map(|tag| tag.trim_start_matches(is_list_marker))
+            .map(|tag| tag.split_whitespace().collect::<Vec<_>>().join("-")),
+    )
+    .into_iter()
+    .filter(|tag| {
+        tag.chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+    })
+    .filter(|tag| !current.contains(tag))
+    .take(MAX_SUGGESTED_TAGS)
+    .collect()
+}
+
+/// Whether a character starts a tag only as markdown, like the `-` of a list item or the
+/// `#` of a hashtag.
+fn is_list_marker(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '-' | '*' | '#')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptMetadata;
+
+    #[test]
+    fn test_parse_tag_suggestions() {
+        let prompt = Prompt::new(
+            PromptMetadata::new("review".to_string(), None, vec!["Code".to_string()]),
+            "Review {{code}}".to_string(),
+        );
+        assert_eq!(
+            vec!["code-review", "rust"],
+            parse_tag_suggestions("<tags>Code Review, code,rust</tags>", &prompt)
+        );
+        assert_eq!(
+            vec!["testing", "ci"],
+            parse_tag_suggestions("- testing\n- #ci\n", &prompt)
+        );
+        assert!(parse_tag_suggestions("Sure! Here are some tags: docs", &prompt).is_empty());
+        assert_eq!(
+            5,
+            parse_tag_suggestions("<tags>a, b, c, d, e, f, g", &prompt).len()
+        );
+    }
+}
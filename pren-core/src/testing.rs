@@ -0,0 +1,126 @@
+//! # Prompt Tests
+//!
+//! This module provides test cases that are stored alongside prompts, so that changes to
+//! shared sub-prompts can't silently break the prompts that reference them.
+//!
+//! Test cases are declared in the prompt's frontmatter. Each one renders the prompt with a
+//! set of arguments and checks the output for substrings and regular expressions:
+//!
+//! ```yaml
+//! tests:
+//!   - name: formal
+//!     args:
+//!       name: Ana
+//!     contains: ["Dear Ana"]
+//!     matches: ["(?i)regards$"]
+//! ```
+//!
+//! Tests are run with [`PromptTemplate::run_tests`](crate::prompt::PromptTemplate::run_tests).
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A test case of a prompt, stored in the prompt's frontmatter.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PromptTestCase {
+    /// The name of the test case, used when reporting results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The arguments the prompt is rendered with.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub args: BTreeMap<String, Value>,
+    /// Substrings the rendered prompt must contain.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contains: Vec<String>,
+    /// Regular expressions the rendered prompt must match.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<String>,
+}
+
+/// The result of running a test case.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptTestResult {
+    /// The name of the tested prompt.
+    pub prompt: String,
+    /// The name of the test case, or its position (e.g., `#2`) if it has no name.
+    pub case: String,
+    /// Why the test case failed. Empty if it passed.
+    pub failures: Vec<String>,
+}
+
+impl PromptTestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl PromptTestCase {
+    /// Checks a rendered prompt against the expectations of the test case.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` - A description of every unmet expectation. Empty if all are met.
+    pub fn check(&self, rendered: &str) -> Vec<String> {
+        check_expectations(&self.contains, &self.matches, rendered)
+    }
+}
+
+/// Checks that a text contains every substring and matches every regular expression.
+///
+/// # Returns
+///
+/// * `Vec<String>` - A description of every unmet expectation. Empty if all are met.
+pub fn check_expectations(contains: &[String], matches: &[String], text: &str) -> Vec<String> {
+    let mut failures: Vec<String> = contains
+        .iter()
+        .filter(|expected| !text.contains(expected.as_str()))
+        .map(|expected| format!("output doesn't contain {:?}", expected))
+        .collect();
+
+    for pattern in matches {
+        match Regex::new(pattern) {
+            Ok(regex) if regex.is_match(text) => {}
+            Ok(_) => failures.push(format!("output doesn't match '{}'", pattern)),
+            Err(e) => failures.push(format!("invalid pattern '{}': {}", pattern, e)),
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_expectations() {
+        let case = PromptTestCase {
+            contains: vec!["Dear Ana".to_string(), "Bye".to_string()],
+            matches: vec!["regards$".to_string(), "(".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            vec![
+                "output doesn't contain \"Bye\"".to_string(),
+                "output doesn't match 'regards$'".to_string(),
+            ],
+            case.check("Dear Ana, hello")[..2]
+        );
+        assert!(case.check("Dear Ana, hello")[2].starts_with("invalid pattern '('"));
+        assert_eq!(1, case.check("Dear Ana. Bye, regards").len());
+    }
+
+    #[test]
+    fn test_deserialize_test_case() {
+        let case: PromptTestCase =
+            serde_json::from_str(r#"{"args": {"name": "Ana"}, "contains": ["Ana"]}"#).unwrap();
+        assert_eq!(None, case.name);
+        assert_eq!(
+            Some(&Value::String("Ana".to_string())),
+            case.args.get("name")
+        );
+        assert!(case.matches.is_empty());
+    }
+}
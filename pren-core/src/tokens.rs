@@ -0,0 +1,57 @@
+//! # Token Counting
+//!
+//! This module provides token counting for rendered prompts.
+//!
+//! With the `tiktoken` feature enabled, tokens are counted exactly with the `cl100k_base`
+//! encoding used by most OpenAI models. Otherwise, tokens are estimated from the length of
+//! the text, which is usually close enough for budgeting.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::tokens::count_tokens;
+//!
+//! assert!(count_tokens("Hello, world!") > 0);
+//! assert_eq!(count_tokens(""), 0);
+//! ```
+
+/// Average number of characters per token used when estimating token counts.
+#[cfg(not(feature = "tiktoken"))]
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Counts the tokens of the given text.
+#[cfg(feature = "tiktoken")]
+pub fn count_tokens(text: &str) -> usize {
+    use std::sync::OnceLock;
+    use tiktoken_rs::CoreBPE;
+
+    static ENCODING: OnceLock<CoreBPE> = OnceLock::new();
+    ENCODING
+        .get_or_init(|| tiktoken_rs::cl100k_base().expect("cl100k_base encoding is bundled"))
+        .encode_with_special_tokens(text)
+        .len()
+}
+
+/// Estimates the tokens of the given text, as roughly one token every four characters.
+#[cfg(not(feature = "tiktoken"))]
+pub fn count_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens_empty() {
+        assert_eq!(count_tokens(""), 0);
+    }
+
+    #[test]
+    fn test_count_tokens_grows_with_text() {
+        let short = count_tokens("Hello");
+        let long = count_tokens(&"Hello, world! ".repeat(20));
+        assert!(short > 0);
+        assert!(long > short);
+    }
+}
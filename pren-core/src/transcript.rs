@@ -0,0 +1,163 @@
+//! # Conversation Transcripts
+//!
+//! This module provides transcripts, which keep a prompt together with the conversation
+//! it started with a model, so conversations can be listed, resumed and exported later.
+//!
+//! Transcripts are stored by implementors of the
+//! [`TranscriptStorage`](crate::storage::TranscriptStorage) trait, alongside the prompts
+//! themselves.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::messages::{RenderedMessage, Role};
+//! use pren_core::transcript::Transcript;
+//! use std::collections::BTreeMap;
+//!
+//! let transcript = Transcript::new(
+//!     "assistant".to_string(),
+//!     BTreeMap::new(),
+//!     "gpt-4o-mini".to_string(),
+//!     vec![RenderedMessage {
+//!         role: Role::User,
+//!         content: "Hello!".to_string(),
+//!     }],
+//! );
+//! assert_eq!("Hello!", transcript.title());
+//! assert!(transcript.to_markdown().contains("## User\n\nHello!"));
+//! ```
+
+use crate::messages::{RenderedMessage, Role};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The maximum number of characters of a transcript's title.
+const MAX_TITLE_CHARS: usize = 60;
+
+/// A conversation with a model, started from a prompt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    /// The number of the transcript, given by the storage when it's first saved. `0` until
+    /// then.
+    pub id: u64,
+    /// When the conversation started.
+    pub created: DateTime<Utc>,
+    /// When the conversation was last saved.
+    pub updated: DateTime<Utc>,
+    /// The name of the prompt the conversation started from.
+    pub prompt: String,
+    /// The arguments the prompt was rendered with.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub arguments: BTreeMap<String, Value>,
+    /// The name of the model the conversation was held with.
+    pub model: String,
+    /// The messages of the conversation, starting with the rendered prompt.
+    pub messages: Vec<RenderedMessage>,
+}
+
+impl Transcript {
+    /// Creates a transcript that hasn't been saved yet.
+    pub fn new(
+        prompt: String,
+        arguments: BTreeMap<String, Value>,
+        model: String,
+        messages: Vec<RenderedMessage>,
+    ) -> Self {
+        let now = Utc::now();
+        Transcript {
+            id: 0,
+            created: now,
+            updated: now,
+            prompt,
+            arguments,
+            model,
+            messages,
+        }
+    }
+
+    /// The first line of the first user message, shortened, to tell transcripts apart when
+    /// they're listed. Empty if there's no user message.
+    pub fn title(&self) -> String {
+        let first_line = self
+            .messages
+            .iter()
+            .find(|message| message.role == Role::User)
+            .and_then(|message| message.content.trim().lines().next())
+            .unwrap_or_default();
+        if first_line.chars().count() > MAX_TITLE_CHARS {
+            let title: String = first_line.chars().take(MAX_TITLE_CHARS - 3).collect();
+            format!("{}...", title.trim_end())
+        } else {
+            first_line.to_string()
+        }
+    }
+
+    /// Formats the transcript as a markdown document, with a section per message.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("# {}\n\n", self.prompt);
+        markdown.push_str(&format!("- Model: {}\n", self.model));
+        markdown.push_str(&format!(
+            "- Started: {}\n",
+            self.created.format("%Y-%m-%d %H:%M")
+        ));
+        for (name, value) in &self.arguments {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            markdown.push_str(&format!("- Argument `{}`: {}\n", name, value));
+        }
+        for message in &self.messages {
+            let role = match message.role {
+                Role::System => "System",
+                Role::User => "User",
+                Role::Assistant => "Assistant",
+            };
+            markdown.push_str(&format!("\n## {}\n\n{}\n", role, message.content.trim()));
+        }
+        markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let mut transcript = Transcript::new(
+            "review".to_string(),
+            BTreeMap::from([("lang".to_string(), Value::from("Rust"))]),
+            "llama3.1".to_string(),
+            vec![
+                RenderedMessage {
+                    role: Role::System,
+                    content: "You review Rust code.\n".to_string(),
+                },
+                RenderedMessage {
+                    role: Role::User,
+                    content: "fn main() {}".to_string(),
+                },
+                RenderedMessage {
+                    role: Role::Assistant,
+                    content: "Looks good.".to_string(),
+                },
+            ],
+        );
+        transcript.created = "2026-01-02T03:04:05Z".parse().unwrap();
+
+        assert_eq!(
+            "# review\n\n\
+             - Model: llama3.1\n\
+             - Started: 2026-01-02 03:04\n\
+             - Argument `lang`: Rust\n\
+             \n## System\n\nYou review Rust code.\n\
+             \n## User\n\nfn main() {}\n\
+             \n## Assistant\n\nLooks good.\n",
+            transcript.to_markdown()
+        );
+        assert_eq!("fn main() {}", transcript.title());
+    }
+}
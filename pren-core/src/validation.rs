@@ -0,0 +1,217 @@
+//! # Prompt File Validation
+//!
+//! This module validates the content of a prompt file before it's stored, for tools like
+//! git hooks checking the prompts of a project as they're committed. Unlike
+//! [`doctor`](crate::doctor), which checks a whole storage, it checks one file at a time,
+//! without needing it to be saved, and reports each problem with the line it's on:
+//!
+//! - Frontmatter that is missing or isn't valid YAML.
+//! - Prompt names that can't be used in prompt references, or don't match the file name,
+//!   which is how prompts are found.
+//! - Templates that can't be parsed.
+//! - References to prompts that don't exist.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use pren_core::policy::Severity;
+//! use pren_core::validation::validate_prompt_file;
+//! use std::path::Path;
+//!
+//! let content = "---\nname: review\ntags: []\n---\n{{prompt:persona}}\nReview {{code}}";
+//! let diagnostics = validate_prompt_file(Path::new("review.md"), content, |_| false);
+//! assert_eq!(1, diagnostics.len());
+//! assert_eq!(5, diagnostics[0].line);
+//! assert_eq!(Severity::Error, diagnostics[0].severity);
+//! ```
+
+use crate::parser::is_valid_prompt_name;
+use crate::policy::Severity;
+use crate::prompt::{Prompt, PromptMetadata, PromptTemplate};
+use serde::Serialize;
+use serde_frontmatter::SerdeFMError;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// A problem found in a prompt file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// The line the problem is on, starting at 1.
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(line: usize, message: impl Into<String>) -> Self {
+        Diagnostic {
+            line,
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    fn warning(line: usize, message: impl Into<String>) -> Self {
+        Diagnostic {
+            line,
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", self.line, severity, self.message)
+    }
+}
+
+/// Validates the content of a prompt file.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file, whose name must match the name of the prompt.
+/// * `content` - The content of the file, which may differ from what's on disk, e.g. when
+///   it's staged in git.
+/// * `prompt_exists` - Whether a prompt with a given name exists, to resolve references.
+///
+/// # Returns
+///
+/// Every problem found, in the order of the file. A file without errors can be stored and
+/// rendered.
+pub fn validate_prompt_file(
+    path: &Path,
+    content: &str,
+    prompt_exists: impl Fn(&str) -> bool,
+) -> Vec<Diagnostic> {
+    let metadata: PromptMetadata = match serde_frontmatter::deserialize(content) {
+        Ok((metadata, _)) => metadata,
+        Err(SerdeFMError::MissingFrontMatter) => {
+            return vec![Diagnostic::error(
+                1,
+                "Missing frontmatter: prompt files start with a YAML block between '---' lines",
+            )];
+        }
+        Err(SerdeFMError::YamlParseError(e)) => {
+            // The YAML starts right after the opening '---', so its lines are the file's
+            let line = e.location().map_or(1, |location| location.line());
+            return vec![Diagnostic::error(
+                line,
+                format!("Invalid frontmatter: {}", e),
+            )];
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    let name = metadata.name.clone();
+    let name_line = line_of(content, |line| line.starts_with("name:")).unwrap_or(1);
+    if !is_valid_prompt_name(&name) {
+        diagnostics.push(Diagnostic::error(
+            name_line,
+            format!(
+                "Invalid prompt name '{}': use letters, digits, '-', '_' and '.'",
+                name
+            ),
+        ));
+    }
+    let stem = path.file_stem().and_then(|stem| stem.to_str());
+    if stem.is_some_and(|stem| stem != name) {
+        diagnostics.push(Diagnostic::error(
+            name_line,
+            format!(
+                "The prompt is named '{}' but its file is '{}', so it can't be found by its name",
+                name,
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        ));
+    }
+
+    let (body_line, body) = body(content);
+    if body.trim().is_empty() {
+        diagnostics.push(Diagnostic::warning(body_line, "The prompt is empty"));
+    }
+    let template = match PromptTemplate::new(Prompt::new(metadata, body.to_string())) {
+        Ok(template) => template,
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(body_line, e.message));
+            return diagnostics;
+        }
+    };
+
+    let references: BTreeSet<String> = template.prompt_references().into_iter().collect();
+    for reference in references {
+        if reference != name && !prompt_exists(&reference) {
+            let needle = format!(":{}", reference);
+            let line = line_of(body, |line| line.contains(&needle))
+                .map_or(body_line, |line| body_line + line - 1);
+            diagnostics.push(Diagnostic::error(
+                line,
+                format!("References the missing prompt '{}'", reference),
+            ));
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    diagnostics
+}
+
+/// Finds the template of a prompt file, after its frontmatter and any blank lines, with
+/// the line it starts on.
+fn body(content: &str) -> (usize, &str) {
+    let end = content[3..]
+        .find("---")
+        .map_or(content.len(), |end| end + 6)
+        .min(content.len());
+    let body = content[end..].trim_start();
+    let line = content[..content.len() - body.len()].matches('\n').count() + 1;
+    (line, body)
+}
+
+/// The number of the first line matching a predicate, starting at 1.
+fn line_of(text: &str, predicate: impl Fn(&str) -> bool) -> Option<usize> {
+    text.lines()
+        .position(|line| predicate(line.trim_start()))
+        .map(|index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(name: &str, content: &str) -> Vec<(usize, Severity)> {
+        validate_prompt_file(Path::new(name), content, |name| name == "persona")
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_prompt_file() {
+        let valid = "---\nname: review\ntags: []\n---\n\n{{prompt:persona}}\nReview {{code}}\n";
+        assert!(problems("review.md", valid).is_empty());
+
+        assert_eq!(vec![(1, Severity::Error)], problems("review.md", "Review"));
+        assert_eq!(
+            vec![(3, Severity::Error)],
+            problems("review.md", "---\nname: review\ntags: a: b\n---\nReview")
+        );
+        assert_eq!(
+            vec![(2, Severity::Error), (2, Severity::Error)],
+            problems("review.md", "---\nname: my review\ntags: []\n---\nReview")
+        );
+        assert_eq!(
+            vec![(7, Severity::Error)],
+            problems(
+                "review.md",
+                "---\nname: review\ntags: []\n---\nReview {{code}}\n\nwith {{prompt_opt:style}}"
+            )
+        );
+        assert_eq!(
+            vec![(5, Severity::Warning)],
+            problems("review.md", "---\nname: review\ntags: []\n---\n")
+        );
+    }
+}
@@ -0,0 +1,52 @@
+/*
+ * C bindings for pren, a prompt management system.
+ *
+ * Strings returned by the library are owned by the caller and must be freed with
+ * pren_string_free. Functions returning a pointer return NULL on failure, with the error
+ * available from pren_last_error.
+ */
+
+#ifndef PREN_H
+#define PREN_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The version of this header, matching pren_abi_version(). */
+#define PREN_ABI_VERSION 1
+
+/* A prompt storage, opened with pren_open. */
+typedef struct PrenStorage PrenStorage;
+
+/* Returns the version of the C ABI of the library. */
+uint32_t pren_abi_version(void);
+
+/* Opens the prompt storage in a directory. */
+PrenStorage *pren_open(const char *path);
+
+/* Closes a storage. Does nothing with NULL. */
+void pren_close(PrenStorage *storage);
+
+/* Gets a prompt as a JSON object with its metadata, content and arguments. */
+char *pren_get_prompt(const PrenStorage *storage, const char *name);
+
+/* Lists the prompts as a JSON array of objects with their name, description, tags and pinned. */
+char *pren_list(const PrenStorage *storage);
+
+/* Renders a prompt with arguments given as a JSON object, or NULL for none. */
+char *pren_render(const PrenStorage *storage, const char *name, const char *arguments);
+
+/* Returns the error of the last failing call on this thread, or NULL. Owned by the library. */
+const char *pren_last_error(void);
+
+/* Frees a string returned by the library. Does nothing with NULL. */
+void pren_string_free(char *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PREN_H */
@@ -0,0 +1,304 @@
+//! # pren FFI
+//!
+//! This crate exposes the core of pren through a C ABI, so bindings for other languages,
+//! such as Python or Node, can read and render the same prompts as the CLI.
+//!
+//! The functions are declared in `include/pren.h`. A storage is opened with [`pren_open`]
+//! and closed with [`pren_close`]. Functions returning a string return one owned by the
+//! caller, to free with [`pren_string_free`], or `NULL` on failure, with the error available
+//! from [`pren_last_error`]. Prompts and lists are returned as JSON.
+//!
+//! # Examples
+//!
+//! ```c
+//! PrenStorage *storage = pren_open("/home/me/.config/pren/prompts");
+//! char *text = pren_render(storage, "greeting", "{\"name\": \"World\"}");
+//! if (text == NULL) {
+//!     fprintf(stderr, "%s\n", pren_last_error());
+//! } else {
+//!     printf("%s\n", text);
+//!     pren_string_free(text);
+//! }
+//! pren_close(storage);
+//! ```
+
+use pren_core::file_storage::FileStorage;
+use pren_core::prompt::PromptTemplate;
+use pren_core::storage::PromptStorage;
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::PathBuf;
+use std::ptr;
+
+/// The version of the C ABI, bumped on every incompatible change to `pren.h`.
+pub const PREN_ABI_VERSION: u32 = 1;
+
+/// A prompt storage opened with [`pren_open`].
+pub struct PrenStorage(FileStorage);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, returning `None` and setting the last error if it fails or panics, so errors
+/// never unwind into the caller.
+fn call<T>(f: impl FnOnce() -> Result<T, String>) -> Option<T> {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(message)) => {
+            set_last_error(message);
+            None
+        }
+        Err(_) => {
+            set_last_error("pren panicked".to_string());
+            None
+        }
+    }
+}
+
+/// Reads a C string argument, failing on `NULL` or invalid UTF-8.
+///
+/// # Safety
+///
+/// `value` must be `NULL` or a valid NUL-terminated string.
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, String> {
+    if value.is_null() {
+        return Err(format!("The {} is NULL", name));
+    }
+    unsafe { CStr::from_ptr(value) }
+        .to_str()
+        .map_err(|_| format!("The {} isn't valid UTF-8", name))
+}
+
+/// Reads the storage argument, failing on `NULL`.
+///
+/// # Safety
+///
+/// `storage` must be `NULL` or a storage returned by [`pren_open`] and not closed yet.
+unsafe fn read_storage<'a>(storage: *const PrenStorage) -> Result<&'a FileStorage, String> {
+    unsafe { storage.as_ref() }
+        .map(|storage| &storage.0)
+        .ok_or_else(|| "The storage is NULL".to_string())
+}
+
+/// Converts a string to one owned by the caller.
+fn into_c_string(value: String) -> Result<*mut c_char, String> {
+    CString::new(value)
+        .map(CString::into_raw)
+        .map_err(|_| "The result contains a NUL byte".to_string())
+}
+
+/// Returns [`PREN_ABI_VERSION`], so bindings can check they match the library.
+#[unsafe(no_mangle)]
+pub extern "C" fn pren_abi_version() -> u32 {
+    PREN_ABI_VERSION
+}
+
+/// Opens the prompt storage in a directory, or returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pren_open(path: *const c_char) -> *mut PrenStorage {
+    call(|| {
+        let path = unsafe { read_str(path, "path") }?;
+        let storage = FileStorage {
+            base_path: PathBuf::from(path),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        if !storage.base_path.is_dir() {
+            return Err(format!("No prompt directory at {}", path));
+        }
+        Ok(Box::into_raw(Box::new(PrenStorage(storage))))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Closes a storage opened with [`pren_open`]. Does nothing with `NULL`.
+///
+/// # Safety
+///
+/// `storage` must be `NULL` or a storage returned by [`pren_open`] and not closed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pren_close(storage: *mut PrenStorage) {
+    if !storage.is_null() {
+        drop(unsafe { Box::from_raw(storage) });
+    }
+}
+
+/// Gets a prompt as a JSON object with its `metadata`, `content` and `arguments`, or
+/// returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `storage` must be a storage returned by [`pren_open`] and not closed yet, and `name` a
+/// valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pren_get_prompt(
+    storage: *const PrenStorage,
+    name: *const c_char,
+) -> *mut c_char {
+    call(|| {
+        let storage = unsafe { read_storage(storage) }?;
+        let name = unsafe { read_str(name, "name") }?;
+        let prompt = storage.get_prompt(name).map_err(|e| e.to_string())?;
+        let arguments = PromptTemplate::new(prompt.clone())
+            .map_err(|e| e.to_string())?
+            .argument_info();
+        let mut value = serde_json::to_value(&prompt).map_err(|e| e.to_string())?;
+        value["arguments"] = json!(arguments);
+        into_c_string(value.to_string())
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Lists the prompts as a JSON array of objects with their `name`, `description`, `tags`
+/// and `pinned`, or returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `storage` must be a storage returned by [`pren_open`] and not closed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pren_list(storage: *const PrenStorage) -> *mut c_char {
+    call(|| {
+        let storage = unsafe { read_storage(storage) }?;
+        let summaries = storage.get_prompt_summaries().map_err(|e| e.to_string())?;
+        into_c_string(json!(summaries).to_string())
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Renders a prompt with arguments given as a JSON object, or `NULL` for none, and returns
+/// the rendered text, or `NULL` on failure.
+///
+/// Argument values that aren't strings can be reached with dotted paths, as with
+/// `generate --args-json`.
+///
+/// # Safety
+///
+/// `storage` must be a storage returned by [`pren_open`] and not closed yet, `name` a valid
+/// NUL-terminated string, and `arguments` `NULL` or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pren_render(
+    storage: *const PrenStorage,
+    name: *const c_char,
+    arguments: *const c_char,
+) -> *mut c_char {
+    call(|| {
+        let storage = unsafe { read_storage(storage) }?;
+        let name = unsafe { read_str(name, "name") }?;
+        let arguments: HashMap<String, Value> = if arguments.is_null() {
+            HashMap::new()
+        } else {
+            let arguments = unsafe { read_str(arguments, "arguments") }?;
+            serde_json::from_str(arguments)
+                .map_err(|e| format!("The arguments aren't a JSON object: {}", e))?
+        };
+        let prompt = storage.get_prompt(name).map_err(|e| e.to_string())?;
+        let text = PromptTemplate::new(prompt)
+            .map_err(|e| e.to_string())?
+            .render(&arguments, storage)
+            .map_err(|e| e.to_string())?;
+        into_c_string(text)
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Returns the error of the last call that failed on this thread, or `NULL` if none did.
+/// The string is owned by the library and valid until the next failing call on the thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn pren_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// Frees a string returned by the library. Does nothing with `NULL`.
+///
+/// # Safety
+///
+/// `value` must be `NULL` or a string returned by the library and not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pren_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(unsafe { CString::from_raw(value) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pren_core::prompt::{Prompt, PromptMetadata};
+    use tempfile::TempDir;
+
+    /// Takes a string returned by the library, panicking with the last error on `NULL`.
+    unsafe fn take(value: *mut c_char) -> String {
+        if value.is_null() {
+            let error = unsafe { CStr::from_ptr(pren_last_error()) };
+            panic!("{}", error.to_str().unwrap());
+        }
+        let text = unsafe { CStr::from_ptr(value) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { pren_string_free(value) };
+        text
+    }
+
+    #[test]
+    fn test_ffi() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        };
+        storage
+            .save_prompt(&Prompt::new(
+                PromptMetadata::new("greeting".to_string(), None, vec!["demo".to_string()]),
+                "Hello {{user.name}}".to_string(),
+            ))
+            .unwrap();
+
+        let path = CString::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let name = CString::new("greeting").unwrap();
+        unsafe {
+            let storage = pren_open(path.as_ptr());
+            assert!(!storage.is_null());
+
+            let listed: Value = serde_json::from_str(&take(pren_list(storage))).unwrap();
+            assert_eq!("greeting", listed[0]["name"]);
+
+            let prompt: Value =
+                serde_json::from_str(&take(pren_get_prompt(storage, name.as_ptr()))).unwrap();
+            assert_eq!("Hello {{user.name}}", prompt["content"]);
+            assert_eq!("user.name", prompt["arguments"][0]["name"]);
+
+            let arguments = CString::new(r#"{"user": {"name": "World"}}"#).unwrap();
+            let rendered = take(pren_render(storage, name.as_ptr(), arguments.as_ptr()));
+            assert_eq!("Hello World", rendered);
+
+            assert!(pren_render(storage, name.as_ptr(), ptr::null()).is_null());
+            let missing = CString::new("missing").unwrap();
+            assert!(pren_get_prompt(storage, missing.as_ptr()).is_null());
+            let error = CStr::from_ptr(pren_last_error()).to_str().unwrap();
+            assert!(error.contains("missing"));
+
+            pren_close(storage);
+        }
+        assert!(unsafe { pren_open(ptr::null()) }.is_null());
+    }
+}
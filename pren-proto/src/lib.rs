@@ -0,0 +1,40 @@
+//! # pren Proto
+//!
+//! This crate provides a gRPC service for prompt operations, so other services can manage
+//! and render the prompts of a pren library with a typed client instead of running the CLI.
+//!
+//! The service is defined in `proto/pren.proto`, from which the messages, the client and
+//! the server trait are generated in [`v1`]. With the `server` feature, `server` implements
+//! the service over a pren `FileStorage`.
+//!
+//! # Modules
+//!
+//! - [`v1`] - Messages, client and server trait generated from the service definition
+//! - `server` - The service implemented over a prompt storage (with the `server` feature)
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use pren_proto::v1::RenderPromptRequest;
+//! use pren_proto::v1::prompt_service_client::PromptServiceClient;
+//! use std::collections::HashMap;
+//!
+//! # async fn render() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut client = PromptServiceClient::connect("http://127.0.0.1:50051").await?;
+//! let response = client
+//!     .render_prompt(RenderPromptRequest {
+//!         name: "greeting".to_string(),
+//!         arguments: HashMap::from([("name".to_string(), "World".to_string())]),
+//!     })
+//!     .await?;
+//! println!("{}", response.into_inner().text);
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "server")]
+pub mod server;
+
+pub mod v1 {
+    tonic::include_proto!("pren.v1");
+}
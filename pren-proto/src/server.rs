@@ -0,0 +1,354 @@
+//! # gRPC Server
+//!
+//! This module implements the [`PromptService`] over a [`FileStorage`], so it can be served
+//! with tonic:
+//!
+//! ```rust,no_run
+//! use pren_core::file_storage::FileStorage;
+//! use pren_proto::server::PromptLibrary;
+//! use pren_proto::v1::prompt_service_server::PromptServiceServer;
+//!
+//! # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+//! let storage = FileStorage {
+//!     base_path: "prompts".into(),
+//!     fallback_paths: Vec::new(),
+//!     vault: false,
+//! };
+//! tonic::transport::Server::builder()
+//!     .add_service(PromptServiceServer::new(PromptLibrary::new(storage)))
+//!     .serve("127.0.0.1:50051".parse()?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::v1::prompt_service_server::PromptService;
+use crate::v1::{
+    Argument, DeletePromptRequest, DeletePromptResponse, GetPromptRequest, ListPromptsRequest,
+    ListPromptsResponse, Prompt, PromptSummary, RenderPromptRequest, RenderPromptResponse,
+    SavePromptRequest,
+};
+use pren_core::file_storage::{FileStorage, FileStorageError};
+use pren_core::parser::is_valid_prompt_name;
+use pren_core::prompt::{self, PromptMetadata, PromptTemplate, RenderTemplateError, normalize_tag};
+use pren_core::storage::PromptStorage;
+use tonic::{Request, Response, Status};
+
+/// The prompt service, serving the prompts of a storage.
+pub struct PromptLibrary {
+    storage: FileStorage,
+    read_only: bool,
+}
+
+impl PromptLibrary {
+    pub fn new(storage: FileStorage) -> Self {
+        PromptLibrary {
+            storage,
+            read_only: false,
+        }
+    }
+
+    /// Refuses saving and deleting prompts with `PERMISSION_DENIED` if `read_only` is set.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    fn check_writable(&self) -> Result<(), Status> {
+        if self.read_only {
+            Err(Status::permission_denied("The server is read-only"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Converts a stored prompt to its message, with the arguments its template uses.
+    fn to_message(prompt: prompt::Prompt) -> Result<Prompt, Status> {
+        let arguments = PromptTemplate::new(prompt.clone())
+            .map_err(|e| Status::internal(e.to_string()))?
+            .argument_info()
+            .into_iter()
+            .map(|argument| Argument {
+                name: argument.name,
+                required: argument.required,
+                default: argument.default,
+                description: argument.description,
+            })
+            .collect();
+        let metadata = prompt.metadata;
+        Ok(Prompt {
+            name: metadata.name,
+            description: metadata.description,
+            tags: metadata.tags,
+            content: prompt.content,
+            arguments,
+            pinned: metadata.pinned,
+            locked: metadata.locked,
+            deprecated: metadata.deprecated,
+        })
+    }
+}
+
+/// The status of a storage error: `NOT_FOUND`, `ALREADY_EXISTS` and `FAILED_PRECONDITION`
+/// (for locked prompts) for the errors clients can act on, `INTERNAL` for the rest.
+pub fn storage_status(error: FileStorageError) -> Status {
+    let message = error.to_string();
+    match error {
+        FileStorageError::PromptNotFound(_) => Status::not_found(message),
+        FileStorageError::PromptAlreadyExists(_) => Status::already_exists(message),
+        FileStorageError::PromptLocked(_) => Status::failed_precondition(message),
+        FileStorageError::InvalidPromptName(_) => Status::invalid_argument(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// The status of a render error: `INVALID_ARGUMENT` for missing arguments, `NOT_FOUND`
+/// for missing referenced prompts, `INTERNAL` for the rest, including referenced prompts
+/// that can't be read.
+pub fn render_status(error: RenderTemplateError) -> Status {
+    let message = error.to_string();
+    match error {
+        RenderTemplateError::MissingArgument { .. } => Status::invalid_argument(message),
+        RenderTemplateError::PromptNotFound { .. } => Status::not_found(message),
+        _ => Status::internal(message),
+    }
+}
+
+#[tonic::async_trait]
+impl PromptService for PromptLibrary {
+    async fn get_prompt(
+        &self,
+        request: Request<GetPromptRequest>,
+    ) -> Result<Response<Prompt>, Status> {
+        let prompt = self
+            .storage
+            .get_prompt(&request.into_inner().name)
+            .map_err(storage_status)?;
+        Ok(Response::new(Self::to_message(prompt)?))
+    }
+
+    async fn list_prompts(
+        &self,
+        request: Request<ListPromptsRequest>,
+    ) -> Result<Response<ListPromptsResponse>, Status> {
+        let tag = request.into_inner().tag.as_deref().map(normalize_tag);
+        let prompts = self
+            .storage
+            .get_prompt_summaries()
+            .map_err(storage_status)?
+            .into_iter()
+            .filter(|summary| {
+                tag.as_ref()
+                    .is_none_or(|tag| summary.tags.iter().any(|t| &normalize_tag(t) == tag))
+            })
+            .map(|summary| PromptSummary {
+                name: summary.name,
+                description: summary.description,
+                tags: summary.tags,
+                pinned: summary.pinned,
+            })
+            .collect();
+        Ok(Response::new(ListPromptsResponse { prompts }))
+    }
+
+    async fn save_prompt(
+        &self,
+        request: Request<SavePromptRequest>,
+    ) -> Result<Response<Prompt>, Status> {
+        self.check_writable()?;
+        let SavePromptRequest { prompt, overwrite } = request.into_inner();
+        let Some(prompt) = prompt else {
+            return Err(Status::invalid_argument("The prompt is missing"));
+        };
+        if prompt.name.trim().is_empty() {
+            return Err(Status::invalid_argument("The prompt name can't be empty"));
+        }
+        if !is_valid_prompt_name(&prompt.name) {
+            return Err(Status::invalid_argument(format!(
+                "Invalid prompt name '{}'. Use letters, digits, '-', '_' and '.' in names.",
+                prompt.name
+            )));
+        }
+        let mut metadata = match self.storage.get_prompt(&prompt.name) {
+            Ok(_) if !overwrite => {
+                return Err(storage_status(FileStorageError::PromptAlreadyExists(
+                    prompt.name,
+                )));
+            }
+            Ok(existing) => existing.metadata,
+            Err(FileStorageError::PromptNotFound(_)) => {
+                PromptMetadata::new(prompt.name.clone(), None, Vec::new())
+            }
+            Err(e) => return Err(storage_status(e)),
+        };
+        metadata.description = prompt.description;
+        metadata.tags = prompt.tags;
+        metadata.pinned = prompt.pinned;
+        metadata.locked = prompt.locked;
+        metadata.deprecated = prompt.deprecated;
+        let saved = prompt::Prompt::new(metadata, prompt.content);
+        PromptTemplate::new(saved.clone()).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        self.storage.save_prompt(&saved).map_err(storage_status)?;
+        Ok(Response::new(Self::to_message(saved)?))
+    }
+
+    async fn delete_prompt(
+        &self,
+        request: Request<DeletePromptRequest>,
+    ) -> Result<Response<DeletePromptResponse>, Status> {
+        self.check_writable()?;
+        self.storage
+            .delete_prompt(&request.into_inner().name)
+            .map_err(storage_status)?;
+        Ok(Response::new(DeletePromptResponse {}))
+    }
+
+    async fn render_prompt(
+        &self,
+        request: Request<RenderPromptRequest>,
+    ) -> Result<Response<RenderPromptResponse>, Status> {
+        let RenderPromptRequest { name, arguments } = request.into_inner();
+        let prompt = self.storage.get_prompt(&name).map_err(storage_status)?;
+        let rendered = PromptTemplate::new(prompt)
+            .map_err(|e| Status::internal(e.to_string()))?
+            .render_with_warnings(&arguments, &self.storage)
+            .map_err(render_status)?;
+        Ok(Response::new(RenderPromptResponse {
+            text: rendered.text,
+            warnings: rendered.warnings.iter().map(|w| w.to_string()).collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+    use tonic::Code;
+
+    #[tokio::test]
+    async fn test_prompt_library() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = PromptLibrary::new(FileStorage {
+            base_path: temp_dir.path().to_path_buf(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        });
+        let prompt = Prompt {
+            name: "greeting".to_string(),
+            tags: vec!["demo".to_string()],
+            content: "Hello {{name}}".to_string(),
+            ..Prompt::default()
+        };
+        let request = |overwrite| {
+            Request::new(SavePromptRequest {
+                prompt: Some(prompt.clone()),
+                overwrite,
+            })
+        };
+
+        let saved = library
+            .save_prompt(request(false))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("name", saved.arguments[0].name);
+        let error = library.save_prompt(request(false)).await.unwrap_err();
+        assert_eq!(Code::AlreadyExists, error.code());
+        library.save_prompt(request(true)).await.unwrap();
+
+        let listed = library
+            .list_prompts(Request::new(ListPromptsRequest {
+                tag: Some("Demo".to_string()),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(1, listed.prompts.len());
+
+        let render = |arguments: HashMap<String, String>| {
+            Request::new(RenderPromptRequest {
+                name: "greeting".to_string(),
+                arguments,
+            })
+        };
+        let rendered = library
+            .render_prompt(render(HashMap::from([(
+                "name".to_string(),
+                "World".to_string(),
+            )])))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("Hello World", rendered.text);
+        let error = library
+            .render_prompt(render(HashMap::new()))
+            .await
+            .unwrap_err();
+        assert_eq!(Code::InvalidArgument, error.code());
+
+        library
+            .delete_prompt(Request::new(DeletePromptRequest {
+                name: "greeting".to_string(),
+            }))
+            .await
+            .unwrap();
+        let error = library
+            .get_prompt(Request::new(GetPromptRequest {
+                name: "greeting".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(Code::NotFound, error.code());
+    }
+
+    #[tokio::test]
+    async fn test_save_prompt_rejects_paths_as_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path().join("prompts");
+        let library = PromptLibrary::new(FileStorage {
+            base_path: base_path.clone(),
+            fallback_paths: Vec::new(),
+            vault: false,
+        });
+        let request = Request::new(SavePromptRequest {
+            prompt: Some(Prompt {
+                name: "../outside".to_string(),
+                content: "Hello".to_string(),
+                ..Prompt::default()
+            }),
+            overwrite: false,
+        });
+
+        let error = library.save_prompt(request).await.unwrap_err();
+        assert_eq!(Code::InvalidArgument, error.code());
+        assert!(!temp_dir.path().join("outside.md").exists());
+    }
+
+    #[test]
+    fn test_render_status() {
+        let not_found = RenderTemplateError::PromptNotFound {
+            name: "missing".to_string(),
+            referenced_from: "main".to_string(),
+        };
+        assert_eq!(Code::NotFound, render_status(not_found).code());
+
+        let unreadable = RenderTemplateError::Storage {
+            name: "broken".to_string(),
+            referenced_from: "main".to_string(),
+            source: Box::new(FileStorageError::DeserializationError(
+                "invalid frontmatter".to_string(),
+            )),
+        };
+        assert_eq!(Code::Internal, render_status(unreadable).code());
+
+        let missing_argument = RenderTemplateError::MissingArgument {
+            name: "name".to_string(),
+        };
+        assert_eq!(
+            Code::InvalidArgument,
+            render_status(missing_argument).code()
+        );
+    }
+}